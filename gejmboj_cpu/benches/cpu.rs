@@ -0,0 +1,88 @@
+//! Benchmarks of the hot paths: decoding, executing ALU instructions and emulating whole frames.
+//!
+//! ```sh
+//! cargo bench -p gejmboj_cpu
+//! ```
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use gejmboj_cpu::{
+    cpu::CpuFlags,
+    gameboy::GameBoy,
+    instructions::{self, Instruction},
+    memory::Memory,
+    model::Model,
+    registers::Registers,
+};
+
+/// Every opcode followed by two operand bytes, opcodes which don't decode are left out.
+fn opcodes() -> Vec<[u8; 3]> {
+    (0..=0xFF)
+        .map(|opcode| [opcode, 0x12, 0x34])
+        .filter(|bytes| instructions::decode_bytes(bytes).is_ok())
+        .collect()
+}
+
+fn decode(c: &mut Criterion) {
+    let opcodes = opcodes();
+
+    c.bench_function("decode all opcodes", |b| {
+        b.iter(|| {
+            for bytes in opcodes.iter() {
+                black_box(instructions::decode_bytes(black_box(bytes)).unwrap());
+            }
+        })
+    });
+}
+
+fn execute_alu(c: &mut Criterion) {
+    // ADD A, B; ADC A, C; SUB D; AND E; XOR H; OR L; CP A; INC A; DEC B
+    let program: Vec<Instruction> = [0x80, 0x89, 0x92, 0xA3, 0xAC, 0xB5, 0xBF, 0x3C, 0x05]
+        .iter()
+        .map(|opcode| instructions::decode_bytes(&[*opcode]).unwrap().0)
+        .collect();
+    let mut registers = Registers::new();
+    let mut memory = Memory::new();
+    let mut flags = CpuFlags::new();
+
+    c.bench_function("execute ALU instructions", |b| {
+        b.iter(|| {
+            for instruction in program.iter() {
+                black_box(
+                    instruction
+                        .execute(&mut registers, &mut memory, &mut flags)
+                        .unwrap(),
+                );
+            }
+        })
+    });
+}
+
+/// A ROM keeping the CPU busy with arithmetic and WRAM writes while the LCD is on.
+fn busy_rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    // JP 0x0150
+    rom[0x0100..0x0103].copy_from_slice(&[0xC3, 0x50, 0x01]);
+    rom[0x0150..0x015C].copy_from_slice(&[
+        0x21, 0x00, 0xC0, // LD HL, 0xC000
+        0x3C, // INC A
+        0x80, // ADD A, B
+        0x04, // INC B
+        0x77, // LD (HL), A
+        0x2C, // INC L
+        0xCB, 0x37, // SWAP A
+        0x18, 0xF7, // JR -9
+    ]);
+    rom
+}
+
+fn run_frame(c: &mut Criterion) {
+    let mut gameboy = GameBoy::new(Model::Dmg);
+    gameboy.load_rom(busy_rom()).unwrap();
+
+    c.bench_function("run a frame of a busy ROM", |b| {
+        b.iter(|| black_box(gameboy.run_frame().unwrap()))
+    });
+}
+
+criterion_group!(benches, decode, execute_alu, run_frame);
+criterion_main!(benches);
@@ -0,0 +1,56 @@
+//! Implements a custom mapper: a flat 32 KiB ROM without banking plus a debug register at
+//! `2000-3FFF` which records every value written to it.
+
+use std::{cell::RefCell, rc::Rc};
+
+use gejmboj_cpu::{cartridge::Mapper, cpu::CPU, memory::Memory, registers::Registers};
+
+struct DebugMapper {
+    rom: Vec<u8>,
+    debug_log: Rc<RefCell<Vec<u8>>>,
+}
+
+impl Mapper for DebugMapper {
+    fn read_rom(&self, address: u16) -> u8 {
+        self.rom.get(address as usize).copied().unwrap_or(0xFF)
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        if let 0x2000..=0x3FFF = address {
+            self.debug_log.borrow_mut().push(value);
+        }
+    }
+
+    fn read_ram(&self, _address: u16) -> u8 {
+        0xFF
+    }
+
+    fn write_ram(&mut self, _address: u16, _value: u8) {}
+}
+
+fn main() {
+    let mut rom = vec![0; 0x8000];
+    rom[..7].copy_from_slice(&[
+        0xAF, // XOR A
+        0x3C, // INC A
+        0xEA, 0x00, 0x20, // LD (0x2000), A
+        0x18, 0xFA, // JR -6
+    ]);
+
+    let debug_log = Rc::new(RefCell::new(vec![]));
+    let mapper = DebugMapper {
+        rom,
+        debug_log: debug_log.clone(),
+    };
+
+    let mut memory = Memory::with_cartridge(Box::new(mapper));
+    let mut registers = Registers::new();
+    let mut cpu = CPU::new();
+
+    for _ in 0..10 {
+        let result = cpu.tick(&mut registers, &mut memory).unwrap();
+        println!("{:04x}: {:?}", result.address, result.instruction);
+    }
+
+    println!("Debug register writes: {:02x?}", debug_log.borrow());
+}
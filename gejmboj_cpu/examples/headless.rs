@@ -0,0 +1,86 @@
+//! Runs a ROM without a display and prints what a test ROM would show: the bytes sent over the
+//! serial port and a hash of the last frame.
+//!
+//! ```sh
+//! cargo run --example headless -- cpu_instrs.gb 600
+//! ```
+//!
+//! There is no serial port yet, transfers are captured by watching the CPU start one: writing
+//! `81` to SC (`FF02`) sends the byte last written to SB (`FF01`).
+
+use std::{cell::RefCell, env, fs, process, rc::Rc};
+
+use gejmboj_cpu::{
+    gameboy::GameBoy,
+    memory::{MemoryAccess, MemoryObserver},
+    model::Model,
+};
+
+const DEFAULT_FRAMES: u32 = 60;
+
+#[derive(Default)]
+struct SerialCapture {
+    data: u8,
+    sent: Rc<RefCell<Vec<u8>>>,
+}
+
+impl MemoryObserver for SerialCapture {
+    fn on_write(&mut self, access: MemoryAccess) {
+        match (access.address, access.value) {
+            (0xFF01, value) => self.data = value,
+            (0xFF02, 0x81) => self.sent.borrow_mut().push(self.data),
+            _ => {}
+        }
+    }
+}
+
+/// FNV-1a, stable across platforms and Rust versions unlike the `std` hashers.
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let (path, frames) = match args.as_slice() {
+        [_, path] => (path, DEFAULT_FRAMES),
+        [_, path, frames] => match frames.parse() {
+            Ok(frames) => (path, frames),
+            Err(_) => exit(&format!("Invalid frame count: {}", frames)),
+        },
+        _ => exit("Usage: headless <rom> [frames]"),
+    };
+
+    let rom = fs::read(path).unwrap_or_else(|e| exit(&format!("Can't read {}: {}", path, e)));
+    let model = match rom.get(0x0143) {
+        Some(0x80) | Some(0xC0) => Model::Cgb,
+        _ => Model::Dmg,
+    };
+    let mut gameboy = GameBoy::new(model);
+    gameboy
+        .load_rom(rom)
+        .unwrap_or_else(|e| exit(&e.to_string()));
+
+    let capture = SerialCapture::default();
+    let sent = capture.sent.clone();
+    gameboy.memory_mut().set_observer(Box::new(capture));
+
+    for frame in 0..frames {
+        if let Err(e) = gameboy.run_frame() {
+            exit(&format!("Stopped in frame {}: {}", frame, e));
+        }
+    }
+
+    println!("Serial: {}", String::from_utf8_lossy(&sent.borrow()));
+    println!(
+        "Frame {} hash: {:016x}",
+        frames,
+        hash(gameboy.framebuffer().pixels())
+    );
+}
+
+fn exit(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1)
+}
@@ -0,0 +1,119 @@
+//! Plays a ROM in a window, needs the `window` feature:
+//!
+//! ```sh
+//! cargo run --example window --features window -- tetris.gb
+//! ```
+//!
+//! The picture reaches the window through a `VideoSink` and frames are paced by a `Throttle`.
+//! There is no APU yet, so the game is silent. Keys:
+//!
+//! ```asciidoc
+//! Arrows:    D-pad
+//! X, Z:      A, B
+//! Enter:     Start
+//! Backspace: Select
+//! Escape:    Quit
+//! ```
+
+use std::{cell::RefCell, env, fs, process, rc::Rc};
+
+use gejmboj_cpu::{
+    cpu::FrameSummary,
+    gameboy::{GameBoy, VideoSink},
+    joypad::Button,
+    model::Model,
+    renderer::{Framebuffer, HEIGHT, WIDTH},
+    throttle::Throttle,
+};
+use minifb::{Key, Scale, Window, WindowOptions};
+
+const KEYS: [(Key, Button); 8] = [
+    (Key::Right, Button::Right),
+    (Key::Left, Button::Left),
+    (Key::Up, Button::Up),
+    (Key::Down, Button::Down),
+    (Key::X, Button::A),
+    (Key::Z, Button::B),
+    (Key::Backspace, Button::Select),
+    (Key::Enter, Button::Start),
+];
+
+/// Shows every frame in the window.
+struct WindowSink {
+    window: Rc<RefCell<Window>>,
+    buffer: Vec<u32>,
+}
+
+impl VideoSink for WindowSink {
+    fn on_frame(&mut self, _frame: &FrameSummary, framebuffer: &Framebuffer) {
+        for (pixel, rgba) in self.buffer.iter_mut().zip(framebuffer.pixels().chunks(4)) {
+            *pixel = u32::from_be_bytes([0, rgba[0], rgba[1], rgba[2]]);
+        }
+
+        if let Err(e) = self
+            .window
+            .borrow_mut()
+            .update_with_buffer(&self.buffer, WIDTH, HEIGHT)
+        {
+            exit(&e.to_string());
+        }
+    }
+}
+
+fn main() {
+    let path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| exit("Usage: window <rom>"));
+    let rom = fs::read(&path).unwrap_or_else(|e| exit(&format!("Can't read {}: {}", path, e)));
+    let model = match rom.get(0x0143) {
+        Some(0x80) | Some(0xC0) => Model::Cgb,
+        _ => Model::Dmg,
+    };
+
+    let options = WindowOptions {
+        scale: Scale::X4,
+        ..WindowOptions::default()
+    };
+    let mut window =
+        Window::new("gejmboj", WIDTH, HEIGHT, options).unwrap_or_else(|e| exit(&e.to_string()));
+    window.set_target_fps(0);
+    let window = Rc::new(RefCell::new(window));
+
+    let cartridge =
+        gejmboj_cpu::cartridge::load(rom, None).unwrap_or_else(|e| exit(&e.to_string()));
+    let mut gameboy = GameBoy::builder()
+        .model(model)
+        .cartridge(cartridge)
+        .video_sink(Box::new(WindowSink {
+            window: window.clone(),
+            buffer: vec![0; WIDTH * HEIGHT],
+        }))
+        .build()
+        .unwrap_or_else(|e| exit(&e.to_string()));
+
+    let mut throttle = Throttle::new();
+    loop {
+        {
+            let window = window.borrow();
+            if !window.is_open() || window.is_key_down(Key::Escape) {
+                break;
+            }
+            for (key, button) in KEYS.iter() {
+                match window.is_key_down(*key) {
+                    true => gameboy.press_button(*button),
+                    false => gameboy.release_button(*button),
+                }
+            }
+        }
+
+        if let Err(e) = gameboy.run_frame() {
+            exit(&e.to_string());
+        }
+        throttle.wait();
+    }
+}
+
+fn exit(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1)
+}
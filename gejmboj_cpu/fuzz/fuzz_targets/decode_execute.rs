@@ -0,0 +1,87 @@
+//! Decodes and executes arbitrary instruction streams on a scratch machine.
+//!
+//! The first 10 bytes of the input set AF, BC, DE, HL and SP, the rest is loaded to `C000` and
+//! executed from there. Decoding and executing must never panic and every instruction must take
+//! between 1 and 6 machine cycles.
+//!
+//! ```sh
+//! cargo +nightly fuzz run decode_execute
+//! ```
+
+#![no_main]
+
+use std::convert::TryInto;
+
+use gejmboj_cpu::{
+    cpu::CpuFlags,
+    instructions,
+    memory::Memory,
+    registers::{DoubleRegister, Registers},
+};
+use libfuzzer_sys::fuzz_target;
+
+const CODE_START: u16 = 0xC000;
+const MAX_CODE: usize = 0x2000;
+const MAX_INSTRUCTIONS: usize = 64;
+
+const REGISTERS: [DoubleRegister; 5] = [
+    DoubleRegister::AF,
+    DoubleRegister::BC,
+    DoubleRegister::DE,
+    DoubleRegister::HL,
+    DoubleRegister::SP,
+];
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < REGISTERS.len() * 2 {
+        return;
+    }
+    let (state, code) = data.split_at(REGISTERS.len() * 2);
+    let code = &code[..code.len().min(MAX_CODE)];
+
+    let mut registers = Registers::new();
+    for (register, value) in REGISTERS.iter().zip(state.chunks(2)) {
+        let value = u16::from_le_bytes(value.try_into().unwrap());
+        // The lower nibble of F is always 0
+        let value = match register {
+            DoubleRegister::AF => value & 0xFFF0,
+            _ => value,
+        };
+        registers.set_double(register, value);
+    }
+    registers.PC = CODE_START;
+
+    let mut memory = Memory::new();
+    memory.load(CODE_START as usize, code);
+    let mut flags = CpuFlags::new();
+
+    for _ in 0..MAX_INSTRUCTIONS {
+        let opcode = memory.get(registers.PC as usize);
+        let (instruction, size) = match instructions::decode(opcode, registers.PC, &memory) {
+            Ok(decoded) => decoded,
+            Err(_) => return,
+        };
+        if instruction.is_illegal() {
+            return;
+        }
+        assert!((1..=3).contains(&size), "{:?} is {} bytes", instruction, size);
+
+        registers.PC = registers.PC.wrapping_add(size as u16);
+        let execution = match instruction.execute(&mut registers, &mut memory, &mut flags) {
+            Ok(execution) => execution,
+            Err(_) => return,
+        };
+        assert!(
+            (1..=6).contains(&execution.cycles),
+            "{:?} took {} machine cycles",
+            instruction,
+            execution.cycles
+        );
+        assert_eq!(
+            0,
+            registers.get_double(&DoubleRegister::AF) & 0x000F,
+            "{:?} set the lower nibble of F",
+            instruction
+        );
+    }
+});
@@ -0,0 +1,422 @@
+//! # Assembler
+//!
+//! Parses SM83 assembly in the syntax printed by the disassembler, i.e. the `Display` of
+//! `Instruction`, into instructions and machine code. Mnemonics and registers are case
+//! insensitive, numbers are decimal, `0x` or `$` prefixed hex, or `0b` or `%` prefixed binary.
+//! Relative jumps take the signed offset, not the target address.
+//!
+//! ```
+//! # use gejmboj_cpu::asm;
+//! let program = asm::assemble("ld a, $3b\nADD A, B ; A += B\nJR -3").unwrap();
+//! assert_eq!(vec![0x3E, 0x3B, 0x80, 0x18, 0xFD], program);
+//!
+//! let instruction = asm::parse("cp (hl)").unwrap();
+//! assert_eq!("CP A, (HL)", instruction.to_string());
+//! ```
+
+use crate::{
+    errors::CpuError,
+    instructions::{
+        alu_16bit::ALU16Bit,
+        alu_8bit::ALU8Bit,
+        bit::Bit,
+        control_flow::ControlFlow,
+        load_16bit::Load16Bit,
+        load_8bit::Load8Bit,
+        misc::{Misc, ILLEGAL_OPCODES},
+        rotate_shift::RotateShift,
+        Condition, Instruction, Target,
+    },
+    registers::{DoubleRegister, SingleRegister},
+};
+
+/// An instruction operand as written in assembly.
+#[derive(Debug)]
+enum Operand {
+    /// `A`, `B`, `C`, `D`, `E`, `H` or `L`
+    Register(SingleRegister),
+    /// `AF`, `BC`, `DE`, `HL` or `SP`
+    Pair(DoubleRegister),
+    /// `(BC)`, `(DE)` or `(HL)`
+    Indirect(DoubleRegister),
+    /// `(HL+)` or `(HLI)`
+    HlIncrement,
+    /// `(HL-)` or `(HLD)`
+    HlDecrement,
+    /// `(C)`
+    IndirectC,
+    /// `SP+e` or `SP-e`
+    SpOffset(i32),
+    /// `NZ`, `Z` or `NC`, the carry condition `C` is parsed as the register
+    Condition(Condition),
+    Number(i32),
+    /// `(n)`
+    Address(i32),
+}
+
+/// Parses a single instruction, e.g. `LD A, (HL+)`.
+pub fn parse(source: &str) -> Result<Instruction, CpuError> {
+    let source = source.trim().to_uppercase();
+    let (mnemonic, operands) = match source.split_once(char::is_whitespace) {
+        Some((mnemonic, operands)) => (mnemonic, operands.trim()),
+        None => (source.as_str(), ""),
+    };
+    let operands = match operands {
+        "" => Some(vec![]),
+        x => x.split(',').map(operand).collect(),
+    };
+
+    operands
+        .and_then(|x| instruction(mnemonic, &x))
+        .ok_or_else(|| CpuError::Error(format!("Invalid instruction: {}", source)))
+}
+
+/// Assembles a program with one instruction per line into machine code. Blank lines and
+/// comments starting with `;` are skipped.
+pub fn assemble(source: &str) -> Result<Vec<u8>, CpuError> {
+    let mut bytes = vec![];
+
+    for (number, line) in source.lines().enumerate() {
+        let line = line.split(';').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let instruction = parse(line).map_err(|_| {
+            CpuError::Error(format!(
+                "Invalid instruction on line {}: {}",
+                number + 1,
+                line
+            ))
+        })?;
+        bytes.extend(instruction.encode()?);
+    }
+
+    Ok(bytes)
+}
+
+fn operand(source: &str) -> Option<Operand> {
+    let source = source.trim();
+
+    if let Some(inner) = source.strip_prefix('(').and_then(|x| x.strip_suffix(')')) {
+        return match inner.trim() {
+            "BC" => Some(Operand::Indirect(DoubleRegister::BC)),
+            "DE" => Some(Operand::Indirect(DoubleRegister::DE)),
+            "HL" => Some(Operand::Indirect(DoubleRegister::HL)),
+            "HL+" | "HLI" => Some(Operand::HlIncrement),
+            "HL-" | "HLD" => Some(Operand::HlDecrement),
+            "C" => Some(Operand::IndirectC),
+            x => number(x).map(Operand::Address),
+        };
+    }
+
+    match source {
+        "A" => Some(Operand::Register(SingleRegister::A)),
+        "B" => Some(Operand::Register(SingleRegister::B)),
+        "C" => Some(Operand::Register(SingleRegister::C)),
+        "D" => Some(Operand::Register(SingleRegister::D)),
+        "E" => Some(Operand::Register(SingleRegister::E)),
+        "H" => Some(Operand::Register(SingleRegister::H)),
+        "L" => Some(Operand::Register(SingleRegister::L)),
+        "AF" => Some(Operand::Pair(DoubleRegister::AF)),
+        "BC" => Some(Operand::Pair(DoubleRegister::BC)),
+        "DE" => Some(Operand::Pair(DoubleRegister::DE)),
+        "HL" => Some(Operand::Pair(DoubleRegister::HL)),
+        "SP" => Some(Operand::Pair(DoubleRegister::SP)),
+        "NZ" => Some(Operand::Condition(Condition::NotZero)),
+        "Z" => Some(Operand::Condition(Condition::Zero)),
+        "NC" => Some(Operand::Condition(Condition::NoCarry)),
+        x if x.starts_with("SP") => {
+            let offset = x[2..].trim_start();
+            let offset = offset.strip_prefix('+').unwrap_or(offset).trim_start();
+            number(offset).map(Operand::SpOffset)
+        }
+        x => number(x).map(Operand::Number),
+    }
+}
+
+fn number(source: &str) -> Option<i32> {
+    let (sign, source) = match source.strip_prefix('-') {
+        Some(x) => (-1, x),
+        None => (1, source),
+    };
+    let value = if let Some(x) = source.strip_prefix("0X").or(source.strip_prefix('$')) {
+        i32::from_str_radix(x, 16)
+    } else if let Some(x) = source.strip_prefix("0B").or(source.strip_prefix('%')) {
+        i32::from_str_radix(x, 2)
+    } else {
+        source.parse()
+    };
+
+    value.ok().map(|x| sign * x)
+}
+
+/// An 8-bit immediate, negative values are stored as two's complement.
+fn n8(value: i32) -> Option<u8> {
+    (-0x80..=0xFF).contains(&value).then_some(value as u8)
+}
+
+fn n16(value: i32) -> Option<u16> {
+    (0..=0xFFFF).contains(&value).then_some(value as u16)
+}
+
+/// A signed 8-bit offset.
+fn e8(value: i32) -> Option<u8> {
+    (-0x80..=0x7F).contains(&value).then_some(value as u8)
+}
+
+/// The low byte of a `LDH` address, either `0xff00`-`0xffff` or the offset itself.
+fn high_page(value: i32) -> Option<u8> {
+    match value {
+        0xFF00..=0xFFFF | 0x00..=0xFF => Some(value as u8),
+        _ => None,
+    }
+}
+
+fn condition(operand: &Operand) -> Option<Condition> {
+    match operand {
+        Operand::Register(SingleRegister::C) => Some(Condition::Carry),
+        Operand::Condition(Condition::Carry) => Some(Condition::Carry),
+        Operand::Condition(Condition::NoCarry) => Some(Condition::NoCarry),
+        Operand::Condition(Condition::Zero) => Some(Condition::Zero),
+        Operand::Condition(Condition::NotZero) => Some(Condition::NotZero),
+        _ => None,
+    }
+}
+
+fn target(operand: &Operand) -> Option<Target> {
+    match operand {
+        Operand::Register(r) => Some(Target::Register(*r)),
+        Operand::Indirect(DoubleRegister::HL) => Some(Target::HLIndirect),
+        _ => None,
+    }
+}
+
+fn alu_8bit(mnemonic: &str, operand: &Operand) -> Option<ALU8Bit> {
+    #[allow(clippy::type_complexity)]
+    let (register, hl, immediate): (fn(SingleRegister) -> ALU8Bit, ALU8Bit, fn(u8) -> ALU8Bit) =
+        match mnemonic {
+            "ADD" => (ALU8Bit::ADD, ALU8Bit::ADD_HL(), ALU8Bit::ADD_N),
+            "ADC" => (ALU8Bit::ADC, ALU8Bit::ADC_HL(), ALU8Bit::ADC_N),
+            "SUB" => (ALU8Bit::SUB, ALU8Bit::SUB_HL(), ALU8Bit::SUB_N),
+            "SBC" => (ALU8Bit::SBC, ALU8Bit::SBC_HL(), ALU8Bit::SBC_N),
+            "AND" => (ALU8Bit::AND, ALU8Bit::AND_HL(), ALU8Bit::AND_N),
+            "XOR" => (ALU8Bit::XOR, ALU8Bit::XOR_HL(), ALU8Bit::XOR_N),
+            "OR" => (ALU8Bit::OR, ALU8Bit::OR_HL(), ALU8Bit::OR_N),
+            "CP" => (ALU8Bit::CP, ALU8Bit::CP_HL(), ALU8Bit::CP_N),
+            _ => return None,
+        };
+
+    match operand {
+        Operand::Register(r) => Some(register(*r)),
+        Operand::Indirect(DoubleRegister::HL) => Some(hl),
+        Operand::Number(n) => n8(*n).map(immediate),
+        _ => None,
+    }
+}
+
+fn cb_prefixed(mnemonic: &str, operands: &[Operand]) -> Option<Instruction> {
+    match operands {
+        [x] => {
+            let target = target(x)?;
+            let rotate_shift = match mnemonic {
+                "RLC" => RotateShift::RLC(target),
+                "RRC" => RotateShift::RRC(target),
+                "RL" => RotateShift::RL(target),
+                "RR" => RotateShift::RR(target),
+                "SLA" => RotateShift::SLA(target),
+                "SRA" => RotateShift::SRA(target),
+                "SWAP" => RotateShift::SWAP(target),
+                "SRL" => RotateShift::SRL(target),
+                _ => return None,
+            };
+            Some(Instruction::RotateShift(rotate_shift))
+        }
+        [Operand::Number(bit), x] if (0..=7).contains(bit) => {
+            let (bit, target) = (*bit as u8, target(x)?);
+            match mnemonic {
+                "BIT" => Some(Instruction::Bit(Bit::BIT(bit, target))),
+                "RES" => Some(Instruction::Bit(Bit::RES(bit, target))),
+                "SET" => Some(Instruction::Bit(Bit::SET(bit, target))),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn instruction(mnemonic: &str, operands: &[Operand]) -> Option<Instruction> {
+    use DoubleRegister::*;
+    use Operand::*;
+    use SingleRegister::A;
+
+    let instruction = match (mnemonic, operands) {
+        ("NOP", []) => Instruction::Misc(Misc::NOP()),
+        ("HALT", []) => Instruction::Misc(Misc::HALT()),
+        ("DI", []) => Instruction::Misc(Misc::DI()),
+        ("EI", []) => Instruction::Misc(Misc::EI()),
+        ("CCF", []) => Instruction::Misc(Misc::CCF()),
+        ("SCF", []) => Instruction::Misc(Misc::SCF()),
+        ("DAA", []) => Instruction::Misc(Misc::DAA()),
+        ("CPL", []) => Instruction::Misc(Misc::CPL()),
+        ("ILLEGAL", [Number(n)]) => {
+            let opcode = n8(*n)?;
+            ILLEGAL_OPCODES.contains(&opcode).then_some(())?;
+            Instruction::Misc(Misc::ILLEGAL(opcode))
+        }
+        ("RLCA", []) => Instruction::RotateShift(RotateShift::RLCA()),
+        ("RLA", []) => Instruction::RotateShift(RotateShift::RLA()),
+        ("RRCA", []) => Instruction::RotateShift(RotateShift::RRCA()),
+        ("RRA", []) => Instruction::RotateShift(RotateShift::RRA()),
+
+        // control flow
+        ("JP", [Number(n)]) => Instruction::ControlFlow(ControlFlow::JP(n16(*n)?)),
+        ("JP", [Pair(HL) | Indirect(HL)]) => Instruction::ControlFlow(ControlFlow::JP_HL()),
+        ("JP", [c, Number(n)]) => {
+            Instruction::ControlFlow(ControlFlow::JPC(n16(*n)?, condition(c)?))
+        }
+        ("JR", [Number(n)]) => Instruction::ControlFlow(ControlFlow::JR(e8(*n)?)),
+        ("JR", [c, Number(n)]) => {
+            Instruction::ControlFlow(ControlFlow::JRC(e8(*n)?, condition(c)?))
+        }
+        ("CALL", [Number(n)]) => Instruction::ControlFlow(ControlFlow::CALL(n16(*n)?)),
+        ("CALL", [c, Number(n)]) => {
+            Instruction::ControlFlow(ControlFlow::CALLC(n16(*n)?, condition(c)?))
+        }
+        ("RET", []) => Instruction::ControlFlow(ControlFlow::RET()),
+        ("RET", [c]) => Instruction::ControlFlow(ControlFlow::RETC(condition(c)?)),
+        ("RETI", []) => Instruction::ControlFlow(ControlFlow::RETI()),
+        ("RST", [Number(n)]) if matches!(n, 0x00..=0x38) && n % 8 == 0 => {
+            Instruction::ControlFlow(ControlFlow::RST(0xC7 | *n as u8))
+        }
+
+        // 8 bit loads
+        ("LD", [Register(r1), Register(r2)]) => Instruction::Load8Bit(Load8Bit::LD(*r1, *r2)),
+        ("LD", [Register(r), Indirect(HL)]) => Instruction::Load8Bit(Load8Bit::LD_FROM_HL(*r)),
+        ("LD", [Indirect(HL), Register(r)]) => Instruction::Load8Bit(Load8Bit::LD_TO_HL(*r)),
+        ("LD", [Register(r), Number(n)]) => Instruction::Load8Bit(Load8Bit::LD_N(*r, n8(*n)?)),
+        ("LD", [Indirect(HL), Number(n)]) => Instruction::Load8Bit(Load8Bit::LD_N_TO_HL(n8(*n)?)),
+        ("LD", [Register(A), Indirect(BC)]) => Instruction::Load8Bit(Load8Bit::LD_BC_TO_A()),
+        ("LD", [Register(A), Indirect(DE)]) => Instruction::Load8Bit(Load8Bit::LD_DE_TO_A()),
+        ("LD", [Indirect(BC), Register(A)]) => Instruction::Load8Bit(Load8Bit::LD_A_TO_BC()),
+        ("LD", [Indirect(DE), Register(A)]) => Instruction::Load8Bit(Load8Bit::LD_A_TO_DE()),
+        ("LD", [Register(A), Address(n)]) => Instruction::Load8Bit(Load8Bit::LD_TO_A(n16(*n)?)),
+        ("LD", [Address(n), Register(A)]) => Instruction::Load8Bit(Load8Bit::LD_FROM_A(n16(*n)?)),
+        ("LD", [Register(A), HlIncrement]) => Instruction::Load8Bit(Load8Bit::LD_A_FROM_HL_INC()),
+        ("LD", [HlIncrement, Register(A)]) => Instruction::Load8Bit(Load8Bit::LD_A_TO_HL_INC()),
+        ("LD", [Register(A), HlDecrement]) => Instruction::Load8Bit(Load8Bit::LD_A_FROM_HL_DEC()),
+        ("LD", [HlDecrement, Register(A)]) => Instruction::Load8Bit(Load8Bit::LD_A_TO_HL_DEC()),
+        ("LDH" | "LD", [Register(A), IndirectC]) => Instruction::Load8Bit(Load8Bit::LDH_C_TO_A()),
+        ("LDH" | "LD", [IndirectC, Register(A)]) => Instruction::Load8Bit(Load8Bit::LDH_C_FROM_A()),
+        ("LDH", [Register(A), Address(n)]) => {
+            Instruction::Load8Bit(Load8Bit::LDH_TO_A(high_page(*n)?))
+        }
+        ("LDH", [Address(n), Register(A)]) => {
+            Instruction::Load8Bit(Load8Bit::LDH_FROM_A(high_page(*n)?))
+        }
+
+        // 16 bit loads
+        ("LD", [Pair(SP), Pair(HL)]) => Instruction::Load16Bit(Load16Bit::LD_HL_TO_SP()),
+        ("LD", [Pair(HL), SpOffset(n)]) => {
+            Instruction::Load16Bit(Load16Bit::LD_SP_OFFSET_TO_HL(e8(*n)?))
+        }
+        ("LD", [Pair(r @ (BC | DE | HL | SP)), Number(n)]) => {
+            Instruction::Load16Bit(Load16Bit::LD(*r, n16(*n)?))
+        }
+        ("LD", [Address(n), Pair(SP)]) => Instruction::Load16Bit(Load16Bit::LD_FROM_SP(n16(*n)?)),
+        ("PUSH", [Pair(r @ (BC | DE | HL | AF))]) => Instruction::Load16Bit(Load16Bit::PUSH(*r)),
+        ("POP", [Pair(r @ (BC | DE | HL | AF))]) => Instruction::Load16Bit(Load16Bit::POP(*r)),
+
+        // 16 bit ALU
+        ("ADD", [Pair(HL), Pair(r @ (BC | DE | HL | SP))]) => {
+            Instruction::ALU16Bit(ALU16Bit::ADD_HL(*r))
+        }
+        ("ADD", [Pair(SP), Number(n)]) => Instruction::ALU16Bit(ALU16Bit::ADD_SP(e8(*n)?)),
+        ("INC", [Pair(r @ (BC | DE | HL | SP))]) => Instruction::ALU16Bit(ALU16Bit::INC(*r)),
+        ("DEC", [Pair(r @ (BC | DE | HL | SP))]) => Instruction::ALU16Bit(ALU16Bit::DEC(*r)),
+
+        // 8 bit ALU
+        ("INC", [Register(r)]) => Instruction::ALU8Bit(ALU8Bit::INC(*r)),
+        ("INC", [Indirect(HL)]) => Instruction::ALU8Bit(ALU8Bit::INC_HL()),
+        ("DEC", [Register(r)]) => Instruction::ALU8Bit(ALU8Bit::DEC(*r)),
+        ("DEC", [Indirect(HL)]) => Instruction::ALU8Bit(ALU8Bit::DEC_HL()),
+        // the `A, ` of 8 bit ALU instructions may be left out
+        (_, [Register(A), x]) | (_, [x]) => match alu_8bit(mnemonic, x) {
+            Some(x) => Instruction::ALU8Bit(x),
+            None => cb_prefixed(mnemonic, operands)?,
+        },
+
+        _ => cb_prefixed(mnemonic, operands)?,
+    };
+
+    Some(instruction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{instructions::decode, memory::Memory};
+
+    #[test]
+    fn disassembled_instructions_assemble_to_themselves() {
+        let mut memory = Memory::new();
+
+        for bytes in (0..=0xFF)
+            .map(|opcode| [opcode, 0x34, 0x12])
+            .chain((0..=0xFF).map(|opcode| [0xCB, opcode, 0x12]))
+        {
+            memory.load(0, &bytes);
+            let instruction = match decode(bytes[0], 0, &memory) {
+                Ok((x, _)) => x,
+                Err(_) => continue,
+            };
+            let text = instruction.to_string();
+
+            assert_eq!(Ok(instruction), parse(&text), "{}", text);
+        }
+    }
+
+    #[test]
+    fn numbers_and_aliases_are_accepted() {
+        for (source, bytes) in [
+            ("ld hl, $c000", vec![0x21, 0x00, 0xC0]),
+            ("LD B, %1010", vec![0x06, 0x0A]),
+            ("LD A, -1", vec![0x3E, 0xFF]),
+            ("LD A, (HLI)", vec![0x2A]),
+            ("LDH A, (0x44)", vec![0xF0, 0x44]),
+            ("CP 10", vec![0xFE, 0x0A]),
+            ("JP C, 0x0150", vec![0xC2, 0x50, 0x01]),
+            ("JP (HL)", vec![0xE9]),
+            ("RST 0x38", vec![0xFF]),
+            ("SET 7, (HL)", vec![0xCB, 0xFE]),
+        ] {
+            assert_eq!(Ok(bytes), assemble(source), "{}", source);
+        }
+    }
+
+    #[test]
+    fn invalid_instructions_are_rejected() {
+        for source in [
+            "HALT A",
+            "LD A",
+            "LD F, B",
+            "LD B, 256",
+            "JR 128",
+            "PUSH SP",
+            "PUSH PC",
+            "INC PC",
+            "RST 0x39",
+            "BIT 8, A",
+            "LDH A, (0xfe00)",
+        ] {
+            assert!(parse(source).is_err(), "{}", source);
+        }
+
+        assert_eq!(
+            Err(CpuError::Error(
+                "Invalid instruction on line 2: LD A, B, C".to_string()
+            )),
+            assemble("NOP\nLD A, B, C ; too many\n")
+        );
+    }
+}
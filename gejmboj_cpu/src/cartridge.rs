@@ -0,0 +1,243 @@
+//! # Cartridge memory bank controllers (MBC)
+//!
+//! The cartridge is connected to the external bus and is responsible for two regions of the
+//! memory map:
+//!
+//! ```asciidoc
+//! 0000-3FFF: ROM bank 0 (usually fixed)
+//! 4000-7FFF: Switchable ROM bank
+//! A000-BFFF: External (cartridge) RAM
+//! ```
+//!
+//! Writes to the ROM region do not modify the ROM but are interpreted as commands by the
+//! memory bank controller, for example to select which ROM bank is visible at `4000-7FFF`.
+
+pub mod huc1;
+pub mod li_cheng;
+pub mod mbc3;
+pub mod mbc5;
+pub mod mbc7;
+pub mod rom_only;
+pub mod wisdom_tree;
+
+use crate::errors::CpuError;
+
+/// Location of the CGB flag in the cartridge header.
+///
+/// Bit 7 is set for cartridges supporting CGB functions, other values mean a DMG-only
+/// cartridge.
+pub const HEADER_CGB_FLAG: usize = 0x0143;
+
+/// Location of the cartridge type in the cartridge header.
+pub const HEADER_CARTRIDGE_TYPE: usize = 0x0147;
+
+/// Location of the RAM size in the cartridge header.
+pub const HEADER_RAM_SIZE: usize = 0x0149;
+
+/// Size of a single ROM bank (16 KiB).
+pub const ROM_BANK_SIZE: usize = 0x4000;
+
+/// Size of a single external RAM bank (8 KiB).
+pub const RAM_BANK_SIZE: usize = 0x2000;
+
+/// A memory bank controller handling accesses to the cartridge regions of the memory map.
+///
+/// Addresses are passed as-is from the bus, i.e. `read_rom` receives addresses in `0000-7FFF` and
+/// `read_ram` addresses in `A000-BFFF`.
+///
+/// Custom mappers only need to implement this trait to be connected with `Memory::with_cartridge`,
+/// see `examples/custom_mapper.rs`.
+pub trait Mapper {
+    /// Reads a byte from the ROM region (`0000-7FFF`).
+    fn read_rom(&self, address: u16) -> u8;
+
+    /// Handles a write to the ROM region (`0000-7FFF`), usually a bank controller command.
+    fn write_rom(&mut self, address: u16, value: u8);
+
+    /// Reads a byte from the external RAM region (`A000-BFFF`).
+    fn read_ram(&self, address: u16) -> u8;
+
+    /// Writes a byte to the external RAM region (`A000-BFFF`).
+    fn write_ram(&mut self, address: u16, value: u8);
+
+    /// Returns the offset into the ROM which `address` (`0000-7FFF`) is mapped to with the
+    /// currently selected banks. Defaults to `address`, i.e. no banking.
+    fn rom_offset(&self, address: u16) -> usize {
+        address as usize
+    }
+
+    /// Returns `false` if writes to the ROM region have no effect, i.e. there is no bank
+    /// controller. Defaults to `true`.
+    fn has_controller(&self) -> bool {
+        true
+    }
+}
+
+/// The supported memory bank controllers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MapperKind {
+    RomOnly,
+    Mbc3 { rtc: bool },
+    Mbc5 { rumble: bool },
+    Mbc7,
+    HuC1,
+    WisdomTree,
+    LiCheng,
+}
+
+impl MapperKind {
+    /// Detects the mapper from the cartridge type in the header of `rom`.
+    ///
+    /// Unlicensed mappers such as `WisdomTree` and `LiCheng` can't be detected since their
+    /// headers don't identify them, select those with an override in `load` instead.
+    pub fn from_header(rom: &[u8]) -> Result<Self, CpuError> {
+        let cartridge_type = rom.get(HEADER_CARTRIDGE_TYPE).copied().unwrap_or(0);
+
+        match cartridge_type {
+            0x00 | 0x08 | 0x09 => Ok(MapperKind::RomOnly),
+            0x0F | 0x10 => Ok(MapperKind::Mbc3 { rtc: true }),
+            0x11..=0x13 => Ok(MapperKind::Mbc3 { rtc: false }),
+            0x19..=0x1B => Ok(MapperKind::Mbc5 { rumble: false }),
+            0x1C..=0x1E => Ok(MapperKind::Mbc5 { rumble: true }),
+            0x22 => Ok(MapperKind::Mbc7),
+            0xFF => Ok(MapperKind::HuC1),
+            _ => Err(CpuError::UnsupportedCartridge(cartridge_type)),
+        }
+    }
+}
+
+/// Returns the external RAM size in bytes declared in the header of `rom`.
+pub fn ram_size(rom: &[u8]) -> usize {
+    match rom.get(HEADER_RAM_SIZE) {
+        Some(0x01) => 0x800,
+        Some(0x02) => RAM_BANK_SIZE,
+        Some(0x03) => 4 * RAM_BANK_SIZE,
+        Some(0x04) => 16 * RAM_BANK_SIZE,
+        Some(0x05) => 8 * RAM_BANK_SIZE,
+        _ => 0,
+    }
+}
+
+/// Creates the mapper for `rom`.
+///
+/// The mapper is detected from the cartridge header unless `kind` overrides it, which is
+/// necessary for unlicensed mappers and ROMs with incorrect headers.
+///
+/// ```
+/// # use gejmboj_cpu::cartridge::{self, MapperKind, Mapper};
+/// let mut rom = vec![0; 0x10000];
+/// rom[0x8000] = 0x42;
+///
+/// let mut mapper = cartridge::load(rom, Some(MapperKind::WisdomTree)).unwrap();
+/// mapper.write_rom(0x0001, 0);
+///
+/// assert_eq!(0x42, mapper.read_rom(0x0000));
+/// ```
+pub fn load(rom: Vec<u8>, kind: Option<MapperKind>) -> Result<Box<dyn Mapper>, CpuError> {
+    let kind = match kind {
+        Some(kind) => kind,
+        None => MapperKind::from_header(&rom)?,
+    };
+    let ram_size = ram_size(&rom);
+
+    Ok(match kind {
+        MapperKind::RomOnly => Box::new(rom_only::RomOnly::new(rom, ram_size)),
+        MapperKind::Mbc3 { rtc } => Box::new(mbc3::Mbc3::new(rom, ram_size, rtc)),
+        MapperKind::Mbc5 { rumble } => Box::new(mbc5::Mbc5::new(rom, ram_size, rumble)),
+        MapperKind::Mbc7 => Box::new(mbc7::Mbc7::new(rom)),
+        MapperKind::HuC1 => Box::new(huc1::HuC1::new(rom, ram_size)),
+        MapperKind::WisdomTree => Box::new(wisdom_tree::WisdomTree::new(rom)),
+        MapperKind::LiCheng => Box::new(li_cheng::LiCheng::new(rom, ram_size)),
+    })
+}
+
+/// Returns the number of 16 KiB banks in `rom`, always at least 1.
+fn rom_bank_count(rom: &[u8]) -> usize {
+    rom.len().div_ceil(ROM_BANK_SIZE).max(1)
+}
+
+/// Returns the offset in `rom` of `address`, with bank 0 fixed at `0000-3FFF` and `bank` switched
+/// in at `4000-7FFF`. Banks past the end of `rom` wrap around.
+fn banked_rom_offset(rom: &[u8], bank: usize, address: u16) -> usize {
+    let bank = match address {
+        0x0000..=0x3FFF => 0,
+        _ => bank % rom_bank_count(rom),
+    };
+
+    bank * ROM_BANK_SIZE + (address as usize % ROM_BANK_SIZE)
+}
+
+/// Reads `address` from `rom` banked as by `banked_rom_offset`, `FF` past the end of `rom`.
+fn read_banked_rom(rom: &[u8], bank: usize, address: u16) -> u8 {
+    rom.get(banked_rom_offset(rom, bank, address))
+        .copied()
+        .unwrap_or(0xFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a ROM where the first two bytes of every bank contain the bank number.
+    pub(super) fn banked_rom(banks: usize) -> Vec<u8> {
+        let mut rom = vec![0; banks * ROM_BANK_SIZE];
+        for bank in 0..banks {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+            rom[bank * ROM_BANK_SIZE + 1] = (bank >> 8) as u8;
+        }
+        rom
+    }
+
+    fn rom_with_header(cartridge_type: u8, ram_size: u8) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[HEADER_CARTRIDGE_TYPE] = cartridge_type;
+        rom[HEADER_RAM_SIZE] = ram_size;
+        rom
+    }
+
+    #[test]
+    fn mapper_kind_is_detected_from_header() {
+        for (cartridge_type, expected) in [
+            (0x00, MapperKind::RomOnly),
+            (0x10, MapperKind::Mbc3 { rtc: true }),
+            (0x13, MapperKind::Mbc3 { rtc: false }),
+            (0x1B, MapperKind::Mbc5 { rumble: false }),
+            (0x1E, MapperKind::Mbc5 { rumble: true }),
+            (0x22, MapperKind::Mbc7),
+            (0xFF, MapperKind::HuC1),
+        ] {
+            let rom = rom_with_header(cartridge_type, 0);
+            assert_eq!(Ok(expected), MapperKind::from_header(&rom));
+        }
+    }
+
+    #[test]
+    fn unknown_cartridge_types_are_unsupported() {
+        let rom = rom_with_header(0xFD, 0);
+
+        assert_eq!(
+            Err(CpuError::UnsupportedCartridge(0xFD)),
+            MapperKind::from_header(&rom)
+        );
+        assert!(load(rom, None).is_err());
+    }
+
+    #[test]
+    fn ram_size_is_read_from_header() {
+        assert_eq!(0, ram_size(&rom_with_header(0x00, 0x00)));
+        assert_eq!(RAM_BANK_SIZE, ram_size(&rom_with_header(0x00, 0x02)));
+        assert_eq!(4 * RAM_BANK_SIZE, ram_size(&rom_with_header(0x00, 0x03)));
+    }
+
+    #[test]
+    fn override_takes_precedence_over_header() {
+        let mut rom = rom_with_header(0x19, 0);
+        rom.resize(0x10000, 0);
+        rom[0x8000] = 0x42;
+
+        let mut mapper = load(rom, Some(MapperKind::WisdomTree)).unwrap();
+        mapper.write_rom(0x0001, 0);
+
+        assert_eq!(0x42, mapper.read_rom(0x0000));
+    }
+}
@@ -0,0 +1,149 @@
+//! # HuC1
+//!
+//! Hudson Soft's controller, used by e.g. Pokémon Card GB and Robopon. It supports up to 1 MiB
+//! ROM (64 banks) and 32 KiB RAM (4 banks) and has an infrared transceiver mapped into the RAM
+//! region.
+//!
+//! | Address     | Register                                                   |
+//! |-------------|------------------------------------------------------------|
+//! | `0000-1FFF` | `0x0E` maps the IR register into `A000-BFFF`, else the RAM |
+//! | `2000-3FFF` | ROM bank number (6 bits)                                   |
+//! | `4000-5FFF` | RAM bank number (2 bits)                                   |
+//!
+//! ## IR register
+//!
+//! The IR register is a stub: the LED state written by the game is kept, but no light is ever
+//! received, so reads always return `0xC0`.
+
+use super::{banked_rom_offset, read_banked_rom, Mapper, RAM_BANK_SIZE};
+
+const IR_MODE: u8 = 0x0E;
+const IR_NO_LIGHT: u8 = 0xC0;
+
+pub struct HuC1 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ir_mode: bool,
+    ir_led: bool,
+    rom_bank: u8,
+    ram_bank: u8,
+}
+
+impl HuC1 {
+    /// Creates a new HuC1 controller with `ram_size` bytes of external RAM.
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> Self {
+        Self {
+            rom,
+            ram: vec![0; ram_size],
+            ir_mode: false,
+            ir_led: false,
+            rom_bank: 1,
+            ram_bank: 0,
+        }
+    }
+
+    /// Returns `true` if the game has turned on the IR LED.
+    pub fn is_ir_led_on(&self) -> bool {
+        self.ir_led
+    }
+
+    /// Returns the external RAM contents, e.g. for writing battery backed saves.
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_offset(&self, address: u16) -> Option<usize> {
+        if self.ram.is_empty() {
+            return None;
+        }
+        let offset = self.ram_bank as usize * RAM_BANK_SIZE + (address as usize - 0xA000);
+
+        Some(offset % self.ram.len())
+    }
+}
+
+impl Mapper for HuC1 {
+    fn read_rom(&self, address: u16) -> u8 {
+        read_banked_rom(&self.rom, self.rom_bank as usize, address)
+    }
+
+    fn rom_offset(&self, address: u16) -> usize {
+        banked_rom_offset(&self.rom, self.rom_bank as usize, address)
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ir_mode = value & 0x0F == IR_MODE,
+            0x2000..=0x3FFF => self.rom_bank = value & 0x3F,
+            0x4000..=0x5FFF => self.ram_bank = value & 0x03,
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if self.ir_mode {
+            return IR_NO_LIGHT;
+        }
+        match self.ram_offset(address) {
+            Some(offset) => self.ram[offset],
+            None => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if self.ir_mode {
+            self.ir_led = value & 1 > 0;
+        } else if let Some(offset) = self.ram_offset(address) {
+            self.ram[offset] = value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::tests::banked_rom;
+
+    #[test]
+    fn rom_bank_is_switchable() {
+        let mut mbc = HuC1::new(banked_rom(64), 0);
+
+        assert_eq!(0, mbc.read_rom(0x0000));
+        assert_eq!(1, mbc.read_rom(0x4000));
+
+        mbc.write_rom(0x2000, 0x3F);
+        assert_eq!(0x3F, mbc.read_rom(0x4000));
+
+        mbc.write_rom(0x2000, 0xC2);
+        assert_eq!(0x02, mbc.read_rom(0x4000));
+    }
+
+    #[test]
+    fn ram_banks_can_be_switched() {
+        let mut mbc = HuC1::new(banked_rom(2), 4 * RAM_BANK_SIZE);
+
+        for bank in 0..4 {
+            mbc.write_rom(0x4000, bank);
+            mbc.write_ram(0xB000, 0x10 + bank);
+        }
+        for bank in 0..4 {
+            mbc.write_rom(0x4000, bank);
+            assert_eq!(0x10 + bank, mbc.read_ram(0xB000));
+        }
+    }
+
+    #[test]
+    fn ir_mode_maps_the_ir_register_over_ram() {
+        let mut mbc = HuC1::new(banked_rom(2), RAM_BANK_SIZE);
+        mbc.write_ram(0xA000, 0x42);
+
+        mbc.write_rom(0x0000, 0x0E);
+        assert_eq!(IR_NO_LIGHT, mbc.read_ram(0xA000));
+
+        mbc.write_ram(0xA000, 0x01);
+        assert!(mbc.is_ir_led_on());
+
+        mbc.write_rom(0x0000, 0x0A);
+        assert_eq!(0x42, mbc.read_ram(0xA000));
+    }
+}
@@ -0,0 +1,61 @@
+//! # Li Cheng
+//!
+//! Bootleg MBC5 variant found on many unlicensed Chinese cartridges. It behaves like an MBC5,
+//! except that ROM bank writes to `2100-21FF` are ignored. Some of these games write garbage to
+//! that range as a copy protection which would crash on a regular MBC5.
+
+use super::{mbc5::Mbc5, Mapper};
+
+pub struct LiCheng {
+    mbc5: Mbc5,
+}
+
+impl LiCheng {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> Self {
+        Self {
+            mbc5: Mbc5::new(rom, ram_size, false),
+        }
+    }
+}
+
+impl Mapper for LiCheng {
+    fn read_rom(&self, address: u16) -> u8 {
+        self.mbc5.read_rom(address)
+    }
+
+    fn rom_offset(&self, address: u16) -> usize {
+        self.mbc5.rom_offset(address)
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        if !(0x2100..=0x21FF).contains(&address) {
+            self.mbc5.write_rom(address, value);
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        self.mbc5.read_ram(address)
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        self.mbc5.write_ram(address, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::tests::banked_rom;
+
+    #[test]
+    fn bank_writes_to_2100_are_ignored() {
+        let mut mbc = LiCheng::new(banked_rom(4), 0);
+
+        mbc.write_rom(0x2000, 2);
+        mbc.write_rom(0x2100, 3);
+        assert_eq!(2, mbc.read_rom(0x4000));
+
+        mbc.write_rom(0x2200, 3);
+        assert_eq!(3, mbc.read_rom(0x4000));
+    }
+}
@@ -0,0 +1,387 @@
+//! # MBC3
+//!
+//! Supports up to 2 MiB ROM (128 banks), 32 KiB RAM (4 banks) and an optional real time
+//! clock (RTC).
+//!
+//! | Address     | Register                                                        |
+//! |-------------|-----------------------------------------------------------------|
+//! | `0000-1FFF` | RAM and RTC enable, `0x0A` enables and anything else disables   |
+//! | `2000-3FFF` | ROM bank number (7 bits), `0` selects bank `1`                  |
+//! | `4000-5FFF` | RAM bank number (`00-03`) or RTC register (`08-0C`)             |
+//! | `6000-7FFF` | Writing `0x00` followed by `0x01` latches the RTC registers     |
+//!
+//! ## RTC registers
+//!
+//! | Register | Contents                                                      |
+//! |----------|---------------------------------------------------------------|
+//! | `08`     | Seconds (`0-59`)                                              |
+//! | `09`     | Minutes (`0-59`)                                              |
+//! | `0A`     | Hours (`0-23`)                                                |
+//! | `0B`     | Lower 8 bits of the day counter                               |
+//! | `0C`     | Bit 0: day counter bit 8, bit 6: halt, bit 7: day counter carry |
+//!
+//! ## Persistence
+//!
+//! The RTC state can be saved and restored in the `.rtc` format used by BGB and most other
+//! emulators, see `Mbc3::save_rtc` and `Mbc3::load_rtc`.
+
+use std::convert::TryInto;
+
+use crate::errors::CpuError;
+
+use super::{banked_rom_offset, read_banked_rom, Mapper, RAM_BANK_SIZE};
+
+const MASK_DAY_HIGH: u8 = 0b0000_0001;
+const MASK_HALT: u8 = 0b0100_0000;
+const MASK_DAY_CARRY: u8 = 0b1000_0000;
+
+/// Size of an `.rtc` file with a 32-bit timestamp.
+pub const RTC_FILE_SIZE_SHORT: usize = 44;
+
+/// Size of an `.rtc` file with a 64-bit timestamp.
+pub const RTC_FILE_SIZE: usize = 48;
+
+/// The MBC3 real time clock registers.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Rtc {
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+    pub days_low: u8,
+    pub days_high: u8,
+}
+
+impl Rtc {
+    /// Returns `true` if the clock is halted.
+    pub fn is_halted(&self) -> bool {
+        self.days_high & MASK_HALT > 0
+    }
+
+    /// Returns the 9-bit day counter.
+    pub fn days(&self) -> u16 {
+        u16::from_le_bytes([self.days_low, self.days_high & MASK_DAY_HIGH])
+    }
+
+    /// Advances the clock by `seconds`, unless it is halted.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::cartridge::mbc3::Rtc;
+    /// let mut rtc = Rtc::default();
+    ///
+    /// rtc.advance(24 * 60 * 60 + 61);
+    ///
+    /// assert_eq!((1, 1, 0, 1), (rtc.seconds, rtc.minutes, rtc.hours, rtc.days()));
+    /// ```
+    pub fn advance(&mut self, seconds: u64) {
+        if self.is_halted() || seconds == 0 {
+            return;
+        }
+        let total = self.seconds as u64 + seconds;
+        self.seconds = (total % 60) as u8;
+
+        let total = self.minutes as u64 + total / 60;
+        self.minutes = (total % 60) as u8;
+
+        let total = self.hours as u64 + total / 60;
+        self.hours = (total % 24) as u8;
+
+        let days = self.days() as u64 + total / 24;
+        if days > 0x1FF {
+            self.days_high |= MASK_DAY_CARRY;
+        }
+        let [lo, hi] = ((days & 0x1FF) as u16).to_le_bytes();
+        self.days_low = lo;
+        self.days_high = (self.days_high & !MASK_DAY_HIGH) | hi;
+    }
+
+    fn get(&self, register: u8) -> u8 {
+        match register {
+            0x08 => self.seconds,
+            0x09 => self.minutes,
+            0x0A => self.hours,
+            0x0B => self.days_low,
+            _ => self.days_high,
+        }
+    }
+
+    fn set(&mut self, register: u8, value: u8) {
+        match register {
+            0x08 => self.seconds = value & 0x3F,
+            0x09 => self.minutes = value & 0x3F,
+            0x0A => self.hours = value & 0x1F,
+            0x0B => self.days_low = value,
+            _ => self.days_high = value & (MASK_DAY_HIGH | MASK_HALT | MASK_DAY_CARRY),
+        }
+    }
+
+    fn to_rtc_bytes(self) -> [u8; 20] {
+        let mut bytes = [0; 20];
+        for (i, register) in (0x08..=0x0C).enumerate() {
+            bytes[i * 4] = self.get(register);
+        }
+        bytes
+    }
+
+    fn from_rtc_bytes(bytes: &[u8]) -> Self {
+        let mut rtc = Rtc::default();
+        for (i, register) in (0x08..=0x0C).enumerate() {
+            rtc.set(register, bytes[i * 4]);
+        }
+        rtc
+    }
+}
+
+pub struct Mbc3 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    rom_bank: u8,
+    ram_bank: u8,
+    has_rtc: bool,
+    rtc: Rtc,
+    latched_rtc: Rtc,
+    latch_armed: bool,
+}
+
+impl Mbc3 {
+    /// Creates a new MBC3 controller with `ram_size` bytes of external RAM.
+    pub fn new(rom: Vec<u8>, ram_size: usize, has_rtc: bool) -> Self {
+        Self {
+            rom,
+            ram: vec![0; ram_size],
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            has_rtc,
+            rtc: Rtc::default(),
+            latched_rtc: Rtc::default(),
+            latch_armed: false,
+        }
+    }
+
+    /// Returns the external RAM contents, e.g. for writing battery backed saves.
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    /// Returns the running RTC registers.
+    pub fn rtc(&self) -> &Rtc {
+        &self.rtc
+    }
+
+    /// Returns the running RTC registers mutably, e.g. to advance the clock.
+    pub fn rtc_mut(&mut self) -> &mut Rtc {
+        &mut self.rtc
+    }
+
+    /// Serializes the RTC state in the 48 byte `.rtc` format.
+    ///
+    /// The format consists of the five running registers, the five latched registers, each
+    /// stored as a little-endian `u32`, followed by `timestamp` (seconds since the UNIX epoch)
+    /// as a little-endian `u64`.
+    pub fn save_rtc(&self, timestamp: u64) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(RTC_FILE_SIZE);
+        bytes.extend_from_slice(&self.rtc.to_rtc_bytes());
+        bytes.extend_from_slice(&self.latched_rtc.to_rtc_bytes());
+        bytes.extend_from_slice(&timestamp.to_le_bytes());
+        bytes
+    }
+
+    /// Restores the RTC state from a 44 or 48 byte `.rtc` file.
+    ///
+    /// The clock is advanced by the time that has passed between the saved timestamp and `now`
+    /// (seconds since the UNIX epoch), unless it was saved halted.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::cartridge::mbc3::Mbc3;
+    /// let mut mbc = Mbc3::new(vec![0; 0x8000], 0, true);
+    /// mbc.rtc_mut().advance(30);
+    /// let saved = mbc.save_rtc(1_000_000);
+    ///
+    /// let mut restored = Mbc3::new(vec![0; 0x8000], 0, true);
+    /// restored.load_rtc(&saved, 1_000_060).unwrap();
+    ///
+    /// assert_eq!(30, restored.rtc().seconds);
+    /// assert_eq!(1, restored.rtc().minutes);
+    /// ```
+    pub fn load_rtc(&mut self, bytes: &[u8], now: u64) -> Result<(), CpuError> {
+        let timestamp = match bytes.len() {
+            RTC_FILE_SIZE => u64::from_le_bytes(bytes[40..48].try_into().unwrap()),
+            RTC_FILE_SIZE_SHORT => u32::from_le_bytes(bytes[40..44].try_into().unwrap()) as u64,
+            len => {
+                return Err(CpuError::Error(format!(
+                    "Invalid RTC file size {}, expected {} or {} bytes",
+                    len, RTC_FILE_SIZE_SHORT, RTC_FILE_SIZE
+                )))
+            }
+        };
+
+        self.rtc = Rtc::from_rtc_bytes(&bytes[0..20]);
+        self.latched_rtc = Rtc::from_rtc_bytes(&bytes[20..40]);
+        self.rtc.advance(now.saturating_sub(timestamp));
+
+        Ok(())
+    }
+}
+
+impl Mapper for Mbc3 {
+    fn read_rom(&self, address: u16) -> u8 {
+        read_banked_rom(&self.rom, self.rom_bank as usize, address)
+    }
+
+    fn rom_offset(&self, address: u16) -> usize {
+        banked_rom_offset(&self.rom, self.rom_bank as usize, address)
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = (value & 0x7F).max(1),
+            0x4000..=0x5FFF => self.ram_bank = value & 0x0F,
+            _ => {
+                if self.latch_armed && value == 0x01 {
+                    self.latched_rtc = self.rtc;
+                }
+                self.latch_armed = value == 0x00;
+            }
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        match self.ram_bank {
+            0x00..=0x03 if !self.ram.is_empty() => {
+                let offset = self.ram_bank as usize * RAM_BANK_SIZE + (address as usize - 0xA000);
+                self.ram[offset % self.ram.len()]
+            }
+            0x08..=0x0C if self.has_rtc => self.latched_rtc.get(self.ram_bank),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.ram_enabled {
+            return;
+        }
+        match self.ram_bank {
+            0x00..=0x03 if !self.ram.is_empty() => {
+                let offset = self.ram_bank as usize * RAM_BANK_SIZE + (address as usize - 0xA000);
+                let len = self.ram.len();
+                self.ram[offset % len] = value;
+            }
+            0x08..=0x0C if self.has_rtc => {
+                self.rtc.set(self.ram_bank, value);
+                self.latched_rtc.set(self.ram_bank, value);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::tests::banked_rom;
+
+    fn mbc_with_rtc() -> Mbc3 {
+        let mut mbc = Mbc3::new(vec![0; 0x8000], RAM_BANK_SIZE, true);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc
+    }
+
+    #[test]
+    fn rom_bank_0_selects_bank_1() {
+        let mut mbc = Mbc3::new(banked_rom(4), 0, false);
+
+        mbc.write_rom(0x2000, 0);
+        assert_eq!(1, mbc.read_rom(0x4000));
+
+        mbc.write_rom(0x2000, 3);
+        assert_eq!(3, mbc.read_rom(0x4000));
+    }
+
+    #[test]
+    fn rtc_registers_are_only_updated_when_latched() {
+        let mut mbc = mbc_with_rtc();
+        mbc.write_rom(0x4000, 0x08);
+
+        mbc.rtc_mut().advance(5);
+        assert_eq!(0, mbc.read_ram(0xA000));
+
+        mbc.write_rom(0x6000, 0x00);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(5, mbc.read_ram(0xA000));
+
+        mbc.rtc_mut().advance(5);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(
+            5,
+            mbc.read_ram(0xA000),
+            "Latching requires writing 0x00 first"
+        );
+    }
+
+    #[test]
+    fn rtc_day_counter_overflow_sets_carry() {
+        let mut rtc = Rtc::default();
+
+        rtc.advance(512 * 24 * 60 * 60);
+
+        assert_eq!(0, rtc.days());
+        assert_eq!(MASK_DAY_CARRY, rtc.days_high & MASK_DAY_CARRY);
+    }
+
+    #[test]
+    fn halted_rtc_does_not_advance() {
+        let mut mbc = mbc_with_rtc();
+        mbc.write_rom(0x4000, 0x0C);
+        mbc.write_ram(0xA000, MASK_HALT);
+
+        mbc.rtc_mut().advance(100);
+
+        assert_eq!(0, mbc.rtc().seconds);
+    }
+
+    #[test]
+    fn save_rtc_uses_the_bgb_layout() {
+        let mut mbc = mbc_with_rtc();
+        mbc.rtc_mut()
+            .advance(2 * 24 * 60 * 60 + 3 * 60 * 60 + 4 * 60 + 5);
+        mbc.write_rom(0x6000, 0x00);
+        mbc.write_rom(0x6000, 0x01);
+
+        let bytes = mbc.save_rtc(0x0102_0304_0506_0708);
+
+        assert_eq!(RTC_FILE_SIZE, bytes.len());
+        assert_eq!(
+            &[5, 0, 0, 0, 4, 0, 0, 0, 3, 0, 0, 0, 2, 0, 0, 0],
+            &bytes[0..16]
+        );
+        assert_eq!(&bytes[0..20], &bytes[20..40]);
+        assert_eq!(
+            &[0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01],
+            &bytes[40..48]
+        );
+    }
+
+    #[test]
+    fn load_rtc_accepts_the_short_format() {
+        let mut bytes = vec![0; RTC_FILE_SIZE_SHORT];
+        bytes[0] = 10;
+        bytes[40..44].copy_from_slice(&100u32.to_le_bytes());
+
+        let mut mbc = mbc_with_rtc();
+        mbc.load_rtc(&bytes, 105).unwrap();
+
+        assert_eq!(15, mbc.rtc().seconds);
+    }
+
+    #[test]
+    fn load_rtc_rejects_invalid_sizes() {
+        let mut mbc = mbc_with_rtc();
+
+        assert!(mbc.load_rtc(&[0; 40], 0).is_err());
+    }
+}
@@ -0,0 +1,253 @@
+//! # MBC5
+//!
+//! Supports up to 8 MiB ROM (512 banks) and 128 KiB RAM (16 banks).
+//!
+//! | Address     | Register                                                  |
+//! |-------------|-----------------------------------------------------------|
+//! | `0000-1FFF` | RAM enable, `0x0A` enables RAM and anything else disables |
+//! | `2000-2FFF` | Lower 8 bits of the 9-bit ROM bank number                 |
+//! | `3000-3FFF` | Bit 8 of the ROM bank number                              |
+//! | `4000-5FFF` | RAM bank number (`0-F`)                                   |
+//!
+//! Unlike earlier controllers, ROM bank `0` can be mapped into `4000-7FFF`.
+//!
+//! ## Rumble
+//!
+//! On rumble cartridges bit 3 of the RAM bank register drives the rumble motor instead of
+//! selecting a RAM bank, leaving only 8 RAM banks addressable. Register a callback with
+//! `Mbc5::on_rumble` to get notified when the motor is turned on or off.
+
+use super::{banked_rom_offset, read_banked_rom, Mapper, RAM_BANK_SIZE};
+
+const MASK_RUMBLE_MOTOR: u8 = 0b0000_1000;
+
+pub struct Mbc5 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    rom_bank: u16,
+    ram_bank: u8,
+    has_rumble: bool,
+    rumble: bool,
+    rumble_callback: Option<Box<dyn FnMut(bool)>>,
+}
+
+impl Mbc5 {
+    /// Creates a new MBC5 controller with `ram_size` bytes of external RAM.
+    ///
+    /// If `has_rumble` is `true` bit 3 of the RAM bank register controls the rumble motor.
+    pub fn new(rom: Vec<u8>, ram_size: usize, has_rumble: bool) -> Self {
+        Self {
+            rom,
+            ram: vec![0; ram_size],
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            has_rumble,
+            rumble: false,
+            rumble_callback: None,
+        }
+    }
+
+    /// Registers a callback which is called with the new motor state whenever the rumble
+    /// motor is switched on or off.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::cartridge::{mbc5::Mbc5, Mapper};
+    /// # use std::{cell::Cell, rc::Rc};
+    /// let mut mbc = Mbc5::new(vec![0; 0x8000], 0, true);
+    /// let motor = Rc::new(Cell::new(false));
+    /// let motor_handle = motor.clone();
+    ///
+    /// mbc.on_rumble(Box::new(move |on| motor_handle.set(on)));
+    /// mbc.write_rom(0x4000, 0b0000_1000);
+    ///
+    /// assert!(motor.get());
+    /// ```
+    pub fn on_rumble(&mut self, callback: Box<dyn FnMut(bool)>) {
+        self.rumble_callback = Some(callback);
+    }
+
+    /// Returns `true` if the rumble motor is currently running.
+    pub fn is_rumbling(&self) -> bool {
+        self.rumble
+    }
+
+    /// Returns the currently selected ROM bank mapped into `4000-7FFF`.
+    pub fn rom_bank(&self) -> u16 {
+        self.rom_bank
+    }
+
+    /// Returns the external RAM contents, e.g. for writing battery backed saves.
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn set_rumble(&mut self, on: bool) {
+        if on != self.rumble {
+            self.rumble = on;
+            if let Some(callback) = self.rumble_callback.as_mut() {
+                callback(on);
+            }
+        }
+    }
+
+    fn ram_offset(&self, address: u16) -> Option<usize> {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return None;
+        }
+        let offset = self.ram_bank as usize * RAM_BANK_SIZE + (address as usize - 0xA000);
+
+        Some(offset % self.ram.len())
+    }
+}
+
+impl Mapper for Mbc5 {
+    fn read_rom(&self, address: u16) -> u8 {
+        read_banked_rom(&self.rom, self.rom_bank as usize, address)
+    }
+
+    fn rom_offset(&self, address: u16) -> usize {
+        banked_rom_offset(&self.rom, self.rom_bank as usize, address)
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => self.rom_bank = (self.rom_bank & 0xFF) | ((value as u16 & 1) << 8),
+            0x4000..=0x5FFF => {
+                if self.has_rumble {
+                    self.ram_bank = value & 0b0000_0111;
+                    self.set_rumble(value & MASK_RUMBLE_MOTOR > 0);
+                } else {
+                    self.ram_bank = value & 0x0F;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        match self.ram_offset(address) {
+            Some(offset) => self.ram[offset],
+            None => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if let Some(offset) = self.ram_offset(address) {
+            self.ram[offset] = value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::tests::banked_rom;
+    use std::{cell::RefCell, rc::Rc};
+
+    #[test]
+    fn bank_0_is_fixed_and_bank_1_is_selected_by_default() {
+        let mbc = Mbc5::new(banked_rom(4), 0, false);
+
+        assert_eq!(0, mbc.read_rom(0x0000));
+        assert_eq!(1, mbc.read_rom(0x4000));
+    }
+
+    #[test]
+    fn rom_bank_uses_all_9_bits() {
+        let mut mbc = Mbc5::new(banked_rom(512), 0, false);
+
+        mbc.write_rom(0x2000, 0x23);
+        mbc.write_rom(0x3000, 0x01);
+
+        assert_eq!(0x123, mbc.rom_bank());
+        assert_eq!(0x23, mbc.read_rom(0x4000));
+        assert_eq!(0x01, mbc.read_rom(0x4001));
+
+        mbc.write_rom(0x3000, 0x00);
+        assert_eq!(0x023, mbc.rom_bank());
+    }
+
+    #[test]
+    fn rom_bank_0_can_be_mapped_to_the_switchable_region() {
+        let mut mbc = Mbc5::new(banked_rom(4), 0, false);
+
+        mbc.write_rom(0x2000, 0);
+
+        assert_eq!(0, mbc.read_rom(0x4000));
+    }
+
+    #[test]
+    fn rom_bank_wraps_around_rom_size() {
+        let mut mbc = Mbc5::new(banked_rom(4), 0, false);
+
+        mbc.write_rom(0x2000, 6);
+
+        assert_eq!(2, mbc.read_rom(0x4000));
+    }
+
+    #[test]
+    fn ram_is_disabled_by_default() {
+        let mut mbc = Mbc5::new(banked_rom(2), 0x2000, false);
+
+        mbc.write_ram(0xA000, 0x42);
+
+        assert_eq!(0xFF, mbc.read_ram(0xA000));
+    }
+
+    #[test]
+    fn ram_banks_can_be_switched() {
+        let mut mbc = Mbc5::new(banked_rom(2), 4 * RAM_BANK_SIZE, false);
+        mbc.write_rom(0x0000, 0x0A);
+
+        for bank in 0..4 {
+            mbc.write_rom(0x4000, bank);
+            mbc.write_ram(0xA010, 0x10 + bank);
+        }
+        for bank in 0..4 {
+            mbc.write_rom(0x4000, bank);
+            assert_eq!(0x10 + bank, mbc.read_ram(0xA010));
+        }
+
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(0xFF, mbc.read_ram(0xA010));
+    }
+
+    #[test]
+    fn rumble_bit_drives_the_motor_and_not_the_ram_bank() {
+        let mut mbc = Mbc5::new(banked_rom(2), 8 * RAM_BANK_SIZE, true);
+        let events = Rc::new(RefCell::new(vec![]));
+        let handle = events.clone();
+        mbc.on_rumble(Box::new(move |on| handle.borrow_mut().push(on)));
+        mbc.write_rom(0x0000, 0x0A);
+
+        mbc.write_rom(0x4000, 0b0000_0001);
+        mbc.write_ram(0xA000, 0x42);
+
+        mbc.write_rom(0x4000, 0b0000_1001);
+        assert!(mbc.is_rumbling());
+        assert_eq!(0x42, mbc.read_ram(0xA000));
+
+        mbc.write_rom(0x4000, 0b0000_1001);
+        mbc.write_rom(0x4000, 0b0000_0001);
+        assert!(!mbc.is_rumbling());
+
+        assert_eq!(vec![true, false], *events.borrow());
+    }
+
+    #[test]
+    fn rumble_bit_selects_ram_bank_without_rumble() {
+        let mut mbc = Mbc5::new(banked_rom(2), 16 * RAM_BANK_SIZE, false);
+        mbc.write_rom(0x0000, 0x0A);
+
+        mbc.write_rom(0x4000, 0b0000_1000);
+        mbc.write_ram(0xA000, 0x42);
+        mbc.write_rom(0x4000, 0b0000_0000);
+
+        assert!(!mbc.is_rumbling());
+        assert_eq!(0x00, mbc.read_ram(0xA000));
+    }
+}
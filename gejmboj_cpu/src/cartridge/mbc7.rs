@@ -0,0 +1,464 @@
+//! # MBC7
+//!
+//! Used by Kirby Tilt 'n' Tumble and Command Master. Instead of RAM the cartridge has a 256 byte
+//! serial EEPROM (93LC56) and a two-axis accelerometer, both accessed through registers in
+//! `A000-AFFF`.
+//!
+//! | Address     | Register                                        |
+//! |-------------|-------------------------------------------------|
+//! | `0000-1FFF` | RAM enable 1, `0x0A` enables                    |
+//! | `2000-3FFF` | ROM bank number (7 bits)                        |
+//! | `4000-5FFF` | RAM enable 2, `0x40` enables                    |
+//!
+//! When both RAM enable registers are set, bits 4-7 of the address select a register:
+//!
+//! | Address | Register                                                   |
+//! |---------|------------------------------------------------------------|
+//! | `Ax0x`  | Write `0x55` to erase the latched accelerometer values     |
+//! | `Ax1x`  | Write `0xAA` to latch the accelerometer values             |
+//! | `Ax2x`  | Latched X value, low byte                                  |
+//! | `Ax3x`  | Latched X value, high byte                                 |
+//! | `Ax4x`  | Latched Y value, low byte                                  |
+//! | `Ax5x`  | Latched Y value, high byte                                 |
+//! | `Ax6x`  | Always `0x00`                                              |
+//! | `Ax7x`  | Always `0xFF`                                              |
+//! | `Ax8x`  | EEPROM: bit 7 `CS`, bit 6 `CLK`, bit 1 `DI`, bit 0 `DO`    |
+//!
+//! ## Accelerometer
+//!
+//! The frontend feeds tilt values through a `TiltSensor` handle, see `Mbc7::tilt_sensor`.
+//! Values are expressed in g, where `0.0` is level.
+
+use std::{cell::Cell, rc::Rc};
+
+use super::{banked_rom_offset, read_banked_rom, Mapper};
+
+/// Accelerometer reading when the cartridge is level.
+pub const ACCELEROMETER_CENTER: u16 = 0x81D0;
+
+/// Change of the accelerometer reading per g of acceleration.
+pub const ACCELEROMETER_PER_G: f32 = 112.0;
+
+const ACCELEROMETER_ERASED: u16 = 0x8000;
+const EEPROM_WORDS: usize = 128;
+
+/// Handle used by frontends to feed tilt values to the cartridge accelerometer.
+#[derive(Clone, Default)]
+pub struct TiltSensor {
+    tilt: Rc<Cell<(f32, f32)>>,
+}
+
+impl TiltSensor {
+    /// Sets the current tilt in g along the X (left/right) and Y (up/down) axes.
+    pub fn set(&self, x: f32, y: f32) {
+        self.tilt.set((x, y));
+    }
+
+    /// Returns the raw accelerometer reading for the current tilt.
+    pub fn read(&self) -> (u16, u16) {
+        let (x, y) = self.tilt.get();
+        let to_raw = |g: f32| (ACCELEROMETER_CENTER as f32 + g * ACCELEROMETER_PER_G) as u16;
+
+        (to_raw(x), to_raw(y))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum EepromState {
+    /// Waiting for a start bit.
+    Idle,
+    /// Shifting in the 10 bit command (2 bit opcode + 8 bit address).
+    Command { value: u16, count: u8 },
+    /// Shifting out a data word, most significant bit first.
+    Read { value: u16, remaining: u8 },
+    /// Shifting in a data word to write to `address`, or all words if `None`.
+    Write {
+        address: Option<u8>,
+        value: u16,
+        count: u8,
+    },
+}
+
+/// 93LC56 serial EEPROM in 16-bit word mode.
+struct Eeprom {
+    data: [u16; EEPROM_WORDS],
+    state: EepromState,
+    write_enabled: bool,
+    cs: bool,
+    clk: bool,
+    di: bool,
+    data_out: bool,
+}
+
+impl Eeprom {
+    fn new() -> Self {
+        Self {
+            data: [0xFFFF; EEPROM_WORDS],
+            state: EepromState::Idle,
+            write_enabled: false,
+            cs: false,
+            clk: false,
+            di: false,
+            data_out: true,
+        }
+    }
+
+    fn read(&self) -> u8 {
+        (self.cs as u8) << 7 | (self.clk as u8) << 6 | (self.di as u8) << 1 | self.data_out as u8
+    }
+
+    fn write(&mut self, value: u8) {
+        let cs = value & 0x80 > 0;
+        let clk = value & 0x40 > 0;
+        let rising_edge = clk && !self.clk;
+
+        self.cs = cs;
+        self.clk = clk;
+        self.di = value & 0x02 > 0;
+
+        if !cs {
+            self.state = EepromState::Idle;
+            self.data_out = true;
+        } else if rising_edge {
+            self.clock_in();
+        }
+    }
+
+    fn clock_in(&mut self) {
+        let bit = self.di as u16;
+
+        self.state = match std::mem::replace(&mut self.state, EepromState::Idle) {
+            EepromState::Idle if bit == 1 => EepromState::Command { value: 0, count: 0 },
+            EepromState::Idle => EepromState::Idle,
+            EepromState::Command { value, count } if count < 9 => EepromState::Command {
+                value: value << 1 | bit,
+                count: count + 1,
+            },
+            EepromState::Command { value, .. } => self.execute(value << 1 | bit),
+            EepromState::Read { value, remaining } => {
+                self.data_out = value & 0x8000 > 0;
+                match remaining {
+                    1 => EepromState::Idle,
+                    _ => EepromState::Read {
+                        value: value << 1,
+                        remaining: remaining - 1,
+                    },
+                }
+            }
+            EepromState::Write {
+                address,
+                value,
+                count,
+            } if count < 15 => EepromState::Write {
+                address,
+                value: value << 1 | bit,
+                count: count + 1,
+            },
+            EepromState::Write { address, value, .. } => {
+                let value = value << 1 | bit;
+                if self.write_enabled {
+                    match address {
+                        Some(address) => self.data[address as usize] = value,
+                        None => self.data = [value; EEPROM_WORDS],
+                    }
+                }
+                self.data_out = true;
+                EepromState::Idle
+            }
+        };
+    }
+
+    fn execute(&mut self, command: u16) -> EepromState {
+        let address = (command & 0x7F) as u8;
+
+        match (command >> 8, (command >> 6) & 0b11) {
+            // READ, a dummy 0 bit precedes the data
+            (0b10, _) => {
+                self.data_out = false;
+                EepromState::Read {
+                    value: self.data[address as usize],
+                    remaining: 16,
+                }
+            }
+            // WRITE
+            (0b01, _) => EepromState::Write {
+                address: Some(address),
+                value: 0,
+                count: 0,
+            },
+            // ERASE
+            (0b11, _) => {
+                if self.write_enabled {
+                    self.data[address as usize] = 0xFFFF;
+                }
+                EepromState::Idle
+            }
+            // EWEN
+            (0b00, 0b11) => {
+                self.write_enabled = true;
+                EepromState::Idle
+            }
+            // EWDS
+            (0b00, 0b00) => {
+                self.write_enabled = false;
+                EepromState::Idle
+            }
+            // ERAL
+            (0b00, 0b10) => {
+                if self.write_enabled {
+                    self.data = [0xFFFF; EEPROM_WORDS];
+                }
+                EepromState::Idle
+            }
+            // WRAL
+            _ => EepromState::Write {
+                address: None,
+                value: 0,
+                count: 0,
+            },
+        }
+    }
+}
+
+pub struct Mbc7 {
+    rom: Vec<u8>,
+    rom_bank: u8,
+    ram_enabled_1: bool,
+    ram_enabled_2: bool,
+    eeprom: Eeprom,
+    tilt_sensor: TiltSensor,
+    latched: (u16, u16),
+}
+
+impl Mbc7 {
+    pub fn new(rom: Vec<u8>) -> Self {
+        Self {
+            rom,
+            rom_bank: 1,
+            ram_enabled_1: false,
+            ram_enabled_2: false,
+            eeprom: Eeprom::new(),
+            tilt_sensor: TiltSensor::default(),
+            latched: (ACCELEROMETER_ERASED, ACCELEROMETER_ERASED),
+        }
+    }
+
+    /// Returns a handle for feeding tilt values to the accelerometer.
+    ///
+    /// The handle can be kept by the frontend after the cartridge has been inserted.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::cartridge::mbc7::{Mbc7, ACCELEROMETER_CENTER};
+    /// let mbc = Mbc7::new(vec![0; 0x8000]);
+    /// let sensor = mbc.tilt_sensor();
+    ///
+    /// sensor.set(1.0, 0.0);
+    ///
+    /// assert_eq!((ACCELEROMETER_CENTER + 112, ACCELEROMETER_CENTER), sensor.read());
+    /// ```
+    pub fn tilt_sensor(&self) -> TiltSensor {
+        self.tilt_sensor.clone()
+    }
+
+    /// Returns the EEPROM contents as little-endian bytes, e.g. for writing saves.
+    pub fn eeprom(&self) -> Vec<u8> {
+        self.eeprom
+            .data
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect()
+    }
+
+    /// Restores the EEPROM contents from little-endian bytes as returned by `eeprom`.
+    pub fn load_eeprom(&mut self, bytes: &[u8]) {
+        for (word, chunk) in self.eeprom.data.iter_mut().zip(bytes.chunks_exact(2)) {
+            *word = u16::from_le_bytes([chunk[0], chunk[1]]);
+        }
+    }
+
+    fn registers_enabled(&self) -> bool {
+        self.ram_enabled_1 && self.ram_enabled_2
+    }
+}
+
+impl Mapper for Mbc7 {
+    fn read_rom(&self, address: u16) -> u8 {
+        read_banked_rom(&self.rom, self.rom_bank as usize, address)
+    }
+
+    fn rom_offset(&self, address: u16) -> usize {
+        banked_rom_offset(&self.rom, self.rom_bank as usize, address)
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled_1 = value == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = value & 0x7F,
+            0x4000..=0x5FFF => self.ram_enabled_2 = value == 0x40,
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.registers_enabled() || address >= 0xB000 {
+            return 0xFF;
+        }
+        let (x, y) = self.latched;
+
+        match (address >> 4) & 0x0F {
+            0x2 => x as u8,
+            0x3 => (x >> 8) as u8,
+            0x4 => y as u8,
+            0x5 => (y >> 8) as u8,
+            0x6 => 0x00,
+            0x8 => self.eeprom.read(),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.registers_enabled() || address >= 0xB000 {
+            return;
+        }
+        match (address >> 4) & 0x0F {
+            0x0 if value == 0x55 => {
+                self.latched = (ACCELEROMETER_ERASED, ACCELEROMETER_ERASED);
+            }
+            0x1 if value == 0xAA
+                && self.latched == (ACCELEROMETER_ERASED, ACCELEROMETER_ERASED) =>
+            {
+                self.latched = self.tilt_sensor.read();
+            }
+            0x8 => self.eeprom.write(value),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unusual_byte_groupings)]
+mod tests {
+    use super::*;
+
+    const CS: u8 = 0x80;
+    const CLK: u8 = 0x40;
+    const DI: u8 = 0x02;
+
+    fn enabled_mbc() -> Mbc7 {
+        let mut mbc = Mbc7::new(vec![0; 0x8000]);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x40);
+        mbc
+    }
+
+    /// Clocks `count` bits of `value` into the EEPROM, most significant bit first.
+    fn send_bits(mbc: &mut Mbc7, value: u16, count: u8) {
+        for i in (0..count).rev() {
+            let di = if value >> i & 1 > 0 { DI } else { 0 };
+            mbc.write_ram(0xA080, CS | di);
+            mbc.write_ram(0xA080, CS | CLK | di);
+        }
+    }
+
+    fn receive_word(mbc: &mut Mbc7) -> u16 {
+        let mut value = 0;
+        for _ in 0..16 {
+            mbc.write_ram(0xA080, CS);
+            mbc.write_ram(0xA080, CS | CLK);
+            value = value << 1 | (mbc.read_ram(0xA080) & 1) as u16;
+        }
+        value
+    }
+
+    fn deselect(mbc: &mut Mbc7) {
+        mbc.write_ram(0xA080, 0);
+    }
+
+    #[test]
+    fn registers_require_both_ram_enables() {
+        let mut mbc = Mbc7::new(vec![0; 0x8000]);
+        assert_eq!(0xFF, mbc.read_ram(0xA060));
+
+        mbc.write_rom(0x0000, 0x0A);
+        assert_eq!(0xFF, mbc.read_ram(0xA060));
+
+        mbc.write_rom(0x4000, 0x40);
+        assert_eq!(0x00, mbc.read_ram(0xA060));
+    }
+
+    #[test]
+    fn accelerometer_values_are_latched() {
+        let mut mbc = enabled_mbc();
+        let sensor = mbc.tilt_sensor();
+        sensor.set(-1.0, 0.5);
+
+        mbc.write_ram(0xA000, 0x55);
+        assert_eq!(0x00, mbc.read_ram(0xA020));
+        assert_eq!(0x80, mbc.read_ram(0xA030));
+
+        mbc.write_ram(0xA010, 0xAA);
+        let (x, y) = sensor.read();
+        assert_eq!(ACCELEROMETER_CENTER - 112, x);
+        assert_eq!(ACCELEROMETER_CENTER + 56, y);
+
+        sensor.set(0.0, 0.0);
+        mbc.write_ram(0xA010, 0xAA);
+
+        assert_eq!(x as u8, mbc.read_ram(0xA020));
+        assert_eq!((x >> 8) as u8, mbc.read_ram(0xA030));
+        assert_eq!(y as u8, mbc.read_ram(0xA040));
+        assert_eq!((y >> 8) as u8, mbc.read_ram(0xA050));
+    }
+
+    #[test]
+    fn eeprom_write_requires_write_enable() {
+        let mut mbc = enabled_mbc();
+
+        send_bits(&mut mbc, 0b1_01_0000_0011, 11);
+        send_bits(&mut mbc, 0x1234, 16);
+        deselect(&mut mbc);
+
+        assert_eq!(0xFFFF, mbc.eeprom.data[3]);
+    }
+
+    #[test]
+    fn eeprom_words_can_be_written_and_read() {
+        let mut mbc = enabled_mbc();
+
+        // EWEN
+        send_bits(&mut mbc, 0b1_00_1100_0000, 11);
+        deselect(&mut mbc);
+
+        // WRITE 0x1234 to word 3
+        send_bits(&mut mbc, 0b1_01_0000_0011, 11);
+        send_bits(&mut mbc, 0x1234, 16);
+        deselect(&mut mbc);
+
+        // READ word 3
+        send_bits(&mut mbc, 0b1_10_0000_0011, 11);
+        assert_eq!(0, mbc.read_ram(0xA080) & 1, "Missing dummy bit");
+        assert_eq!(0x1234, receive_word(&mut mbc));
+        deselect(&mut mbc);
+
+        assert_eq!(vec![0x34, 0x12], mbc.eeprom()[6..8].to_vec());
+    }
+
+    #[test]
+    fn eeprom_can_be_erased() {
+        let mut mbc = enabled_mbc();
+        mbc.load_eeprom(&[0; 256]);
+
+        send_bits(&mut mbc, 0b1_00_1100_0000, 11);
+        deselect(&mut mbc);
+        send_bits(&mut mbc, 0b1_11_0000_0001, 11);
+        deselect(&mut mbc);
+
+        assert_eq!(0x0000, mbc.eeprom.data[0]);
+        assert_eq!(0xFFFF, mbc.eeprom.data[1]);
+
+        send_bits(&mut mbc, 0b1_00_1000_0000, 11);
+        deselect(&mut mbc);
+
+        assert!(mbc.eeprom.data.iter().all(|word| *word == 0xFFFF));
+    }
+}
@@ -0,0 +1,45 @@
+//! # ROM only
+//!
+//! Cartridges without a memory bank controller, a flat 32 KiB ROM and optionally up to 8 KiB
+//! RAM. Writes to the ROM region are ignored.
+
+use super::Mapper;
+
+pub struct RomOnly {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+}
+
+impl RomOnly {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> Self {
+        Self {
+            rom,
+            ram: vec![0; ram_size],
+        }
+    }
+}
+
+impl Mapper for RomOnly {
+    fn read_rom(&self, address: u16) -> u8 {
+        self.rom.get(address as usize).copied().unwrap_or(0xFF)
+    }
+
+    fn write_rom(&mut self, _address: u16, _value: u8) {}
+
+    fn has_controller(&self) -> bool {
+        false
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        self.ram
+            .get(address as usize - 0xA000)
+            .copied()
+            .unwrap_or(0xFF)
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if let Some(byte) = self.ram.get_mut(address as usize - 0xA000) {
+            *byte = value;
+        }
+    }
+}
@@ -0,0 +1,78 @@
+//! # Wisdom Tree
+//!
+//! Unlicensed mapper used by Wisdom Tree's games (e.g. Joshua, Exodus). Instead of a separate
+//! fixed and switchable bank, the whole `0000-7FFF` region is switched in 32 KiB banks.
+//!
+//! A write to any address in `0000-3FFF` selects the bank given by the lower 8 bits of the
+//! *address*, the written value is ignored. There is no external RAM.
+
+use super::{Mapper, ROM_BANK_SIZE};
+
+const BANK_SIZE: usize = 2 * ROM_BANK_SIZE;
+
+pub struct WisdomTree {
+    rom: Vec<u8>,
+    bank: u8,
+}
+
+impl WisdomTree {
+    pub fn new(rom: Vec<u8>) -> Self {
+        Self { rom, bank: 0 }
+    }
+
+    /// Returns the currently selected 32 KiB bank.
+    pub fn bank(&self) -> u8 {
+        self.bank
+    }
+}
+
+impl Mapper for WisdomTree {
+    fn read_rom(&self, address: u16) -> u8 {
+        self.rom
+            .get(self.rom_offset(address))
+            .copied()
+            .unwrap_or(0xFF)
+    }
+
+    fn rom_offset(&self, address: u16) -> usize {
+        let banks = self.rom.len().div_ceil(BANK_SIZE).max(1);
+
+        (self.bank as usize % banks) * BANK_SIZE + address as usize
+    }
+
+    fn write_rom(&mut self, address: u16, _value: u8) {
+        if address < 0x4000 {
+            self.bank = address as u8;
+        }
+    }
+
+    fn read_ram(&self, _address: u16) -> u8 {
+        0xFF
+    }
+
+    fn write_ram(&mut self, _address: u16, _value: u8) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn address_selects_the_whole_32k_bank() {
+        let mut rom = vec![0; 4 * BANK_SIZE];
+        for bank in 0..4 {
+            rom[bank * BANK_SIZE] = bank as u8;
+            rom[bank * BANK_SIZE + 0x4000] = 0x10 + bank as u8;
+        }
+        let mut mbc = WisdomTree::new(rom);
+
+        mbc.write_rom(0x0002, 0xFF);
+
+        assert_eq!(2, mbc.bank());
+        assert_eq!(0x02, mbc.read_rom(0x0000));
+        assert_eq!(0x12, mbc.read_rom(0x4000));
+
+        mbc.write_rom(0x4003, 0x00);
+        assert_eq!(2, mbc.bank(), "Writes above 3FFF are ignored");
+    }
+}
@@ -0,0 +1,176 @@
+//! # DMG game colorization
+//!
+//! Without a boot ROM nothing assigns colors to a DMG-only game running on CGB. The CGB boot
+//! ROM picks a palette set from a table keyed on the cartridge header:
+//!
+//! 1. Games not licensed by Nintendo get the default colorization
+//! 2. Otherwise the sum of the 16 title bytes (`0134-0143`) is looked up in the table
+//! 3. Checksums shared by several titles are told apart by the 4th title letter (`0137`)
+//!
+//! `select` implements this lookup over a caller supplied table of `TitleColorization`s, the
+//! per-title entries of the boot ROM are not bundled. `Memory::finish_boot` applies the result
+//! to the palette RAM, see `Memory::set_colorization_table`.
+
+use crate::palette::PaletteRam;
+
+const HEADER_TITLE: usize = 0x0134;
+const HEADER_TITLE_LENGTH: usize = 16;
+const HEADER_NEW_LICENSEE: usize = 0x0144;
+const HEADER_OLD_LICENSEE: usize = 0x014B;
+
+/// Palettes assigned to a DMG game, as 15-bit CGB colors from lightest to darkest.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Colorization {
+    pub background: [u16; 4],
+    pub object0: [u16; 4],
+    pub object1: [u16; 4],
+}
+
+impl Colorization {
+    /// Writes the palettes to background palette 0 and object palettes 0 and 1.
+    pub fn apply(&self, background: &mut PaletteRam, objects: &mut PaletteRam) {
+        for color in 0..4 {
+            background.set_raw_color(0, color as u8, self.background[color]);
+            objects.set_raw_color(0, color as u8, self.object0[color]);
+            objects.set_raw_color(1, color as u8, self.object1[color]);
+        }
+    }
+}
+
+/// Colorization for unknown and non-Nintendo games.
+pub const DEFAULT: Colorization = Colorization {
+    background: [0x7FFF, 0x1BEF, 0x6180, 0x0000],
+    object0: [0x7FFF, 0x421F, 0x1CF2, 0x0000],
+    object1: [0x7FFF, 0x421F, 0x1CF2, 0x0000],
+};
+
+/// A colorization table entry.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TitleColorization {
+    /// Sum of the title bytes
+    pub checksum: u8,
+    /// 4th title letter, for checksums shared by several titles
+    pub fourth_letter: Option<u8>,
+    pub colorization: Colorization,
+}
+
+/// Returns the sum of the title bytes in the cartridge header.
+pub fn title_checksum(header: impl Fn(usize) -> u8) -> u8 {
+    (HEADER_TITLE..HEADER_TITLE + HEADER_TITLE_LENGTH)
+        .map(header)
+        .fold(0, u8::wrapping_add)
+}
+
+/// Returns `true` if the header declares Nintendo as licensee.
+pub fn is_nintendo(header: impl Fn(usize) -> u8) -> bool {
+    match header(HEADER_OLD_LICENSEE) {
+        0x33 => header(HEADER_NEW_LICENSEE) == b'0' && header(HEADER_NEW_LICENSEE + 1) == b'1',
+        licensee => licensee == 0x01,
+    }
+}
+
+/// Selects the colorization for the cartridge `header` from `table`, see module documentation.
+///
+/// ```
+/// # use gejmboj_cpu::colorization::{self, Colorization, TitleColorization};
+/// let mut rom = vec![0; 0x150];
+/// rom[0x014B] = 0x01;
+/// rom[0x0134..0x0138].copy_from_slice(b"GAME");
+/// let checksum = colorization::title_checksum(|address| rom[address]);
+///
+/// let sepia = Colorization {
+///     background: [0x3BDF, 0x2EB5, 0x1D8C, 0x0C63],
+///     ..colorization::DEFAULT
+/// };
+/// let table = [TitleColorization { checksum, fourth_letter: None, colorization: sepia }];
+///
+/// assert_eq!(sepia, colorization::select(|address| rom[address], &table));
+/// ```
+pub fn select(header: impl Fn(usize) -> u8, table: &[TitleColorization]) -> Colorization {
+    if !is_nintendo(&header) {
+        return DEFAULT;
+    }
+
+    let checksum = title_checksum(&header);
+    let fourth_letter = header(HEADER_TITLE + 3);
+
+    table
+        .iter()
+        .filter(|entry| entry.checksum == checksum)
+        .find(|entry| entry.fourth_letter.is_none() || entry.fourth_letter == Some(fourth_letter))
+        .map_or(DEFAULT, |entry| entry.colorization)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Colorization = Colorization {
+        background: [0x001F; 4],
+        object0: [0x001F; 4],
+        object1: [0x001F; 4],
+    };
+
+    const BLUE: Colorization = Colorization {
+        background: [0x7C00; 4],
+        object0: [0x7C00; 4],
+        object1: [0x7C00; 4],
+    };
+
+    fn header(title: &[u8], old_licensee: u8) -> Vec<u8> {
+        let mut rom = vec![0; 0x150];
+        rom[HEADER_TITLE..HEADER_TITLE + title.len()].copy_from_slice(title);
+        rom[HEADER_OLD_LICENSEE] = old_licensee;
+        rom
+    }
+
+    #[test]
+    fn non_nintendo_games_get_the_default() {
+        let rom = header(b"AB", 0x02);
+        let table = [TitleColorization {
+            checksum: b'A' + b'B',
+            fourth_letter: None,
+            colorization: RED,
+        }];
+
+        assert_eq!(DEFAULT, select(|a| rom[a], &table));
+    }
+
+    #[test]
+    fn new_licensee_code_is_checked() {
+        let mut rom = header(b"AB", 0x33);
+        assert!(!is_nintendo(|a| rom[a]));
+
+        rom[HEADER_NEW_LICENSEE..HEADER_NEW_LICENSEE + 2].copy_from_slice(b"01");
+        assert!(is_nintendo(|a| rom[a]));
+    }
+
+    #[test]
+    fn fourth_letter_disambiguates_checksums() {
+        // Same letters, same checksum
+        let rom = header(b"ABCD", 0x01);
+        let other = header(b"ABDC", 0x01);
+        let table = [
+            TitleColorization {
+                checksum: title_checksum(|a| rom[a]),
+                fourth_letter: Some(b'C'),
+                colorization: RED,
+            },
+            TitleColorization {
+                checksum: title_checksum(|a| rom[a]),
+                fourth_letter: Some(b'D'),
+                colorization: BLUE,
+            },
+        ];
+
+        assert_eq!(BLUE, select(|a| rom[a], &table));
+        assert_eq!(RED, select(|a| other[a], &table));
+    }
+
+    #[test]
+    fn title_checksum_wraps() {
+        let rom = header(&[0xFF; 16], 0x01);
+
+        assert_eq!(0xF0, title_checksum(|a| rom[a]));
+    }
+}
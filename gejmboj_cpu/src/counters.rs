@@ -0,0 +1,223 @@
+//! # Execution counters
+//!
+//! Opt-in counters of executed instructions, see `CPU::enable_counters`. Every executed
+//! instruction is counted per opcode and per address, and the machine cycles it took are
+//! accounted to its address. They show where a program spends its time, and which instructions
+//! the emulator executes most often.
+//!
+//! ```
+//! # use gejmboj_cpu::{cpu::CPU, memory::Memory, registers::Registers};
+//! let mut cpu = CPU::new();
+//! let mut registers = Registers::new();
+//! let mut memory = Memory::new();
+//! // INC A, JR -3
+//! memory.load(0x0000, &[0x3C, 0x18, 0xFD]);
+//! cpu.enable_counters();
+//!
+//! for _ in 0..10 {
+//!     cpu.tick(&mut registers, &mut memory).unwrap();
+//! }
+//!
+//! let counters = cpu.counters().unwrap();
+//! assert_eq!(10, counters.total());
+//! assert_eq!(5, counters.opcode(0x3C));
+//! // JR takes more cycles than INC A
+//! assert_eq!(0x0001, counters.hot_addresses(1)[0].address);
+//! println!("{}", counters.report(16));
+//! ```
+
+use std::fmt::Display;
+
+/// Number of counted opcodes: the 256 opcodes and the 256 `CB` prefixed ones.
+const OPCODES: usize = 0x200;
+
+/// Counts of an address.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hotspot {
+    pub address: u16,
+    /// Number of instructions executed at the address
+    pub count: u64,
+    /// Machine cycles spent executing them
+    pub cycles: u64,
+}
+
+/// Executed instructions per opcode and address, see module documentation.
+#[derive(Debug, Clone)]
+pub struct Counters {
+    opcodes: Vec<u64>,
+    addresses: Vec<u64>,
+    cycles: Vec<u64>,
+    total: u64,
+}
+
+impl Default for Counters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Counters {
+    pub fn new() -> Self {
+        Self {
+            opcodes: vec![0; OPCODES],
+            addresses: vec![0; 0x10000],
+            cycles: vec![0; 0x10000],
+            total: 0,
+        }
+    }
+
+    /// Counts an instruction, `cb_opcode` is the second byte of `CB` prefixed instructions.
+    pub(crate) fn record(&mut self, address: u16, opcode: u8, cb_opcode: Option<u8>, cycles: u16) {
+        let index = match cb_opcode {
+            Some(x) => 0x100 | x as usize,
+            None => opcode as usize,
+        };
+
+        self.opcodes[index] += 1;
+        self.addresses[address as usize] += 1;
+        self.cycles[address as usize] += cycles as u64;
+        self.total += 1;
+    }
+
+    /// Returns the number of executed instructions.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Returns the number of executed instructions with `opcode`.
+    pub fn opcode(&self, opcode: u8) -> u64 {
+        self.opcodes[opcode as usize]
+    }
+
+    /// Returns the number of executed `CB` prefixed instructions with `opcode`.
+    pub fn cb_opcode(&self, opcode: u8) -> u64 {
+        self.opcodes[0x100 | opcode as usize]
+    }
+
+    /// Returns the counts of `address`.
+    pub fn address(&self, address: u16) -> Hotspot {
+        Hotspot {
+            address,
+            count: self.addresses[address as usize],
+            cycles: self.cycles[address as usize],
+        }
+    }
+
+    /// Returns the `n` addresses most machine cycles were spent at, most first.
+    pub fn hot_addresses(&self, n: usize) -> Vec<Hotspot> {
+        let mut hotspots: Vec<Hotspot> = (0..=0xFFFF)
+            .map(|address| self.address(address))
+            .filter(|hotspot| hotspot.count > 0)
+            .collect();
+
+        hotspots.sort_by(|a, b| b.cycles.cmp(&a.cycles).then(a.address.cmp(&b.address)));
+        hotspots.truncate(n);
+        hotspots
+    }
+
+    /// Returns the `n` most executed opcodes with their counts, most first. `CB` prefixed
+    /// opcodes are returned as `0xCBxx`.
+    pub fn hot_opcodes(&self, n: usize) -> Vec<(u16, u64)> {
+        let mut opcodes: Vec<(u16, u64)> = self
+            .opcodes
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(index, count)| match index {
+                0x100..=0x1FF => (0xCB00 | (index as u16 & 0xFF), *count),
+                _ => (index as u16, *count),
+            })
+            .collect();
+
+        opcodes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        opcodes.truncate(n);
+        opcodes
+    }
+
+    /// Returns a printable report of the `n` hottest addresses and opcodes.
+    pub fn report(&self, n: usize) -> Report<'_> {
+        Report { counters: self, n }
+    }
+
+    /// Resets all counters.
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+}
+
+/// Hottest addresses and opcodes, see `Counters::report`.
+pub struct Report<'a> {
+    counters: &'a Counters,
+    n: usize,
+}
+
+impl Display for Report<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let total = self.counters.total.max(1) as f64;
+        let total_cycles = self.counters.cycles.iter().sum::<u64>().max(1) as f64;
+
+        writeln!(f, "Executed instructions: {}", self.counters.total)?;
+
+        writeln!(f, "Hot addresses:")?;
+        for hotspot in self.counters.hot_addresses(self.n) {
+            writeln!(
+                f,
+                "  {:04x}: {:>10} cycles {:>5.1}% {:>10} executions",
+                hotspot.address,
+                hotspot.cycles,
+                hotspot.cycles as f64 * 100.0 / total_cycles,
+                hotspot.count
+            )?;
+        }
+
+        writeln!(f, "Hot opcodes:")?;
+        for (opcode, count) in self.counters.hot_opcodes(self.n) {
+            let opcode = match opcode {
+                0xCB00..=0xCBFF => format!("cb {:02x}", opcode & 0xFF),
+                _ => format!("{:02x}", opcode),
+            };
+            writeln!(
+                f,
+                "  {:<5} {:>10} executions {:>5.1}%",
+                opcode,
+                count,
+                count as f64 * 100.0 / total
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_are_ordered_by_cycles_and_opcodes_by_count() {
+        let mut counters = Counters::new();
+        counters.record(0x0100, 0x00, None, 1);
+        counters.record(0x0100, 0x00, None, 1);
+        counters.record(0x0101, 0xCB, Some(0x37), 2);
+        counters.record(0x0150, 0xCD, None, 6);
+
+        assert_eq!(
+            vec![0x0150, 0x0100],
+            counters
+                .hot_addresses(2)
+                .iter()
+                .map(|x| x.address)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(0x00, 2), (0xCD, 1), (0xCB37, 1)],
+            counters.hot_opcodes(16)
+        );
+        assert_eq!(1, counters.cb_opcode(0x37));
+        assert_eq!(0, counters.opcode(0xCB));
+
+        counters.clear();
+        assert_eq!(0, counters.total());
+        assert!(counters.hot_addresses(16).is_empty());
+    }
+}
@@ -0,0 +1,102 @@
+//! # ROM coverage
+//!
+//! Opt-in record of the ROM bytes which were ever executed, see `CPU::enable_coverage`. The
+//! opcode and operand bytes of every instruction executed from ROM are marked by their offset
+//! into the ROM, so code in switched banks is told apart by its bank, see
+//! `MemoryBus::rom_offset`.
+//!
+//! ```
+//! # use gejmboj_cpu::{cpu::CPU, memory::Memory, registers::Registers};
+//! let mut cpu = CPU::new();
+//! let mut registers = Registers::new();
+//! let mut memory = Memory::new();
+//! // ADD A, 0x3B
+//! memory.load(0x0000, &[0xC6, 0x3B]);
+//! cpu.enable_coverage();
+//!
+//! cpu.tick(&mut registers, &mut memory).unwrap();
+//!
+//! let coverage = cpu.coverage().unwrap();
+//! assert!(coverage.is_executed(0x0000));
+//! assert!(coverage.is_executed(0x0001));
+//! assert!(!coverage.is_executed(0x0002));
+//! assert_eq!(2, coverage.executed_bytes());
+//! ```
+
+/// Executed ROM bytes, see module documentation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Coverage {
+    bitmap: Vec<u8>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Marks the ROM byte at `offset` as executed.
+    pub(crate) fn mark(&mut self, offset: usize) {
+        let index = offset / 8;
+        if index >= self.bitmap.len() {
+            self.bitmap.resize(index + 1, 0);
+        }
+        self.bitmap[index] |= 1 << (offset % 8);
+    }
+
+    /// Returns `true` if the ROM byte at `offset` was executed.
+    pub fn is_executed(&self, offset: usize) -> bool {
+        matches!(self.bitmap.get(offset / 8), Some(byte) if byte & (1 << (offset % 8)) != 0)
+    }
+
+    /// Returns the number of executed ROM bytes.
+    pub fn executed_bytes(&self) -> usize {
+        self.bitmap
+            .iter()
+            .map(|byte| byte.count_ones() as usize)
+            .sum()
+    }
+
+    /// Returns the coverage bitmap, one bit per ROM byte with the lowest offset in the least
+    /// significant bit. It only reaches up to the highest executed offset.
+    pub fn bitmap(&self) -> &[u8] {
+        &self.bitmap
+    }
+
+    /// Returns the offsets of the executed ROM bytes in ascending order.
+    pub fn offsets(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.bitmap.len() * 8).filter(move |offset| self.is_executed(*offset))
+    }
+
+    /// Forgets all executed bytes.
+    pub fn clear(&mut self) {
+        self.bitmap.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{cartridge, cpu::CPU, memory::Memory, registers::Registers};
+
+    #[test]
+    fn switched_banks_are_covered_by_rom_offset() {
+        let mut rom = vec![0; 4 * cartridge::ROM_BANK_SIZE];
+        rom[0x0147] = 0x19; // MBC5
+        let mut memory = Memory::with_cartridge(cartridge::load(rom, None).unwrap());
+        let mut registers = Registers::new();
+        let mut cpu = CPU::new();
+        cpu.enable_coverage();
+
+        memory.set(0x2000, 2);
+        registers.PC = 0x4000;
+        cpu.tick(&mut registers, &mut memory).unwrap();
+        registers.PC = 0xC000;
+        cpu.tick(&mut registers, &mut memory).unwrap();
+
+        let coverage = cpu.coverage().unwrap();
+        assert_eq!(
+            vec![2 * cartridge::ROM_BANK_SIZE],
+            coverage.offsets().collect::<Vec<_>>()
+        );
+        assert!(!coverage.is_executed(0x4000));
+    }
+}
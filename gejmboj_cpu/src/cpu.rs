@@ -1,11 +1,26 @@
 //! Sharp SM83 CPU implementation
 
 use crate::{
-    errors::CpuError, instructions, instructions::Instruction, memory::Memory, registers::Registers,
+    counters::Counters,
+    coverage::Coverage,
+    debugger::{Break, Debugger, WatchedBus},
+    decode_cache::DecodeCache,
+    errors::CpuError,
+    hooks::InstructionHook,
+    instructions,
+    instructions::{misc::Misc, Instruction},
+    interrupts::Interrupt,
+    io::REGISTER_IF,
+    memory::{MemoryBus, TimedBus, REGISTER_IE},
+    model::Model,
+    registers::Registers,
+    stack, symbols,
+    trace::{Trace, TraceEntry},
 };
 
 #[allow(non_snake_case)]
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CpuFlags {
     /// Interrupt Master Enable
     ///
@@ -14,6 +29,10 @@ pub struct CpuFlags {
 
     /// If true at the start of a machine cycle IME should be enabled
     pub IME_scheduled: bool,
+
+    /// `HALT` was executed, no instructions are executed until an enabled interrupt is requested
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub halted: bool,
 }
 
 impl CpuFlags {
@@ -21,41 +40,808 @@ impl CpuFlags {
         Self {
             IME: false,
             IME_scheduled: false,
+            halted: false,
         }
     }
 }
 
+/// What the CPU does when it executes one of the illegal opcodes, e.g. `0xD3`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum IllegalOpcode {
+    /// Fail with `CpuError::UnknownInstruction`, leaving `PC` at the opcode
+    #[default]
+    Error,
+    /// Lock up like the hardware does, see `CPU::is_locked`
+    Hang,
+    /// Execute it as a `NOP`, e.g. to explore a badly dumped ROM. Opcodes the decoder doesn't
+    /// know are skipped too, see `CPU::skipped_opcodes`.
+    Nop,
+}
+
+/// T-cycles, i.e. clock cycles, of a machine cycle.
+pub const T_CYCLES_PER_M_CYCLE: u64 = 4;
+
+/// Machine cycles of a video frame, 70224 T-cycles.
+pub const FRAME_CYCLES: u64 = 17556;
+
+/// Machine cycles spent entering an interrupt handler: two wait states, pushing `PC` and
+/// jumping to the vector. Waking up from `HALT` takes one more.
+pub const INTERRUPT_DISPATCH_CYCLES: u16 = 5;
+
+/// What `CPU::run_frame` executed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameSummary {
+    /// Executed machine cycles
+    pub cycles: u64,
+    /// Executed instructions, or cycles spent halted or locked up
+    pub instructions: u64,
+}
+
+/// What `CPU::tick` executed.
+#[derive(Debug, PartialEq)]
+pub struct TickResult {
+    /// Address of the executed instruction
+    pub address: u16,
+    pub instruction: Instruction,
+    /// Consumed machine cycles
+    pub cycles: u16,
+    /// An interrupt was serviced before the instruction, its `INTERRUPT_DISPATCH_CYCLES` and the
+    /// cycle waking up from `HALT` are included in `cycles`
+    pub interrupt_serviced: bool,
+    /// The CPU doesn't execute instructions until it's woken up, after `HALT` or when it locked
+    /// up, see `CPU::is_locked`. `STOP` is not decoded yet.
+    pub halted: bool,
+}
+
+/// Why `CPU::run_until_event` returned.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// The given number of machine cycles were executed
+    CyclesElapsed,
+    /// A breakpoint, watchpoint or stack mismatch stopped the CPU
+    Break(Break),
+    /// The CPU locked up on an illegal opcode, see `CPU::is_locked`
+    Locked,
+}
+
 pub struct CPU {
     flags: CpuFlags,
+    model: Model,
+    illegal_opcode: IllegalOpcode,
+    /// Opcodes executed as `NOP` with `IllegalOpcode::Nop`
+    skipped_opcodes: u64,
+    /// Address and opcode of each skipped opcode, if enabled
+    skipped_opcode_log: Option<Vec<(u16, u8)>>,
+    /// Address and opcode of the illegal instruction which locked up the CPU, and `SP` then
+    locked: Option<(u16, u8, u16)>,
+    cycles: u64,
+    /// Machine cycle the current frame of `run_frame` ends at
+    frame_end: u64,
+    /// T-cycles of the last instruction not yet spent by `step_t_cycle`
+    pending_t_cycles: u64,
+    debugger: Debugger,
+    trace: Option<Trace>,
+    counters: Option<Counters>,
+    coverage: Option<Coverage>,
+    decode_cache: Option<DecodeCache>,
+    hooks: Vec<Box<dyn InstructionHook>>,
 }
 
 impl CPU {
     pub fn new() -> Self {
+        Self::with_model(Model::default())
+    }
+
+    /// Creates a CPU of the given hardware model.
+    pub fn with_model(model: Model) -> Self {
         Self {
             flags: CpuFlags::new(),
+            model,
+            illegal_opcode: IllegalOpcode::default(),
+            skipped_opcodes: 0,
+            skipped_opcode_log: None,
+            locked: None,
+            cycles: 0,
+            frame_end: 0,
+            pending_t_cycles: 0,
+            debugger: Debugger::new(),
+            trace: None,
+            counters: None,
+            coverage: None,
+            decode_cache: None,
+            hooks: vec![],
         }
     }
 
+    /// Returns the hardware model.
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    /// Sets what executing an illegal opcode does, `IllegalOpcode::Error` by default.
+    pub fn set_illegal_opcode(&mut self, behavior: IllegalOpcode) {
+        self.illegal_opcode = behavior;
+    }
+
+    /// Returns how many opcodes were executed as `NOP` with `IllegalOpcode::Nop`.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::{cpu::{CPU, IllegalOpcode}, memory::Memory, registers::Registers};
+    /// let mut cpu = CPU::new();
+    /// let mut registers = Registers::new();
+    /// let mut memory = Memory::new();
+    /// memory.load(0x0000, &[0xD3, 0x3C]);
+    /// cpu.set_illegal_opcode(IllegalOpcode::Nop);
+    /// cpu.enable_skipped_opcode_log();
+    ///
+    /// cpu.tick(&mut registers, &mut memory).unwrap();
+    /// cpu.tick(&mut registers, &mut memory).unwrap();
+    ///
+    /// assert_eq!(1, cpu.skipped_opcodes());
+    /// assert_eq!(vec![(0x0000, 0xD3)], cpu.take_skipped_opcode_log());
+    /// assert_eq!(0x0002, registers.PC);
+    /// ```
+    pub fn skipped_opcodes(&self) -> u64 {
+        self.skipped_opcodes
+    }
+
+    /// Starts logging the address and opcode of every opcode executed as `NOP`.
+    pub fn enable_skipped_opcode_log(&mut self) {
+        if self.skipped_opcode_log.is_none() {
+            self.skipped_opcode_log = Some(vec![]);
+        }
+    }
+
+    /// Returns the opcodes executed as `NOP` since the last call, if logging is enabled.
+    pub fn take_skipped_opcode_log(&mut self) -> Vec<(u16, u8)> {
+        self.skipped_opcode_log
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Counts and logs the opcode at `address`, returning the `NOP` executed instead.
+    fn skip_opcode(&mut self, address: u16, opcode: u8) -> (Instruction, usize) {
+        self.skipped_opcodes += 1;
+        if let Some(log) = self.skipped_opcode_log.as_mut() {
+            log.push((address, opcode));
+        }
+        (Instruction::Misc(Misc::NOP()), 1)
+    }
+
+    /// Returns `true` if the CPU executed an illegal opcode with `IllegalOpcode::Hang`.
+    ///
+    /// A locked CPU doesn't fetch instructions anymore, every `tick` only spends a machine cycle
+    /// and returns the illegal instruction again. Only a reset, i.e. a new CPU, recovers it.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::{cpu::{CPU, IllegalOpcode}, memory::Memory, registers::Registers};
+    /// let mut cpu = CPU::new();
+    /// let mut registers = Registers::new();
+    /// let mut memory = Memory::new();
+    /// memory.load(0x0000, &[0xD3, 0x3C]);
+    ///
+    /// assert!(cpu.tick(&mut registers, &mut memory).is_err());
+    ///
+    /// cpu.set_illegal_opcode(IllegalOpcode::Hang);
+    /// cpu.tick(&mut registers, &mut memory).unwrap();
+    /// cpu.tick(&mut registers, &mut memory).unwrap();
+    ///
+    /// assert!(cpu.is_locked());
+    /// assert_eq!(0x0001, registers.PC);
+    /// assert_eq!(2, cpu.cycles());
+    /// ```
+    pub fn is_locked(&self) -> bool {
+        self.locked.is_some()
+    }
+
+    /// Returns the interrupt flags.
+    pub fn flags(&self) -> &CpuFlags {
+        &self.flags
+    }
+
+    /// Returns the interrupt flags mutably, e.g. to set up a test.
+    pub fn flags_mut(&mut self) -> &mut CpuFlags {
+        &mut self.flags
+    }
+
+    /// Returns the number of machine cycles executed since the CPU was created.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Returns the number of T-cycles spent since the CPU was created, see `step_t_cycle`.
+    pub fn t_cycles(&self) -> u64 {
+        self.cycles * T_CYCLES_PER_M_CYCLE - self.pending_t_cycles
+    }
+
+    /// Returns the debugger.
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    /// Returns the debugger, to set breakpoints.
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    /// Adds a hook called around every instruction, see `hooks`.
+    pub fn add_hook(&mut self, hook: Box<dyn InstructionHook>) {
+        self.hooks.push(hook);
+    }
+
+    /// Removes all hooks and returns them.
+    pub fn take_hooks(&mut self) -> Vec<Box<dyn InstructionHook>> {
+        std::mem::take(&mut self.hooks)
+    }
+
+    /// Starts recording the last `capacity` executed instructions, see `trace`.
+    pub fn enable_trace(&mut self, capacity: usize) {
+        self.trace = Some(Trace::new(capacity));
+    }
+
+    /// Stops recording executed instructions and returns the trace.
+    pub fn take_trace(&mut self) -> Option<Trace> {
+        self.trace.take()
+    }
+
+    /// Returns the trace of the last executed instructions, if enabled.
+    pub fn trace(&self) -> Option<&Trace> {
+        self.trace.as_ref()
+    }
+
+    /// Starts counting executed instructions per opcode and address, see `counters`.
+    pub fn enable_counters(&mut self) {
+        self.counters = Some(Counters::new());
+    }
+
+    /// Stops counting executed instructions and returns the counters.
+    pub fn take_counters(&mut self) -> Option<Counters> {
+        self.counters.take()
+    }
+
+    /// Returns the executed instruction counters, if enabled.
+    pub fn counters(&self) -> Option<&Counters> {
+        self.counters.as_ref()
+    }
+
+    /// Starts recording which ROM bytes are executed, see `coverage`.
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(Coverage::new());
+    }
+
+    /// Stops recording executed ROM bytes and returns the coverage.
+    pub fn take_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take()
+    }
+
+    /// Starts caching instructions decoded from ROM, see `decode_cache`.
+    pub fn enable_decode_cache(&mut self) {
+        self.decode_cache = Some(DecodeCache::new());
+    }
+
+    /// Stops caching decoded instructions and returns the cache.
+    pub fn take_decode_cache(&mut self) -> Option<DecodeCache> {
+        self.decode_cache.take()
+    }
+
+    /// Returns the executed ROM bytes, if enabled.
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Restores state saved in a save state.
+    pub(crate) fn restore(&mut self, model: Model, flags: CpuFlags, cycles: u64) {
+        self.model = model;
+        self.flags = flags;
+        self.cycles = cycles;
+        self.frame_end = cycles;
+        self.pending_t_cycles = 0;
+        self.locked = None;
+        self.debugger.call_stack_mut().clear();
+    }
+
+    /// Executes the next instruction.
+    ///
+    /// If `IME` is set and an enabled interrupt is requested, the interrupt is serviced first
+    /// and the first instruction of its handler is executed.
     pub fn tick(
         &mut self,
         registers: &mut Registers,
-        memory: &mut Memory,
-    ) -> Result<(u16, Instruction), CpuError> {
+        memory: &mut impl MemoryBus,
+    ) -> Result<TickResult, CpuError> {
+        if let Some((address, opcode, _)) = self.locked {
+            memory.step(1);
+            self.cycles += 1;
+
+            #[cfg(feature = "paranoid")]
+            self.check_invariants(registers, memory)
+                .map_err(|reason| CpuError::InvariantViolation { address, reason })?;
+
+            return Ok(TickResult {
+                address,
+                instruction: Instruction::Misc(Misc::ILLEGAL(opcode)),
+                cycles: 1,
+                interrupt_serviced: false,
+                halted: true,
+            });
+        }
+
+        // A halted CPU idles until an enabled interrupt is requested, even with `IME` unset
+        let woken = self.flags.halted;
+        if woken {
+            if pending_interrupts(memory) == 0 {
+                memory.step(1);
+                self.cycles += 1;
+                return Ok(TickResult {
+                    address: registers.PC.wrapping_sub(1),
+                    instruction: Instruction::Misc(Misc::HALT()),
+                    cycles: 1,
+                    interrupt_serviced: false,
+                    halted: true,
+                });
+            }
+            self.flags.halted = false;
+        }
+
+        let dispatch_cycles = self.dispatch_interrupt(registers, memory, woken);
+
+        self.debugger
+            .check(registers, memory)
+            .map_err(CpuError::Break)?;
+
+        for hook in self.hooks.iter_mut() {
+            hook.before(registers.PC, registers, memory);
+        }
+
+        memory.begin_instruction(registers.PC);
+
         let opcode = memory.get(registers.PC.into());
         let instruction_location = registers.PC.clone();
 
-        let instruction = instructions::decode(opcode, registers.PC.into(), memory)?;
+        let skip = self.illegal_opcode == IllegalOpcode::Nop;
+        let (instruction, size) = match self.decode(opcode, registers.PC, memory) {
+            Ok((instruction, _)) if skip && instruction.is_illegal() => {
+                self.skip_opcode(instruction_location, opcode)
+            }
+            Ok(decoded) => decoded,
+            Err(CpuError::UnknownInstruction(_)) if skip => {
+                self.skip_opcode(instruction_location, opcode)
+            }
+            Err(e) => {
+                let error = instruction_error(e, instruction_location, 3, registers, memory);
+                return Err(error);
+            }
+        };
+
+        if instruction.is_illegal() {
+            if self.illegal_opcode == IllegalOpcode::Error {
+                let error = CpuError::UnknownInstruction(opcode);
+                return Err(instruction_error(
+                    error,
+                    instruction_location,
+                    3,
+                    registers,
+                    memory,
+                ));
+            }
+            registers.PC = registers.PC.wrapping_add(1);
+            self.locked = Some((instruction_location, opcode, registers.SP));
+            memory.step(1);
+            self.cycles += 1;
+            return Ok(TickResult {
+                address: instruction_location,
+                instruction,
+                cycles: dispatch_cycles + 1,
+                interrupt_serviced: dispatch_cycles > 0,
+                halted: true,
+            });
+        }
+
+        let mut bytes = [0; 3];
+        if self.trace.is_some() {
+            for (offset, byte) in bytes.iter_mut().enumerate() {
+                *byte = memory.peek(instruction_location as usize + offset);
+            }
+        }
 
-        registers.PC += instruction.length();
+        if let Some(coverage) = self.coverage.as_mut() {
+            for offset in 0..size as u16 {
+                let location = instruction_location.wrapping_add(offset) as usize;
+                if let Some(rom_offset) = memory.rom_offset(location) {
+                    coverage.mark(rom_offset);
+                }
+            }
+        }
+
+        registers.PC = registers.PC.wrapping_add(size as u16);
 
         if self.flags.IME_scheduled {
             self.flags.IME = true;
             self.flags.IME_scheduled = false;
         }
 
-        instruction.execute(registers, memory, &mut self.flags)?;
+        let sp = registers.SP;
+        let mut watchpoint_hit = None;
+        let mut bus = TimedBus::new(memory, size as u16);
+        let executed = if self.debugger.watchpoints().is_empty() {
+            instruction.execute(registers, &mut bus, &mut self.flags)
+        } else {
+            let watchpoints = self.debugger.watchpoints();
+            let mut watched = WatchedBus::new(&mut bus, watchpoints, instruction_location);
+            let executed = instruction.execute(registers, &mut watched, &mut self.flags);
+            watchpoint_hit = watched.hit();
+            executed
+        };
+        let cycles = match executed {
+            Ok(execution) => execution.cycles,
+            Err(e) => {
+                let error = instruction_error(e, instruction_location, size, registers, &bus);
+                return Err(error);
+            }
+        };
+        bus.finish(cycles);
+        self.cycles += cycles as u64;
+
+        let stack_mismatch = match &instruction {
+            Instruction::ControlFlow(instruction) => {
+                self.debugger
+                    .track(instruction_location, instruction, sp, registers, memory)
+            }
+            _ => None,
+        };
 
-        Ok((instruction_location, instruction))
+        if let Some(counters) = self.counters.as_mut() {
+            let cb_opcode =
+                (opcode == 0xCB).then(|| memory.peek(instruction_location as usize + 1));
+            counters.record(instruction_location, opcode, cb_opcode, cycles);
+        }
+
+        if let Some(trace) = self.trace.as_mut() {
+            let bytes = &bytes[..size];
+            let bank = symbols::bank(memory, instruction_location);
+            trace.push(TraceEntry::new(
+                instruction_location,
+                bank,
+                bytes,
+                registers,
+            ));
+            registers.clear_dirty();
+        }
+
+        if let Some((address, kind)) = memory.take_access_violation() {
+            let error = CpuError::MemoryAccessViolation { address, kind };
+            return Err(instruction_error(
+                error,
+                instruction_location,
+                size,
+                registers,
+                memory,
+            ));
+        }
+
+        #[cfg(feature = "paranoid")]
+        self.check_invariants(registers, memory).map_err(|reason| {
+            CpuError::InvariantViolation {
+                address: instruction_location,
+                reason,
+            }
+        })?;
+
+        for hook in self.hooks.iter_mut() {
+            hook.after(instruction_location, &instruction, registers, memory);
+        }
+
+        if let Some(hit) = watchpoint_hit.or(stack_mismatch) {
+            return Err(CpuError::Break(hit));
+        }
+
+        Ok(TickResult {
+            address: instruction_location,
+            instruction,
+            cycles: dispatch_cycles + cycles,
+            interrupt_serviced: dispatch_cycles > 0,
+            halted: false,
+        })
+    }
+
+    /// Decodes the instruction with `opcode` at `pc`, through the decode cache if it's enabled.
+    fn decode(
+        &mut self,
+        opcode: u8,
+        pc: u16,
+        memory: &impl MemoryBus,
+    ) -> Result<(Instruction, usize), CpuError> {
+        let cache = match self.decode_cache.as_mut() {
+            Some(cache) => cache,
+            None => return instructions::decode(opcode, pc, memory),
+        };
+        let offset = memory.rom_offset(pc as usize);
+        if let Some(cached) = offset.and_then(|offset| cache.get(offset, opcode)) {
+            return Ok(cached);
+        }
+
+        let (instruction, size) = instructions::decode(opcode, pc, memory)?;
+        if let Some(offset) = offset {
+            let last = memory.rom_offset(pc.wrapping_add(size as u16 - 1) as usize);
+            if last == Some(offset + size - 1) {
+                cache.insert(offset, opcode, instruction.clone(), size);
+            }
+        }
+        Ok((instruction, size))
+    }
+
+    /// Services the highest priority interrupt which is both enabled and requested if `IME` is
+    /// set, returning the spent machine cycles. A CPU `woken` from `HALT` spends an extra
+    /// machine cycle before the dispatch.
+    ///
+    /// `PC` is pushed one byte per machine cycle. The interrupt to service is only selected after
+    /// the upper byte is pushed: if that push overwrote `IE` so no interrupt is pending anymore,
+    /// the dispatch is cancelled and `PC` is set to `0000` without acknowledging any request.
+    fn dispatch_interrupt(
+        &mut self,
+        registers: &mut Registers,
+        memory: &mut impl MemoryBus,
+        woken: bool,
+    ) -> u16 {
+        if !self.flags.IME || pending_interrupts(memory) == 0 {
+            return 0;
+        }
+        self.flags.IME = false;
+
+        let cycles = INTERRUPT_DISPATCH_CYCLES + woken as u16;
+        let [lo, hi] = registers.PC.to_le_bytes();
+        let mut bus = TimedBus::new(memory, 2 + woken as u16);
+        stack::push_u8(registers, &mut bus, hi);
+        let interrupt = Interrupt::from_pending(pending_interrupts(&bus));
+        stack::push_u8(registers, &mut bus, lo);
+        bus.finish(cycles);
+
+        registers.PC = match interrupt {
+            Some(interrupt) => {
+                let requested = memory.peek(REGISTER_IF as usize);
+                memory.set(REGISTER_IF as usize, requested & !interrupt.mask());
+                interrupt.vector()
+            }
+            None => 0x0000,
+        };
+
+        self.cycles += cycles as u64;
+        cycles
+    }
+
+    /// Checks the emulator state for corruption.
+    ///
+    /// Run after every instruction with the `paranoid` feature, so that corrupted state is
+    /// reported at the instruction which caused it.
+    #[cfg(any(test, feature = "paranoid"))]
+    pub(crate) fn check_invariants(
+        &self,
+        registers: &Registers,
+        memory: &impl MemoryBus,
+    ) -> Result<(), String> {
+        // A locked up CPU neither fetches nor pushes anything
+        if let Some((address, _, sp)) = self.locked {
+            if registers.PC != address.wrapping_add(1) {
+                return Err(format!(
+                    "PC moved while locked up at {:04x}: {:04x}",
+                    address, registers.PC
+                ));
+            }
+            if registers.SP != sp {
+                return Err(format!(
+                    "SP moved while locked up at {:04x}: {:04x}, was {:04x}",
+                    address, registers.SP, sp
+                ));
+            }
+        }
+        registers.check_invariants()?;
+        memory.check_invariants()
+    }
+}
+
+/// Returns the interrupts which are both enabled and requested.
+fn pending_interrupts(memory: &impl MemoryBus) -> u8 {
+    memory.peek(REGISTER_IE) & memory.peek(REGISTER_IF as usize) & 0x1F
+}
+
+/// Wraps `error` with the `size` bytes of the instruction at `pc` and the registers.
+fn instruction_error(
+    error: CpuError,
+    pc: u16,
+    size: usize,
+    registers: &Registers,
+    memory: &impl MemoryBus,
+) -> CpuError {
+    let bytes = (0..size as u16)
+        .map(|offset| memory.peek(pc.wrapping_add(offset) as usize))
+        .collect();
+    error.in_instruction(pc, bytes, Some(registers.into()))
+}
+
+impl CPU {
+    /// Executes instructions for at least `cycles` machine cycles, returning early when
+    /// something observable to the embedder happens.
+    ///
+    /// Breaks are returned as `Event::Break` instead of `CpuError::Break`. Calling it again
+    /// resumes after a breakpoint. Other errors are returned as is.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::{cpu::{Event, CPU}, memory::Memory, registers::Registers};
+    /// let mut cpu = CPU::new();
+    /// let mut registers = Registers::new();
+    /// let mut memory = Memory::new();
+    /// cpu.debugger_mut().add_breakpoint(0x0100);
+    ///
+    /// assert_eq!(Event::CyclesElapsed, cpu.run_until_event(&mut registers, &mut memory, 16).unwrap());
+    /// assert_eq!(16, cpu.cycles());
+    ///
+    /// let event = cpu.run_until_event(&mut registers, &mut memory, 1000).unwrap();
+    /// assert!(matches!(event, Event::Break(_)));
+    /// assert_eq!(0x0100, registers.PC);
+    /// ```
+    pub fn run_until_event(
+        &mut self,
+        registers: &mut Registers,
+        memory: &mut impl MemoryBus,
+        cycles: u64,
+    ) -> Result<Event, CpuError> {
+        let end = self.cycles + cycles;
+        let was_locked = self.is_locked();
+
+        while self.cycles < end {
+            match self.tick(registers, memory) {
+                Ok(_) if !was_locked && self.is_locked() => return Ok(Event::Locked),
+                Ok(_) => {}
+                Err(CpuError::Break(reason)) => return Ok(Event::Break(reason)),
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(Event::CyclesElapsed)
+    }
+
+    /// Advances the CPU by a single T-cycle, for embedders driving peripherals which need to be
+    /// ordered within a machine cycle.
+    ///
+    /// The first T-cycle of an instruction executes it like `tick` does and returns its result,
+    /// the remaining T-cycles of the instruction return `None`. The bus is still stepped in
+    /// machine cycles. Finish an instruction before switching back to `tick`.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::{cpu::CPU, memory::Memory, registers::Registers};
+    /// let mut cpu = CPU::new();
+    /// let mut registers = Registers::new();
+    /// let mut memory = Memory::new();
+    ///
+    /// assert!(cpu.step_t_cycle(&mut registers, &mut memory).unwrap().is_some());
+    /// assert_eq!(1, cpu.t_cycles());
+    /// for _ in 0..3 {
+    ///     assert_eq!(None, cpu.step_t_cycle(&mut registers, &mut memory).unwrap());
+    /// }
+    /// assert!(cpu.step_t_cycle(&mut registers, &mut memory).unwrap().is_some());
+    /// ```
+    pub fn step_t_cycle(
+        &mut self,
+        registers: &mut Registers,
+        memory: &mut impl MemoryBus,
+    ) -> Result<Option<TickResult>, CpuError> {
+        if self.pending_t_cycles > 0 {
+            self.pending_t_cycles -= 1;
+            return Ok(None);
+        }
+
+        let result = self.tick(registers, memory)?;
+        self.pending_t_cycles = result.cycles as u64 * T_CYCLES_PER_M_CYCLE - 1;
+        Ok(Some(result))
+    }
+
+    /// Executes the instructions of one video frame, `FRAME_CYCLES` machine cycles.
+    ///
+    /// Frames are laid out back to back: an instruction crossing the end of a frame shortens
+    /// the next one, so frames take `FRAME_CYCLES` on average. When a break stops the frame,
+    /// calling it again completes the same frame. The CPU doesn't emulate the CGB double speed
+    /// mode, a frame is always `FRAME_CYCLES` long.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::{cpu::{CPU, FRAME_CYCLES}, memory::Memory, registers::Registers};
+    /// let mut cpu = CPU::new();
+    /// let mut registers = Registers::new();
+    /// let mut memory = Memory::new();
+    ///
+    /// let frame = cpu.run_frame(&mut registers, &mut memory).unwrap();
+    ///
+    /// assert_eq!(FRAME_CYCLES, frame.cycles);
+    /// assert_eq!(FRAME_CYCLES, frame.instructions);
+    /// ```
+    pub fn run_frame(
+        &mut self,
+        registers: &mut Registers,
+        memory: &mut impl MemoryBus,
+    ) -> Result<FrameSummary, CpuError> {
+        if self.cycles >= self.frame_end {
+            self.frame_end = match self.frame_end + FRAME_CYCLES {
+                end if end > self.cycles => end,
+                _ => self.cycles + FRAME_CYCLES,
+            };
+        }
+
+        let start = self.cycles;
+        let mut instructions = 0;
+        while self.cycles < self.frame_end {
+            self.tick(registers, memory)?;
+            instructions += 1;
+        }
+
+        Ok(FrameSummary {
+            cycles: self.cycles - start,
+            instructions,
+        })
+    }
+
+    /// Executes one instruction, running a called function until it returns.
+    ///
+    /// A `CALL` whose condition is not fulfilled, or any other instruction, is executed like
+    /// `tick` does. The called function is considered returned once it returns to the instruction
+    /// after the call, with the stack pointer back where it was before the call, so recursive
+    /// calls are run through. Breakpoints and watchpoints inside the function stop it.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::{cpu::CPU, memory::Memory, registers::*};
+    /// let mut cpu = CPU::new();
+    /// let mut registers = Registers::new();
+    /// let mut memory = Memory::new();
+    /// // CALL 0x0010; ... 0x0010: INC A, INC A, RET
+    /// memory.load(0x0000, &[0xCD, 0x10, 0x00]);
+    /// memory.load(0x0010, &[0x3C, 0x3C, 0xC9]);
+    ///
+    /// cpu.step_over(&mut registers, &mut memory).unwrap();
+    ///
+    /// assert_eq!(0x0003, registers.PC);
+    /// assert_eq!(0xFFFE, registers.SP);
+    /// assert_eq!(2, registers.get_single(&SingleRegister::A));
+    /// ```
+    pub fn step_over(
+        &mut self,
+        registers: &mut Registers,
+        memory: &mut impl MemoryBus,
+    ) -> Result<(), CpuError> {
+        let sp = registers.SP;
+        let tick = self.tick(registers, memory)?;
+        let return_address = tick.address.wrapping_add(tick.instruction.length());
+
+        let called = match tick.instruction {
+            Instruction::ControlFlow(instruction) => instruction.is_call() && registers.SP < sp,
+            _ => false,
+        };
+
+        while called && !(registers.PC == return_address && registers.SP >= sp) {
+            self.tick(registers, memory)?;
+        }
+
+        Ok(())
+    }
+
+    /// Runs until the current function returns.
+    ///
+    /// Returns once a `RET`, `RETC` or `RETI` pops the return address of the current function,
+    /// returns of functions called meanwhile are skipped. Breakpoints and watchpoints stop it.
+    pub fn step_out(
+        &mut self,
+        registers: &mut Registers,
+        memory: &mut impl MemoryBus,
+    ) -> Result<(), CpuError> {
+        let sp = registers.SP;
+
+        loop {
+            let instruction = self.tick(registers, memory)?.instruction;
+
+            if let Instruction::ControlFlow(instruction) = instruction {
+                if instruction.is_return() && registers.SP > sp {
+                    return Ok(());
+                }
+            }
+        }
     }
 }
 
@@ -63,6 +849,7 @@ impl CPU {
 mod test {
 
     use super::*;
+    use crate::memory::Memory;
     use instructions::misc;
     use instructions::Instruction;
 
@@ -77,10 +864,12 @@ mod test {
 
         assert_eq!(0, registers.PC);
 
-        let (_, instruction) = cpu.tick(&mut registers, &mut memory).unwrap();
+        let result = cpu.tick(&mut registers, &mut memory).unwrap();
 
-        assert_eq!(Instruction::Misc(misc::Misc::NOP()), instruction);
-        assert_eq!(instruction.length(), registers.PC);
+        assert_eq!(Instruction::Misc(misc::Misc::NOP()), result.instruction);
+        assert_eq!(result.instruction.length(), registers.PC);
+        assert_eq!(1, result.cycles);
+        assert!(!result.halted);
     }
 
     #[test]
@@ -95,16 +884,18 @@ mod test {
         memory.set_u16(0x0000, ei_op);
         memory.set_u16(0x0002, noop);
 
-        let (_, instruction) = cpu
+        let instruction = cpu
             .tick(&mut registers, &mut memory)
-            .expect("Failed to execute EI instruction");
+            .expect("Failed to execute EI instruction")
+            .instruction;
 
         assert_eq!(Instruction::Misc(misc::Misc::EI()), instruction);
         assert_eq!(instruction.length(), registers.PC);
         assert_eq!(
             CpuFlags {
                 IME: false,
-                IME_scheduled: true
+                IME_scheduled: true,
+                halted: false,
             },
             cpu.flags
         );
@@ -116,8 +907,507 @@ mod test {
             CpuFlags {
                 IME: true,
                 IME_scheduled: false,
+                halted: false,
             },
             cpu.flags
         );
     }
+
+    #[test]
+    fn decode_cache_tells_rom_banks_apart() {
+        let mut rom = vec![0; 0xC000];
+        rom[0x4000] = 0x3C; // INC A
+        rom[0x8000] = 0x04; // INC B
+        let mut memory =
+            Memory::with_cartridge(Box::new(crate::cartridge::mbc5::Mbc5::new(rom, 0, false)));
+        let mut registers = Registers::new();
+        let mut cpu = CPU::new();
+        cpu.enable_decode_cache();
+
+        for bank in [1, 2, 1] {
+            memory.set(0x2000, bank);
+            registers.PC = 0x4000;
+            cpu.tick(&mut registers, &mut memory).unwrap();
+        }
+
+        assert_eq!(
+            2,
+            registers.get_single(&crate::registers::SingleRegister::A)
+        );
+        assert_eq!(
+            1,
+            registers.get_single(&crate::registers::SingleRegister::B)
+        );
+        assert_eq!(1, cpu.take_decode_cache().unwrap().hits());
+    }
+
+    #[test]
+    fn cpu_tick_advances_oam_dma() {
+        let mut registers = Registers::new();
+        let mut memory = Memory::new();
+        let mut cpu = CPU::new();
+
+        memory.set(0xC000, 0xAB);
+        memory.set(0xFF46, 0xC0);
+
+        cpu.tick(&mut registers, &mut memory).unwrap();
+
+        assert_eq!(0xAB, memory.peek(0xFE00));
+        assert_eq!(0x00, memory.peek(0xFE01));
+    }
+
+    #[test]
+    fn cpu_tick_starts_oam_dma_in_the_cycle_of_the_write() {
+        let mut registers = Registers::new();
+        let mut memory = Memory::new();
+        let mut cpu = CPU::new();
+
+        memory.set(0xC000, 0xAB);
+        memory.set(0xC001, 0xCD);
+        registers.set_single(&crate::registers::SingleRegister::A, 0xC0);
+        // LD (0xff46), A; NOP
+        memory.load(0x0000, &[0xE0, 0x46, 0x00]);
+
+        cpu.tick(&mut registers, &mut memory).unwrap();
+        assert_eq!(0x00, memory.peek(0xFE00));
+
+        cpu.tick(&mut registers, &mut memory).unwrap();
+        assert_eq!(0xAB, memory.peek(0xFE00));
+        assert_eq!(0x00, memory.peek(0xFE01));
+    }
+
+    #[test]
+    fn state_after_tick_satisfies_invariants() {
+        let mut registers = Registers::new();
+        let mut memory = Memory::new();
+        let mut cpu = CPU::new();
+
+        memory.set(0xFF46, 0xC0);
+        cpu.tick(&mut registers, &mut memory).unwrap();
+
+        assert_eq!(Ok(()), cpu.check_invariants(&registers, &memory));
+    }
+
+    #[test]
+    fn strict_memory_fails_the_instruction_making_a_violation() {
+        let rom = vec![0; 0x8000];
+        let cartridge = crate::cartridge::rom_only::RomOnly::new(rom, 0);
+        let mut memory = Memory::with_cartridge(Box::new(cartridge));
+        let mut registers = Registers::new();
+        let mut cpu = CPU::new();
+        memory.set_strict(true);
+        registers.PC = 0xC000;
+        // NOP; LD (0x2000), A
+        memory.load(0xC000, &[0x00, 0xEA, 0x00, 0x20]);
+
+        cpu.tick(&mut registers, &mut memory).unwrap();
+        let error = cpu.tick(&mut registers, &mut memory).unwrap_err();
+
+        assert_eq!(
+            &CpuError::MemoryAccessViolation {
+                address: 0x2000,
+                kind: crate::memory::AccessKind::RomWrite
+            },
+            error.root()
+        );
+        assert_eq!(
+            "Memory access violation: write to ROM at 2000 at PC=0xC001 (bytes EA 00 20)",
+            error.to_string()
+        );
+
+        // Without a cartridge the write would patch the flat memory
+        let mut memory = Memory::new();
+        memory.set_strict(true);
+        registers.PC = 0xC000;
+        registers.set_single(&crate::registers::SingleRegister::A, 0x42);
+        memory.load(0xC000, &[0xEA, 0x00, 0x10]);
+
+        assert!(cpu.tick(&mut registers, &mut memory).is_err());
+        assert_eq!(0x00, memory.peek(0x1000));
+    }
+
+    #[test]
+    fn opcodes_the_decoder_does_not_know_can_be_skipped() {
+        let mut registers = Registers::new();
+        let mut memory = Memory::new();
+        let mut cpu = CPU::new();
+        cpu.set_illegal_opcode(IllegalOpcode::Nop);
+        // STOP isn't decoded yet; INC A
+        memory.load(0x0000, &[0x10, 0x3C]);
+
+        let skipped = cpu.tick(&mut registers, &mut memory).unwrap();
+        cpu.tick(&mut registers, &mut memory).unwrap();
+
+        assert_eq!(Instruction::Misc(misc::Misc::NOP()), skipped.instruction);
+        assert_eq!(1, skipped.cycles);
+        assert_eq!(1, cpu.skipped_opcodes());
+        assert_eq!(
+            1,
+            registers.get_single(&crate::registers::SingleRegister::A)
+        );
+    }
+
+    #[test]
+    fn illegal_opcodes_fail_or_lock_up_the_cpu() {
+        let mut registers = Registers::new();
+        let mut memory = Memory::new();
+        let mut cpu = CPU::new();
+        // ILLEGAL 0xe4; INC A
+        memory.load(0x0000, &[0xE4, 0x3C]);
+
+        let error = cpu.tick(&mut registers, &mut memory).unwrap_err();
+        assert_eq!(&CpuError::UnknownInstruction(0xE4), error.root());
+        assert_eq!(
+            "Unknown opcode: 11100100 at PC=0x0000 (bytes E4 3C 00) with AF=0000 BC=0000 \
+             DE=0000 HL=0000 SP=FFFE PC=0000",
+            format!("{:#}", error)
+        );
+        assert_eq!(0x0000, registers.PC);
+        assert!(!cpu.is_locked());
+
+        cpu.set_illegal_opcode(IllegalOpcode::Hang);
+        for _ in 0..3 {
+            let result = cpu.tick(&mut registers, &mut memory).unwrap();
+            assert_eq!(0x0000, result.address);
+            assert_eq!(
+                Instruction::Misc(misc::Misc::ILLEGAL(0xE4)),
+                result.instruction
+            );
+            assert!(result.halted);
+        }
+
+        assert!(cpu.is_locked());
+        assert_eq!(
+            0,
+            registers.get_single(&crate::registers::SingleRegister::A)
+        );
+        assert_eq!(3, cpu.cycles());
+    }
+
+    #[cfg(feature = "paranoid")]
+    #[test]
+    fn paranoid_tick_reports_invariant_violations() {
+        let mut registers = Registers::new();
+        let mut memory = Memory::new();
+        let mut cpu = CPU::new();
+        memory.io_mut().set_raw(REGISTER_IF, 0xFF);
+
+        match cpu.tick(&mut registers, &mut memory) {
+            Err(CpuError::InvariantViolation { address, .. }) => assert_eq!(0x0000, address),
+            result => panic!("Expected an invariant violation, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn check_invariants_rejects_pc_and_sp_moving_while_locked_up() {
+        let mut registers = Registers::new();
+        let mut memory = Memory::new();
+        let mut cpu = CPU::new();
+        cpu.set_illegal_opcode(IllegalOpcode::Hang);
+        memory.load(0x0000, &[0xE4]);
+
+        cpu.tick(&mut registers, &mut memory).unwrap();
+        assert_eq!(Ok(()), cpu.check_invariants(&registers, &memory));
+
+        registers.SP = 0xFFFC;
+        assert!(cpu.check_invariants(&registers, &memory).is_err());
+        registers.SP = 0xFFFE;
+
+        registers.PC = 0x0000;
+        assert!(cpu.check_invariants(&registers, &memory).is_err());
+    }
+
+    #[test]
+    fn run_until_event_stops_when_the_cpu_locks_up() {
+        let mut registers = Registers::new();
+        let mut memory = Memory::new();
+        let mut cpu = CPU::new();
+        cpu.set_illegal_opcode(IllegalOpcode::Hang);
+        memory.load(0x0010, &[0xDD]);
+
+        assert_eq!(
+            Ok(Event::Locked),
+            cpu.run_until_event(&mut registers, &mut memory, 100)
+        );
+        assert_eq!(17, cpu.cycles());
+
+        assert_eq!(
+            Ok(Event::CyclesElapsed),
+            cpu.run_until_event(&mut registers, &mut memory, 100)
+        );
+        assert_eq!(117, cpu.cycles());
+    }
+
+    #[test]
+    fn run_frame_carries_overshoot_into_the_next_frame() {
+        let mut registers = Registers::new();
+        let mut memory = Memory::new();
+        let mut cpu = CPU::new();
+        // JP 0x0010 ... 0x0010: NOP, JP 0x0000; a 9 machine cycle loop crossing the frame end
+        memory.load(0x0000, &[0xC3, 0x10, 0x00]);
+        memory.load(0x0010, &[0x00, 0xC3, 0x00, 0x00]);
+
+        let first = cpu.run_frame(&mut registers, &mut memory).unwrap();
+        let second = cpu.run_frame(&mut registers, &mut memory).unwrap();
+
+        assert_eq!(FRAME_CYCLES + 3, first.cycles);
+        // The second frame is 3 cycles shorter, give or take its own overshoot
+        assert!(first.cycles + second.cycles - 2 * FRAME_CYCLES < 9);
+        assert_eq!(FRAME_CYCLES / 9 * 3 + 3, first.instructions);
+    }
+
+    #[test]
+    fn interrupts_are_serviced_in_five_machine_cycles() {
+        let mut registers = Registers::new();
+        let mut memory = Memory::new();
+        let mut cpu = CPU::new();
+        cpu.flags.IME = true;
+        registers.PC = 0x1234;
+        registers.SP = 0xD000;
+        // Timer and joypad requested, only the timer enabled
+        memory.set(REGISTER_IE, 0x04);
+        memory.set(REGISTER_IF as usize, 0x14);
+
+        let result = cpu.tick(&mut registers, &mut memory).unwrap();
+
+        assert!(result.interrupt_serviced);
+        assert_eq!(0x0050, result.address);
+        assert_eq!(INTERRUPT_DISPATCH_CYCLES + 1, result.cycles);
+        assert_eq!(6, cpu.cycles());
+        assert_eq!(0x0051, registers.PC);
+        assert_eq!(0xCFFE, registers.SP);
+        assert_eq!(0x1234, memory.get_u16(0xCFFE));
+        assert_eq!(0x10, memory.io().get_raw(REGISTER_IF));
+        assert!(!cpu.flags.IME);
+
+        assert!(
+            !cpu.tick(&mut registers, &mut memory)
+                .unwrap()
+                .interrupt_serviced
+        );
+    }
+
+    #[test]
+    fn halt_waits_for_an_interrupt_and_wakes_up_in_an_extra_cycle() {
+        let mut registers = Registers::new();
+        let mut memory = Memory::new();
+        let mut cpu = CPU::new();
+        cpu.flags.IME = true;
+        registers.SP = 0xD000;
+        memory.load(0x0000, &[0x76]);
+        memory.set(REGISTER_IE, 0x04);
+
+        let result = cpu.tick(&mut registers, &mut memory).unwrap();
+        assert_eq!(Instruction::Misc(misc::Misc::HALT()), result.instruction);
+        assert!(cpu.flags.halted);
+
+        for _ in 0..3 {
+            let result = cpu.tick(&mut registers, &mut memory).unwrap();
+            assert!(result.halted);
+            assert_eq!((0x0000, 1), (result.address, result.cycles));
+            assert_eq!(0x0001, registers.PC);
+        }
+        assert_eq!(4, cpu.cycles());
+
+        memory.set(REGISTER_IF as usize, 0x04);
+        let result = cpu.tick(&mut registers, &mut memory).unwrap();
+
+        assert!(result.interrupt_serviced);
+        assert!(!result.halted);
+        assert_eq!(INTERRUPT_DISPATCH_CYCLES + 2, result.cycles);
+        assert_eq!(11, cpu.cycles());
+        assert_eq!(0x0051, registers.PC);
+        assert_eq!(0x0001, memory.get_u16(0xCFFE));
+        assert!(!cpu.flags.halted);
+    }
+
+    #[test]
+    fn halt_without_ime_resumes_without_servicing_the_interrupt() {
+        let mut registers = Registers::new();
+        let mut memory = Memory::new();
+        let mut cpu = CPU::new();
+        // HALT; INC A
+        memory.load(0x0000, &[0x76, 0x3C]);
+        memory.set(REGISTER_IE, 0x04);
+        cpu.tick(&mut registers, &mut memory).unwrap();
+        assert!(cpu.tick(&mut registers, &mut memory).unwrap().halted);
+
+        memory.set(REGISTER_IF as usize, 0x04);
+        let result = cpu.tick(&mut registers, &mut memory).unwrap();
+
+        assert!(!result.interrupt_serviced);
+        assert_eq!((0x0001, 1), (result.address, result.cycles));
+        assert_eq!(0x0002, registers.PC);
+        assert_eq!(0x04, memory.io().get_raw(REGISTER_IF) & 0x1F);
+    }
+
+    #[test]
+    fn pushing_pc_over_ie_cancels_the_interrupt() {
+        let mut registers = Registers::new();
+        let mut memory = Memory::new();
+        let mut cpu = CPU::new();
+        cpu.flags.IME = true;
+
+        // The upper byte 0x02 lands in IE, disabling the timer but enabling the STAT interrupt
+        registers.PC = 0x0234;
+        registers.SP = 0x0000;
+        memory.set(REGISTER_IE, 0x04);
+        memory.set(REGISTER_IF as usize, 0x06);
+        cpu.tick(&mut registers, &mut memory).unwrap();
+        assert_eq!(0x0049, registers.PC);
+        assert_eq!(0x04, memory.io().get_raw(REGISTER_IF));
+
+        // The upper byte 0x01 disables the timer, no interrupt is left to service
+        cpu.flags.IME = true;
+        registers.PC = 0x0134;
+        registers.SP = 0x0000;
+        memory.set(REGISTER_IE, 0x04);
+        let result = cpu.tick(&mut registers, &mut memory).unwrap();
+        assert!(result.interrupt_serviced);
+        assert_eq!(0x0000, result.address);
+        assert_eq!(0x04, memory.io().get_raw(REGISTER_IF));
+        assert_eq!(0x01, memory.get(REGISTER_IE));
+    }
+
+    #[test]
+    fn interrupt_pushes_into_rom_do_not_switch_banks() {
+        let mut rom = vec![0; 0xC000];
+        rom[0x4000] = 0x01;
+        rom[0x8000] = 0x02;
+        let mut memory =
+            Memory::with_cartridge(Box::new(crate::cartridge::mbc5::Mbc5::new(rom, 0, false)));
+        let mut registers = Registers::new();
+        let mut cpu = CPU::new();
+        cpu.flags.IME = true;
+        registers.enable_dirty_tracking();
+
+        // The upper byte 0x02 would select ROM bank 2 through the MBC5 bank register
+        registers.PC = 0x0234;
+        registers.SP = 0x2001;
+        memory.set(REGISTER_IE, 0x04);
+        memory.set(REGISTER_IF as usize, 0x04);
+        let result = cpu.tick(&mut registers, &mut memory).unwrap();
+
+        assert!(result.interrupt_serviced);
+        assert_eq!(0x1FFF, registers.SP);
+        assert!(registers.is_double_dirty(&crate::registers::DoubleRegister::SP));
+        assert_eq!(0x01, memory.get(0x4000));
+    }
+
+    #[test]
+    fn step_t_cycle_spends_every_t_cycle_of_an_instruction() {
+        let mut registers = Registers::new();
+        let mut memory = Memory::new();
+        let mut cpu = CPU::new();
+        // JP 0x0000, 4 machine cycles
+        memory.load(0x0000, &[0xC3, 0x00, 0x00]);
+
+        let executed = (0..64)
+            .map(|_| cpu.step_t_cycle(&mut registers, &mut memory).unwrap())
+            .enumerate()
+            .filter_map(|(t_cycle, result)| result.map(|_| t_cycle))
+            .collect::<Vec<_>>();
+
+        assert_eq!(vec![0, 16, 32, 48], executed);
+        assert_eq!(64, cpu.t_cycles());
+        assert_eq!(16, cpu.cycles());
+    }
+
+    #[test]
+    fn hooks_run_around_every_instruction() {
+        use crate::{hooks::InstructionHook, registers::SingleRegister};
+        use std::{cell::RefCell, rc::Rc};
+
+        /// Skips the instruction at 0x0000 and logs executed instructions
+        struct Log(Rc<RefCell<Vec<String>>>);
+
+        impl InstructionHook for Log {
+            fn before(&mut self, pc: u16, registers: &mut Registers, _: &mut dyn MemoryBus) {
+                if pc == 0x0000 {
+                    registers.PC = 0x0001;
+                }
+            }
+
+            fn after(
+                &mut self,
+                pc: u16,
+                instruction: &Instruction,
+                _: &mut Registers,
+                _: &mut dyn MemoryBus,
+            ) {
+                self.0
+                    .borrow_mut()
+                    .push(format!("{:04x} {}", pc, instruction));
+            }
+        }
+
+        let mut registers = Registers::new();
+        let mut memory = Memory::new();
+        let mut cpu = CPU::new();
+        let log = Rc::new(RefCell::new(vec![]));
+        cpu.add_hook(Box::new(Log(log.clone())));
+        // INC A; INC B
+        memory.load(0x0000, &[0x3C, 0x04]);
+
+        cpu.tick(&mut registers, &mut memory).unwrap();
+        cpu.take_hooks();
+        cpu.tick(&mut registers, &mut memory).unwrap();
+
+        assert_eq!(vec!["0001 INC B"], *log.borrow());
+        assert_eq!(0, registers.get_single(&SingleRegister::A));
+    }
+
+    #[test]
+    fn step_over_skips_untaken_calls_and_other_instructions() {
+        let mut registers = Registers::new();
+        let mut memory = Memory::new();
+        let mut cpu = CPU::new();
+        // CALL C, 0x0010; INC A
+        memory.load(0x0000, &[0xDC, 0x10, 0x00, 0x3C]);
+        // The condition decodes as NZ, make it unfulfilled either way
+        registers.set_zero(true);
+
+        cpu.step_over(&mut registers, &mut memory).unwrap();
+        assert_eq!(0x0003, registers.PC);
+
+        cpu.step_over(&mut registers, &mut memory).unwrap();
+        assert_eq!(0x0004, registers.PC);
+    }
+
+    #[test]
+    fn step_out_skips_returns_of_nested_calls() {
+        let mut registers = Registers::new();
+        let mut memory = Memory::new();
+        let mut cpu = CPU::new();
+        // Caller pushed a return address to 0x0040
+        registers.SP = 0xFFFC;
+        memory.set_u16(0xFFFC, 0x0040);
+        // CALL 0x0010; RET ... 0x0010: RET
+        memory.load(0x0000, &[0xCD, 0x10, 0x00, 0xC9]);
+        memory.load(0x0010, &[0xC9]);
+
+        cpu.step_out(&mut registers, &mut memory).unwrap();
+
+        assert_eq!(0x0040, registers.PC);
+        assert_eq!(0xFFFE, registers.SP);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn cpu_flags_round_trip() {
+        let flags = CpuFlags {
+            IME: true,
+            IME_scheduled: false,
+            halted: true,
+        };
+
+        let json = serde_json::to_string(&flags).unwrap();
+
+        assert_eq!(r#"{"IME":true,"IME_scheduled":false,"halted":true}"#, json);
+        assert_eq!(flags, serde_json::from_str(&json).unwrap());
+
+        // Flags saved before `halted` existed
+        let json = r#"{"IME":true,"IME_scheduled":false}"#;
+        assert!(!serde_json::from_str::<CpuFlags>(json).unwrap().halted);
+    }
 }
@@ -0,0 +1,388 @@
+//! # Debugger
+//!
+//! Every `CPU` has a `Debugger`, see `CPU::debugger_mut`. Before executing an instruction
+//! `CPU::tick` checks whether a breakpoint is set at PC. If so nothing is executed and the tick
+//! returns `CpuError::Break` instead.
+//!
+//! A breakpoint can have a `Condition`, it then only stops the CPU if the condition holds when
+//! PC reaches it, see `Debugger::add_conditional_breakpoint`.
+//!
+//! Watchpoints stop the CPU when an instruction reads or writes a watched address range, see
+//! `Debugger::add_watchpoint`. The instruction is completed first, `tick` then returns the
+//! access which hit the watchpoint as `CpuError::Break`.
+//!
+//! Calls and returns are tracked in a `CallStack`, see `Debugger::call_stack`.
+//!
+//! Calling `tick` again continues execution: the breakpoint which stopped the CPU is skipped
+//! once, so the instruction at it is executed.
+//!
+//! ```
+//! # use gejmboj_cpu::{cpu::CPU, debugger::Break, errors::CpuError};
+//! # use gejmboj_cpu::{memory::Memory, registers::Registers};
+//! let mut cpu = CPU::new();
+//! let mut registers = Registers::new();
+//! let mut memory = Memory::new();
+//! cpu.debugger_mut().add_breakpoint(0x0001);
+//!
+//! cpu.tick(&mut registers, &mut memory).unwrap();
+//! assert_eq!(
+//!     Err(CpuError::Break(Break::Breakpoint { address: 0x0001 })),
+//!     cpu.tick(&mut registers, &mut memory)
+//! );
+//! assert_eq!(0x0001, registers.PC);
+//!
+//! // Continue
+//! cpu.tick(&mut registers, &mut memory).unwrap();
+//! assert_eq!(0x0002, registers.PC);
+//! ```
+
+mod call_stack;
+mod condition;
+mod watch;
+
+pub use call_stack::{CallStack, Frame, StackMismatch};
+pub use condition::Condition;
+pub(crate) use watch::WatchedBus;
+pub use watch::{Access, Watchpoint};
+
+use std::{collections::BTreeMap, fmt::Display};
+
+use crate::{
+    errors::CpuError, instructions::control_flow::ControlFlow, memory::MemoryBus,
+    registers::Registers, symbols::Symbols,
+};
+
+/// The reason the CPU stopped.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Break {
+    /// PC reached a breakpoint
+    Breakpoint { address: u16 },
+    /// The instruction at `pc` accessed a watched `address`
+    Watchpoint {
+        pc: u16,
+        address: u16,
+        value: u8,
+        access: Access,
+    },
+    /// A return did not return to the address pushed by its call
+    StackMismatch(StackMismatch),
+}
+
+impl Display for Break {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Break::Breakpoint { address } => write!(f, "Breakpoint at {:04x}", address),
+            Break::Watchpoint {
+                pc,
+                address,
+                value,
+                access,
+            } => write!(
+                f,
+                "Watchpoint at {:04x}: {} of {:02x} by instruction at {:04x}",
+                address, access, value, pc
+            ),
+            Break::StackMismatch(StackMismatch {
+                pc,
+                expected,
+                actual,
+            }) => write!(
+                f,
+                "Return at {:04x} to {:04x}, called function should return to {:04x}",
+                pc, actual, expected
+            ),
+        }
+    }
+}
+
+/// Breakpoints checked by `CPU::tick`, see module documentation.
+#[derive(Debug, Default)]
+pub struct Debugger {
+    breakpoints: BTreeMap<u16, Option<Condition>>,
+    stopped_at: Option<u16>,
+    watchpoints: Vec<Watchpoint>,
+    call_stack: CallStack,
+    break_on_stack_mismatch: bool,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets a breakpoint at `address`, returns `false` if one was already set.
+    ///
+    /// Replaces the condition of a conditional breakpoint at `address`.
+    pub fn add_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.insert(address, None).is_none()
+    }
+
+    /// Sets a breakpoint at `address` stopping only when `condition` holds, returns `false` if
+    /// one was already set.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::{cpu::CPU, memory::Memory, registers::*};
+    /// let mut cpu = CPU::new();
+    /// let mut registers = Registers::new();
+    /// let mut memory = Memory::new();
+    /// // INC A, JR -3
+    /// memory.load(0x0000, &[0x3C, 0x18, 0xFD]);
+    ///
+    /// let condition = "A == 3".parse().unwrap();
+    /// cpu.debugger_mut().add_conditional_breakpoint(0x0001, condition);
+    ///
+    /// while cpu.tick(&mut registers, &mut memory).is_ok() {}
+    ///
+    /// assert_eq!(0x0001, registers.PC);
+    /// assert_eq!(3, registers.get_single(&SingleRegister::A));
+    /// ```
+    pub fn add_conditional_breakpoint(&mut self, address: u16, condition: Condition) -> bool {
+        self.breakpoints.insert(address, Some(condition)).is_none()
+    }
+
+    /// Sets a breakpoint at the address of `label`, returns `false` if one was already set.
+    ///
+    /// The breakpoint stops at the address whichever bank is mapped there.
+    pub fn add_symbol_breakpoint(
+        &mut self,
+        symbols: &Symbols,
+        label: &str,
+    ) -> Result<bool, CpuError> {
+        let (_, address) = symbols
+            .address(label)
+            .ok_or_else(|| CpuError::Error(format!("Unknown symbol: {}", label)))?;
+
+        Ok(self.add_breakpoint(address))
+    }
+
+    /// Removes the breakpoint at `address`, returns `false` if none was set.
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address).is_some()
+    }
+
+    /// Returns the condition of the breakpoint at `address`, if it is conditional.
+    pub fn condition(&self, address: u16) -> Option<&Condition> {
+        self.breakpoints.get(&address)?.as_ref()
+    }
+
+    /// Removes all breakpoints.
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Returns `true` if a breakpoint is set at `address`.
+    pub fn has_breakpoint(&self, address: u16) -> bool {
+        self.breakpoints.contains_key(&address)
+    }
+
+    /// Returns the breakpoint addresses in ascending order.
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.keys().copied()
+    }
+
+    /// Adds a watchpoint.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::{cpu::CPU, debugger::*, errors::CpuError};
+    /// # use gejmboj_cpu::{memory::Memory, registers::Registers};
+    /// let mut cpu = CPU::new();
+    /// let mut registers = Registers::new();
+    /// let mut memory = Memory::new();
+    /// // LD (0xC000), A
+    /// memory.load(0x0000, &[0xEA, 0x00, 0xC0]);
+    ///
+    /// cpu.debugger_mut().add_watchpoint(Watchpoint::write(0xC000..=0xDFFF));
+    ///
+    /// assert_eq!(
+    ///     Err(CpuError::Break(Break::Watchpoint {
+    ///         pc: 0x0000,
+    ///         address: 0xC000,
+    ///         value: 0x00,
+    ///         access: Access::Write,
+    ///     })),
+    ///     cpu.tick(&mut registers, &mut memory)
+    /// );
+    /// assert_eq!(0x0003, registers.PC);
+    /// ```
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    /// Removes watchpoints equal to `watchpoint`, returns `false` if there were none.
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|x| x != watchpoint);
+        self.watchpoints.len() != count
+    }
+
+    /// Removes all watchpoints.
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    /// Returns the watchpoints.
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Returns the calls not returned from yet.
+    pub fn call_stack(&self) -> &CallStack {
+        &self.call_stack
+    }
+
+    pub fn call_stack_mut(&mut self) -> &mut CallStack {
+        &mut self.call_stack
+    }
+
+    /// Stops the CPU with `Break::StackMismatch` after a return not matching its call.
+    pub fn set_break_on_stack_mismatch(&mut self, enabled: bool) {
+        self.break_on_stack_mismatch = enabled;
+    }
+
+    /// Called after the control flow instruction at `pc` is executed, `sp` is SP before it.
+    pub(crate) fn track(
+        &mut self,
+        pc: u16,
+        instruction: &ControlFlow,
+        sp: u16,
+        registers: &Registers,
+        memory: &impl MemoryBus,
+    ) -> Option<Break> {
+        if instruction.is_call() && registers.SP == sp.wrapping_sub(2) {
+            self.call_stack.call(pc, registers, memory);
+        } else if instruction.is_return() && registers.SP == sp.wrapping_add(2) {
+            let mismatch = self.call_stack.ret(pc, registers)?;
+            return self
+                .break_on_stack_mismatch
+                .then_some(Break::StackMismatch(mismatch));
+        }
+
+        None
+    }
+
+    /// Called before the instruction at PC is executed, returns the reason to stop if any.
+    pub(crate) fn check(
+        &mut self,
+        registers: &Registers,
+        memory: &impl MemoryBus,
+    ) -> Result<(), Break> {
+        let pc = registers.PC;
+        if self.stopped_at.take() == Some(pc) {
+            return Ok(());
+        }
+
+        match self.breakpoints.get(&pc) {
+            Some(None) => {}
+            Some(Some(condition)) if condition.evaluate(registers, memory) => {}
+            _ => return Ok(()),
+        }
+
+        self.stopped_at = Some(pc);
+        Err(Break::Breakpoint { address: pc })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{errors::CpuError, memory::Memory, registers::SingleRegister};
+
+    fn check_at(debugger: &mut Debugger, pc: u16) -> Result<(), Break> {
+        let mut registers = Registers::new();
+        registers.PC = pc;
+        registers.set_single(&SingleRegister::A, 0x3C);
+
+        debugger.check(&registers, &Memory::new())
+    }
+
+    #[test]
+    fn breakpoint_stops_once_per_arrival() {
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(0x0150);
+
+        assert_eq!(Ok(()), check_at(&mut debugger, 0x0100));
+        assert_eq!(
+            Err(Break::Breakpoint { address: 0x0150 }),
+            check_at(&mut debugger, 0x0150)
+        );
+        assert_eq!(Ok(()), check_at(&mut debugger, 0x0150));
+        assert_eq!(Ok(()), check_at(&mut debugger, 0x0151));
+
+        // A loop arriving at the breakpoint again stops again
+        assert!(check_at(&mut debugger, 0x0150).is_err());
+    }
+
+    #[test]
+    fn symbol_breakpoints_stop_at_the_label_address() {
+        let mut debugger = Debugger::new();
+        let symbols: Symbols = "00:0150 Main".parse().unwrap();
+
+        assert_eq!(Ok(true), debugger.add_symbol_breakpoint(&symbols, "Main"));
+        assert!(debugger.add_symbol_breakpoint(&symbols, "Missing").is_err());
+        assert!(check_at(&mut debugger, 0x0150).is_err());
+    }
+
+    #[test]
+    fn removed_breakpoints_do_not_stop() {
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(0x0150);
+        debugger.add_breakpoint(0x0100);
+
+        assert!(debugger.remove_breakpoint(0x0150));
+        assert!(!debugger.remove_breakpoint(0x0150));
+
+        assert_eq!(Ok(()), check_at(&mut debugger, 0x0150));
+        assert_eq!(vec![0x0100], debugger.breakpoints().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn conditional_breakpoints_stop_when_the_condition_holds() {
+        let mut debugger = Debugger::new();
+        debugger.add_conditional_breakpoint(0x0100, "A == 0x3C".parse().unwrap());
+        debugger.add_conditional_breakpoint(0x0150, "A != 0x3C".parse().unwrap());
+
+        assert!(check_at(&mut debugger, 0x0100).is_err());
+        assert!(check_at(&mut debugger, 0x0150).is_ok());
+        assert_eq!("A != 0x3C", debugger.condition(0x0150).unwrap().to_string());
+
+        debugger.add_breakpoint(0x0150);
+        assert_eq!(None, debugger.condition(0x0150));
+        assert!(check_at(&mut debugger, 0x0150).is_err());
+    }
+
+    #[test]
+    fn watchpoints_report_the_first_matching_access() {
+        let mut cpu = crate::cpu::CPU::new();
+        let mut registers = Registers::new();
+        let mut memory = Memory::new();
+        registers.SP = 0xD000;
+        registers.set_double(&crate::registers::DoubleRegister::BC, 0x1234);
+        // LD A, (0xC000); PUSH BC; NOP
+        memory.load(0x0000, &[0xFA, 0x00, 0xC0, 0xC5, 0x00]);
+        memory.set(0xC000, 0xAB);
+
+        cpu.debugger_mut()
+            .add_watchpoint(Watchpoint::write(0xC000..=0xC000));
+        cpu.debugger_mut()
+            .add_watchpoint(Watchpoint::access(0xCFFE..=0xCFFF));
+
+        // Reads of a write watchpoint do not stop
+        assert!(cpu.tick(&mut registers, &mut memory).is_ok());
+        assert_eq!(
+            Err(CpuError::Break(Break::Watchpoint {
+                pc: 0x0003,
+                address: 0xCFFF,
+                value: 0x12,
+                access: Access::Write,
+            })),
+            cpu.tick(&mut registers, &mut memory)
+        );
+        assert_eq!(0x1234, memory.get_u16(0xCFFE));
+
+        assert!(cpu
+            .debugger_mut()
+            .remove_watchpoint(&Watchpoint::access(0xCFFE..=0xCFFF)));
+        assert_eq!(1, cpu.debugger().watchpoints().len());
+        assert!(cpu.tick(&mut registers, &mut memory).is_ok());
+    }
+}
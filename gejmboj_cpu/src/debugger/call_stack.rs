@@ -0,0 +1,240 @@
+//! # Call stack
+//!
+//! A shadow of the stack holding one frame per function call. `CPU::tick` pushes a frame when a
+//! `CALL` or `RST` pushes a return address, and pops it when a `RET`, `RETC` or `RETI` pops that
+//! address again. Frames whose stack slot is popped in another way, e.g. by `POP` or by changing
+//! SP directly, are discarded.
+//!
+//! A return to another address than the one pushed by the call means the stack was smashed.
+//! The frame is popped anyway, and the debugger stops if
+//! `Debugger::set_break_on_stack_mismatch` is enabled.
+//!
+//! Printing the call stack gives a backtrace, innermost frame first:
+//!
+//! ```
+//! # use gejmboj_cpu::{cpu::CPU, memory::Memory, registers::Registers};
+//! let mut cpu = CPU::new();
+//! let mut registers = Registers::new();
+//! let mut memory = Memory::new();
+//! // CALL 0x0010; ... 0x0010: CALL 0x0020
+//! memory.load(0x0000, &[0xCD, 0x10, 0x00]);
+//! memory.load(0x0010, &[0xCD, 0x20, 0x00]);
+//!
+//! cpu.tick(&mut registers, &mut memory).unwrap();
+//! cpu.tick(&mut registers, &mut memory).unwrap();
+//!
+//! assert_eq!(
+//!     "#0 0020 called from 0010\n\
+//!      #1 0010 called from 0000\n",
+//!     cpu.debugger().call_stack().to_string()
+//! );
+//! ```
+
+use std::fmt::Display;
+
+use crate::{memory::MemoryBus, registers::Registers};
+
+/// Frames kept before the outermost ones are dropped, e.g. by runaway recursion.
+const MAX_DEPTH: usize = 1024;
+
+/// A called function.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    /// Address of the call instruction
+    pub call_site: u16,
+    /// Address of the called function
+    pub target: u16,
+    /// Address pushed by the call
+    pub return_address: u16,
+    /// SP after the call, i.e. where the return address is stored
+    pub sp: u16,
+}
+
+/// A return not matching the call it returned from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StackMismatch {
+    /// Address of the return instruction
+    pub pc: u16,
+    /// Address pushed by the call
+    pub expected: u16,
+    /// Address returned to
+    pub actual: u16,
+}
+
+/// Frames of the called functions, see module documentation.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CallStack {
+    frames: Vec<Frame>,
+}
+
+impl CallStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pushes a frame for the call at `call_site`, which has just been executed.
+    pub(crate) fn call(&mut self, call_site: u16, registers: &Registers, memory: &impl MemoryBus) {
+        let sp = registers.SP;
+        // The slot of the return address was popped before if it is reused
+        self.discard_popped(sp as u32 + 1);
+
+        let return_address = u16::from_le_bytes([
+            memory.peek(sp as usize),
+            memory.peek(sp.wrapping_add(1) as usize),
+        ]);
+
+        if self.frames.len() == MAX_DEPTH {
+            self.frames.remove(0);
+        }
+        self.frames.push(Frame {
+            call_site,
+            target: registers.PC,
+            return_address,
+            sp,
+        });
+    }
+
+    /// Pops the frame of the return at `pc`, which has just been executed.
+    pub(crate) fn ret(&mut self, pc: u16, registers: &Registers) -> Option<StackMismatch> {
+        let sp = registers.SP as u32;
+        self.discard_popped(sp.saturating_sub(2));
+
+        match self.frames.last() {
+            Some(frame) if frame.sp as u32 + 2 == sp => {
+                let frame = self.frames.pop()?;
+                (frame.return_address != registers.PC).then_some(StackMismatch {
+                    pc,
+                    expected: frame.return_address,
+                    actual: registers.PC,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Discards frames whose return address is stored below `sp`, i.e. was already popped.
+    fn discard_popped(&mut self, sp: u32) {
+        while matches!(self.frames.last(), Some(frame) if (frame.sp as u32) < sp) {
+            self.frames.pop();
+        }
+    }
+
+    /// Returns the frames, innermost first.
+    pub fn frames(&self) -> impl Iterator<Item = &Frame> {
+        self.frames.iter().rev()
+    }
+
+    /// Returns the number of frames.
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Removes all frames.
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+}
+
+impl Display for CallStack {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (depth, frame) in self.frames().enumerate() {
+            writeln!(
+                f,
+                "#{} {:04x} called from {:04x}",
+                depth, frame.target, frame.call_site
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cpu::CPU, debugger::Break, errors::CpuError, memory::Memory};
+
+    fn run(program: &[(usize, &[u8])], ticks: usize) -> (CPU, Registers, Memory) {
+        let mut cpu = CPU::new();
+        let mut registers = Registers::new();
+        let mut memory = Memory::new();
+        registers.SP = 0xD000;
+        for (address, bytes) in program {
+            memory.load(*address, bytes);
+        }
+
+        for _ in 0..ticks {
+            cpu.tick(&mut registers, &mut memory).unwrap();
+        }
+        (cpu, registers, memory)
+    }
+
+    #[test]
+    fn returns_pop_their_frames() {
+        // CALL 0x0010; ... 0x0010: CALL 0x0020; RET; ... 0x0020: RET
+        let program: &[(usize, &[u8])] = &[
+            (0x0000, &[0xCD, 0x10, 0x00]),
+            (0x0010, &[0xCD, 0x20, 0x00]),
+            (0x0013, &[0xC9]),
+            (0x0020, &[0xC9]),
+        ];
+
+        let (cpu, ..) = run(program, 2);
+        assert_eq!(
+            vec![0x0020, 0x0010],
+            cpu.debugger()
+                .call_stack()
+                .frames()
+                .map(|x| x.target)
+                .collect::<Vec<_>>()
+        );
+
+        let (cpu, registers, _) = run(program, 3);
+        assert_eq!(1, cpu.debugger().call_stack().depth());
+        assert_eq!(0x0013, registers.PC);
+
+        let (cpu, registers, _) = run(program, 4);
+        assert_eq!(0, cpu.debugger().call_stack().depth());
+        assert_eq!(0x0003, registers.PC);
+    }
+
+    #[test]
+    fn popped_return_addresses_discard_frames() {
+        // CALL 0x0010; ... 0x0010: POP BC, CALL 0x0020
+        let program: &[(usize, &[u8])] = &[
+            (0x0000, &[0xCD, 0x10, 0x00]),
+            (0x0010, &[0xC1, 0xCD, 0x20, 0x00]),
+        ];
+
+        let (cpu, ..) = run(program, 3);
+
+        assert_eq!(
+            vec![0x0020],
+            cpu.debugger()
+                .call_stack()
+                .frames()
+                .map(|x| x.target)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn smashed_return_addresses_are_detected() {
+        // CALL 0x0010
+        let program: &[(usize, &[u8])] = &[(0x0000, &[0xCD, 0x10, 0x00])];
+        let (mut cpu, mut registers, mut memory) = run(program, 1);
+        cpu.debugger_mut().set_break_on_stack_mismatch(true);
+        // Overwrite the return address in place
+        memory.set(registers.SP as usize, 0x34);
+        memory.set(0x0010, 0xC9);
+
+        assert_eq!(
+            Err(CpuError::Break(Break::StackMismatch(StackMismatch {
+                pc: 0x0010,
+                expected: 0x0003,
+                actual: 0x0034,
+            }))),
+            cpu.tick(&mut registers, &mut memory)
+        );
+        assert_eq!(0, cpu.debugger().call_stack().depth());
+    }
+}
@@ -0,0 +1,346 @@
+//! # Breakpoint conditions
+//!
+//! A condition is a small expression evaluated against the registers and memory:
+//!
+//! ```asciidoc
+//! Registers:  A B C D E F H L AF BC DE HL SP PC
+//! Flags:      is_zero is_negative is_half_carry is_carry
+//! Memory:     [address], the byte at address, e.g. [HL] or [0xC000]
+//! Numbers:    decimal, or hexadecimal with a 0x or $ prefix
+//! Operators:  ( ) ! == != < <= > >= && ||
+//! ```
+//!
+//! Operators bind in the order `!`, comparisons, `&&`, `||`. Any value other than `0` is true,
+//! comparisons and logical operators evaluate to `1` or `0`.
+//!
+//! ```
+//! # use gejmboj_cpu::{debugger::Condition, memory::Memory, registers::*};
+//! let condition: Condition = "A == 0x3C && is_carry".parse().unwrap();
+//! let mut registers = Registers::new();
+//! let memory = Memory::new();
+//!
+//! registers.set_single(&SingleRegister::A, 0x3C);
+//! assert!(!condition.evaluate(&registers, &memory));
+//!
+//! registers.set_carry(true);
+//! assert!(condition.evaluate(&registers, &memory));
+//! ```
+
+use std::{fmt::Display, iter::Peekable, str::FromStr, vec::IntoIter};
+
+use crate::{
+    errors::CpuError,
+    memory::MemoryBus,
+    registers::{DoubleRegister, Flag, Registers, SingleRegister},
+};
+
+/// A parsed breakpoint condition, see module documentation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    source: String,
+    expression: Expression,
+}
+
+impl Condition {
+    /// Evaluates the condition, memory is read with `MemoryBus::peek`.
+    pub fn evaluate(&self, registers: &Registers, memory: &impl MemoryBus) -> bool {
+        self.expression.evaluate(registers, memory) != 0
+    }
+}
+
+impl FromStr for Condition {
+    type Err = CpuError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = tokenize(s)?.into_iter().peekable();
+        let expression = parse_or(&mut tokens)?;
+
+        match tokens.next() {
+            None => Ok(Self {
+                source: s.trim().to_string(),
+                expression,
+            }),
+            Some(token) => Err(error(format!("unexpected {:?}", token))),
+        }
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    Single(SingleRegister),
+    Double(DoubleRegister),
+    Flag(Flag),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinaryOperator {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expression {
+    Number(u32),
+    Operand(Operand),
+    Memory(Box<Expression>),
+    Not(Box<Expression>),
+    Binary(BinaryOperator, Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+    fn evaluate(&self, registers: &Registers, memory: &impl MemoryBus) -> u32 {
+        match self {
+            Expression::Number(x) => *x,
+            Expression::Operand(Operand::Single(r)) => registers.get_single(r) as u32,
+            Expression::Operand(Operand::Double(r)) => registers.get_double(r) as u32,
+            Expression::Operand(Operand::Flag(flag)) => registers.get_flag(*flag) as u32,
+            Expression::Memory(address) => {
+                let address = address.evaluate(registers, memory) as u16;
+                memory.peek(address.into()) as u32
+            }
+            Expression::Not(x) => (x.evaluate(registers, memory) == 0) as u32,
+            Expression::Binary(operator, left, right) => {
+                let left = left.evaluate(registers, memory);
+
+                // Short-circuit, memory reads may have side effects on custom buses
+                match operator {
+                    BinaryOperator::And if left == 0 => return 0,
+                    BinaryOperator::Or if left != 0 => return 1,
+                    _ => {}
+                }
+
+                let right = right.evaluate(registers, memory);
+
+                (match operator {
+                    BinaryOperator::Equal => left == right,
+                    BinaryOperator::NotEqual => left != right,
+                    BinaryOperator::Less => left < right,
+                    BinaryOperator::LessOrEqual => left <= right,
+                    BinaryOperator::Greater => left > right,
+                    BinaryOperator::GreaterOrEqual => left >= right,
+                    BinaryOperator::And | BinaryOperator::Or => right != 0,
+                }) as u32
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(u32),
+    Identifier(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 14] = [
+    "==", "!=", "<=", ">=", "&&", "||", "<", ">", "!", "(", ")", "[", "]", "$",
+];
+
+fn error(reason: String) -> CpuError {
+    CpuError::Error(format!("Invalid breakpoint condition: {}", reason))
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, CpuError> {
+    let mut tokens = vec![];
+    let mut rest = s.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            let word = &rest[..end];
+            let hex_prefixed = tokens.last() == Some(&Token::Symbol("$"));
+
+            let token = if hex_prefixed {
+                tokens.pop();
+                Token::Number(parse_number(word, 16)?)
+            } else if let Some(hex) = word.strip_prefix("0x") {
+                Token::Number(parse_number(hex, 16)?)
+            } else if c.is_ascii_digit() {
+                Token::Number(parse_number(word, 10)?)
+            } else {
+                Token::Identifier(word.to_string())
+            };
+
+            tokens.push(token);
+            rest = &rest[end..];
+        } else {
+            let symbol = SYMBOLS
+                .iter()
+                .find(|symbol| rest.starts_with(*symbol))
+                .ok_or_else(|| error(format!("unexpected character '{}'", c)))?;
+
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        }
+
+        rest = rest.trim_start();
+    }
+
+    if tokens.last() == Some(&Token::Symbol("$")) {
+        return Err(error("missing number after '$'".to_string()));
+    }
+
+    Ok(tokens)
+}
+
+fn parse_number(digits: &str, radix: u32) -> Result<u32, CpuError> {
+    u32::from_str_radix(digits, radix).map_err(|_| error(format!("invalid number '{}'", digits)))
+}
+
+type Tokens = Peekable<IntoIter<Token>>;
+
+fn parse_or(tokens: &mut Tokens) -> Result<Expression, CpuError> {
+    let mut left = parse_and(tokens)?;
+
+    while tokens.next_if_eq(&Token::Symbol("||")).is_some() {
+        let right = parse_and(tokens)?;
+        left = Expression::Binary(BinaryOperator::Or, Box::new(left), Box::new(right));
+    }
+
+    Ok(left)
+}
+
+fn parse_and(tokens: &mut Tokens) -> Result<Expression, CpuError> {
+    let mut left = parse_comparison(tokens)?;
+
+    while tokens.next_if_eq(&Token::Symbol("&&")).is_some() {
+        let right = parse_comparison(tokens)?;
+        left = Expression::Binary(BinaryOperator::And, Box::new(left), Box::new(right));
+    }
+
+    Ok(left)
+}
+
+fn parse_comparison(tokens: &mut Tokens) -> Result<Expression, CpuError> {
+    let left = parse_unary(tokens)?;
+
+    let operator = match tokens.peek() {
+        Some(Token::Symbol("==")) => BinaryOperator::Equal,
+        Some(Token::Symbol("!=")) => BinaryOperator::NotEqual,
+        Some(Token::Symbol("<")) => BinaryOperator::Less,
+        Some(Token::Symbol("<=")) => BinaryOperator::LessOrEqual,
+        Some(Token::Symbol(">")) => BinaryOperator::Greater,
+        Some(Token::Symbol(">=")) => BinaryOperator::GreaterOrEqual,
+        _ => return Ok(left),
+    };
+    tokens.next();
+
+    let right = parse_unary(tokens)?;
+    Ok(Expression::Binary(
+        operator,
+        Box::new(left),
+        Box::new(right),
+    ))
+}
+
+fn parse_unary(tokens: &mut Tokens) -> Result<Expression, CpuError> {
+    match tokens.next() {
+        Some(Token::Symbol("!")) => Ok(Expression::Not(Box::new(parse_unary(tokens)?))),
+        Some(Token::Symbol("(")) => {
+            let expression = parse_or(tokens)?;
+            expect(tokens, ")")?;
+            Ok(expression)
+        }
+        Some(Token::Symbol("[")) => {
+            let address = parse_or(tokens)?;
+            expect(tokens, "]")?;
+            Ok(Expression::Memory(Box::new(address)))
+        }
+        Some(Token::Number(x)) => Ok(Expression::Number(x)),
+        Some(Token::Identifier(name)) => operand(&name).map(Expression::Operand),
+        Some(token) => Err(error(format!("unexpected {:?}", token))),
+        None => Err(error("unexpected end".to_string())),
+    }
+}
+
+fn expect(tokens: &mut Tokens, symbol: &'static str) -> Result<(), CpuError> {
+    tokens
+        .next_if_eq(&Token::Symbol(symbol))
+        .map(|_| ())
+        .ok_or_else(|| error(format!("expected '{}'", symbol)))
+}
+
+fn operand(name: &str) -> Result<Operand, CpuError> {
+    if let Ok(register) = name.parse() {
+        return Ok(Operand::Single(register));
+    }
+    if let Ok(register) = name.parse() {
+        return Ok(Operand::Double(register));
+    }
+    Ok(match name {
+        "is_zero" => Operand::Flag(Flag::Z),
+        "is_negative" => Operand::Flag(Flag::N),
+        "is_half_carry" => Operand::Flag(Flag::H),
+        "is_carry" => Operand::Flag(Flag::C),
+        _ => return Err(error(format!("unknown name '{}'", name))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+
+    fn evaluate(condition: &str) -> bool {
+        let mut registers = Registers::new();
+        let mut memory = Memory::new();
+        registers.set_double(&DoubleRegister::HL, 0xC000);
+        registers.set_single(&SingleRegister::B, 0x10);
+        registers.set_zero(true);
+        memory.set(0xC000, 0xAB);
+
+        condition
+            .parse::<Condition>()
+            .unwrap()
+            .evaluate(&registers, &memory)
+    }
+
+    #[test]
+    fn registers_flags_and_memory_are_read() {
+        assert!(evaluate("HL == 0xC000"));
+        assert!(evaluate("[HL] == $AB"));
+        assert!(evaluate("[0xC000] == 171"));
+        assert!(evaluate("is_zero && !is_carry"));
+        assert!(evaluate("B"));
+        assert!(!evaluate("C"));
+    }
+
+    #[test]
+    fn operators_bind_as_documented() {
+        assert!(evaluate("B > 1 || C > 1 && D > 1"));
+        assert!(!evaluate("(B > 1 || C > 1) && D > 1"));
+        assert!(evaluate("!(B < 0x10) && B <= 0x10 && B >= 16"));
+    }
+
+    #[test]
+    fn invalid_conditions_are_rejected() {
+        for invalid in [
+            "",
+            "A ==",
+            "A == 0xZZ",
+            "X == 1",
+            "(A",
+            "[HL",
+            "A = 1",
+            "A == $",
+        ]
+        .iter()
+        {
+            assert!(invalid.parse::<Condition>().is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! # Watchpoints
+//!
+//! While watchpoints are set `CPU::tick` executes instructions on a `WatchedBus`, which
+//! forwards every access to the memory and remembers the first one hitting a watchpoint.
+//! Fetching and decoding the instruction is not watched.
+
+use std::{cell::Cell, fmt::Display, ops::RangeInclusive};
+
+use super::Break;
+use crate::memory::MemoryBus;
+
+/// A kind of memory access.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+impl Display for Access {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Access::Read => f.write_str("read"),
+            Access::Write => f.write_str("write"),
+        }
+    }
+}
+
+/// An address range stopping the CPU when an instruction reads or writes it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<u16>,
+    pub read: bool,
+    pub write: bool,
+}
+
+impl Watchpoint {
+    /// Watches reads of `range`.
+    pub fn read(range: RangeInclusive<u16>) -> Self {
+        Self {
+            range,
+            read: true,
+            write: false,
+        }
+    }
+
+    /// Watches writes to `range`.
+    pub fn write(range: RangeInclusive<u16>) -> Self {
+        Self {
+            range,
+            read: false,
+            write: true,
+        }
+    }
+
+    /// Watches reads of and writes to `range`.
+    pub fn access(range: RangeInclusive<u16>) -> Self {
+        Self {
+            range,
+            read: true,
+            write: true,
+        }
+    }
+
+    fn matches(&self, address: u16, access: Access) -> bool {
+        let watched = match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+        };
+
+        watched && self.range.contains(&address)
+    }
+}
+
+/// A memory bus reporting accesses hitting watchpoints, see module documentation.
+pub(crate) struct WatchedBus<'a, M> {
+    memory: &'a mut M,
+    watchpoints: &'a [Watchpoint],
+    pc: u16,
+    hit: Cell<Option<Break>>,
+}
+
+impl<'a, M: MemoryBus> WatchedBus<'a, M> {
+    pub(crate) fn new(memory: &'a mut M, watchpoints: &'a [Watchpoint], pc: u16) -> Self {
+        Self {
+            memory,
+            watchpoints,
+            pc,
+            hit: Cell::new(None),
+        }
+    }
+
+    /// Returns the first access which hit a watchpoint.
+    pub(crate) fn hit(self) -> Option<Break> {
+        self.hit.into_inner()
+    }
+
+    fn watch(&self, location: usize, value: u8, access: Access) {
+        let address = location as u16;
+        let hit = self.hit.take().or_else(|| {
+            self.watchpoints
+                .iter()
+                .any(|watchpoint| watchpoint.matches(address, access))
+                .then_some(Break::Watchpoint {
+                    pc: self.pc,
+                    address,
+                    value,
+                    access,
+                })
+        });
+        self.hit.set(hit);
+    }
+}
+
+impl<M: MemoryBus> MemoryBus for WatchedBus<'_, M> {
+    fn get(&self, location: usize) -> u8 {
+        let value = self.memory.get(location);
+        self.watch(location, value, Access::Read);
+        value
+    }
+
+    fn set(&mut self, location: usize, value: u8) {
+        self.memory.set(location, value);
+        self.watch(location, value, Access::Write);
+    }
+
+    fn peek(&self, location: usize) -> u8 {
+        self.memory.peek(location)
+    }
+
+    fn set_stack(&mut self, location: usize, value: u8) {
+        self.memory.set_stack(location, value);
+        self.watch(location, value, Access::Write);
+    }
+
+    fn set_stack_u16(&mut self, location: usize, value: u16) {
+        self.memory.set_stack_u16(location, value);
+
+        let [lo, hi] = value.to_le_bytes();
+        self.watch(location + 1, hi, Access::Write);
+        self.watch(location, lo, Access::Write);
+    }
+
+    fn rom_offset(&self, location: usize) -> Option<usize> {
+        self.memory.rom_offset(location)
+    }
+
+    fn step(&mut self, cycles: u16) {
+        self.memory.step(cycles)
+    }
+
+    fn begin_instruction(&mut self, pc: u16) {
+        self.memory.begin_instruction(pc)
+    }
+
+    fn check_invariants(&self) -> Result<(), String> {
+        self.memory.check_invariants()
+    }
+}
@@ -0,0 +1,86 @@
+//! # Decoded instruction cache
+//!
+//! Opt-in cache of decoded instructions, see `CPU::enable_decode_cache`. ROM can't be written,
+//! so an instruction decoded from ROM stays valid and hot loops are decoded only once.
+//! Instructions are keyed by their offset into the ROM, see `MemoryBus::rom_offset`, so a bank
+//! switch maps other entries rather than invalidating any.
+//!
+//! Instructions crossing into a switchable bank, i.e. whose bytes are not consecutive in ROM,
+//! are not cached. A cached instruction is only used if the opcode read by the CPU matches, so
+//! e.g. a boot ROM overlay or an OAM DMA bus conflict is still decoded. Operand reads served
+//! from the cache are not seen by a `MemoryObserver`.
+//!
+//! Only enable the cache for memory whose `0000-7FFF` is read-only, e.g. a `Memory` with a
+//! cartridge connected. `GameBoy` enables it.
+//!
+//! ```
+//! # use gejmboj_cpu::{cartridge::mbc5::Mbc5, cpu::CPU, memory::Memory, registers::Registers};
+//! // JR -2
+//! let mut rom = vec![0; 0x8000];
+//! rom[0..2].copy_from_slice(&[0x18, 0xFE]);
+//! let mut memory = Memory::with_cartridge(Box::new(Mbc5::new(rom, 0, false)));
+//! let mut registers = Registers::new();
+//! let mut cpu = CPU::new();
+//! cpu.enable_decode_cache();
+//!
+//! for _ in 0..10 {
+//!     cpu.tick(&mut registers, &mut memory).unwrap();
+//! }
+//!
+//! let cache = cpu.take_decode_cache().unwrap();
+//! assert_eq!(1, cache.len());
+//! assert_eq!(9, cache.hits());
+//! ```
+
+use std::collections::HashMap;
+
+use crate::instructions::Instruction;
+
+/// Decoded instructions by ROM offset, see module documentation.
+#[derive(Debug, Default)]
+pub struct DecodeCache {
+    /// Opcode, instruction and its size
+    entries: HashMap<usize, (u8, Instruction, usize)>,
+    hits: u64,
+}
+
+impl DecodeCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the instruction with `opcode` decoded at ROM `offset` and its size.
+    pub(crate) fn get(&mut self, offset: usize, opcode: u8) -> Option<(Instruction, usize)> {
+        let (cached_opcode, instruction, size) = self.entries.get(&offset)?;
+        if *cached_opcode != opcode {
+            return None;
+        }
+        self.hits += 1;
+        Some((instruction.clone(), *size))
+    }
+
+    pub(crate) fn insert(
+        &mut self,
+        offset: usize,
+        opcode: u8,
+        instruction: Instruction,
+        size: usize,
+    ) {
+        self.entries.insert(offset, (opcode, instruction, size));
+    }
+
+    /// Returns the number of cached instructions.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if no instruction is cached.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns how many instructions were taken from the cache instead of being decoded.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+}
@@ -0,0 +1,146 @@
+//! # Disassembler
+//!
+//! Decodes the instructions in a range of memory one after another, see `disassemble`. Every
+//! instruction is yielded with its address and raw bytes, operands and the `CB` prefix included.
+//! Bytes which are not a known opcode are yielded as an error one byte at a time, so data mixed
+//! into code doesn't stop the disassembly.
+//!
+//! ```
+//! # use gejmboj_cpu::{disassembler::disassemble, memory::Memory};
+//! let mut memory = Memory::new();
+//! // NOP, SWAP A, JP 0x0150
+//! memory.load(0x0100, &[0x00, 0xCB, 0x37, 0xC3, 0x50, 0x01]);
+//!
+//! let lines: Vec<String> = disassemble(&memory, 0x0100..0x0106)
+//!     .map(|(address, instruction, bytes)| {
+//!         format!("{:04x} {:02x?} {}", address, bytes, instruction.unwrap())
+//!     })
+//!     .collect();
+//!
+//! assert_eq!(
+//!     vec![
+//!         "0100 [00] NOP",
+//!         "0101 [cb, 37] SWAP A",
+//!         "0103 [c3, 50, 01] JP 0x0150",
+//!     ],
+//!     lines
+//! );
+//! ```
+//!
+//! Print instructions with `display_at` to show the absolute target of relative jumps.
+
+use std::ops::Range;
+
+use crate::{
+    errors::CpuError,
+    instructions::{control_flow::ControlFlow, Operand},
+    instructions::{decode, Instruction},
+    memory::MemoryBus,
+};
+
+/// Iterator over the instructions in a range of memory, see `disassemble`.
+pub struct Disassembler<'a, M: MemoryBus> {
+    memory: &'a M,
+    address: usize,
+    end: usize,
+}
+
+/// Disassembles the instructions starting in `range`. An instruction starting at the end of the
+/// range may extend past it.
+pub fn disassemble<M: MemoryBus>(memory: &M, range: Range<usize>) -> Disassembler<'_, M> {
+    Disassembler {
+        memory,
+        address: range.start,
+        end: range.end.min(0x10000),
+    }
+}
+
+/// Prints the `instruction` at `address` like its `Display`, but with the absolute target of a
+/// `JR` or `JRC` instead of the signed offset, e.g. `JR NZ, 0x0485`.
+///
+/// ```
+/// # use gejmboj_cpu::{disassembler::display_at, instructions::decode_bytes};
+/// let (instruction, _) = decode_bytes(&[0x18, 0xFA]).unwrap();
+///
+/// assert_eq!("JR -6", instruction.to_string());
+/// assert_eq!("JR 0x047c", display_at(&instruction, 0x0480));
+/// ```
+pub fn display_at(instruction: &Instruction, address: u16) -> String {
+    let target = match instruction {
+        Instruction::ControlFlow(instruction) => instruction.relative_target(address),
+        _ => None,
+    };
+
+    match (instruction, target) {
+        (Instruction::ControlFlow(ControlFlow::JRC(_, condition)), Some(target)) => format!(
+            "{} {}, {}",
+            instruction.mnemonic(),
+            condition,
+            Operand::Imm16(target)
+        ),
+        (_, Some(target)) => format!("{} {}", instruction.mnemonic(), Operand::Imm16(target)),
+        (_, None) => instruction.to_string(),
+    }
+}
+
+impl<M: MemoryBus> Iterator for Disassembler<'_, M> {
+    type Item = (u16, Result<Instruction, CpuError>, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.address >= self.end {
+            return None;
+        }
+
+        let address = self.address as u16;
+        let (instruction, length) =
+            match decode(self.memory.peek(self.address), address, self.memory) {
+                Ok((instruction, length)) => (Ok(instruction), length),
+                Err(error) => (Err(error), 1),
+            };
+        let bytes = (0..length)
+            .map(|offset| self.memory.peek((self.address + offset) & 0xFFFF))
+            .collect();
+
+        self.address += length;
+        Some((address, instruction, bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+
+    #[test]
+    fn unknown_opcodes_are_skipped_one_byte_at_a_time() {
+        let mut memory = Memory::new();
+        // STOP is not decoded, followed by LD HL, 0xc000
+        memory.load(0xC000, &[0x10, 0x00, 0x21, 0x00, 0xC0]);
+
+        let disassembly: Vec<_> = disassemble(&memory, 0xC000..0xC003)
+            .map(|(address, instruction, bytes)| (address, instruction.is_ok(), bytes))
+            .collect();
+
+        assert_eq!(
+            vec![
+                (0xC000, false, vec![0x10]),
+                (0xC001, true, vec![0x00]),
+                (0xC002, true, vec![0x21, 0x00, 0xC0]),
+            ],
+            disassembly
+        );
+    }
+
+    #[test]
+    fn relative_jumps_are_displayed_with_their_target() {
+        let mut memory = Memory::new();
+        // JR -4 at the start of memory wraps around, 0x20 decodes as JR C; JR 2; INC A
+        memory.load(0x0000, &[0x20, 0xFC, 0x18, 0x02, 0x3C]);
+
+        let lines: Vec<String> = disassemble(&memory, 0x0000..0x0005)
+            .map(|(address, instruction, _)| display_at(&instruction.unwrap(), address))
+            .collect();
+
+        assert_eq!(vec!["JR C, 0xfffe", "JR 0x0006", "INC A"], lines);
+    }
+}
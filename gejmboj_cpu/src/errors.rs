@@ -2,14 +2,112 @@
 
 use std::{error::Error, fmt::Display};
 
-use crate::registers::SingleRegister;
+use crate::{
+    debugger::Break,
+    memory::AccessKind,
+    registers::{DoubleRegister, Registers, SingleRegister},
+};
 
 #[derive(Debug, PartialEq)]
 pub enum CpuError {
     Error(String),
     UnsupportedSingleRegister(SingleRegister),
+    UnsupportedDoubleRegister(DoubleRegister),
     UnknownInstruction(u8),
     SingleRegisterParseError(u8),
+    UnsupportedCartridge(u8),
+    InvariantViolation {
+        address: u16,
+        reason: String,
+    },
+    InvalidSaveState(String),
+    InvalidConfiguration(String),
+    Break(Break),
+    /// An access rejected in strict mode, see `Memory::set_strict`
+    MemoryAccessViolation {
+        address: u16,
+        kind: AccessKind,
+    },
+    /// `error` occurred decoding or executing the instruction at `pc`, see `CpuError::root`.
+    Instruction {
+        pc: u16,
+        /// The instruction bytes, or the next three bytes if it couldn't be decoded
+        bytes: Vec<u8>,
+        /// The registers when the error occurred
+        registers: Option<RegisterSnapshot>,
+        error: Box<CpuError>,
+    },
+}
+
+impl CpuError {
+    /// Wraps the error with the instruction it occurred in.
+    pub fn in_instruction(
+        self,
+        pc: u16,
+        bytes: Vec<u8>,
+        registers: Option<RegisterSnapshot>,
+    ) -> Self {
+        CpuError::Instruction {
+            pc,
+            bytes,
+            registers,
+            error: Box::new(self),
+        }
+    }
+
+    /// Returns the error without the instruction it occurred in, e.g. to match on it.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::errors::CpuError;
+    /// let bytes = vec![0xDB, 0x41, 0x20];
+    /// let error = CpuError::UnknownInstruction(0xDB).in_instruction(0x4123, bytes, None);
+    ///
+    /// assert_eq!(&CpuError::UnknownInstruction(0xDB), error.root());
+    /// assert_eq!(
+    ///     "Unknown opcode: 11011011 at PC=0x4123 (bytes DB 41 20)",
+    ///     error.to_string()
+    /// );
+    /// ```
+    pub fn root(&self) -> &CpuError {
+        match self {
+            CpuError::Instruction { error, .. } => error.root(),
+            error => error,
+        }
+    }
+}
+
+/// The registers at the time of an error, see `CpuError::Instruction`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegisterSnapshot {
+    pub af: u16,
+    pub bc: u16,
+    pub de: u16,
+    pub hl: u16,
+    pub sp: u16,
+    pub pc: u16,
+}
+
+impl From<&Registers> for RegisterSnapshot {
+    fn from(registers: &Registers) -> Self {
+        Self {
+            af: registers.get_double(&DoubleRegister::AF),
+            bc: registers.get_double(&DoubleRegister::BC),
+            de: registers.get_double(&DoubleRegister::DE),
+            hl: registers.get_double(&DoubleRegister::HL),
+            sp: registers.get_double(&DoubleRegister::SP),
+            pc: registers.get_double(&DoubleRegister::PC),
+        }
+    }
+}
+
+impl Display for RegisterSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "AF={:04X} BC={:04X} DE={:04X} HL={:04X} SP={:04X} PC={:04X}",
+            self.af, self.bc, self.de, self.hl, self.sp, self.pc
+        )
+    }
 }
 
 impl Display for CpuError {
@@ -20,11 +118,56 @@ impl Display for CpuError {
             CpuError::UnsupportedSingleRegister(register) => {
                 write!(f, "Instruction does not support register {:?}", register)
             }
+            CpuError::UnsupportedDoubleRegister(register) => {
+                write!(f, "Instruction does not support register {:?}", register)
+            }
             CpuError::SingleRegisterParseError(x) => {
                 write!(f, "No single register matching {:08b}", x)
             }
+            CpuError::UnsupportedCartridge(cartridge_type) => {
+                write!(f, "Unsupported cartridge type: {:02x}", cartridge_type)
+            }
+            CpuError::InvariantViolation { address, reason } => {
+                write!(
+                    f,
+                    "Invariant violated after instruction at {:04x}: {}",
+                    address, reason
+                )
+            }
+            CpuError::InvalidSaveState(reason) => write!(f, "Invalid save state: {}", reason),
+            CpuError::InvalidConfiguration(reason) => {
+                write!(f, "Invalid configuration: {}", reason)
+            }
+            CpuError::Break(reason) => write!(f, "Stopped: {}", reason),
+            CpuError::MemoryAccessViolation { address, kind } => {
+                write!(f, "Memory access violation: {} at {:04x}", kind, address)
+            }
+            CpuError::Instruction {
+                pc,
+                bytes,
+                registers,
+                error,
+            } => {
+                write!(f, "{} at PC=0x{:04X} (bytes", error, pc)?;
+                for byte in bytes.iter() {
+                    write!(f, " {:02X}", byte)?;
+                }
+                write!(f, ")")?;
+                // The registers only with the alternate flag, `{:#}`
+                match registers {
+                    Some(registers) if f.alternate() => write!(f, " with {}", registers),
+                    _ => Ok(()),
+                }
+            }
         }
     }
 }
 
-impl Error for CpuError {}
+impl Error for CpuError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CpuError::Instruction { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
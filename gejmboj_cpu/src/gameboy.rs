@@ -0,0 +1,516 @@
+//! # Game Boy
+//!
+//! `GameBoy` owns and wires the CPU, registers, memory with the cartridge and the peripherals,
+//! for embedders which just want to run a game.
+//!
+//! Unless a boot ROM is configured with `GameBoy::builder`, cartridges are started in the state
+//! the boot ROM of the model leaves the machine in. `take_frame` reports what the CPU executed in
+//! the frame, the picture is read from `framebuffer`. Input is pressed right away with
+//! `press_button`, or deterministically at a frame boundary with `queue_input`, see `input`.
+//!
+//! ```
+//! # use gejmboj_cpu::{cpu::FRAME_CYCLES, gameboy::GameBoy, joypad::Button, model::Model};
+//! // JR -2 at the entry point
+//! let mut rom = vec![0; 0x8000];
+//! rom[0x0100..0x0102].copy_from_slice(&[0x18, 0xFE]);
+//!
+//! let mut gameboy = GameBoy::new(Model::Dmg);
+//! gameboy.load_rom(rom).unwrap();
+//! gameboy.press_button(Button::Start);
+//! gameboy.run_frame().unwrap();
+//!
+//! assert!(gameboy.take_frame().unwrap().cycles >= FRAME_CYCLES);
+//! assert_eq!(None, gameboy.take_frame());
+//! ```
+
+use crate::{
+    cartridge::{self, Mapper, HEADER_CGB_FLAG},
+    cpu::{FrameSummary, CPU},
+    errors::CpuError,
+    input::{InputEvent, InputQueue},
+    joypad::Button,
+    memory::Memory,
+    model::Model,
+    ppu::LcdEvent,
+    registers::Registers,
+    renderer::Framebuffer,
+    savestate,
+};
+
+#[cfg(feature = "profiling")]
+use crate::profiling::{FrameStats, Subsystem};
+
+/// Receives every frame completed by `GameBoy::run_frame`.
+pub trait VideoSink {
+    /// Called with the picture of the frame.
+    fn on_frame(&mut self, frame: &FrameSummary, framebuffer: &Framebuffer);
+}
+
+/// A complete machine, see module documentation.
+pub struct GameBoy {
+    model: Model,
+    boot_rom: Option<Vec<u8>>,
+    cpu: CPU,
+    registers: Registers,
+    memory: Memory,
+    frame: Option<FrameSummary>,
+    video_sink: Option<Box<dyn VideoSink>>,
+    /// Frames run since the cartridge was loaded
+    frames: u64,
+    input: InputQueue,
+    input_log: Option<Vec<InputEvent>>,
+}
+
+impl GameBoy {
+    /// Creates a machine of the given hardware model without a cartridge.
+    pub fn new(model: Model) -> Self {
+        let mut memory = Memory::new();
+        memory.set_model(model);
+
+        Self {
+            model,
+            boot_rom: None,
+            cpu: CPU::with_model(model),
+            registers: Registers::new(),
+            memory,
+            frame: None,
+            video_sink: None,
+            frames: 0,
+            input: InputQueue::new(),
+            input_log: None,
+        }
+    }
+
+    /// Returns a builder to configure the machine before it's created.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::{cartridge, gameboy::GameBoy, model::Model};
+    /// let cartridge = cartridge::load(vec![0; 0x8000], None).unwrap();
+    ///
+    /// let gameboy = GameBoy::builder()
+    ///     .model(Model::Cgb)
+    ///     .boot_rom(vec![0; 0x900])
+    ///     .cartridge(cartridge)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(0x0000, gameboy.registers().PC);
+    /// assert!(GameBoy::builder().boot_rom(vec![0; 0x900]).build().is_err());
+    /// ```
+    pub fn builder() -> GameBoyBuilder {
+        GameBoyBuilder::new()
+    }
+
+    /// Inserts the cartridge `rom` and starts it, detecting its memory bank controller from the
+    /// header.
+    ///
+    /// The machine is reset, only debugger settings of the CPU are lost too. Without a boot ROM
+    /// the cartridge starts in the state the boot ROM would leave the machine in.
+    pub fn load_rom(&mut self, rom: Vec<u8>) -> Result<(), CpuError> {
+        self.insert(cartridge::load(rom, None)?)
+    }
+
+    fn insert(&mut self, cartridge: Box<dyn Mapper>) -> Result<(), CpuError> {
+        if cartridge.read_rom(HEADER_CGB_FLAG as u16) == 0xC0 && !self.model.is_cgb() {
+            return Err(CpuError::InvalidConfiguration(format!(
+                "CGB-only cartridge can't run on {:?}",
+                self.model
+            )));
+        }
+
+        let mut memory = Memory::with_cartridge(cartridge);
+        memory.set_model(self.model);
+        self.registers = match &self.boot_rom {
+            Some(boot_rom) => {
+                memory.set_boot_rom(boot_rom.clone());
+                Registers::new()
+            }
+            None => {
+                memory.skip_boot();
+                Registers::new_post_boot(self.model)
+            }
+        };
+
+        self.cpu = CPU::with_model(self.model);
+        self.cpu.enable_decode_cache();
+        self.memory = memory;
+        self.frame = None;
+        self.frames = 0;
+        self.input.clear();
+        Ok(())
+    }
+
+    /// Runs the machine for one video frame, see `CPU::run_frame`. Queued input due at the frame
+    /// is latched first.
+    pub fn run_frame(&mut self) -> Result<FrameSummary, CpuError> {
+        #[cfg(feature = "profiling")]
+        self.memory.profiler_mut().restart();
+
+        for event in self.input.latch(self.frames) {
+            self.memory.io_mut().set_button(event.button, event.pressed);
+            if let Some(log) = self.input_log.as_mut() {
+                log.push(event);
+            }
+        }
+        self.frames += 1;
+        #[cfg(feature = "profiling")]
+        self.memory.profiler_mut().lap(Subsystem::Scheduler);
+
+        let frame = self.cpu.run_frame(&mut self.registers, &mut self.memory);
+        // Time since the last rendered line was spent executing instructions
+        #[cfg(feature = "profiling")]
+        {
+            let profiler = self.memory.profiler_mut();
+            profiler.lap(Subsystem::Cpu);
+            if let Ok(frame) = frame.as_ref() {
+                profiler.count_steps(frame.instructions);
+            }
+        }
+
+        let frame = frame?;
+        if let Some(sink) = self.video_sink.as_mut() {
+            sink.on_frame(&frame, self.memory.framebuffer());
+        }
+        self.frame = Some(frame);
+        Ok(frame)
+    }
+
+    /// Returns the host time spent per subsystem since the last call, see `profiling`.
+    #[cfg(feature = "profiling")]
+    pub fn frame_stats(&mut self) -> FrameStats {
+        self.memory.profiler_mut().frame_stats()
+    }
+
+    /// Takes the last completed frame, `None` if no frame was completed since the last call.
+    pub fn take_frame(&mut self) -> Option<FrameSummary> {
+        self.frame.take()
+    }
+
+    /// Takes the events of turning the LCD off and on since the last call, the framebuffer is
+    /// white while `is_blank` returns `true`.
+    pub fn take_lcd_events(&mut self) -> Vec<LcdEvent> {
+        self.memory.io_mut().ppu_mut().take_events()
+    }
+
+    /// Returns the picture, see `renderer`.
+    pub fn framebuffer(&self) -> &Framebuffer {
+        self.memory.framebuffer()
+    }
+
+    /// Returns `true` if the LCD shows no picture, see `Ppu::is_blank`.
+    pub fn is_blank(&self) -> bool {
+        self.memory.io().ppu().is_blank()
+    }
+
+    /// Presses `button` until it is released with `release_button`.
+    pub fn press_button(&mut self, button: Button) {
+        self.memory.io_mut().set_button(button, true);
+    }
+
+    /// Releases `button`.
+    pub fn release_button(&mut self, button: Button) {
+        self.memory.io_mut().set_button(button, false);
+    }
+
+    /// Queues `event` to be latched at the start of its frame, see `input`.
+    pub fn queue_input(&mut self, event: InputEvent) {
+        self.input.push(event);
+    }
+
+    /// Returns the queued input events.
+    pub fn input_queue(&self) -> &InputQueue {
+        &self.input
+    }
+
+    /// Returns the number of the next frame `run_frame` runs, counted since the cartridge was
+    /// loaded.
+    pub fn frame_number(&self) -> u64 {
+        self.frames
+    }
+
+    /// Starts logging the latched input events, stamped with the frame they were latched at.
+    /// Queueing the log into a machine loaded with the same cartridge replays the input.
+    pub fn enable_input_log(&mut self) {
+        self.input_log = Some(vec![]);
+    }
+
+    /// Stops logging input events and returns the log.
+    pub fn take_input_log(&mut self) -> Option<Vec<InputEvent>> {
+        self.input_log.take()
+    }
+
+    /// Serializes the machine into a save state, see `savestate`.
+    pub fn save_state(&self) -> Vec<u8> {
+        savestate::save(&self.cpu, &self.registers, &self.memory)
+    }
+
+    /// Restores the machine from a save state created by `save_state`.
+    ///
+    /// The cartridge stays inserted, nothing is changed if the state is invalid.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), CpuError> {
+        savestate::load(state, &mut self.cpu, &mut self.registers, &mut self.memory)?;
+        self.model = self.cpu.model();
+        self.frame = None;
+        Ok(())
+    }
+
+    /// Returns the CPU.
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    /// Returns the CPU mutably, e.g. to set breakpoints.
+    pub fn cpu_mut(&mut self) -> &mut CPU {
+        &mut self.cpu
+    }
+
+    /// Returns the registers.
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    /// Returns the registers mutably.
+    pub fn registers_mut(&mut self) -> &mut Registers {
+        &mut self.registers
+    }
+
+    /// Returns the memory.
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    /// Returns the memory mutably.
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+}
+
+/// Configures a `GameBoy`, see `GameBoy::builder`.
+pub struct GameBoyBuilder {
+    model: Model,
+    boot_rom: Option<Vec<u8>>,
+    cartridge: Option<Box<dyn Mapper>>,
+    video_sink: Option<Box<dyn VideoSink>>,
+}
+
+impl GameBoyBuilder {
+    fn new() -> Self {
+        Self {
+            model: Model::default(),
+            boot_rom: None,
+            cartridge: None,
+            video_sink: None,
+        }
+    }
+
+    /// Selects the hardware model, `Model::Dmg` by default.
+    pub fn model(mut self, model: Model) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Starts the machine in `boot_rom` instead of skipping to the cartridge.
+    ///
+    /// Must be the size of the boot ROM of the model, see `Model::boot_rom_size`.
+    pub fn boot_rom(mut self, boot_rom: Vec<u8>) -> Self {
+        self.boot_rom = Some(boot_rom);
+        self
+    }
+
+    /// Inserts `cartridge`.
+    pub fn cartridge(mut self, cartridge: Box<dyn Mapper>) -> Self {
+        self.cartridge = Some(cartridge);
+        self
+    }
+
+    /// Hands every completed frame to `sink`.
+    pub fn video_sink(mut self, sink: Box<dyn VideoSink>) -> Self {
+        self.video_sink = Some(sink);
+        self
+    }
+
+    /// Creates the machine.
+    ///
+    /// Fails with `CpuError::InvalidConfiguration` if the boot ROM doesn't belong to the model,
+    /// a CGB-only cartridge is inserted into a model without CGB features or a boot ROM is given
+    /// without a cartridge to hand over to.
+    pub fn build(self) -> Result<GameBoy, CpuError> {
+        if let Some(boot_rom) = &self.boot_rom {
+            if boot_rom.len() != self.model.boot_rom_size() {
+                return Err(CpuError::InvalidConfiguration(format!(
+                    "Boot ROM of {} bytes, {:?} expects {}",
+                    boot_rom.len(),
+                    self.model,
+                    self.model.boot_rom_size()
+                )));
+            }
+        }
+
+        let mut gameboy = GameBoy::new(self.model);
+        gameboy.boot_rom = self.boot_rom;
+        gameboy.video_sink = self.video_sink;
+
+        match self.cartridge {
+            Some(cartridge) => gameboy.insert(cartridge)?,
+            None if gameboy.boot_rom.is_some() => {
+                return Err(CpuError::InvalidConfiguration(
+                    "Boot ROM without a cartridge".to_string(),
+                ))
+            }
+            None => {}
+        }
+        Ok(gameboy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::Cell, rc::Rc};
+
+    use crate::{io::REGISTER_P1, registers::SingleRegister};
+
+    fn gameboy() -> GameBoy {
+        // INC A, JR -3
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0103].copy_from_slice(&[0x3C, 0x18, 0xFD]);
+
+        let mut gameboy = GameBoy::new(Model::Dmg);
+        gameboy.load_rom(rom).unwrap();
+        gameboy
+    }
+
+    #[test]
+    fn load_rom_starts_the_cartridge_after_the_boot_rom() {
+        let gameboy = gameboy();
+
+        assert_eq!(0x0100, gameboy.registers().PC);
+        assert!(gameboy.memory().io().is_boot_finished());
+        assert_eq!(0x3C, gameboy.memory().get(0x0100));
+    }
+
+    #[test]
+    fn buttons_are_visible_in_p1() {
+        let mut gameboy = gameboy();
+        gameboy.memory_mut().set(REGISTER_P1 as usize, 0x10);
+
+        gameboy.press_button(Button::Start);
+        assert_eq!(0xD7, gameboy.memory().get(REGISTER_P1 as usize));
+
+        gameboy.release_button(Button::Start);
+        assert_eq!(0xDF, gameboy.memory().get(REGISTER_P1 as usize));
+    }
+
+    #[test]
+    fn input_log_replays_late_input_at_the_frame_it_was_latched() {
+        let mut gameboy = gameboy();
+        gameboy.enable_input_log();
+        gameboy.run_frame().unwrap();
+        gameboy.run_frame().unwrap();
+
+        gameboy.queue_input(InputEvent::press(0, Button::B));
+        gameboy.run_frame().unwrap();
+        let log = gameboy.take_input_log().unwrap();
+        assert_eq!(vec![InputEvent::press(2, Button::B)], log);
+
+        let mut replay = self::gameboy();
+        for event in log {
+            replay.queue_input(event);
+        }
+        for _ in 0..3 {
+            replay.run_frame().unwrap();
+        }
+        assert_eq!(3, replay.frame_number());
+        assert_eq!(gameboy.registers(), replay.registers());
+        assert!(replay.memory().io().joypad().is_pressed(Button::B));
+    }
+
+    #[test]
+    fn save_state_restores_the_machine() {
+        let mut gameboy = gameboy();
+        gameboy.run_frame().unwrap();
+        let state = gameboy.save_state();
+        let a = gameboy.registers().get_single(&SingleRegister::A);
+
+        gameboy.run_frame().unwrap();
+        assert_ne!(a, gameboy.registers().get_single(&SingleRegister::A));
+
+        gameboy.load_state(&state).unwrap();
+        assert_eq!(a, gameboy.registers().get_single(&SingleRegister::A));
+        assert_eq!(None, gameboy.take_frame());
+        assert!(gameboy.load_state(&state[1..]).is_err());
+    }
+
+    struct FrameCount(Rc<Cell<usize>>);
+
+    impl VideoSink for FrameCount {
+        fn on_frame(&mut self, _frame: &FrameSummary, _framebuffer: &Framebuffer) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn builder_runs_the_boot_rom_and_feeds_the_video_sink() {
+        // INC A; LDH (0x50), A at the end of the boot ROM
+        let mut boot_rom = vec![0; 0x100];
+        boot_rom[0xFD..].copy_from_slice(&[0x3C, 0xE0, 0x50]);
+        let frames = Rc::new(Cell::new(0));
+
+        let mut gameboy = GameBoy::builder()
+            .boot_rom(boot_rom)
+            .cartridge(cartridge::load(vec![0; 0x8000], None).unwrap())
+            .video_sink(Box::new(FrameCount(frames.clone())))
+            .build()
+            .unwrap();
+        gameboy.run_frame().unwrap();
+
+        assert!(gameboy.memory().io().is_boot_finished());
+        assert_eq!(1, frames.get());
+    }
+
+    #[test]
+    fn builder_rejects_inconsistent_configurations() {
+        let mut cgb_only = vec![0; 0x8000];
+        cgb_only[HEADER_CGB_FLAG] = 0xC0;
+
+        for builder in [
+            GameBoy::builder()
+                .model(Model::Cgb)
+                .boot_rom(vec![0; 0x100])
+                .cartridge(cartridge::load(vec![0; 0x8000], None).unwrap()),
+            GameBoy::builder().cartridge(cartridge::load(cgb_only.clone(), None).unwrap()),
+            GameBoy::builder().boot_rom(vec![0; 0x100]),
+        ] {
+            assert!(matches!(
+                builder.build(),
+                Err(CpuError::InvalidConfiguration(_))
+            ));
+        }
+
+        assert!(GameBoy::builder()
+            .model(Model::Cgb)
+            .cartridge(cartridge::load(cgb_only, None).unwrap())
+            .build()
+            .is_ok());
+    }
+
+    #[cfg(feature = "profiling")]
+    #[test]
+    fn run_frame_accounts_time_per_subsystem() {
+        use std::time::{Duration, Instant};
+
+        let mut gameboy = gameboy();
+
+        let started = Instant::now();
+        let frame = gameboy.run_frame().unwrap();
+        let elapsed = started.elapsed();
+
+        let stats = gameboy.frame_stats();
+        assert_eq!(frame.instructions, stats.steps());
+        assert!(stats.get(Subsystem::Cpu) > Duration::ZERO);
+        assert!(stats.get(Subsystem::Ppu) > Duration::ZERO);
+        assert!(stats.get(Subsystem::Scheduler) > Duration::ZERO);
+        assert!(stats.total() <= elapsed);
+        assert_eq!(FrameStats::default(), gameboy.frame_stats());
+    }
+}
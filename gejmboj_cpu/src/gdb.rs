@@ -0,0 +1,466 @@
+//! # GDB remote serial protocol
+//!
+//! Lets gdb (or any other client speaking the GDB remote serial protocol) debug a running
+//! program: read and write the registers and memory, set breakpoints, single-step and continue.
+//! Only available with the `gdb` feature.
+//!
+//! GDB has no built in SM83 support, the registers are described to it by a target description
+//! in this order: `a f b c d e h l` (8 bits) and `sp pc` (16 bits, little endian).
+//!
+//! Breakpoints set from gdb are regular `Debugger` breakpoints. Watchpoints and stack mismatches
+//! of the `Debugger` stop the CPU with `SIGTRAP`, an unknown instruction with `SIGILL`. Any other
+//! `CpuError` ends the session.
+//!
+//! ```no_run
+//! # use std::net::TcpListener;
+//! # use gejmboj_cpu::{cpu::CPU, gdb, memory::Memory, registers::Registers};
+//! let mut cpu = CPU::new();
+//! let mut registers = Registers::new();
+//! let mut memory = Memory::new();
+//!
+//! // Connect with `target remote localhost:9001`
+//! let (connection, _) = TcpListener::bind("localhost:9001").unwrap().accept().unwrap();
+//! gdb::run(connection, &mut cpu, &mut registers, &mut memory).unwrap();
+//! ```
+
+use gdbstub::{
+    arch::{Arch, Registers as GdbRegisters},
+    common::Signal,
+    conn::{Connection, ConnectionExt},
+    stub::{run_blocking, DisconnectReason, GdbStub, SingleThreadStopReason},
+    target::{
+        ext::{
+            base::{
+                singlethread::{
+                    SingleThreadBase, SingleThreadResume, SingleThreadResumeOps,
+                    SingleThreadSingleStep, SingleThreadSingleStepOps,
+                },
+                BaseOps,
+            },
+            breakpoints::{Breakpoints, BreakpointsOps, SwBreakpoint, SwBreakpointOps},
+        },
+        Target, TargetError, TargetResult,
+    },
+};
+
+use crate::{
+    cpu::CPU,
+    debugger::Break,
+    errors::CpuError,
+    memory::MemoryBus,
+    registers::{DoubleRegister, Registers, SingleRegister},
+};
+
+/// Number of instructions executed between checking for an interrupt from gdb.
+const POLL_INTERVAL: usize = 1024;
+
+const TARGET_DESCRIPTION: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.gejmboj.sm83">
+    <reg name="a" bitsize="8" type="uint8"/>
+    <reg name="f" bitsize="8" type="uint8"/>
+    <reg name="b" bitsize="8" type="uint8"/>
+    <reg name="c" bitsize="8" type="uint8"/>
+    <reg name="d" bitsize="8" type="uint8"/>
+    <reg name="e" bitsize="8" type="uint8"/>
+    <reg name="h" bitsize="8" type="uint8"/>
+    <reg name="l" bitsize="8" type="uint8"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>"#;
+
+/// Serves a gdb session on `connection` until gdb disconnects or kills the program.
+pub fn run<C, M>(
+    connection: C,
+    cpu: &mut CPU,
+    registers: &mut Registers,
+    memory: &mut M,
+) -> Result<(), CpuError>
+where
+    C: ConnectionExt,
+    C::Error: std::fmt::Display,
+    M: MemoryBus,
+{
+    let mut target = GdbTarget::new(cpu, registers, memory);
+
+    match GdbStub::new(connection).run_blocking::<EventLoop<C, GdbTarget<M>>>(&mut target) {
+        Ok(DisconnectReason::Disconnect) | Ok(DisconnectReason::Kill) => Ok(()),
+        Ok(reason) => Err(CpuError::Error(format!("GDB session ended: {:?}", reason))),
+        Err(e) => Err(CpuError::Error(format!("GDB session failed: {}", e))),
+    }
+}
+
+/// The SM83 architecture as seen by gdb.
+pub enum Sm83 {}
+
+impl Arch for Sm83 {
+    type Usize = u16;
+    type Registers = Sm83Registers;
+    type BreakpointKind = usize;
+    type RegId = ();
+
+    fn target_description_xml() -> Option<&'static str> {
+        Some(TARGET_DESCRIPTION)
+    }
+}
+
+/// The registers in the order gdb expects them, see module documentation.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Sm83Registers {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+}
+
+const REGISTERS_SIZE: usize = 12;
+
+impl GdbRegisters for Sm83Registers {
+    type ProgramCounter = u16;
+
+    fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    fn gdb_serialize(&self, mut write_byte: impl FnMut(Option<u8>)) {
+        let [sp_lo, sp_hi] = self.sp.to_le_bytes();
+        let [pc_lo, pc_hi] = self.pc.to_le_bytes();
+
+        for byte in [
+            self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l, sp_lo, sp_hi, pc_lo,
+            pc_hi,
+        ]
+        .iter()
+        {
+            write_byte(Some(*byte));
+        }
+    }
+
+    fn gdb_deserialize(&mut self, bytes: &[u8]) -> Result<(), ()> {
+        if bytes.len() != REGISTERS_SIZE {
+            return Err(());
+        }
+
+        self.a = bytes[0];
+        self.f = bytes[1];
+        self.b = bytes[2];
+        self.c = bytes[3];
+        self.d = bytes[4];
+        self.e = bytes[5];
+        self.h = bytes[6];
+        self.l = bytes[7];
+        self.sp = u16::from_le_bytes([bytes[8], bytes[9]]);
+        self.pc = u16::from_le_bytes([bytes[10], bytes[11]]);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ExecutionMode {
+    Step,
+    Continue,
+}
+
+/// The emulator as a gdb target.
+struct GdbTarget<'a, M> {
+    cpu: &'a mut CPU,
+    registers: &'a mut Registers,
+    memory: &'a mut M,
+    mode: ExecutionMode,
+}
+
+impl<'a, M: MemoryBus> GdbTarget<'a, M> {
+    fn new(cpu: &'a mut CPU, registers: &'a mut Registers, memory: &'a mut M) -> Self {
+        Self {
+            cpu,
+            registers,
+            memory,
+            mode: ExecutionMode::Continue,
+        }
+    }
+
+    /// Executes according to the mode until the CPU stops or `interrupted` returns `true`.
+    fn execute(
+        &mut self,
+        mut interrupted: impl FnMut() -> bool,
+    ) -> Result<Option<SingleThreadStopReason<u16>>, CpuError> {
+        if self.mode == ExecutionMode::Step {
+            let stop = self.tick()?;
+            return Ok(Some(stop.unwrap_or(SingleThreadStopReason::DoneStep)));
+        }
+
+        loop {
+            for _ in 0..POLL_INTERVAL {
+                if let Some(stop) = self.tick()? {
+                    return Ok(Some(stop));
+                }
+            }
+
+            if interrupted() {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn tick(&mut self) -> Result<Option<SingleThreadStopReason<u16>>, CpuError> {
+        match self.cpu.tick(self.registers, self.memory) {
+            Ok(_) => Ok(None),
+            Err(CpuError::Break(Break::Breakpoint { .. })) => {
+                Ok(Some(SingleThreadStopReason::SwBreak(())))
+            }
+            Err(CpuError::Break(Break::Watchpoint { .. }))
+            | Err(CpuError::Break(Break::StackMismatch(_))) => {
+                Ok(Some(SingleThreadStopReason::Signal(Signal::SIGTRAP)))
+            }
+            Err(e) if matches!(e.root(), CpuError::UnknownInstruction(_)) => {
+                Ok(Some(SingleThreadStopReason::Signal(Signal::SIGILL)))
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl<M: MemoryBus> Target for GdbTarget<'_, M> {
+    type Arch = Sm83;
+    type Error = CpuError;
+
+    fn base_ops(&mut self) -> BaseOps<'_, Sm83, CpuError> {
+        BaseOps::SingleThread(self)
+    }
+
+    fn support_breakpoints(&mut self) -> Option<BreakpointsOps<'_, Self>> {
+        Some(self)
+    }
+}
+
+impl<M: MemoryBus> SingleThreadBase for GdbTarget<'_, M> {
+    fn read_registers(&mut self, regs: &mut Sm83Registers) -> TargetResult<(), Self> {
+        let single = |r| self.registers.get_single(&r);
+
+        *regs = Sm83Registers {
+            a: single(SingleRegister::A),
+            f: self.registers.get_flags(),
+            b: single(SingleRegister::B),
+            c: single(SingleRegister::C),
+            d: single(SingleRegister::D),
+            e: single(SingleRegister::E),
+            h: single(SingleRegister::H),
+            l: single(SingleRegister::L),
+            sp: self.registers.get_double(&DoubleRegister::SP),
+            pc: self.registers.get_double(&DoubleRegister::PC),
+        };
+        Ok(())
+    }
+
+    fn write_registers(&mut self, regs: &Sm83Registers) -> TargetResult<(), Self> {
+        self.registers
+            .set_double(&DoubleRegister::AF, u16::from_be_bytes([regs.a, regs.f]));
+        self.registers
+            .set_double(&DoubleRegister::BC, u16::from_be_bytes([regs.b, regs.c]));
+        self.registers
+            .set_double(&DoubleRegister::DE, u16::from_be_bytes([regs.d, regs.e]));
+        self.registers
+            .set_double(&DoubleRegister::HL, u16::from_be_bytes([regs.h, regs.l]));
+        self.registers.set_double(&DoubleRegister::SP, regs.sp);
+        self.registers.set_double(&DoubleRegister::PC, regs.pc);
+        Ok(())
+    }
+
+    fn read_addrs(&mut self, start_addr: u16, data: &mut [u8]) -> TargetResult<usize, Self> {
+        let length = data.len().min(0x10000 - start_addr as usize);
+
+        for (offset, byte) in data[..length].iter_mut().enumerate() {
+            *byte = self.memory.peek(start_addr as usize + offset);
+        }
+        Ok(length)
+    }
+
+    fn write_addrs(&mut self, start_addr: u16, data: &[u8]) -> TargetResult<(), Self> {
+        if start_addr as usize + data.len() > 0x10000 {
+            return Err(TargetError::NonFatal);
+        }
+
+        for (offset, byte) in data.iter().enumerate() {
+            self.memory.set(start_addr as usize + offset, *byte);
+        }
+        Ok(())
+    }
+
+    fn support_resume(&mut self) -> Option<SingleThreadResumeOps<'_, Self>> {
+        Some(self)
+    }
+}
+
+impl<M: MemoryBus> SingleThreadResume for GdbTarget<'_, M> {
+    fn resume(&mut self, _signal: Option<Signal>) -> Result<(), CpuError> {
+        self.mode = ExecutionMode::Continue;
+        Ok(())
+    }
+
+    fn support_single_step(&mut self) -> Option<SingleThreadSingleStepOps<'_, Self>> {
+        Some(self)
+    }
+}
+
+impl<M: MemoryBus> SingleThreadSingleStep for GdbTarget<'_, M> {
+    fn step(&mut self, _signal: Option<Signal>) -> Result<(), CpuError> {
+        self.mode = ExecutionMode::Step;
+        Ok(())
+    }
+}
+
+impl<M: MemoryBus> Breakpoints for GdbTarget<'_, M> {
+    fn support_sw_breakpoint(&mut self) -> Option<SwBreakpointOps<'_, Self>> {
+        Some(self)
+    }
+}
+
+impl<M: MemoryBus> SwBreakpoint for GdbTarget<'_, M> {
+    fn add_sw_breakpoint(&mut self, addr: u16, _kind: usize) -> TargetResult<bool, Self> {
+        self.cpu.debugger_mut().add_breakpoint(addr);
+        Ok(true)
+    }
+
+    fn remove_sw_breakpoint(&mut self, addr: u16, _kind: usize) -> TargetResult<bool, Self> {
+        Ok(self.cpu.debugger_mut().remove_breakpoint(addr))
+    }
+}
+
+struct EventLoop<C, T> {
+    _marker: std::marker::PhantomData<(C, T)>,
+}
+
+impl<'a, C: ConnectionExt, M: MemoryBus> run_blocking::BlockingEventLoop
+    for EventLoop<C, GdbTarget<'a, M>>
+{
+    type Target = GdbTarget<'a, M>;
+    type Connection = C;
+    type StopReason = SingleThreadStopReason<u16>;
+
+    fn wait_for_stop_reason(
+        target: &mut Self::Target,
+        conn: &mut C,
+    ) -> Result<
+        run_blocking::Event<Self::StopReason>,
+        run_blocking::WaitForStopReasonError<CpuError, <C as Connection>::Error>,
+    > {
+        let mut connection_error = None;
+        let stop = target
+            .execute(|| match conn.peek() {
+                Ok(data) => data.is_some(),
+                Err(e) => {
+                    connection_error = Some(e);
+                    true
+                }
+            })
+            .map_err(run_blocking::WaitForStopReasonError::Target)?;
+
+        if let Some(e) = connection_error {
+            return Err(run_blocking::WaitForStopReasonError::Connection(e));
+        }
+
+        match stop {
+            Some(stop) => Ok(run_blocking::Event::TargetStopped(stop)),
+            None => conn
+                .read()
+                .map(run_blocking::Event::IncomingData)
+                .map_err(run_blocking::WaitForStopReasonError::Connection),
+        }
+    }
+
+    fn on_interrupt(target: &mut Self::Target) -> Result<Option<Self::StopReason>, CpuError> {
+        target.mode = ExecutionMode::Continue;
+        Ok(Some(SingleThreadStopReason::Signal(Signal::SIGINT)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+
+    #[test]
+    fn registers_are_serialized_in_description_order() {
+        let registers = Sm83Registers {
+            a: 0x01,
+            f: 0xB0,
+            b: 0x02,
+            c: 0x03,
+            d: 0x04,
+            e: 0x05,
+            h: 0x06,
+            l: 0x07,
+            sp: 0xFFFE,
+            pc: 0x0150,
+        };
+        let mut bytes = vec![];
+        registers.gdb_serialize(|byte| bytes.push(byte.unwrap()));
+
+        assert_eq!(
+            vec![0x01, 0xB0, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0xFE, 0xFF, 0x50, 0x01],
+            bytes
+        );
+
+        let mut deserialized = Sm83Registers::default();
+        deserialized.gdb_deserialize(&bytes).unwrap();
+        assert_eq!(registers, deserialized);
+    }
+
+    #[test]
+    fn registers_and_memory_are_read_and_written() {
+        let mut cpu = CPU::new();
+        let mut registers = Registers::new();
+        let mut memory = Memory::new();
+        let mut target = GdbTarget::new(&mut cpu, &mut registers, &mut memory);
+
+        let mut regs = Sm83Registers::default();
+        assert!(target.read_registers(&mut regs).is_ok());
+        regs.a = 0x3C;
+        regs.pc = 0xC000;
+        assert!(target.write_registers(&regs).is_ok());
+        assert!(target.write_addrs(0xFFFE, &[0xAB, 0xCD]).is_ok());
+
+        let mut data = [0; 4];
+        assert_eq!(Some(2), target.read_addrs(0xFFFE, &mut data).ok());
+        assert_eq!([0xAB, 0xCD, 0x00, 0x00], data);
+        assert!(target.write_addrs(0xFFFF, &[0x00, 0x00]).is_err());
+
+        assert_eq!(0x3C, registers.get_single(&SingleRegister::A));
+        assert_eq!(0xC000, registers.PC);
+    }
+
+    #[test]
+    fn execution_stops_at_breakpoints_and_after_steps() {
+        let mut cpu = CPU::new();
+        let mut registers = Registers::new();
+        let mut memory = Memory::new();
+        let mut target = GdbTarget::new(&mut cpu, &mut registers, &mut memory);
+
+        assert!(target.add_sw_breakpoint(0x0003, 1).is_ok());
+        target.resume(None).unwrap();
+        assert_eq!(
+            Ok(Some(SingleThreadStopReason::SwBreak(()))),
+            target.execute(|| false)
+        );
+        assert_eq!(0x0003, target.registers.PC);
+
+        target.step(None).unwrap();
+        assert_eq!(
+            Ok(Some(SingleThreadStopReason::DoneStep)),
+            target.execute(|| false)
+        );
+        assert_eq!(0x0004, target.registers.PC);
+
+        assert_eq!(Some(true), target.remove_sw_breakpoint(0x0003, 1).ok());
+        target.resume(None).unwrap();
+        assert_eq!(Ok(None), target.execute(|| true));
+    }
+}
@@ -0,0 +1,50 @@
+//! # Instruction hooks
+//!
+//! An `InstructionHook` added with `CPU::add_hook` is called by `CPU::tick` before every
+//! instruction is decoded and after it is executed. Hooks can inspect and modify the registers
+//! and memory, e.g. for tracing, cheats or scripting.
+//!
+//! Hooks are called in the order they were added. Memory accesses of hooks don't take machine
+//! cycles, and are not seen by watchpoints.
+//!
+//! ```
+//! # use gejmboj_cpu::{cpu::CPU, hooks::InstructionHook, memory::{Memory, MemoryBus}};
+//! # use gejmboj_cpu::{instructions::Instruction, registers::*};
+//! /// Infinite lives: keeps the counter at C000 at 3
+//! struct Lives;
+//!
+//! impl InstructionHook for Lives {
+//!     fn after(&mut self, _pc: u16, _: &Instruction, _: &mut Registers, memory: &mut dyn MemoryBus) {
+//!         memory.set(0xC000, 3);
+//!     }
+//! }
+//!
+//! let mut cpu = CPU::new();
+//! let mut registers = Registers::new();
+//! let mut memory = Memory::new();
+//! cpu.add_hook(Box::new(Lives));
+//!
+//! cpu.tick(&mut registers, &mut memory).unwrap();
+//!
+//! assert_eq!(3, memory.get(0xC000));
+//! ```
+
+use crate::{instructions::Instruction, memory::MemoryBus, registers::Registers};
+
+/// Gets called around every instruction executed by `CPU::tick`, see module documentation.
+pub trait InstructionHook {
+    /// Called before the instruction at `pc` is decoded.
+    ///
+    /// Changing `PC` executes the instruction at the new address instead.
+    fn before(&mut self, _pc: u16, _registers: &mut Registers, _memory: &mut dyn MemoryBus) {}
+
+    /// Called after `instruction` at `pc` was executed.
+    fn after(
+        &mut self,
+        _pc: u16,
+        _instruction: &Instruction,
+        _registers: &mut Registers,
+        _memory: &mut dyn MemoryBus,
+    ) {
+    }
+}
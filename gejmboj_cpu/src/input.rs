@@ -0,0 +1,128 @@
+//! # Input queue
+//!
+//! Frontends push button events stamped with the frame they are meant for, instead of pressing
+//! buttons while a frame is emulated. `GameBoy::run_frame` latches the events due into the
+//! joypad before running the frame, so the game sees the same input at the same point no matter
+//! when the frontend delivered it, and a log of latched events replays a session exactly.
+//!
+//! Events for the same frame are latched in the order they were pushed. Events for a frame which
+//! already ran are latched at the next frame.
+//!
+//! ```
+//! # use gejmboj_cpu::{gameboy::GameBoy, input::InputEvent, joypad::Button, model::Model};
+//! let mut gameboy = GameBoy::new(Model::Dmg);
+//! gameboy.load_rom(vec![0; 0x8000]).unwrap();
+//! gameboy.enable_input_log();
+//!
+//! gameboy.queue_input(InputEvent::press(1, Button::A));
+//! gameboy.queue_input(InputEvent::release(2, Button::A));
+//!
+//! gameboy.run_frame().unwrap();
+//! assert!(!gameboy.memory().io().joypad().is_pressed(Button::A));
+//! gameboy.run_frame().unwrap();
+//! assert!(gameboy.memory().io().joypad().is_pressed(Button::A));
+//! gameboy.run_frame().unwrap();
+//!
+//! assert_eq!(2, gameboy.take_input_log().unwrap().len());
+//! ```
+
+use std::collections::VecDeque;
+
+use crate::joypad::Button;
+
+/// A button pressed or released at the start of a frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InputEvent {
+    /// Number of the frame, counted from `0` since the cartridge was loaded
+    pub frame: u64,
+    pub button: Button,
+    pub pressed: bool,
+}
+
+impl InputEvent {
+    pub fn press(frame: u64, button: Button) -> Self {
+        Self {
+            frame,
+            button,
+            pressed: true,
+        }
+    }
+
+    pub fn release(frame: u64, button: Button) -> Self {
+        Self {
+            frame,
+            button,
+            pressed: false,
+        }
+    }
+}
+
+/// Pending input events ordered by frame, see module documentation.
+#[derive(Debug, Clone, Default)]
+pub struct InputQueue {
+    events: VecDeque<InputEvent>,
+}
+
+impl InputQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `event` after all events of the same or an earlier frame.
+    pub fn push(&mut self, event: InputEvent) {
+        let index = self.events.partition_point(|x| x.frame <= event.frame);
+        self.events.insert(index, event);
+    }
+
+    /// Removes and returns the events due at `frame`, stamped with `frame`.
+    pub fn latch(&mut self, frame: u64) -> Vec<InputEvent> {
+        let due = self.events.partition_point(|x| x.frame <= frame);
+        self.events
+            .drain(..due)
+            .map(|event| InputEvent { frame, ..event })
+            .collect()
+    }
+
+    /// Returns the pending events, earliest first.
+    pub fn events(&self) -> impl Iterator<Item = &InputEvent> {
+        self.events.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Drops all pending events.
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_latched_by_frame_in_push_order() {
+        let mut queue = InputQueue::new();
+        queue.push(InputEvent::press(3, Button::B));
+        queue.push(InputEvent::press(1, Button::A));
+        queue.push(InputEvent::release(1, Button::A));
+        queue.push(InputEvent::press(0, Button::Start));
+
+        assert_eq!(vec![InputEvent::press(0, Button::Start)], queue.latch(0));
+        assert!(queue.latch(0).is_empty());
+        assert_eq!(
+            vec![
+                InputEvent::press(2, Button::A),
+                InputEvent::release(2, Button::A)
+            ],
+            queue.latch(2)
+        );
+        assert_eq!(1, queue.len());
+    }
+}
@@ -1,36 +1,123 @@
 //! Sharp SM83 instruction set
 
-use crate::combine_instructions;
-use crate::{errors::CpuError, memory::Memory, registers::Registers};
+use std::fmt::Display;
+
+use crate::{combine_instructions, opcode_table};
+use crate::{
+    errors::CpuError,
+    memory::MemoryBus,
+    registers::{DoubleRegister, Registers, SingleRegister},
+};
 
 pub mod alu_16bit;
 pub mod alu_8bit;
 pub mod bit;
 pub mod control_flow;
+pub mod flag_effects;
 pub mod load_16bit;
 pub mod load_8bit;
 pub mod misc;
+pub mod opcodes;
+pub mod operand;
 pub mod rotate_shift;
-mod utils;
+pub(crate) mod utils;
 
 use alu_16bit::ALU16Bit;
 use alu_8bit::ALU8Bit;
 use bit::Bit;
 use control_flow::ControlFlow;
+use flag_effects::FlagEffects;
 use load_16bit::Load16Bit;
 use load_8bit::Load8Bit;
 use misc::Misc;
+pub use operand::Operand;
 use rotate_shift::RotateShift;
-use utils::into_bits;
 
 /// Return either the number of consumed machine cycles, or a `CpuError`.
 pub type InstructionResult = Result<u16, CpuError>;
 
+/// Outcome of executing an instruction, see `Instruction::execute`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Execution {
+    /// Consumed machine cycles
+    pub cycles: u16,
+    /// Whether the `Condition` of a conditional jump, call or return was fulfilled, `None` for
+    /// other instructions
+    pub branch_taken: Option<bool>,
+}
+
 combine_instructions! {
     Instruction(ALU16Bit, ALU8Bit, Bit, ControlFlow, Load8Bit, Load16Bit, Misc, RotateShift)
 }
 
-#[derive(Debug, PartialEq)]
+impl Instruction {
+    /// Returns `true` for jumps, calls, returns and restarts.
+    pub fn is_control_flow(&self) -> bool {
+        matches!(self, Instruction::ControlFlow(_))
+    }
+
+    /// Returns `true` for `CALL` and `RST`, the instructions a debugger steps over.
+    pub fn is_call(&self) -> bool {
+        matches!(
+            self,
+            Instruction::ControlFlow(
+                ControlFlow::CALL(_) | ControlFlow::CALLC(..) | ControlFlow::RST(_)
+            )
+        )
+    }
+
+    /// Returns `true` for the opcodes locking up the CPU, see `misc::ILLEGAL_OPCODES`.
+    pub fn is_illegal(&self) -> bool {
+        matches!(self, Instruction::Misc(Misc::ILLEGAL(_)))
+    }
+
+    /// Returns `true` for 8-bit and 16-bit loads, `PUSH` and `POP` included.
+    pub fn is_load(&self) -> bool {
+        matches!(self, Instruction::Load8Bit(_) | Instruction::Load16Bit(_))
+    }
+
+    /// Returns `true` for control flow depending on a `Condition`.
+    pub fn is_conditional(&self) -> bool {
+        self.condition().is_some()
+    }
+
+    /// Returns the `Condition` of a conditional jump, call or return.
+    pub fn condition(&self) -> Option<Condition> {
+        match self {
+            Instruction::ControlFlow(
+                ControlFlow::JPC(_, condition)
+                | ControlFlow::JRC(_, condition)
+                | ControlFlow::CALLC(_, condition)
+                | ControlFlow::RETC(condition),
+            ) => Some(*condition),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if executing the instruction may write to memory, the stack included.
+    pub fn writes_memory(&self) -> bool {
+        match self {
+            Instruction::ALU8Bit(instruction) => {
+                matches!(instruction, ALU8Bit::INC_HL() | ALU8Bit::DEC_HL())
+            }
+            Instruction::Bit(Bit::SET(_, target) | Bit::RES(_, target)) => {
+                *target == Target::HLIndirect
+            }
+            Instruction::RotateShift(instruction) => {
+                instruction.operands().contains(&Operand::HLIndirect)
+            }
+            Instruction::Load8Bit(instruction) => {
+                !matches!(instruction.operands().first(), Some(Operand::Reg8(_)))
+            }
+            Instruction::Load16Bit(instruction) => {
+                matches!(instruction, Load16Bit::LD_FROM_SP(_) | Load16Bit::PUSH(_))
+            }
+            _ => self.is_call(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Condition {
     Carry,
     NoCarry,
@@ -52,6 +139,16 @@ impl Condition {
         }
     }
 
+    /// Returns the 2 bit code of the condition used in opcodes, the inverse of `parse`.
+    pub fn code(&self) -> u8 {
+        match self {
+            Condition::Carry => 0b00,
+            Condition::NoCarry => 0b01,
+            Condition::Zero => 0b10,
+            Condition::NotZero => 0b11,
+        }
+    }
+
     pub fn is_fulfilled(&self, registers: &Registers) -> bool {
         match self {
             Condition::Carry => registers.is_carry(),
@@ -62,186 +159,281 @@ impl Condition {
     }
 }
 
-fn get_8bit_operand(pc: u16, memory: &Memory) -> u8 {
+impl Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Condition::Carry => "C",
+            Condition::NoCarry => "NC",
+            Condition::Zero => "Z",
+            Condition::NotZero => "NZ",
+        })
+    }
+}
+
+/// Target of `CB` prefixed instructions, designated by the lowest 3 bits of the operand:
+/// a `SingleRegister` or `110` for `(HL)`, the memory contents pointed to by HL.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    Register(SingleRegister),
+    HLIndirect,
+}
+
+impl Target {
+    /// Returns the 3 bit code of the target used in operands, the inverse of `from`.
+    pub fn code(&self) -> u8 {
+        match self {
+            Target::Register(r) => utils::single_register_code(r),
+            Target::HLIndirect => 0b110,
+        }
+    }
+
+    /// Returns the machine cycles of a `CB` prefixed instruction writing its result back to the
+    /// target.
+    pub fn read_modify_write_cycles(&self) -> u16 {
+        match self {
+            Target::Register(_) => 2,
+            Target::HLIndirect => 4,
+        }
+    }
+
+    pub fn get(&self, registers: &Registers, memory: &impl MemoryBus) -> u8 {
+        match self {
+            Target::Register(r) => registers.get_single(r),
+            Target::HLIndirect => memory.get(registers.get_double(&DoubleRegister::HL).into()),
+        }
+    }
+
+    pub fn set(&self, registers: &mut Registers, memory: &mut impl MemoryBus, value: u8) {
+        match self {
+            Target::Register(r) => registers.set_single(r, value),
+            Target::HLIndirect => {
+                memory.set(registers.get_double(&DoubleRegister::HL).into(), value)
+            }
+        }
+    }
+}
+
+impl From<u8> for Target {
+    /// Resolves the lowest 3 bits of `operand`.
+    fn from(operand: u8) -> Self {
+        match operand & 0b111 {
+            0b110 => Target::HLIndirect,
+            r => Target::Register(((r >> 2) & 1, (r >> 1) & 1, r & 1).into()),
+        }
+    }
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Target::Register(r) => write!(f, "{}", r),
+            Target::HLIndirect => f.write_str("(HL)"),
+        }
+    }
+}
+
+fn get_8bit_operand(pc: u16, memory: &impl MemoryBus) -> u8 {
     memory.get((pc as usize) + 1)
 }
 
-fn get_16bit_operand(pc: u16, memory: &Memory) -> u16 {
+fn get_16bit_operand(pc: u16, memory: &impl MemoryBus) -> u16 {
     memory.get_u16((pc as usize) + 1)
 }
 
-/// Decode an operation code into an `Instruction`.
-pub fn decode(opcode: u8, pc: u16, memory: &Memory) -> Result<Instruction, CpuError> {
-    match into_bits(opcode) {
-        // ABSOLUTE MATCHES
-        //
+/// Bytes to decode, read as memory starting at address `0`.
+struct Bytes<'a>(&'a [u8]);
+
+impl MemoryBus for Bytes<'_> {
+    fn get(&self, location: usize) -> u8 {
+        self.0.get(location).copied().unwrap_or_default()
+    }
+
+    fn set(&mut self, _location: usize, _value: u8) {}
+}
+
+/// Decode the instruction at the start of `bytes`, returning it with its length in bytes.
+pub fn decode_bytes(bytes: &[u8]) -> Result<(Instruction, usize), CpuError> {
+    let opcode = *bytes
+        .first()
+        .ok_or_else(|| CpuError::Error("No bytes to decode".to_string()))?;
+    let (instruction, length) = decode(opcode, 0, &Bytes(bytes))?;
+
+    if bytes.len() < length {
+        return Err(CpuError::Error(format!(
+            "Instruction {:02x?} is truncated, expected {} bytes",
+            bytes, length
+        )));
+    }
+
+    Ok((instruction, length))
+}
+
+/// Decode an operation code into an `Instruction`, returning it with its size in bytes including
+/// the `CB` prefix and immediate operands.
+pub fn decode(
+    opcode: u8,
+    pc: u16,
+    memory: &impl MemoryBus,
+) -> Result<(Instruction, usize), CpuError> {
+    let instruction = decode_instruction(opcode, pc, memory)?;
+    let size = match opcode {
+        0xCB => 2,
+        _ => instruction.length() as usize,
+    };
+
+    Ok((instruction, size))
+}
+
+/// Decode the operand of a `CB` prefixed instruction into a `RotateShift` or `Bit` instruction.
+pub fn decode_cb(operand: u8) -> Result<Instruction, CpuError> {
+    rotate_shift::decode(operand)
+        .map(Instruction::RotateShift)
+        .or_else(|_| bit::decode(operand).map(Instruction::Bit))
+}
+
+opcode_table! {
+    /// Decodes an unprefixed opcode, other than an illegal one or the `CB` prefix.
+    fn decode_unprefixed(opcode, pc, memory) -> Instruction;
+
+    /// The patterns of the unprefixed opcodes the decoder matches in order, see
+    /// `opcodes::pattern`.
+    const OPCODE_PATTERNS;
+
+    {
         // misc
-        (0, 0, 0, 0, 0, 0, 0, 0) => Ok(Instruction::Misc(Misc::NOP())),
-        (1, 1, 1, 1, 0, 0, 1, 1) => Ok(Instruction::Misc(Misc::DI())),
-        (1, 1, 1, 1, 1, 0, 1, 1) => Ok(Instruction::Misc(Misc::EI())),
-        (0, 0, 1, 1, 1, 1, 1, 1) => Ok(Instruction::Misc(Misc::CCF())),
-        (0, 0, 1, 1, 0, 1, 1, 1) => Ok(Instruction::Misc(Misc::SCF())),
-        (0, 0, 1, 0, 0, 1, 1, 1) => Ok(Instruction::Misc(Misc::DAA())),
-        (0, 0, 1, 0, 1, 1, 1, 1) => Ok(Instruction::Misc(Misc::CPL())),
+        (0, 0, 0, 0, 0, 0, 0, 0) "NOP" => Misc::NOP(),
+        (0, 1, 1, 1, 0, 1, 1, 0) "HALT" => Misc::HALT(),
+        (1, 1, 1, 1, 0, 0, 1, 1) "DI" => Misc::DI(),
+        (1, 1, 1, 1, 1, 0, 1, 1) "EI" => Misc::EI(),
+        (0, 0, 1, 1, 1, 1, 1, 1) "CCF" => Misc::CCF(),
+        (0, 0, 1, 1, 0, 1, 1, 1) "SCF" => Misc::SCF(),
+        (0, 0, 1, 0, 0, 1, 1, 1) "DAA" => Misc::DAA(),
+        (0, 0, 1, 0, 1, 1, 1, 1) "CPL" => Misc::CPL(),
 
         // control flow
-        (1, 1, 0, 0, 0, 0, 1, 1) => Ok(Instruction::ControlFlow(ControlFlow::JP(
-            get_16bit_operand(pc, memory),
-        ))),
-        (1, 1, 0, 0, 1, 0, 0, 1) => Ok(Instruction::ControlFlow(ControlFlow::RET())),
-        (1, 1, 0, 1, 1, 0, 0, 1) => Ok(Instruction::ControlFlow(ControlFlow::RETI())),
-        (1, 1, 0, 0, 1, 1, 0, 1) => Ok(Instruction::ControlFlow(ControlFlow::CALL(
-            get_16bit_operand(pc, memory),
-        ))),
-        (1, 1, 1, 0, 1, 0, 0, 1) => Ok(Instruction::ControlFlow(ControlFlow::JP_HL())),
-        (0, 0, 0, 1, 1, 0, 0, 0) => Ok(Instruction::ControlFlow(ControlFlow::JR(
-            get_8bit_operand(pc, memory),
-        ))),
+        (1, 1, 0, 0, 0, 0, 1, 1) "JP" => ControlFlow::JP(get_16bit_operand(pc, memory)),
+        (1, 1, 0, 0, 1, 0, 0, 1) "RET" => ControlFlow::RET(),
+        (1, 1, 0, 1, 1, 0, 0, 1) "RETI" => ControlFlow::RETI(),
+        (1, 1, 0, 0, 1, 1, 0, 1) "CALL" => ControlFlow::CALL(get_16bit_operand(pc, memory)),
+        (1, 1, 1, 0, 1, 0, 0, 1) "JP" => ControlFlow::JP_HL(),
+        (0, 0, 0, 1, 1, 0, 0, 0) "JR" => ControlFlow::JR(get_8bit_operand(pc, memory)),
 
         // 8 bit load instructions
-        (0, 0, 0, 0, 1, 0, 1, 0) => Ok(Instruction::Load8Bit(Load8Bit::LD_BC_TO_A())),
-        (0, 0, 0, 1, 1, 0, 1, 0) => Ok(Instruction::Load8Bit(Load8Bit::LD_DE_TO_A())),
-        (0, 0, 0, 0, 0, 0, 1, 0) => Ok(Instruction::Load8Bit(Load8Bit::LD_A_TO_BC())),
-        (0, 0, 0, 1, 0, 0, 1, 0) => Ok(Instruction::Load8Bit(Load8Bit::LD_A_TO_DE())),
-        (1, 1, 1, 1, 1, 0, 1, 0) => Ok(Instruction::Load8Bit(Load8Bit::LD_TO_A(
-            get_16bit_operand(pc, memory),
-        ))),
-        (1, 1, 1, 1, 0, 0, 1, 0) => Ok(Instruction::Load8Bit(Load8Bit::LDH_C_TO_A())),
-        (1, 1, 1, 0, 0, 0, 1, 0) => Ok(Instruction::Load8Bit(Load8Bit::LDH_C_FROM_A())),
-        (1, 1, 1, 1, 0, 0, 0, 0) => Ok(Instruction::Load8Bit(Load8Bit::LDH_TO_A(
-            get_8bit_operand(pc, memory),
-        ))),
-        (1, 1, 1, 0, 0, 0, 0, 0) => Ok(Instruction::Load8Bit(Load8Bit::LDH_FROM_A(
+        (0, 0, 0, 0, 1, 0, 1, 0) "LD" => Load8Bit::LD_BC_TO_A(),
+        (0, 0, 0, 1, 1, 0, 1, 0) "LD" => Load8Bit::LD_DE_TO_A(),
+        (0, 0, 0, 0, 0, 0, 1, 0) "LD" => Load8Bit::LD_A_TO_BC(),
+        (0, 0, 0, 1, 0, 0, 1, 0) "LD" => Load8Bit::LD_A_TO_DE(),
+        (1, 1, 1, 1, 1, 0, 1, 0) "LD" => Load8Bit::LD_TO_A(get_16bit_operand(pc, memory)),
+        (1, 1, 1, 1, 0, 0, 1, 0) "LDH" => Load8Bit::LDH_C_TO_A(),
+        (1, 1, 1, 0, 0, 0, 1, 0) "LDH" => Load8Bit::LDH_C_FROM_A(),
+        (1, 1, 1, 1, 0, 0, 0, 0) "LDH" => Load8Bit::LDH_TO_A(get_8bit_operand(pc, memory)),
+        (1, 1, 1, 0, 0, 0, 0, 0) "LDH" => Load8Bit::LDH_FROM_A(get_8bit_operand(pc, memory)),
+        (1, 1, 1, 0, 1, 0, 1, 0) "LD" => Load8Bit::LD_FROM_A(get_16bit_operand(pc, memory)),
+        (0, 0, 1, 1, 1, 0, 1, 0) "LD" => Load8Bit::LD_A_FROM_HL_DEC(),
+        (0, 0, 1, 1, 0, 0, 1, 0) "LD" => Load8Bit::LD_A_TO_HL_DEC(),
+        (0, 0, 1, 0, 1, 0, 1, 0) "LD" => Load8Bit::LD_A_FROM_HL_INC(),
+        (0, 0, 1, 0, 0, 0, 1, 0) "LD" => Load8Bit::LD_A_TO_HL_INC(),
+        (0, 0, 0, 0, 1, 0, 0, 0) "LD" => Load16Bit::LD_FROM_SP(get_16bit_operand(pc, memory)),
+        (1, 1, 1, 1, 1, 0, 0, 1) "LD" => Load16Bit::LD_HL_TO_SP(),
+        (1, 1, 1, 1, 1, 0, 0, 0) "LD" => Load16Bit::LD_SP_OFFSET_TO_HL(
             get_8bit_operand(pc, memory),
-        ))),
-        (1, 1, 1, 0, 1, 0, 1, 0) => Ok(Instruction::Load8Bit(Load8Bit::LD_FROM_A(
-            get_16bit_operand(pc, memory),
-        ))),
-        (0, 0, 1, 1, 1, 0, 1, 0) => Ok(Instruction::Load8Bit(Load8Bit::LD_A_FROM_HL_DEC())),
-        (0, 0, 1, 1, 0, 0, 1, 0) => Ok(Instruction::Load8Bit(Load8Bit::LD_A_TO_HL_DEC())),
-        (0, 0, 1, 0, 1, 0, 1, 0) => Ok(Instruction::Load8Bit(Load8Bit::LD_A_FROM_HL_INC())),
-        (0, 0, 1, 0, 0, 0, 1, 0) => Ok(Instruction::Load8Bit(Load8Bit::LD_A_TO_HL_INC())),
-        (0, 0, 0, 0, 1, 0, 0, 0) => Ok(Instruction::Load16Bit(Load16Bit::LD_FROM_SP(
-            get_16bit_operand(pc, memory),
-        ))),
-        (1, 1, 1, 1, 1, 0, 0, 1) => Ok(Instruction::Load16Bit(Load16Bit::LD_HL_TO_SP())),
+        ),
 
         // ALU 8-bit instructions
-        (1, 0, 0, 0, 0, 1, 1, 0) => Ok(Instruction::ALU8Bit(ALU8Bit::ADD_HL())),
-        (1, 1, 0, 0, 0, 1, 1, 0) => Ok(Instruction::ALU8Bit(ALU8Bit::ADD_N(get_8bit_operand(
-            pc, memory,
-        )))),
-        (1, 0, 0, 0, 1, 1, 1, 0) => Ok(Instruction::ALU8Bit(ALU8Bit::ADC_HL())),
-        (1, 1, 0, 0, 1, 1, 1, 0) => Ok(Instruction::ALU8Bit(ALU8Bit::ADC_N(get_8bit_operand(
-            pc, memory,
-        )))),
-        (1, 0, 0, 1, 0, 1, 1, 0) => Ok(Instruction::ALU8Bit(ALU8Bit::SUB_HL())),
-        (1, 1, 0, 1, 0, 1, 1, 0) => Ok(Instruction::ALU8Bit(ALU8Bit::SUB_N(get_8bit_operand(
-            pc, memory,
-        )))),
-        (1, 0, 0, 1, 1, 1, 1, 0) => Ok(Instruction::ALU8Bit(ALU8Bit::SBC_HL())),
-        (1, 1, 0, 1, 1, 1, 1, 0) => Ok(Instruction::ALU8Bit(ALU8Bit::SBC_N(get_8bit_operand(
-            pc, memory,
-        )))),
-        (1, 0, 1, 0, 0, 1, 1, 0) => Ok(Instruction::ALU8Bit(ALU8Bit::AND_HL())),
-        (1, 1, 1, 0, 0, 1, 1, 0) => Ok(Instruction::ALU8Bit(ALU8Bit::AND_N(get_8bit_operand(
-            pc, memory,
-        )))),
-        (1, 0, 1, 1, 0, 1, 1, 0) => Ok(Instruction::ALU8Bit(ALU8Bit::OR_HL())),
-        (1, 1, 1, 1, 0, 1, 1, 0) => Ok(Instruction::ALU8Bit(ALU8Bit::OR_N(get_8bit_operand(
-            pc, memory,
-        )))),
-        (1, 0, 1, 0, 1, 1, 1, 0) => Ok(Instruction::ALU8Bit(ALU8Bit::XOR_HL())),
-        (1, 1, 1, 0, 1, 1, 1, 0) => Ok(Instruction::ALU8Bit(ALU8Bit::XOR_N(get_8bit_operand(
-            pc, memory,
-        )))),
-        (1, 0, 1, 1, 1, 1, 1, 0) => Ok(Instruction::ALU8Bit(ALU8Bit::CP_HL())),
-        (1, 1, 1, 1, 1, 1, 1, 0) => Ok(Instruction::ALU8Bit(ALU8Bit::CP_N(get_8bit_operand(
-            pc, memory,
-        )))),
-        (0, 0, 1, 1, 0, 1, 0, 0) => Ok(Instruction::ALU8Bit(ALU8Bit::INC_HL())),
-        (0, 0, 1, 1, 0, 1, 0, 1) => Ok(Instruction::ALU8Bit(ALU8Bit::DEC_HL())),
+        (1, 0, 0, 0, 0, 1, 1, 0) "ADD" => ALU8Bit::ADD_HL(),
+        (1, 1, 0, 0, 0, 1, 1, 0) "ADD" => ALU8Bit::ADD_N(get_8bit_operand(pc, memory)),
+        (1, 0, 0, 0, 1, 1, 1, 0) "ADC" => ALU8Bit::ADC_HL(),
+        (1, 1, 0, 0, 1, 1, 1, 0) "ADC" => ALU8Bit::ADC_N(get_8bit_operand(pc, memory)),
+        (1, 0, 0, 1, 0, 1, 1, 0) "SUB" => ALU8Bit::SUB_HL(),
+        (1, 1, 0, 1, 0, 1, 1, 0) "SUB" => ALU8Bit::SUB_N(get_8bit_operand(pc, memory)),
+        (1, 0, 0, 1, 1, 1, 1, 0) "SBC" => ALU8Bit::SBC_HL(),
+        (1, 1, 0, 1, 1, 1, 1, 0) "SBC" => ALU8Bit::SBC_N(get_8bit_operand(pc, memory)),
+        (1, 0, 1, 0, 0, 1, 1, 0) "AND" => ALU8Bit::AND_HL(),
+        (1, 1, 1, 0, 0, 1, 1, 0) "AND" => ALU8Bit::AND_N(get_8bit_operand(pc, memory)),
+        (1, 0, 1, 1, 0, 1, 1, 0) "OR" => ALU8Bit::OR_HL(),
+        (1, 1, 1, 1, 0, 1, 1, 0) "OR" => ALU8Bit::OR_N(get_8bit_operand(pc, memory)),
+        (1, 0, 1, 0, 1, 1, 1, 0) "XOR" => ALU8Bit::XOR_HL(),
+        (1, 1, 1, 0, 1, 1, 1, 0) "XOR" => ALU8Bit::XOR_N(get_8bit_operand(pc, memory)),
+        (1, 0, 1, 1, 1, 1, 1, 0) "CP" => ALU8Bit::CP_HL(),
+        (1, 1, 1, 1, 1, 1, 1, 0) "CP" => ALU8Bit::CP_N(get_8bit_operand(pc, memory)),
+        (0, 0, 1, 1, 0, 1, 0, 0) "INC" => ALU8Bit::INC_HL(),
+        (0, 0, 1, 1, 0, 1, 0, 1) "DEC" => ALU8Bit::DEC_HL(),
 
         // ALU 16-bit instructions
-        (1, 1, 1, 0, 1, 0, 0, 0) => Ok(Instruction::ALU16Bit(ALU16Bit::ADD_SP(get_8bit_operand(
-            pc, memory,
-        )))),
+        (1, 1, 1, 0, 1, 0, 0, 0) "ADD" => ALU16Bit::ADD_SP(get_8bit_operand(pc, memory)),
 
         // Rotate Shift instructions
-        (0, 0, 0, 0, 0, 1, 1, 1) => Ok(Instruction::RotateShift(RotateShift::RLCA())),
-        (0, 0, 0, 0, 1, 1, 1, 1) => Ok(Instruction::RotateShift(RotateShift::RRCA())),
-        (0, 0, 0, 1, 0, 1, 1, 1) => Ok(Instruction::RotateShift(RotateShift::RLA())),
-        (0, 0, 0, 1, 1, 1, 1, 1) => Ok(Instruction::RotateShift(RotateShift::RRA())),
-        (1, 1, 0, 0, 1, 0, 1, 1) => {
-            let operand = get_8bit_operand(pc, memory);
-
-            rotate_shift::decode(operand)
-                .map(|op| Instruction::RotateShift(op))
-                .or_else(|_| bit::decode(operand).map(|op| Instruction::Bit(op)))
-        }
+        (0, 0, 0, 0, 0, 1, 1, 1) "RLCA" => RotateShift::RLCA(),
+        (0, 0, 0, 0, 1, 1, 1, 1) "RRCA" => RotateShift::RRCA(),
+        (0, 0, 0, 1, 0, 1, 1, 1) "RLA" => RotateShift::RLA(),
+        (0, 0, 0, 1, 1, 1, 1, 1) "RRA" => RotateShift::RRA(),
 
-        // VARIABLE MATCHES
-        //
         // control flow
-        (1, 1, 0, c, d, 0, 1, 0) => Ok(Instruction::ControlFlow(ControlFlow::JPC(
+        (1, 1, 0, c, d, 0, 1, 0) "JP" => ControlFlow::JPC(
             get_16bit_operand(pc, memory),
             Condition::parse(c, d).unwrap(),
-        ))),
-        (0, 0, 1, c, d, 0, 0, 0) => Ok(Instruction::ControlFlow(ControlFlow::JRC(
+        ),
+        (0, 0, 1, c, d, 0, 0, 0) "JR" => ControlFlow::JRC(
             get_8bit_operand(pc, memory),
             Condition::parse(c, d).unwrap(),
-        ))),
-        (1, 1, 0, c, d, 1, 0, 0) => Ok(Instruction::ControlFlow(ControlFlow::CALLC(
+        ),
+        (1, 1, 0, c, d, 1, 0, 0) "CALL" => ControlFlow::CALLC(
             get_16bit_operand(pc, memory),
             Condition::parse(c, d).unwrap(),
-        ))),
-        (1, 1, 0, c, d, 0, 0, 0) => Ok(Instruction::ControlFlow(ControlFlow::RETC(
-            Condition::parse(c, d).unwrap(),
-        ))),
-        (1, 1, _, _, _, 1, 1, 1) => Ok(Instruction::ControlFlow(ControlFlow::RST(opcode))),
+        ),
+        (1, 1, 0, c, d, 0, 0, 0) "RET" => ControlFlow::RETC(Condition::parse(c, d).unwrap()),
+        (1, 1, _, _, _, 1, 1, 1) "RST" => ControlFlow::RST(opcode),
 
         // 8 bit load instructions
-        (0, 1, a, b, c, 1, 1, 0) => Ok(Instruction::Load8Bit(Load8Bit::LD_FROM_HL(
-            (a, b, c).into(),
-        ))),
-        (0, 1, 1, 1, 0, a, b, c) => Ok(Instruction::Load8Bit(Load8Bit::LD_TO_HL((a, b, c).into()))),
-        (0, 1, a, b, c, x, y, z) => Ok(Instruction::Load8Bit(Load8Bit::LD(
-            (a, b, c).into(),
-            (x, y, z).into(),
-        ))),
+        (0, 1, a, b, c, 1, 1, 0) "LD" => Load8Bit::LD_FROM_HL((a, b, c).into()),
+        (0, 1, 1, 1, 0, a, b, c) "LD" => Load8Bit::LD_TO_HL((a, b, c).into()),
+        (0, 1, a, b, c, x, y, z) "LD" => Load8Bit::LD((a, b, c).into(), (x, y, z).into()),
 
         // 16 bit load instructions
-        (0, 0, a, b, 0, 0, 0, 1) => Ok(Instruction::Load16Bit(Load16Bit::LD(
+        (0, 0, a, b, 0, 0, 0, 1) "LD" => Load16Bit::LD(
             (0, a, b).into(),
             get_16bit_operand(pc, memory),
-        ))),
-        (1, 1, a, b, 0, 1, 0, 1) => Ok(Instruction::Load16Bit(Load16Bit::PUSH((1, a, b).into()))),
-        (1, 1, a, b, 0, 0, 0, 1) => Ok(Instruction::Load16Bit(Load16Bit::POP((1, a, b).into()))),
+        ),
+        (1, 1, a, b, 0, 1, 0, 1) "PUSH" => Load16Bit::PUSH((1, a, b).into()),
+        (1, 1, a, b, 0, 0, 0, 1) "POP" => Load16Bit::POP((1, a, b).into()),
 
         // ALU 8-bit instructions
-        (1, 0, 0, 0, 0, a, b, c) => Ok(Instruction::ALU8Bit(ALU8Bit::ADD((a, b, c).into()))),
-        (1, 0, 0, 0, 1, a, b, c) => Ok(Instruction::ALU8Bit(ALU8Bit::ADC((a, b, c).into()))),
-        (1, 0, 0, 1, 0, a, b, c) => Ok(Instruction::ALU8Bit(ALU8Bit::SUB((a, b, c).into()))),
-        (1, 0, 0, 1, 1, a, b, c) => Ok(Instruction::ALU8Bit(ALU8Bit::SBC((a, b, c).into()))),
-        (1, 0, 1, 0, 0, a, b, c) => Ok(Instruction::ALU8Bit(ALU8Bit::AND((a, b, c).into()))),
-        (1, 0, 1, 1, 0, a, b, c) => Ok(Instruction::ALU8Bit(ALU8Bit::OR((a, b, c).into()))),
-        (1, 0, 1, 0, 1, a, b, c) => Ok(Instruction::ALU8Bit(ALU8Bit::XOR((a, b, c).into()))),
-        (1, 0, 1, 1, 1, a, b, c) => Ok(Instruction::ALU8Bit(ALU8Bit::CP((a, b, c).into()))),
-        (0, 0, a, b, c, 1, 0, 0) => Ok(Instruction::ALU8Bit(ALU8Bit::INC((a, b, c).into()))),
-        (0, 0, a, b, c, 1, 0, 1) => Ok(Instruction::ALU8Bit(ALU8Bit::DEC((a, b, c).into()))),
+        (1, 0, 0, 0, 0, a, b, c) "ADD" => ALU8Bit::ADD((a, b, c).into()),
+        (1, 0, 0, 0, 1, a, b, c) "ADC" => ALU8Bit::ADC((a, b, c).into()),
+        (1, 0, 0, 1, 0, a, b, c) "SUB" => ALU8Bit::SUB((a, b, c).into()),
+        (1, 0, 0, 1, 1, a, b, c) "SBC" => ALU8Bit::SBC((a, b, c).into()),
+        (1, 0, 1, 0, 0, a, b, c) "AND" => ALU8Bit::AND((a, b, c).into()),
+        (1, 0, 1, 1, 0, a, b, c) "OR" => ALU8Bit::OR((a, b, c).into()),
+        (1, 0, 1, 0, 1, a, b, c) "XOR" => ALU8Bit::XOR((a, b, c).into()),
+        (1, 0, 1, 1, 1, a, b, c) "CP" => ALU8Bit::CP((a, b, c).into()),
+        (0, 0, a, b, c, 1, 0, 0) "INC" => ALU8Bit::INC((a, b, c).into()),
+        (0, 0, a, b, c, 1, 0, 1) "DEC" => ALU8Bit::DEC((a, b, c).into()),
 
         // ALU 16-bit instructions
-        (0, 0, b, c, 1, 0, 0, 1) => Ok(Instruction::ALU16Bit(ALU16Bit::ADD_HL((0, b, c).into()))),
-        (0, 0, b, c, 0, 0, 1, 1) => Ok(Instruction::ALU16Bit(ALU16Bit::INC((0, b, c).into()))),
-        (0, 0, b, c, 1, 0, 1, 1) => Ok(Instruction::ALU16Bit(ALU16Bit::DEC((0, b, c).into()))),
+        (0, 0, b, c, 1, 0, 0, 1) "ADD" => ALU16Bit::ADD_HL((0, b, c).into()),
+        (0, 0, b, c, 0, 0, 1, 1) "INC" => ALU16Bit::INC((0, b, c).into()),
+        (0, 0, b, c, 1, 0, 1, 1) "DEC" => ALU16Bit::DEC((0, b, c).into()),
+    }
+}
 
-        // Catch all
-        _ => Err(CpuError::UnknownInstruction(opcode)),
+fn decode_instruction(
+    opcode: u8,
+    pc: u16,
+    memory: &impl MemoryBus,
+) -> Result<Instruction, CpuError> {
+    if misc::ILLEGAL_OPCODES.contains(&opcode) {
+        return Ok(Instruction::Misc(Misc::ILLEGAL(opcode)));
     }
+    if opcode == 0xCB {
+        return decode_cb(get_8bit_operand(pc, memory));
+    }
+
+    decode_unprefixed(opcode, pc, memory).ok_or(CpuError::UnknownInstruction(opcode))
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::memory::Memory;
     use crate::registers::{DoubleRegister as DR, SingleRegister as SR};
 
     use super::Condition as C;
@@ -258,20 +450,38 @@ mod tests {
         let mut memory = Memory::new();
 
         for (operand, instruction) in vec![
-            (0b0000_0111, I::RotateShift(RS::RLC(0b0000_0111))),
-            (0b0000_1111, I::RotateShift(RS::RRC(0b0000_1111))),
-            (0b0001_0111, I::RotateShift(RS::RL(0b0001_0111))),
-            (0b0001_1111, I::RotateShift(RS::RR(0b0001_1111))),
-            (0b0010_0111, I::RotateShift(RS::SLA(0b0010_0111))),
-            (0b0010_1111, I::RotateShift(RS::SRA(0b0010_1111))),
-            (0b0011_0111, I::RotateShift(RS::SWAP(0b0011_0111))),
-            (0b0011_1111, I::RotateShift(RS::SRL(0b0011_1111))),
+            (
+                0b0000_0111,
+                I::RotateShift(RS::RLC(Target::Register(SR::A))),
+            ),
+            (
+                0b0000_1111,
+                I::RotateShift(RS::RRC(Target::Register(SR::A))),
+            ),
+            (0b0001_0111, I::RotateShift(RS::RL(Target::Register(SR::A)))),
+            (0b0001_1111, I::RotateShift(RS::RR(Target::Register(SR::A)))),
+            (
+                0b0010_0111,
+                I::RotateShift(RS::SLA(Target::Register(SR::A))),
+            ),
+            (
+                0b0010_1111,
+                I::RotateShift(RS::SRA(Target::Register(SR::A))),
+            ),
+            (
+                0b0011_0111,
+                I::RotateShift(RS::SWAP(Target::Register(SR::A))),
+            ),
+            (
+                0b0011_1111,
+                I::RotateShift(RS::SRL(Target::Register(SR::A))),
+            ),
         ] {
             memory.set((pc as usize) + 1, operand);
 
             assert_eq!(
                 instruction,
-                decode(code, pc, &memory).unwrap(),
+                decode(code, pc, &memory).unwrap().0,
                 "Failed to decode with operand 0b{:08b}",
                 operand
             );
@@ -285,21 +495,223 @@ mod tests {
         let mut memory = Memory::new();
 
         for (operand, instruction) in vec![
-            (0b0100_0111, I::Bit(Bit::BIT(0b0100_0111))),
-            (0b1100_1111, I::Bit(Bit::SET(0b1100_1111))),
-            (0b1001_0111, I::Bit(Bit::RES(0b1001_0111))),
+            (0b0100_0111, I::Bit(Bit::BIT(0, Target::Register(SR::A)))),
+            (0b1100_1111, I::Bit(Bit::SET(1, Target::Register(SR::A)))),
+            (0b1001_0111, I::Bit(Bit::RES(2, Target::Register(SR::A)))),
         ] {
             memory.set((pc as usize) + 1, operand);
 
             assert_eq!(
                 instruction,
-                decode(code, pc, &memory).unwrap(),
+                decode(code, pc, &memory).unwrap().0,
                 "Failed to decode with operand 0b{:08b}",
                 operand
             );
         }
     }
 
+    #[test]
+    fn instructions_are_displayed_as_mnemonics() {
+        let mut memory = Memory::new();
+
+        for (bytes, expected) in [
+            (vec![0xC3, 0x50, 0x01], "JP 0x0150"),
+            (vec![0x18, 0xFD], "JR -3"),
+            (vec![0xFF], "RST 0x38"),
+            (vec![0x2A], "LD A, (HL+)"),
+            (vec![0x32], "LD (HL-), A"),
+            (vec![0x60], "LD H, B"),
+            (vec![0x7E], "LD A, (HL)"),
+            (vec![0xF0, 0x44], "LDH A, (0xff44)"),
+            (vec![0xE2], "LDH (C), A"),
+            (vec![0x21, 0x00, 0xC0], "LD HL, 0xc000"),
+            (vec![0x08, 0x00, 0xC0], "LD (0xc000), SP"),
+            (vec![0xF5], "PUSH AF"),
+            (vec![0xC6, 0x3B], "ADD A, 0x3b"),
+            (vec![0xBE], "CP A, (HL)"),
+            (vec![0x35], "DEC (HL)"),
+            (vec![0xE8, 0xFE], "ADD SP, -2"),
+            (vec![0x09], "ADD HL, BC"),
+            (vec![0xCB, 0x7C], "BIT 7, H"),
+            (vec![0xCB, 0xC6], "SET 0, (HL)"),
+            (vec![0xCB, 0x37], "SWAP A"),
+            (vec![0x17], "RLA"),
+            (vec![0xFB], "EI"),
+        ] {
+            memory.load(0, &bytes);
+            let (instruction, _) = decode(bytes[0], 0, &memory).unwrap();
+
+            assert_eq!(expected, instruction.to_string());
+            assert!(expected.starts_with(instruction.mnemonic()));
+        }
+
+        assert_eq!(
+            "JP NZ, 0x0150",
+            I::ControlFlow(CF::JPC(0x0150, C::NotZero)).to_string()
+        );
+        assert_eq!("RET NC", I::ControlFlow(CF::RETC(C::NoCarry)).to_string());
+    }
+
+    #[test]
+    fn targets_resolve_registers_and_hl() {
+        let mut registers = Registers::new();
+        let mut memory = Memory::new();
+        registers.set_double(&DR::HL, 0xC000);
+
+        // H and L last, as they move HL
+        for code in [0, 1, 2, 3, 6, 7, 4, 5] {
+            let target = Target::from(code);
+            target.set(&mut registers, &mut memory, code + 1);
+
+            assert_eq!(code, target.code());
+            assert_eq!(code + 1, target.get(&registers, &memory));
+        }
+
+        assert_eq!(Target::HLIndirect, Target::from(0b1100_0110));
+        assert_eq!(7, memory.get(0xC000));
+        assert_eq!(8, registers.get_single(&SR::A));
+    }
+
+    #[test]
+    fn cb_operands_are_decoded_on_their_own() {
+        assert_eq!(
+            Ok(I::RotateShift(RS::SWAP(Target::Register(SR::A)))),
+            decode_cb(0x37)
+        );
+        assert_eq!(Ok(I::Bit(Bit::SET(0, Target::HLIndirect))), decode_cb(0xC6));
+    }
+
+    #[test]
+    fn bytes_are_decoded_without_memory() {
+        assert_eq!(
+            Ok((I::ControlFlow(CF::JP(0x0150)), 3)),
+            decode_bytes(&[0xC3, 0x50, 0x01, 0x00])
+        );
+        assert_eq!(
+            Ok((I::RotateShift(RS::SWAP(Target::Register(SR::A))), 2)),
+            decode_bytes(&[0xCB, 0x37])
+        );
+        assert!(decode_bytes(&[0xC3, 0x50]).is_err());
+        assert!(decode_bytes(&[]).is_err());
+    }
+
+    #[test]
+    fn instructions_encode_to_their_bytes() {
+        assert_eq!(
+            Ok(vec![0x06, 0x12]),
+            I::Load8Bit(Load8Bit::LD_N(SR::B, 0x12)).encode()
+        );
+        assert_eq!(
+            Ok(vec![0xC2, 0x50, 0x01]),
+            I::ControlFlow(CF::JPC(0x0150, C::Carry)).encode()
+        );
+        assert_eq!(
+            Err(CpuError::UnsupportedDoubleRegister(DR::PC)),
+            I::Load16Bit(Load16Bit::PUSH(DR::PC)).encode()
+        );
+        assert_eq!(
+            Err(CpuError::UnsupportedDoubleRegister(DR::SP)),
+            I::Load16Bit(Load16Bit::POP(DR::SP)).encode()
+        );
+        assert_eq!(
+            Err(CpuError::UnsupportedDoubleRegister(DR::PC)),
+            I::ALU16Bit(ALU16Bit::INC(DR::PC)).encode()
+        );
+    }
+
+    #[test]
+    fn execution_reports_whether_a_branch_was_taken() {
+        let mut registers = Registers::new();
+        let mut memory = Memory::new();
+        let mut cpu_flags = crate::cpu::CpuFlags::new();
+
+        let jump = I::ControlFlow(CF::JPC(0x0150, C::Zero));
+        for (zero, expected) in [
+            (
+                false,
+                Execution {
+                    cycles: 3,
+                    branch_taken: Some(false),
+                },
+            ),
+            (
+                true,
+                Execution {
+                    cycles: 4,
+                    branch_taken: Some(true),
+                },
+            ),
+        ] {
+            registers.set_zero(zero);
+            assert_eq!(
+                Ok(expected),
+                jump.execute(&mut registers, &mut memory, &mut cpu_flags)
+            );
+        }
+
+        assert_eq!(
+            Ok(Execution {
+                cycles: 1,
+                branch_taken: None
+            }),
+            I::Misc(Misc::NOP()).execute(&mut registers, &mut memory, &mut cpu_flags)
+        );
+    }
+
+    #[test]
+    fn instructions_are_classified() {
+        let call = I::ControlFlow(CF::CALLC(0x0150, C::Zero));
+        assert!(call.is_control_flow() && call.is_call() && call.is_conditional());
+        assert!(call.writes_memory());
+
+        let ret = I::ControlFlow(CF::RET());
+        assert!(ret.is_control_flow() && !ret.is_call() && !ret.is_conditional());
+        assert!(!ret.writes_memory());
+
+        let push = I::Load16Bit(Load16Bit::PUSH(DR::BC));
+        assert!(push.is_load() && push.writes_memory() && !push.is_control_flow());
+
+        assert!(I::Load8Bit(Load8Bit::LDH_FROM_A(0x80)).writes_memory());
+        assert!(!I::Load8Bit(Load8Bit::LD_FROM_HL(SR::B)).writes_memory());
+        assert!(I::RotateShift(RS::SWAP(Target::HLIndirect)).writes_memory());
+        assert!(!I::RotateShift(RS::SWAP(Target::Register(SR::A))).writes_memory());
+        assert!(!I::Bit(Bit::BIT(0, Target::HLIndirect)).writes_memory());
+        assert!(I::Bit(Bit::RES(0, Target::HLIndirect)).writes_memory());
+        assert!(!I::ALU8Bit(ALU8Bit::ADD_HL()).writes_memory());
+        assert!(I::ALU8Bit(ALU8Bit::INC_HL()).writes_memory());
+    }
+
+    #[test]
+    fn operands_are_listed_in_display_order() {
+        let mut memory = Memory::new();
+
+        for bytes in (0..=0xFF)
+            .map(|opcode| [opcode, 0xFE, 0xFF])
+            .chain((0..=0xFF).map(|opcode| [0xCB, opcode, 0x12]))
+        {
+            memory.load(0, &bytes);
+            if let Ok((instruction, _)) = decode(bytes[0], 0, &memory) {
+                let operands: Vec<String> = instruction
+                    .operands()
+                    .iter()
+                    .map(|operand| operand.to_string())
+                    .collect();
+                let expected = if operands.is_empty() {
+                    instruction.mnemonic().to_string()
+                } else {
+                    format!("{} {}", instruction.mnemonic(), operands.join(", "))
+                };
+
+                assert_eq!(expected, instruction.to_string());
+            }
+        }
+
+        assert_eq!(
+            vec![Operand::Bit(3), Operand::HLIndirect],
+            I::Bit(Bit::SET(3, Target::HLIndirect)).operands()
+        );
+    }
+
     #[test]
     fn decode_works() {
         let memory = Memory::new();
@@ -308,6 +720,7 @@ mod tests {
         for (code, instruction) in vec![
             // Misc instructions
             (0b00000000, I::Misc(Misc::NOP())),
+            (0b01110110, I::Misc(Misc::HALT())),
             (0b00111111, I::Misc(Misc::CCF())),
             (0b00110111, I::Misc(Misc::SCF())),
             (0b00100111, I::Misc(Misc::DAA())),
@@ -477,10 +890,31 @@ mod tests {
         ] {
             assert_eq!(
                 instruction,
-                decode(code, pc, &memory).unwrap(),
+                decode(code, pc, &memory).unwrap().0,
                 "Failed to decode 0b{:08b}",
                 code
             );
         }
     }
+
+    #[test]
+    fn mnemonics_are_derived_from_instruction_names() {
+        for (mnemonic, group_name, instruction) in [
+            ("LDH", "Load8Bit", I::Load8Bit(Load8Bit::LDH_C_TO_A())),
+            ("LD", "Load16Bit", I::Load16Bit(Load16Bit::LD_HL_TO_SP())),
+            ("ADD", "ALU8Bit", I::ALU8Bit(ALU8Bit::ADD_N(0))),
+            (
+                "RLC",
+                "RotateShift",
+                I::RotateShift(RS::RLC(Target::HLIndirect)),
+            ),
+            ("JP", "ControlFlow", I::ControlFlow(CF::JP_HL())),
+            ("JP", "ControlFlow", I::ControlFlow(CF::JPC(0, C::Zero))),
+            ("RET", "ControlFlow", I::ControlFlow(CF::RETC(C::Carry))),
+            ("RETI", "ControlFlow", I::ControlFlow(CF::RETI())),
+        ] {
+            assert_eq!(mnemonic, instruction.mnemonic());
+            assert_eq!(group_name, instruction.group_name());
+        }
+    }
 }
@@ -1,7 +1,7 @@
-use crate::{
-    instruction_group,
-    registers::{DoubleRegister, MASK_FLAG_CARRY, MASK_FLAG_HALF_CARRY, MASK_FLAG_ZERO},
-};
+use std::fmt::Display;
+
+use super::{flag_effects::FlagEffects, utils, Operand};
+use crate::{errors::CpuError, instruction_group, registers::DoubleRegister};
 
 instruction_group! {
     /// 16-bit ALU instructions
@@ -21,41 +21,34 @@ instruction_group! {
             let operand = registers.get_double(&r);
             let (result, carry) = hl.overflowing_add(operand);
 
-            let mut flags = registers.get_flags() & MASK_FLAG_ZERO; // Keep the Z flag unchanged
-            if carry {
-                flags |= MASK_FLAG_CARRY;
-            }
-            if ((hl & 0xFFF) + (operand & 0xFFF)) > 0x1000 {
-                flags |= MASK_FLAG_HALF_CARRY;
-            }
             registers.set_double(&DoubleRegister::HL, result);
-            registers.set_flags(flags);
+            registers.set_negative(false);
+            registers.set_half_carry(((hl & 0xFFF) + (operand & 0xFFF)) > 0x0FFF);
+            registers.set_carry(carry);
             Ok(2)
         }
 
-        /// Add contents of `u8` operand to `SP`
+        /// Add the signed `operand` to `SP`
         ///
-        /// | Flag | Effect                               |
-        /// |------|--------------------------------------|
-        /// | `Z`  | `0`                                  |
-        /// | `N`  | `0`                                  |
-        /// | `H`  | Set if carry from bit 11, else reset |
-        /// | `C`  | Set if carry from bit 15, else reset |
+        /// **Flags**
+        ///
+        /// | Flag | Effect                              |
+        /// |------|-------------------------------------|
+        /// | `Z`  | `0`                                 |
+        /// | `N`  | `0`                                 |
+        /// | `H`  | Set if carry from bit 3, else reset |
+        /// | `C`  | Set if carry from bit 7, else reset |
         ADD_SP(operand: u8) [2] => {
             let sp = registers.get_double(&DoubleRegister::SP);
-            let operand: u16 = *operand as u16;
-            let (result, carry) = sp.overflowing_add(operand);
-
-            let mut flags = 0b0000_0000;
-            if carry {
-                flags |= MASK_FLAG_CARRY;
-            }
-            if ((sp & 0xFFF) + (operand & 0xFFF)) > 0x1000 {
-                flags |= MASK_FLAG_HALF_CARRY;
-            }
-
-            registers.set_double(&DoubleRegister::SP, result);
-            registers.set_flags(flags);
+            let operand = *operand as i8 as u16;
+
+            registers.set_double(&DoubleRegister::SP, sp.wrapping_add(operand));
+            registers.set_flags_from(
+                false,
+                false,
+                (sp & 0xF) + (operand & 0xF) > 0xF,
+                (sp & 0xFF) + (operand & 0xFF) > 0xFF,
+            );
             Ok(4)
         }
 
@@ -79,6 +72,51 @@ instruction_group! {
     }
 }
 
+impl ALU16Bit {
+    /// Returns the machine code of the instruction.
+    pub fn encode(&self) -> Result<Vec<u8>, CpuError> {
+        Ok(match self {
+            ALU16Bit::ADD_HL(r) => vec![0x09 | utils::double_register_code(r)? << 4],
+            ALU16Bit::ADD_SP(e) => vec![0xE8, *e],
+            ALU16Bit::INC(r) => vec![0x03 | utils::double_register_code(r)? << 4],
+            ALU16Bit::DEC(r) => vec![0x0B | utils::double_register_code(r)? << 4],
+        })
+    }
+
+    /// Returns the operands of the instruction.
+    pub fn operands(&self) -> Vec<Operand> {
+        match self {
+            ALU16Bit::ADD_HL(r) => vec![Operand::Reg16(DoubleRegister::HL), Operand::Reg16(*r)],
+            ALU16Bit::ADD_SP(e) => vec![
+                Operand::Reg16(DoubleRegister::SP),
+                Operand::Offset(*e as i8),
+            ],
+            ALU16Bit::INC(r) | ALU16Bit::DEC(r) => vec![Operand::Reg16(*r)],
+        }
+    }
+
+    /// Returns the effects of the instruction on the flags.
+    pub fn flag_effects(&self) -> FlagEffects {
+        match self {
+            ALU16Bit::ADD_HL(_) => FlagEffects::parse("-0HC"),
+            ALU16Bit::ADD_SP(_) => FlagEffects::parse("00HC"),
+            ALU16Bit::INC(_) | ALU16Bit::DEC(_) => FlagEffects::NONE,
+        }
+    }
+}
+
+impl Display for ALU16Bit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mnemonic = self.mnemonic();
+
+        match self {
+            ALU16Bit::ADD_HL(r) => write!(f, "{} HL, {}", mnemonic, r),
+            ALU16Bit::ADD_SP(e) => write!(f, "{} SP, {}", mnemonic, *e as i8),
+            ALU16Bit::INC(r) | ALU16Bit::DEC(r) => write!(f, "{} {}", mnemonic, r),
+        }
+    }
+}
+
 #[cfg(test)]
 crate::instruction_tests! {
     addhl_takes_2_machine_cycles(registers, memory, cpu_flags) => {
@@ -99,8 +137,9 @@ crate::instruction_tests! {
             (0x0001, 0x0002, 0b0000_0000, 0b0000_0000),
             (0x0001, 0x0002, 0b0100_0000, 0b0000_0000),
             (0x0001, 0x0002, 0b1000_0000, 0b1000_0000),
-            (0xFF00, 0x1100, 0b0000_0000, 0b0001_0000),
+            (0xFF00, 0x1100, 0b0000_0000, 0b0011_0000),
             (0x0FFF, 0x0111, 0b0000_0000, 0b0010_0000),
+            (0x0800, 0x0800, 0b0000_0000, 0b0010_0000),
             (0xFFFF, 0x1111, 0b0000_0000, 0b0011_0000),
             (0xFFFF, 0x1111, 0b1000_0000, 0b1011_0000),
         ] {
@@ -121,9 +160,14 @@ crate::instruction_tests! {
 
     addsp_adds_operand_to_sp(registers, memory, cpu_flags) => {
         registers.set_double(&DoubleRegister::SP, 0x1122);
+        ALU16Bit::ADD_SP(0x2B).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+
+        assert_eq!(0x114D, registers.get_double(&DoubleRegister::SP));
+
+        // The operand is signed, 0xAB is -85
         ALU16Bit::ADD_SP(0xAB).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
-        assert_eq!(0x11CD, registers.get_double(&DoubleRegister::SP));
+        assert_eq!(0x10F8, registers.get_double(&DoubleRegister::SP));
     }
 
     addsp_sets_flags_correctly(registers, memory, cpu_flags) => {
@@ -131,7 +175,9 @@ crate::instruction_tests! {
             (0x0001, 0x02, 0b0000_0000, 0b0000_0000),
             (0x0003, 0x04, 0b0100_0000, 0b0000_0000),
             (0x0005, 0x06, 0b1000_0000, 0b0000_0000),
-            (0x0F11, 0xFF, 0b0000_0000, 0b0010_0000),
+            (0x000F, 0x01, 0b0000_0000, 0b0010_0000),
+            (0x00F0, 0x10, 0b0000_0000, 0b0001_0000),
+            (0x0F11, 0xFF, 0b0000_0000, 0b0011_0000),
             (0xFFFF, 0xFF, 0b0000_0000, 0b0011_0000),
             (0xFFFF, 0xFF, 0b1000_0000, 0b0011_0000),
         ] {
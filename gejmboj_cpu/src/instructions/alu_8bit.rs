@@ -1,3 +1,6 @@
+use std::fmt::Display;
+
+use super::{flag_effects::FlagEffects, utils, Operand};
 use crate::{
     errors::CpuError,
     instruction_group,
@@ -250,11 +253,11 @@ instruction_group! {
 
             let operand = registers.get_single(r);
             let (result, flags) = AluOp::Add.calculate(operand, 1);
-            // Set Carry if already set, otherwise reset
-            let flags = if registers.is_carry() { flags | MASK_FLAG_CARRY } else { flags & 0b1110_0000 };
+            let carry = registers.is_carry();
 
             registers.set_single(r, result);
             registers.set_flags(flags);
+            registers.set_carry(carry);
 
             Ok(1)
         }
@@ -265,11 +268,11 @@ instruction_group! {
         INC_HL() [1] => {
             let operand = memory.get(registers.get_double(&DoubleRegister::HL).into());
             let (result, flags) = AluOp::Add.calculate(operand, 1);
-            // Set Carry if already set, otherwise reset
-            let flags = if registers.is_carry() { flags | MASK_FLAG_CARRY } else { flags & 0b1110_0000 };
+            let carry = registers.is_carry();
 
             memory.set(registers.get_double(&DoubleRegister::HL).into(), result);
             registers.set_flags(flags);
+            registers.set_carry(carry);
 
             Ok(3)
         }
@@ -284,11 +287,11 @@ instruction_group! {
 
             let operand = registers.get_single(r);
             let (result, flags) = AluOp::Sub.calculate(operand, 1);
-            // Set Carry if already set, otherwise reset
-            let flags = if registers.is_carry() { flags | MASK_FLAG_CARRY } else { flags & 0b1110_0000 };
+            let carry = registers.is_carry();
 
             registers.set_single(r, result);
             registers.set_flags(flags);
+            registers.set_carry(carry);
 
             Ok(1)
         }
@@ -299,11 +302,11 @@ instruction_group! {
         DEC_HL() [1] => {
             let operand = memory.get(registers.get_double(&DoubleRegister::HL).into());
             let (result, flags) = AluOp::Sub.calculate(operand, 1);
-            // Set Carry if already set, otherwise reset
-            let flags = if registers.is_carry() { flags | MASK_FLAG_CARRY } else { flags & 0b1110_0000 };
+            let carry = registers.is_carry();
 
             memory.set(registers.get_double(&DoubleRegister::HL).into(), result);
             registers.set_flags(flags);
+            registers.set_carry(carry);
 
             Ok(3)
         }
@@ -397,6 +400,147 @@ impl AluOp {
     }
 }
 
+impl ALU8Bit {
+    /// Returns the machine code of the instruction.
+    pub fn encode(&self) -> Result<Vec<u8>, CpuError> {
+        let r = |r| utils::single_register_code(r);
+        // The register variants are `base | r`, `(HL)` is register code `110`
+        let base = match self {
+            ALU8Bit::ADD(_) | ALU8Bit::ADD_N(_) | ALU8Bit::ADD_HL() => 0x80,
+            ALU8Bit::ADC(_) | ALU8Bit::ADC_N(_) | ALU8Bit::ADC_HL() => 0x88,
+            ALU8Bit::SUB(_) | ALU8Bit::SUB_N(_) | ALU8Bit::SUB_HL() => 0x90,
+            ALU8Bit::SBC(_) | ALU8Bit::SBC_N(_) | ALU8Bit::SBC_HL() => 0x98,
+            ALU8Bit::AND(_) | ALU8Bit::AND_N(_) | ALU8Bit::AND_HL() => 0xA0,
+            ALU8Bit::XOR(_) | ALU8Bit::XOR_N(_) | ALU8Bit::XOR_HL() => 0xA8,
+            ALU8Bit::OR(_) | ALU8Bit::OR_N(_) | ALU8Bit::OR_HL() => 0xB0,
+            ALU8Bit::CP(_) | ALU8Bit::CP_N(_) | ALU8Bit::CP_HL() => 0xB8,
+            ALU8Bit::INC(x) => return Ok(vec![0x04 | r(x) << 3]),
+            ALU8Bit::INC_HL() => return Ok(vec![0x34]),
+            ALU8Bit::DEC(x) => return Ok(vec![0x05 | r(x) << 3]),
+            ALU8Bit::DEC_HL() => return Ok(vec![0x35]),
+        };
+
+        Ok(match self {
+            ALU8Bit::ADD(x)
+            | ALU8Bit::ADC(x)
+            | ALU8Bit::SUB(x)
+            | ALU8Bit::SBC(x)
+            | ALU8Bit::AND(x)
+            | ALU8Bit::XOR(x)
+            | ALU8Bit::OR(x)
+            | ALU8Bit::CP(x) => vec![base | r(x)],
+            ALU8Bit::ADD_N(n)
+            | ALU8Bit::ADC_N(n)
+            | ALU8Bit::SUB_N(n)
+            | ALU8Bit::SBC_N(n)
+            | ALU8Bit::AND_N(n)
+            | ALU8Bit::XOR_N(n)
+            | ALU8Bit::OR_N(n)
+            | ALU8Bit::CP_N(n) => vec![base + 0x46, *n],
+            _ => vec![base | 0b110],
+        })
+    }
+
+    /// Returns the operands of the instruction.
+    pub fn operands(&self) -> Vec<Operand> {
+        let a = Operand::Reg8(SingleRegister::A);
+
+        match self {
+            ALU8Bit::INC(r) | ALU8Bit::DEC(r) => vec![Operand::Reg8(*r)],
+            ALU8Bit::INC_HL() | ALU8Bit::DEC_HL() => vec![Operand::HLIndirect],
+            ALU8Bit::ADD(r)
+            | ALU8Bit::ADC(r)
+            | ALU8Bit::SUB(r)
+            | ALU8Bit::SBC(r)
+            | ALU8Bit::AND(r)
+            | ALU8Bit::OR(r)
+            | ALU8Bit::XOR(r)
+            | ALU8Bit::CP(r) => vec![a, Operand::Reg8(*r)],
+            ALU8Bit::ADD_N(n)
+            | ALU8Bit::ADC_N(n)
+            | ALU8Bit::SUB_N(n)
+            | ALU8Bit::SBC_N(n)
+            | ALU8Bit::AND_N(n)
+            | ALU8Bit::OR_N(n)
+            | ALU8Bit::XOR_N(n)
+            | ALU8Bit::CP_N(n) => vec![a, Operand::Imm8(*n)],
+            ALU8Bit::ADD_HL()
+            | ALU8Bit::ADC_HL()
+            | ALU8Bit::SUB_HL()
+            | ALU8Bit::SBC_HL()
+            | ALU8Bit::AND_HL()
+            | ALU8Bit::OR_HL()
+            | ALU8Bit::XOR_HL()
+            | ALU8Bit::CP_HL() => vec![a, Operand::HLIndirect],
+        }
+    }
+
+    /// Returns the effects of the instruction on the flags.
+    pub fn flag_effects(&self) -> FlagEffects {
+        FlagEffects::parse(match self {
+            ALU8Bit::ADD(_)
+            | ALU8Bit::ADD_N(_)
+            | ALU8Bit::ADD_HL()
+            | ALU8Bit::ADC(_)
+            | ALU8Bit::ADC_N(_)
+            | ALU8Bit::ADC_HL() => "Z0HC",
+            ALU8Bit::SUB(_)
+            | ALU8Bit::SUB_N(_)
+            | ALU8Bit::SUB_HL()
+            | ALU8Bit::SBC(_)
+            | ALU8Bit::SBC_N(_)
+            | ALU8Bit::SBC_HL()
+            | ALU8Bit::CP(_)
+            | ALU8Bit::CP_N(_)
+            | ALU8Bit::CP_HL() => "Z1HC",
+            ALU8Bit::AND(_) | ALU8Bit::AND_N(_) | ALU8Bit::AND_HL() => "Z010",
+            ALU8Bit::OR(_)
+            | ALU8Bit::OR_N(_)
+            | ALU8Bit::OR_HL()
+            | ALU8Bit::XOR(_)
+            | ALU8Bit::XOR_N(_)
+            | ALU8Bit::XOR_HL() => "Z000",
+            ALU8Bit::INC(_) | ALU8Bit::INC_HL() => "Z0H-",
+            ALU8Bit::DEC(_) | ALU8Bit::DEC_HL() => "Z1H-",
+        })
+    }
+}
+
+impl Display for ALU8Bit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mnemonic = self.mnemonic();
+
+        match self {
+            ALU8Bit::INC(r) | ALU8Bit::DEC(r) => write!(f, "{} {}", mnemonic, r),
+            ALU8Bit::INC_HL() | ALU8Bit::DEC_HL() => write!(f, "{} (HL)", mnemonic),
+            ALU8Bit::ADD(r)
+            | ALU8Bit::ADC(r)
+            | ALU8Bit::SUB(r)
+            | ALU8Bit::SBC(r)
+            | ALU8Bit::AND(r)
+            | ALU8Bit::OR(r)
+            | ALU8Bit::XOR(r)
+            | ALU8Bit::CP(r) => write!(f, "{} A, {}", mnemonic, r),
+            ALU8Bit::ADD_N(n)
+            | ALU8Bit::ADC_N(n)
+            | ALU8Bit::SUB_N(n)
+            | ALU8Bit::SBC_N(n)
+            | ALU8Bit::AND_N(n)
+            | ALU8Bit::OR_N(n)
+            | ALU8Bit::XOR_N(n)
+            | ALU8Bit::CP_N(n) => write!(f, "{} A, 0x{:02x}", mnemonic, n),
+            ALU8Bit::ADD_HL()
+            | ALU8Bit::ADC_HL()
+            | ALU8Bit::SUB_HL()
+            | ALU8Bit::SBC_HL()
+            | ALU8Bit::AND_HL()
+            | ALU8Bit::OR_HL()
+            | ALU8Bit::XOR_HL()
+            | ALU8Bit::CP_HL() => write!(f, "{} A, (HL)", mnemonic),
+        }
+    }
+}
+
 #[cfg(test)]
 crate::instruction_tests! {
     add_takes_one_machine_cycle(registers, memory, cpu_flags) => {
@@ -414,33 +558,17 @@ crate::instruction_tests! {
         assert_eq!(3, registers.get_single(&SingleRegister::A));
     }
 
-    add_sets_z_flag_if_result_is_zero(registers, memory, cpu_flags) => {
-        assert_eq!(0b0000_0000, registers.get_flags());
-
-        registers.set_single(&SingleRegister::A, 0);
-        registers.set_single(&SingleRegister::B, 0);
-
-        ALU8Bit::ADD(SingleRegister::B).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-
-        assert_eq!(0b1000_0000, registers.get_flags());
-    }
-
-    add_sets_h_flag_if_carry_from_bit_3(registers, memory, cpu_flags) => {
-        registers.set_single(&SingleRegister::A, 0b0000_0111);
-        registers.set_single(&SingleRegister::B, 0b0000_1001);
-
-        ALU8Bit::ADD(SingleRegister::B).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-
-        assert_eq!(0b0010_0000, registers.get_flags());
-    }
-
-    add_sets_c_flag_if_carry_from_bit_7(registers, memory, cpu_flags) => {
-        registers.set_single(&SingleRegister::A, 0b1111_0000);
-        registers.set_single(&SingleRegister::B, 0b0001_0001);
+    add_sets_flags(registers, memory, cpu_flags) for (a, b, expected) in [
+        z_if_result_is_zero: (0, 0, 0b1000_0000),
+        h_if_carry_from_bit_3: (0b0000_0111, 0b0000_1001, 0b0010_0000),
+        c_if_carry_from_bit_7: (0b1111_0000, 0b0001_0001, 0b0001_0000),
+    ] => {
+        registers.set_single(&SingleRegister::A, a);
+        registers.set_single(&SingleRegister::B, b);
 
         ALU8Bit::ADD(SingleRegister::B).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
-        assert_eq!(0b0001_0000, registers.get_flags());
+        assert_eq!(expected, registers.get_flags());
     }
 
     add_handles_overflow(registers, memory, cpu_flags) => {
@@ -452,8 +580,16 @@ crate::instruction_tests! {
         assert_eq!(4, registers.get_single(&SingleRegister::A));
     }
 
-    add_does_not_support_the_f_register(registers, memory, cpu_flags) => {
-        let result = ALU8Bit::ADD(SingleRegister::F).execute(&mut registers, &mut memory, &mut cpu_flags);
+    does_not_support_the_f_register(registers, memory, cpu_flags) for instruction in [
+        add: ALU8Bit::ADD(SingleRegister::F),
+        and: ALU8Bit::AND(SingleRegister::F),
+        or: ALU8Bit::OR(SingleRegister::F),
+        xor: ALU8Bit::XOR(SingleRegister::F),
+        cp: ALU8Bit::CP(SingleRegister::F),
+        inc: ALU8Bit::INC(SingleRegister::F),
+        dec: ALU8Bit::DEC(SingleRegister::F),
+    ] => {
+        let result = instruction.execute(&mut registers, &mut memory, &mut cpu_flags);
         let expected = Err(crate::errors::CpuError::UnsupportedSingleRegister(SingleRegister::F));
 
         assert_eq!(expected, result);
@@ -691,25 +827,27 @@ crate::instruction_tests! {
         assert_eq!(0b0111_0000, registers.get_flags(), "SbcHL sets incorrect flags");
     }
 
-    and_takes_the_correct_amount_of_machine_cycles(registers, memory, cpu_flags) => {
-        let cycles = ALU8Bit::AND(SingleRegister::B).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-
-        assert_eq!(1, cycles, "Incorrect machine cycle count for And");
-
-        let cycles = ALU8Bit::AND_N(42).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-
-        assert_eq!(2, cycles, "Incorrect machine cycle count for AndN");
-
-        let cycles = ALU8Bit::AND_HL().execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-
-        assert_eq!(2, cycles, "Incorrect machine cycle count for AndHL");
-    }
-
-    and_does_not_support_the_f_register(registers, memory, cpu_flags) => {
-        let result = ALU8Bit::AND(SingleRegister::F).execute(&mut registers, &mut memory, &mut cpu_flags);
-        let expected = Err(crate::errors::CpuError::UnsupportedSingleRegister(SingleRegister::F));
-
-        assert_eq!(expected, result);
+    takes_the_correct_amount_of_machine_cycles(registers, memory, cpu_flags) for (instruction, expected) in [
+        and: (ALU8Bit::AND(SingleRegister::B), 1),
+        and_n: (ALU8Bit::AND_N(42), 2),
+        and_hl: (ALU8Bit::AND_HL(), 2),
+        or: (ALU8Bit::OR(SingleRegister::B), 1),
+        or_n: (ALU8Bit::OR_N(42), 2),
+        or_hl: (ALU8Bit::OR_HL(), 2),
+        xor: (ALU8Bit::XOR(SingleRegister::B), 1),
+        xor_n: (ALU8Bit::XOR_N(42), 2),
+        xor_hl: (ALU8Bit::XOR_HL(), 2),
+        cp: (ALU8Bit::CP(SingleRegister::B), 1),
+        cp_n: (ALU8Bit::CP_N(42), 2),
+        cp_hl: (ALU8Bit::CP_HL(), 2),
+        inc: (ALU8Bit::INC(SingleRegister::B), 1),
+        inc_hl: (ALU8Bit::INC_HL(), 3),
+        dec: (ALU8Bit::DEC(SingleRegister::B), 1),
+        dec_hl: (ALU8Bit::DEC_HL(), 3),
+    ] => {
+        let cycles = instruction.execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+
+        assert_eq!(expected, cycles);
     }
 
     and_computes_and_handles_flags_correctly(registers, memory, cpu_flags) => {
@@ -734,27 +872,6 @@ crate::instruction_tests! {
         assert_eq!(0b1010_0000, registers.get_flags(), "AndHL sets incorrect flags");
     }
 
-    or_takes_the_correct_amount_of_machine_cycles(registers, memory, cpu_flags) => {
-        let cycles = ALU8Bit::OR(SingleRegister::B).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-
-        assert_eq!(1, cycles, "Incorrect machine cycle count for Or");
-
-        let cycles = ALU8Bit::OR_N(42).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-
-        assert_eq!(2, cycles, "Incorrect machine cycle count for OrN");
-
-        let cycles = ALU8Bit::OR_HL().execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-
-        assert_eq!(2, cycles, "Incorrect machine cycle count for OrHL");
-    }
-
-    or_does_not_support_the_f_register(registers, memory, cpu_flags) => {
-        let result = ALU8Bit::OR(SingleRegister::F).execute(&mut registers, &mut memory, &mut cpu_flags);
-        let expected = Err(crate::errors::CpuError::UnsupportedSingleRegister(SingleRegister::F));
-
-        assert_eq!(expected, result);
-    }
-
     or_computes_and_handles_flags_correctly(registers, memory, cpu_flags) => {
         memory.set(registers.get_double(&DoubleRegister::HL).into(), 0x0F);
         registers.set_single(&SingleRegister::A, 0x5A);
@@ -776,27 +893,6 @@ crate::instruction_tests! {
         assert_eq!(0b0000_0000, registers.get_flags(), "OrHL sets incorrect flags");
     }
 
-    xor_takes_the_correct_amount_of_machine_cycles(registers, memory, cpu_flags) => {
-        let cycles = ALU8Bit::XOR(SingleRegister::B).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-
-        assert_eq!(1, cycles, "Incorrect machine cycle count for Xor");
-
-        let cycles = ALU8Bit::XOR_N(42).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-
-        assert_eq!(2, cycles, "Incorrect machine cycle count for XorN");
-
-        let cycles = ALU8Bit::XOR_HL().execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-
-        assert_eq!(2, cycles, "Incorrect machine cycle count for XorHL");
-    }
-
-    xor_does_not_support_the_f_register(registers, memory, cpu_flags) => {
-        let result = ALU8Bit::XOR(SingleRegister::F).execute(&mut registers, &mut memory, &mut cpu_flags);
-        let expected = Err(crate::errors::CpuError::UnsupportedSingleRegister(SingleRegister::F));
-
-        assert_eq!(expected, result);
-    }
-
     xor_computes_and_handles_flags_correctly(registers, memory, cpu_flags) => {
         memory.set(registers.get_double(&DoubleRegister::HL).into(), 0x8A);
         registers.set_single(&SingleRegister::A, 0xFF);
@@ -818,27 +914,6 @@ crate::instruction_tests! {
         assert_eq!(0b0000_0000, registers.get_flags(), "XorHL sets incorrect flags");
     }
 
-    cp_takes_the_correct_amount_of_machine_cycles(registers, memory, cpu_flags) => {
-        let cycles = ALU8Bit::CP(SingleRegister::B).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-
-        assert_eq!(1, cycles, "Incorrect machine cycle count for Cp");
-
-        let cycles = ALU8Bit::CP_N(42).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-
-        assert_eq!(2, cycles, "Incorrect machine cycle count for CpN");
-
-        let cycles = ALU8Bit::CP_HL().execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-
-        assert_eq!(2, cycles, "Incorrect machine cycle count for CpHL");
-    }
-
-    cp_does_not_support_the_f_register(registers, memory, cpu_flags) => {
-        let result = ALU8Bit::CP(SingleRegister::F).execute(&mut registers, &mut memory, &mut cpu_flags);
-        let expected = Err(crate::errors::CpuError::UnsupportedSingleRegister(SingleRegister::F));
-
-        assert_eq!(expected, result);
-    }
-
     cp_handles_flags_correctly(registers, memory, cpu_flags) => {
         memory.set(registers.get_double(&DoubleRegister::HL).into(), 0x40);
         registers.set_single(&SingleRegister::B, 0x2F);
@@ -856,23 +931,6 @@ crate::instruction_tests! {
         assert_eq!(0b0101_0000, registers.get_flags(), "CpHL sets incorrect flags");
     }
 
-    inc_takes_the_correct_amount_of_machine_cycles(registers, memory, cpu_flags) => {
-        let cycles = ALU8Bit::INC(SingleRegister::B).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-
-        assert_eq!(1, cycles, "Incorrect machine cycle count for Inc");
-
-        let cycles = ALU8Bit::INC_HL().execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-
-        assert_eq!(3, cycles, "Incorrect machine cycle count for IncHL");
-    }
-
-    inc_does_not_support_the_f_register(registers, memory, cpu_flags) => {
-        let result = ALU8Bit::INC(SingleRegister::F).execute(&mut registers, &mut memory, &mut cpu_flags);
-        let expected = Err(crate::errors::CpuError::UnsupportedSingleRegister(SingleRegister::F));
-
-        assert_eq!(expected, result);
-    }
-
     inc_handles_flags_correctly(registers, memory, cpu_flags) => {
         memory.set(registers.get_double(&DoubleRegister::HL).into(), 0x50);
         registers.set_single(&SingleRegister::A, 0xFF);
@@ -894,23 +952,6 @@ crate::instruction_tests! {
         assert_eq!(0b0001_0000, registers.get_flags(), "IncHL did not maintain Carry flag");
     }
 
-    dec_takes_the_correct_amount_of_machine_cycles(registers, memory, cpu_flags) => {
-        let cycles = ALU8Bit::DEC(SingleRegister::B).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-
-        assert_eq!(1, cycles, "Incorrect machine cycle count for Dec");
-
-        let cycles = ALU8Bit::DEC_HL().execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-
-        assert_eq!(3, cycles, "Incorrect machine cycle count for DecHL");
-    }
-
-    dec_does_not_support_the_f_register(registers, memory, cpu_flags) => {
-        let result = ALU8Bit::DEC(SingleRegister::F).execute(&mut registers, &mut memory, &mut cpu_flags);
-        let expected = Err(crate::errors::CpuError::UnsupportedSingleRegister(SingleRegister::F));
-
-        assert_eq!(expected, result);
-    }
-
     dec_handles_flags_correctly(registers, memory, cpu_flags) => {
         memory.set(registers.get_double(&DoubleRegister::HL).into(), 0x00);
         registers.set_single(&SingleRegister::A, 0x01);
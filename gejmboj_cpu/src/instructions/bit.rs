@@ -1,6 +1,8 @@
-use crate::{errors::CpuError, instruction_group, registers::DoubleRegister};
+use std::fmt::Display;
 
-use super::utils::{self, get_register_value};
+use crate::{errors::CpuError, instruction_group};
+
+use super::{flag_effects::FlagEffects, utils, Operand, Target};
 
 /// Decodes the `operand` into a `Bit` instruction.
 ///
@@ -10,25 +12,22 @@ use super::utils::{self, get_register_value};
 /// | `11_bbb_rrr` | `Set`       |
 /// | `10_bbb_rrr` | `Res`        |
 pub fn decode(operand: u8) -> Result<Bit, CpuError> {
+    let bit = (operand >> 3) & 0b111;
+    let target = Target::from(operand);
+
     match utils::into_bits(operand) {
-        (0, 1, _, _, _, _, _, _) => Ok(Bit::BIT(operand)),
-        (1, 1, _, _, _, _, _, _) => Ok(Bit::SET(operand)),
-        (1, 0, _, _, _, _, _, _) => Ok(Bit::RES(operand)),
+        (0, 1, _, _, _, _, _, _) => Ok(Bit::BIT(bit, target)),
+        (1, 1, _, _, _, _, _, _) => Ok(Bit::SET(bit, target)),
+        (1, 0, _, _, _, _, _, _) => Ok(Bit::RES(bit, target)),
         _ => Err(CpuError::UnknownInstruction(operand)),
     }
 }
 
-fn get_bit_mask(operand: &u8) -> u8 {
-    let bit_designator = (operand >> 3) & 0b111;
-
-    1 << bit_designator
-}
-
 instruction_group! {
     /// Bit operations
     ///
     /// These operations act on specific bits of a register or location in memory: `xx-bbb-rrr`.
-    /// The registers are resolved as usual, and the specific bit is resolved as the following table:
+    /// The `Target` is resolved from `rrr`, and the specific bit is resolved as the following table:
     ///
     /// | Bit | `bbb`      |
     /// |:----|:-----------|
@@ -44,76 +43,95 @@ instruction_group! {
     Bit (registers, memory, _cpu_flags) {
 
         /// Copies the complement of the contents of the specified bit in `m` to the Z flag of the program status word (PSW).
-        BIT(operand: u8) [2] => {
-            let (value, register) = get_register_value(registers, memory, *operand);
-            let bit_mask = get_bit_mask(operand);
-            let designated_bit = value & bit_mask;
+        BIT(bit: u8, target: Target) [2] => {
+            let value = target.get(registers, memory);
+            let designated_bit = value & (1 << bit);
 
             registers.set_zero(designated_bit == 0);
             registers.set_negative(false);
             registers.set_half_carry(true);
 
-            match register {
-                Some(_) => Ok(2),
-                None => Ok(3),
+            match target {
+                Target::Register(_) => Ok(2),
+                Target::HLIndirect => Ok(3),
             }
         }
 
         /// Sets the specified bit to 1 in `m`.
-        SET(operand: u8) [2] => {
-            let (value, register) = get_register_value(registers, memory, *operand);
-            let bit_mask = get_bit_mask(operand);
-            let new_value = value | bit_mask;
-
-            match register {
-                Some(r) => {
-                    registers.set_single(&r, new_value);
-                    Ok(2)
-                },
-                None => {
-                    memory.set(registers.get_double(&DoubleRegister::HL).into(), new_value);
-                    Ok(4)
-                },
-            }
+        SET(bit: u8, target: Target) [2] => {
+            let value = target.get(registers, memory);
+            target.set(registers, memory, value | (1 << bit));
+            Ok(target.read_modify_write_cycles())
         }
 
         /// Resets the specified bit to 0 in `m`.
-        RES(operand: u8) [2] => {
-            let (value, register) = get_register_value(registers, memory, *operand);
-            let bit_mask = get_bit_mask(operand);
-            let new_value = value & !bit_mask;
-
-            match register {
-                Some(r) => {
-                    registers.set_single(&r, new_value);
-                    Ok(2)
-                },
-                None => {
-                    memory.set(registers.get_double(&DoubleRegister::HL).into(), new_value);
-                    Ok(4)
-                },
-            }
+        RES(bit: u8, target: Target) [2] => {
+            let value = target.get(registers, memory);
+            target.set(registers, memory, value & !(1 << bit));
+            Ok(target.read_modify_write_cycles())
         }
     }
 }
 
+impl Bit {
+    /// Returns the machine code of the instruction.
+    pub fn encode(&self) -> Result<Vec<u8>, CpuError> {
+        let (prefix, bit, target) = match self {
+            Bit::BIT(bit, target) => (0x40, bit, target),
+            Bit::RES(bit, target) => (0x80, bit, target),
+            Bit::SET(bit, target) => (0xC0, bit, target),
+        };
+
+        Ok(vec![0xCB, prefix | (bit & 0b111) << 3 | target.code()])
+    }
+
+    /// Returns the operands of the instruction.
+    pub fn operands(&self) -> Vec<Operand> {
+        let (Bit::BIT(bit, target) | Bit::SET(bit, target) | Bit::RES(bit, target)) = self;
+
+        vec![Operand::Bit(*bit), (*target).into()]
+    }
+
+    /// Returns the effects of the instruction on the flags.
+    pub fn flag_effects(&self) -> FlagEffects {
+        match self {
+            Bit::BIT(..) => FlagEffects::parse("Z01-"),
+            Bit::SET(..) | Bit::RES(..) => FlagEffects::NONE,
+        }
+    }
+}
+
+impl Display for Bit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (Bit::BIT(bit, target) | Bit::SET(bit, target) | Bit::RES(bit, target)) = self;
+
+        write!(f, "{} {}, {}", self.mnemonic(), bit, target)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::registers::SingleRegister;
 
     #[test]
-    fn get_bit_mask_works() {
-        for (operand, expected) in vec![
-            (0b01_000_111, 0b0000_0001),
-            (0b01_001_111, 0b0000_0010),
-            (0b01_010_111, 0b0000_0100),
-            (0b01_011_111, 0b0000_1000),
-            (0b01_100_111, 0b0001_0000),
-            (0b01_101_111, 0b0010_0000),
-            (0b01_110_111, 0b0100_0000),
-            (0b01_111_111, 0b1000_0000),
+    fn decode_resolves_the_bit_and_target() {
+        for (operand, expected) in [
+            (
+                0b01_000_111,
+                Bit::BIT(0, Target::Register(SingleRegister::A)),
+            ),
+            (
+                0b01_111_000,
+                Bit::BIT(7, Target::Register(SingleRegister::B)),
+            ),
+            (0b11_010_110, Bit::SET(2, Target::HLIndirect)),
+            (
+                0b10_101_101,
+                Bit::RES(5, Target::Register(SingleRegister::L)),
+            ),
         ] {
-            assert_eq!(expected, get_bit_mask(&operand));
+            assert_eq!(Ok(expected), decode(operand));
         }
     }
 }
@@ -123,7 +141,7 @@ crate::instruction_tests! {
 
     bit_returns_the_correct_number_of_machine_cycles(registers, memory, cpu_flags) => {
         for operand in 0..8 {
-            let cycles = Bit::BIT(operand).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+            let cycles = Bit::BIT(0, Target::from(operand)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
             if operand == 0b110 {
                 assert_eq!(3, cycles, "Incorrect number of machine cycles for HL");
@@ -136,7 +154,7 @@ crate::instruction_tests! {
     bit_sets_zero_flag_to_zero_if_specified_bit_is_one(registers, memory, cpu_flags) => {
         registers.set_single(&SingleRegister::A, 0x80);
 
-        Bit::BIT(0b01_111_111).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        Bit::BIT(7, Target::Register(SingleRegister::A)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
         assert_eq!(false, registers.is_zero());
     }
@@ -144,13 +162,13 @@ crate::instruction_tests! {
     bit_sets_zero_flag_to_one_if_specified_bit_is_zero(registers, memory, cpu_flags) => {
         registers.set_single(&SingleRegister::L, 0xEF);
 
-        Bit::BIT(0b01_100_101).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        Bit::BIT(4, Target::Register(SingleRegister::L)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
         assert_eq!(true, registers.is_zero());
     }
 
     bit_sets_the_half_carry_flag(registers, memory, cpu_flags) => {
-        Bit::BIT(0b01_111_111).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        Bit::BIT(7, Target::Register(SingleRegister::A)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
         assert_eq!(true, registers.is_half_carry());
     }
@@ -158,7 +176,7 @@ crate::instruction_tests! {
     bit_resets_the_negative_flag(registers, memory, cpu_flags) => {
         registers.set_flags(MASK_FLAG_NEGATIVE);
 
-        Bit::BIT(0b01_111_111).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        Bit::BIT(7, Target::Register(SingleRegister::A)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
         assert_eq!(false, registers.is_negative());
     }
@@ -166,20 +184,20 @@ crate::instruction_tests! {
     bit_leaves_carry_flag_unchanged(registers, memory, cpu_flags) => {
         registers.set_flags(MASK_FLAG_CARRY);
 
-        Bit::BIT(0b01_111_111).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        Bit::BIT(7, Target::Register(SingleRegister::A)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
         assert_eq!(true, registers.is_carry());
 
         registers.set_flags(0);
 
-        Bit::BIT(0b01_111_111).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        Bit::BIT(7, Target::Register(SingleRegister::A)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
         assert_eq!(false, registers.is_carry());
     }
 
     set_returns_the_correct_number_of_machine_cycles(registers, memory, cpu_flags) => {
         for operand in 0..8 {
-            let cycles = Bit::SET(operand).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+            let cycles = Bit::SET(0, Target::from(operand)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
             if operand == 0b110 {
                 assert_eq!(4, cycles, "Incorrect number of machine cycles for HL");
@@ -190,15 +208,15 @@ crate::instruction_tests! {
     }
 
     set_sets_the_specified_bit_to_one_in_the_register(registers, memory, cpu_flags) => {
-        for (operand, expected) in vec![(0b11_000_111, 0b0000_0001),
-                                        (0b11_001_111, 0b0000_0010),
-                                        (0b11_010_111, 0b0000_0100),
-                                        (0b11_011_111, 0b0000_1000),
-                                        (0b11_100_111, 0b0001_0000),
-                                        (0b11_101_111, 0b0010_0000),
-                                        (0b11_110_111, 0b0100_0000),
-                                        (0b11_111_111, 0b1000_0000)] {
-            Bit::SET(operand).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        for (bit, expected) in vec![(0, 0b0000_0001),
+                                     (1, 0b0000_0010),
+                                     (2, 0b0000_0100),
+                                     (3, 0b0000_1000),
+                                     (4, 0b0001_0000),
+                                     (5, 0b0010_0000),
+                                     (6, 0b0100_0000),
+                                     (7, 0b1000_0000)] {
+            Bit::SET(bit, Target::Register(SingleRegister::A)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
             assert_eq!(expected, registers.get_single(&SingleRegister::A));
             registers.clear();
         }
@@ -207,16 +225,16 @@ crate::instruction_tests! {
     set_sets_the_specified_bit_to_one_in_memory(registers, memory, cpu_flags) => {
         registers.set_double(&DoubleRegister::HL, 0xABCD);
 
-        for (operand, expected) in vec![(0b11_000_110, 0b0000_0001),
-                                        (0b11_001_110, 0b0000_0010),
-                                        (0b11_010_110, 0b0000_0100),
-                                        (0b11_011_110, 0b0000_1000),
-                                        (0b11_100_110, 0b0001_0000),
-                                        (0b11_101_110, 0b0010_0000),
-                                        (0b11_110_110, 0b0100_0000),
-                                        (0b11_111_110, 0b1000_0000)] {
+        for (bit, expected) in vec![(0, 0b0000_0001),
+                                     (1, 0b0000_0010),
+                                     (2, 0b0000_0100),
+                                     (3, 0b0000_1000),
+                                     (4, 0b0001_0000),
+                                     (5, 0b0010_0000),
+                                     (6, 0b0100_0000),
+                                     (7, 0b1000_0000)] {
             memory.set(registers.get_double(&DoubleRegister::HL).into(), 0);
-            Bit::SET(operand).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+            Bit::SET(bit, Target::HLIndirect).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
             assert_eq!(expected, memory.get(registers.get_double(&DoubleRegister::HL).into()));
         }
@@ -226,7 +244,7 @@ crate::instruction_tests! {
         for flags in vec![0xF0, 0x00] {
             registers.set_flags(flags);
 
-            Bit::SET(0b11_000_111).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+            Bit::SET(0, Target::Register(SingleRegister::A)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
             assert_eq!(flags, registers.get_flags());
         }
@@ -234,7 +252,7 @@ crate::instruction_tests! {
 
     res_returns_the_correct_number_of_machine_cycles(registers, memory, cpu_flags) => {
         for operand in 0..8 {
-            let cycles = Bit::RES(operand).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+            let cycles = Bit::RES(0, Target::from(operand)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
             if operand == 0b110 {
                 assert_eq!(4, cycles, "Incorrect number of machine cycles for HL");
@@ -245,17 +263,17 @@ crate::instruction_tests! {
     }
 
     res_resets_the_specified_bit_to_zero_in_the_register(registers, memory, cpu_flags) => {
-        for (operand, expected) in vec![(0b10_000_111, 0b1111_1110),
-                                        (0b10_001_111, 0b1111_1101),
-                                        (0b10_010_111, 0b1111_1011),
-                                        (0b10_011_111, 0b1111_0111),
-                                        (0b10_100_111, 0b1110_1111),
-                                        (0b10_101_111, 0b1101_1111),
-                                        (0b10_110_111, 0b1011_1111),
-                                        (0b10_111_111, 0b0111_1111)] {
+        for (bit, expected) in vec![(0, 0b1111_1110),
+                                     (1, 0b1111_1101),
+                                     (2, 0b1111_1011),
+                                     (3, 0b1111_0111),
+                                     (4, 0b1110_1111),
+                                     (5, 0b1101_1111),
+                                     (6, 0b1011_1111),
+                                     (7, 0b0111_1111)] {
             registers.set_single(&SingleRegister::A, 0xFF);
 
-            Bit::RES(operand).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+            Bit::RES(bit, Target::Register(SingleRegister::A)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
             assert_eq!(expected, registers.get_single(&SingleRegister::A));
         }
@@ -264,17 +282,17 @@ crate::instruction_tests! {
     res_resets_the_specified_bit_to_zero_in_memory(registers, memory, cpu_flags) => {
         registers.set_double(&DoubleRegister::HL, 0xABCD);
 
-        for (operand, expected) in vec![(0b10_000_110, 0b1111_1110),
-                                        (0b10_001_110, 0b1111_1101),
-                                        (0b10_010_110, 0b1111_1011),
-                                        (0b10_011_110, 0b1111_0111),
-                                        (0b10_100_110, 0b1110_1111),
-                                        (0b10_101_110, 0b1101_1111),
-                                        (0b10_110_110, 0b1011_1111),
-                                        (0b10_111_110, 0b0111_1111)] {
+        for (bit, expected) in vec![(0, 0b1111_1110),
+                                     (1, 0b1111_1101),
+                                     (2, 0b1111_1011),
+                                     (3, 0b1111_0111),
+                                     (4, 0b1110_1111),
+                                     (5, 0b1101_1111),
+                                     (6, 0b1011_1111),
+                                     (7, 0b0111_1111)] {
             memory.set(registers.get_double(&DoubleRegister::HL).into(), 0xFF);
 
-            Bit::RES(operand).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+            Bit::RES(bit, Target::HLIndirect).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
             assert_eq!(expected, memory.get(registers.get_double(&DoubleRegister::HL).into()));
         }
@@ -284,7 +302,7 @@ crate::instruction_tests! {
         for flags in vec![0xF0, 0x00] {
             registers.set_flags(flags);
 
-            Bit::RES(0b11_000_111).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+            Bit::RES(0, Target::Register(SingleRegister::A)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
             assert_eq!(flags, registers.get_flags());
         }
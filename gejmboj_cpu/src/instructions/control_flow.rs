@@ -1,5 +1,12 @@
+use std::fmt::Display;
+
 use crate::instruction_group;
-use crate::{instructions::Condition, registers::DoubleRegister};
+use crate::{
+    errors::CpuError,
+    instructions::{flag_effects::FlagEffects, Condition, Operand},
+    registers::DoubleRegister,
+    stack,
+};
 
 instruction_group! {
     /// Program control flow instructions
@@ -12,7 +19,7 @@ instruction_group! {
         }
 
         /// Conditional jump to location specified by 16-bit operand.
-        JPC(operand: u16, condition: Condition) [3] => {
+        JPC(operand: u16, condition: Condition) [3] as "JP" => {
             if condition.is_fulfilled(registers) {
                 registers.PC = *operand;
                 Ok(4)
@@ -55,9 +62,9 @@ instruction_group! {
             let offset = *operand as i8;
 
             if offset >= 0 {
-                registers.PC += offset as u16;
+                registers.PC = registers.PC.wrapping_add(offset as u16);
             } else {
-                registers.PC -= offset.abs() as u16;
+                registers.PC = registers.PC.wrapping_sub(offset.unsigned_abs() as u16);
             }
 
             Ok(3)
@@ -86,14 +93,14 @@ instruction_group! {
         /// |    0x47F | -             |
         /// |    0x480 | JR            |
         /// |    0x481 | 0xFA          |
-        JRC(operand: u8, condition: Condition) [2] => {
+        JRC(operand: u8, condition: Condition) [2] as "JR" => {
             if condition.is_fulfilled(registers) {
                 let offset = *operand as i8;
 
                 if offset >= 0 {
-                    registers.PC += offset as u16;
+                    registers.PC = registers.PC.wrapping_add(offset as u16);
                 } else {
-                    registers.PC -= offset.abs() as u16;
+                    registers.PC = registers.PC.wrapping_sub(offset.unsigned_abs() as u16);
                 }
 
                 Ok(3)
@@ -104,20 +111,16 @@ instruction_group! {
 
         /// Unconditional call of the function at operand address.
         CALL(operand: u16) [3] => {
-            let sp = registers.decrement_sp();
-            let next_pc = registers.PC + 3;
-            memory.set_u16(sp.into(), next_pc);
+            stack::push_u16(registers, memory, registers.PC);
             registers.PC = *operand;
 
             Ok(6)
         }
 
         /// Conditional function call.
-        CALLC(operand: u16, condition: Condition) [3] => {
+        CALLC(operand: u16, condition: Condition) [3] as "CALL" => {
             if condition.is_fulfilled(registers) {
-                let sp = registers.decrement_sp();
-
-                memory.set_u16(sp.into(), registers.PC);
+                stack::push_u16(registers, memory, registers.PC);
                 registers.PC = *operand;
 
                 Ok(6)
@@ -128,16 +131,14 @@ instruction_group! {
 
         /// Unconditional return from function.
         RET() [1] => {
-            registers.PC = memory.get_u16(registers.SP.into());
-            registers.increment_sp();
+            registers.PC = stack::pop_u16(registers, memory);
             Ok(4)
         }
 
         /// Conditionally return from function.
-        RETC(condition: Condition) [1] => {
+        RETC(condition: Condition) [1] as "RET" => {
             if condition.is_fulfilled(registers) {
-                registers.PC = memory.get_u16(registers.SP.into());
-                registers.increment_sp();
+                registers.PC = stack::pop_u16(registers, memory);
                 Ok(5)
             } else {
                 Ok(2)
@@ -146,8 +147,7 @@ instruction_group! {
 
         /// Unconditional return from a function which enables interrupts
         RETI() [1] => {
-            registers.PC = memory.get_u16(registers.SP.into());
-            registers.increment_sp();
+            registers.PC = stack::pop_u16(registers, memory);
             cpu_flags.IME = true;
             Ok(4)
         }
@@ -165,16 +165,182 @@ instruction_group! {
         /// * `0x30`
         /// * `0x38`
         RST(opcode: u8) [1] => {
+            stack::push_u16(registers, memory, registers.PC);
             registers.PC = get_reset_address(*opcode);
             Ok(4)
         }
     }
 }
 
+impl ControlFlow {
+    /// Returns the machine code of the instruction.
+    pub fn encode(&self) -> Result<Vec<u8>, CpuError> {
+        let with_address = |opcode: u8, address: &u16| {
+            let [lo, hi] = address.to_le_bytes();
+            vec![opcode, lo, hi]
+        };
+
+        Ok(match self {
+            ControlFlow::JP(address) => with_address(0xC3, address),
+            ControlFlow::JPC(address, condition) => {
+                with_address(0xC2 | condition.code() << 3, address)
+            }
+            ControlFlow::JP_HL() => vec![0xE9],
+            ControlFlow::JR(offset) => vec![0x18, *offset],
+            ControlFlow::JRC(offset, condition) => vec![0x20 | condition.code() << 3, *offset],
+            ControlFlow::CALL(address) => with_address(0xCD, address),
+            ControlFlow::CALLC(address, condition) => {
+                with_address(0xC4 | condition.code() << 3, address)
+            }
+            ControlFlow::RET() => vec![0xC9],
+            ControlFlow::RETC(condition) => vec![0xC0 | condition.code() << 3],
+            ControlFlow::RETI() => vec![0xD9],
+            ControlFlow::RST(opcode) => vec![*opcode],
+        })
+    }
+
+    /// Returns the operands of the instruction.
+    pub fn operands(&self) -> Vec<Operand> {
+        match self {
+            ControlFlow::JP(address) | ControlFlow::CALL(address) => vec![Operand::Imm16(*address)],
+            ControlFlow::JPC(address, condition) | ControlFlow::CALLC(address, condition) => {
+                vec![Operand::Condition(*condition), Operand::Imm16(*address)]
+            }
+            ControlFlow::JP_HL() => vec![Operand::Reg16(DoubleRegister::HL)],
+            ControlFlow::JR(offset) => vec![Operand::Offset(*offset as i8)],
+            ControlFlow::JRC(offset, condition) => {
+                vec![
+                    Operand::Condition(*condition),
+                    Operand::Offset(*offset as i8),
+                ]
+            }
+            ControlFlow::RETC(condition) => vec![Operand::Condition(*condition)],
+            ControlFlow::RET() | ControlFlow::RETI() => vec![],
+            ControlFlow::RST(opcode) => vec![Operand::ResetVector(get_reset_address(*opcode))],
+        }
+    }
+
+    /// Returns the effects of the instruction on the flags, none.
+    pub fn flag_effects(&self) -> FlagEffects {
+        FlagEffects::NONE
+    }
+
+    /// Returns `true` for instructions calling a function, i.e. `CALL`, `CALLC` and `RST`.
+    pub fn is_call(&self) -> bool {
+        matches!(
+            self,
+            ControlFlow::CALL(..) | ControlFlow::CALLC(..) | ControlFlow::RST(..)
+        )
+    }
+
+    /// Returns the absolute address a `JR` or `JRC` at `address` jumps to, `None` for other
+    /// instructions. The offset is relative to the next instruction.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::instructions::control_flow::ControlFlow;
+    /// assert_eq!(Some(0x0485), ControlFlow::JR(0x03).relative_target(0x0480));
+    /// assert_eq!(Some(0x047C), ControlFlow::JR(0xFA).relative_target(0x0480));
+    /// assert_eq!(None, ControlFlow::JP(0x0150).relative_target(0x0480));
+    /// ```
+    pub fn relative_target(&self, address: u16) -> Option<u16> {
+        match self {
+            ControlFlow::JR(offset) | ControlFlow::JRC(offset, _) => Some(
+                address
+                    .wrapping_add(self.length())
+                    .wrapping_add(*offset as i8 as u16),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` for instructions returning from a function, i.e. `RET`, `RETC` and `RETI`.
+    pub fn is_return(&self) -> bool {
+        matches!(
+            self,
+            ControlFlow::RET() | ControlFlow::RETC(..) | ControlFlow::RETI()
+        )
+    }
+}
+
+impl Display for ControlFlow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mnemonic = self.mnemonic();
+
+        match self {
+            ControlFlow::JP(address) | ControlFlow::CALL(address) => {
+                write!(f, "{} 0x{:04x}", mnemonic, address)
+            }
+            ControlFlow::JPC(address, condition) | ControlFlow::CALLC(address, condition) => {
+                write!(f, "{} {}, 0x{:04x}", mnemonic, condition, address)
+            }
+            ControlFlow::JP_HL() => write!(f, "{} HL", mnemonic),
+            ControlFlow::JR(offset) => write!(f, "{} {}", mnemonic, *offset as i8),
+            ControlFlow::JRC(offset, condition) => {
+                write!(f, "{} {}, {}", mnemonic, condition, *offset as i8)
+            }
+            ControlFlow::RETC(condition) => write!(f, "{} {}", mnemonic, condition),
+            ControlFlow::RET() | ControlFlow::RETI() => f.write_str(mnemonic),
+            ControlFlow::RST(opcode) => {
+                write!(f, "{} 0x{:02x}", mnemonic, get_reset_address(*opcode))
+            }
+        }
+    }
+}
+
 fn get_reset_address(opcode: u8) -> u16 {
     (opcode & 0b00111000) as u16
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cartridge::mbc5::Mbc5,
+        cpu::CpuFlags,
+        memory::{Diagnostic, Memory},
+        registers::Registers,
+    };
+
+    #[test]
+    fn call_drops_stack_writes_to_rom() {
+        let mut rom = vec![0; 0x10000];
+        rom[0x4000] = 0x01;
+        rom[0xC000] = 0x03;
+        let mut memory = Memory::with_cartridge(Box::new(Mbc5::new(rom, 0, false)));
+        let mut registers = Registers::new();
+        let mut cpu_flags = CpuFlags::new();
+        memory.enable_diagnostics();
+
+        // SP - 2 targets the MBC5 ROM bank register
+        registers.SP = 0x2002;
+        registers.PC = 0x0100;
+        ControlFlow::CALL(0x0200)
+            .execute(&mut registers, &mut memory, &mut cpu_flags)
+            .unwrap();
+
+        assert_eq!(0x0200, registers.PC);
+        assert_eq!(0x2000, registers.SP);
+        assert_eq!(
+            0x01,
+            memory.get(0x4000),
+            "ROM bank was switched by the stack write"
+        );
+        assert_eq!(
+            vec![
+                Diagnostic::StackWriteDropped {
+                    address: 0x2001,
+                    value: 0x01
+                },
+                Diagnostic::StackWriteDropped {
+                    address: 0x2000,
+                    value: 0x00
+                },
+            ],
+            memory.take_diagnostics()
+        );
+    }
+}
+
 #[cfg(test)]
 crate::instruction_tests! {
     jp_jumps_to_address(registers, memory, cpu_flags) => {
@@ -299,7 +465,7 @@ crate::instruction_tests! {
 
         assert_eq!(0xABCD, registers.PC);
         assert_eq!(0xFFFC, registers.SP);
-        assert_eq!(0xAAAD, memory.get_u16(registers.SP.into()));
+        assert_eq!(0xAAAA, memory.get_u16(registers.SP.into()));
     }
 
     call_sets_sp_correctly(registers, memory, cpu_flags) => {
@@ -312,7 +478,7 @@ crate::instruction_tests! {
         assert_eq!(0x1234, registers.PC);
         assert_eq!(0xFFFC, registers.SP);
         assert_eq!(0x80, memory.get(0xFFFD));
-        assert_eq!(0x03, memory.get(0xFFFC));
+        assert_eq!(0x00, memory.get(0xFFFC));
     }
 
     callc_does_not_call_function_if_condition_is_unfulfilled(registers, memory, cpu_flags) => {
@@ -346,7 +512,7 @@ crate::instruction_tests! {
         function_call.execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
         return_call.execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
-        assert_eq!(0xAAAD, registers.PC);
+        assert_eq!(0xAAAA, registers.PC);
         assert_eq!(0xFFFE, registers.SP);
     }
 
@@ -366,7 +532,7 @@ crate::instruction_tests! {
         registers.set_flags(MASK_FLAG_CARRY);
         let cycles = ret.execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
-        assert_eq!(0xAAAD, registers.PC);
+        assert_eq!(0xAAAA, registers.PC);
         assert_eq!(0xFFFE, registers.SP);
         assert_eq!(5, cycles);
     }
@@ -379,7 +545,7 @@ crate::instruction_tests! {
         call.execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
         reti.execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
-        assert_eq!(0xAAAD, registers.PC);
+        assert_eq!(0xAAAA, registers.PC);
         assert_eq!(0xFFFE, registers.SP);
         assert_eq!(true, cpu_flags.IME);
     }
@@ -387,8 +553,11 @@ crate::instruction_tests! {
     rst_calls_function_at_reset_address(registers, memory, cpu_flags) => {
         let instruction = ControlFlow::RST(0b1101_0111);
 
+        registers.PC = 0xAAAA;
         instruction.execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
         assert_eq!(0x10, registers.PC);
+        assert_eq!(0xFFFC, registers.SP);
+        assert_eq!(0xAAAA, memory.get_u16(registers.SP.into()));
     }
 }
@@ -0,0 +1,112 @@
+//! Effects of instructions on the flags, see `Instruction::flag_effects`.
+//!
+//! The effects are written in the notation of the Pan Docs opcode tables, one character per flag
+//! in the order `Z N H C`: `-` leaves the flag unchanged, `0` resets it, `1` sets it and the
+//! name of the flag means it's set depending on the result.
+//!
+//! ```
+//! # use gejmboj_cpu::instructions::{decode_bytes, flag_effects::FlagEffect};
+//! # use gejmboj_cpu::registers::Flag;
+//! // AND A, 0x0f
+//! let (instruction, _) = decode_bytes(&[0xE6, 0x0F]).unwrap();
+//! let effects = instruction.flag_effects();
+//!
+//! assert_eq!("Z010", effects.to_string());
+//! assert_eq!(FlagEffect::Set, effects.effect(Flag::H));
+//! ```
+
+use std::fmt::Display;
+
+use crate::registers::Flag;
+
+/// Effect of an instruction on a single flag.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlagEffect {
+    Unchanged,
+    Reset,
+    Set,
+    /// Set or reset depending on the result
+    Affected,
+}
+
+/// Effects of an instruction on the flags `Z`, `N`, `H` and `C`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlagEffects {
+    pub zero: FlagEffect,
+    pub negative: FlagEffect,
+    pub half_carry: FlagEffect,
+    pub carry: FlagEffect,
+}
+
+impl FlagEffects {
+    /// Leaves all flags unchanged.
+    pub const NONE: FlagEffects = FlagEffects::parse("----");
+
+    /// Parses the Pan Docs notation, e.g. `Z0HC`.
+    ///
+    /// Panics if `notation` isn't 4 characters of `-`, `0`, `1` or the name of the flag.
+    pub const fn parse(notation: &str) -> FlagEffects {
+        let bytes = notation.as_bytes();
+        assert!(bytes.len() == 4, "Flag effects are 4 characters");
+
+        FlagEffects {
+            zero: effect(bytes[0], b'Z'),
+            negative: effect(bytes[1], b'N'),
+            half_carry: effect(bytes[2], b'H'),
+            carry: effect(bytes[3], b'C'),
+        }
+    }
+
+    /// Returns the effect on `flag`.
+    pub fn effect(&self, flag: Flag) -> FlagEffect {
+        match flag {
+            Flag::Z => self.zero,
+            Flag::N => self.negative,
+            Flag::H => self.half_carry,
+            Flag::C => self.carry,
+        }
+    }
+
+    /// Returns `true` if the instruction may change any flag.
+    pub fn changes_flags(&self) -> bool {
+        *self != FlagEffects::NONE
+    }
+
+    /// Returns the bit mask of the flags in register `F` with the given `effect`.
+    pub fn mask(&self, effect: FlagEffect) -> u8 {
+        [Flag::Z, Flag::N, Flag::H, Flag::C]
+            .iter()
+            .filter(|flag| self.effect(**flag) == effect)
+            .fold(0, |mask, flag| mask | flag.mask())
+    }
+}
+
+const fn effect(notation: u8, name: u8) -> FlagEffect {
+    match notation {
+        b'-' => FlagEffect::Unchanged,
+        b'0' => FlagEffect::Reset,
+        b'1' => FlagEffect::Set,
+        _ if notation == name => FlagEffect::Affected,
+        _ => panic!("Unknown flag effect"),
+    }
+}
+
+impl Display for FlagEffects {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (flag, name) in [
+            (Flag::Z, 'Z'),
+            (Flag::N, 'N'),
+            (Flag::H, 'H'),
+            (Flag::C, 'C'),
+        ] {
+            let c = match self.effect(flag) {
+                FlagEffect::Unchanged => '-',
+                FlagEffect::Reset => '0',
+                FlagEffect::Set => '1',
+                FlagEffect::Affected => name,
+            };
+            write!(f, "{}", c)?;
+        }
+        Ok(())
+    }
+}
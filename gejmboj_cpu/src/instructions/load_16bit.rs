@@ -1,5 +1,10 @@
+use std::fmt::Display;
+
+use super::{flag_effects::FlagEffects, utils, Operand};
+use crate::errors::CpuError;
 use crate::instruction_group;
 use crate::registers::DoubleRegister;
+use crate::stack;
 
 instruction_group! {
     /// 16-bit load instructions.
@@ -25,25 +30,113 @@ instruction_group! {
             Ok(2)
         }
 
+        /// Loads SP plus the signed `offset` into HL
+        ///
+        /// **Flags**
+        ///
+        /// | Flag | Effect                              |
+        /// |------|-------------------------------------|
+        /// | `Z`  | `0`                                 |
+        /// | `N`  | `0`                                 |
+        /// | `H`  | Set if carry from bit 3, else reset |
+        /// | `C`  | Set if carry from bit 7, else reset |
+        LD_SP_OFFSET_TO_HL(offset: u8) [2] => {
+            let sp = registers.get_double(&DoubleRegister::SP);
+            let offset = *offset as i8 as u16;
+
+            registers.set_double(&DoubleRegister::HL, sp.wrapping_add(offset));
+            registers.set_flags_from(
+                false,
+                false,
+                (sp & 0xF) + (offset & 0xF) > 0xF,
+                (sp & 0xFF) + (offset & 0xFF) > 0xFF,
+            );
+            Ok(3)
+        }
+
         /// Push data from 16-bit register to stack memory
         PUSH(r: DoubleRegister) [1] => {
-            let sp = registers.decrement_sp();
             let value = registers.get_double(r);
-            memory.set_u16(sp.into(), value);
+            stack::push_u16(registers, memory, value);
             Ok(4)
         }
 
         /// Pop data from stack memory to 16-bit register
         POP(r: DoubleRegister) [1] => {
-            let sp = registers.get_double(&DoubleRegister::SP);
-            let value = memory.get_u16(sp.into());
-            registers.set_double(&r, value);
-            registers.increment_sp();
+            let value = stack::pop_u16(registers, memory);
+            registers.set_double(r, value);
             Ok(3)
         }
     }
 }
 
+impl Load16Bit {
+    /// Returns the machine code of the instruction.
+    pub fn encode(&self) -> Result<Vec<u8>, CpuError> {
+        Ok(match self {
+            Load16Bit::LD(r, operand) => {
+                let [lo, hi] = operand.to_le_bytes();
+                vec![0x01 | utils::double_register_code(r)? << 4, lo, hi]
+            }
+            Load16Bit::LD_FROM_SP(address) => {
+                let [lo, hi] = address.to_le_bytes();
+                vec![0x08, lo, hi]
+            }
+            Load16Bit::LD_HL_TO_SP() => vec![0xF9],
+            Load16Bit::LD_SP_OFFSET_TO_HL(offset) => vec![0xF8, *offset],
+            Load16Bit::PUSH(r) => vec![0xC5 | utils::stack_register_code(r)? << 4],
+            Load16Bit::POP(r) => vec![0xC1 | utils::stack_register_code(r)? << 4],
+        })
+    }
+
+    /// Returns the operands of the instruction.
+    pub fn operands(&self) -> Vec<Operand> {
+        match self {
+            Load16Bit::LD(r, operand) => vec![Operand::Reg16(*r), Operand::Imm16(*operand)],
+            Load16Bit::LD_FROM_SP(address) => {
+                vec![
+                    Operand::Address(*address),
+                    Operand::Reg16(DoubleRegister::SP),
+                ]
+            }
+            Load16Bit::LD_HL_TO_SP() => vec![
+                Operand::Reg16(DoubleRegister::SP),
+                Operand::Reg16(DoubleRegister::HL),
+            ],
+            Load16Bit::LD_SP_OFFSET_TO_HL(offset) => vec![
+                Operand::Reg16(DoubleRegister::HL),
+                Operand::StackOffset(*offset as i8),
+            ],
+            Load16Bit::PUSH(r) | Load16Bit::POP(r) => vec![Operand::Reg16(*r)],
+        }
+    }
+
+    /// Returns the effects of the instruction on the flags.
+    pub fn flag_effects(&self) -> FlagEffects {
+        match self {
+            Load16Bit::POP(DoubleRegister::AF) => FlagEffects::parse("ZNHC"),
+            Load16Bit::LD_SP_OFFSET_TO_HL(_) => FlagEffects::parse("00HC"),
+            _ => FlagEffects::NONE,
+        }
+    }
+}
+
+impl Display for Load16Bit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mnemonic = self.mnemonic();
+
+        match self {
+            Load16Bit::LD(r, operand) => write!(f, "{} {}, 0x{:04x}", mnemonic, r, operand),
+            Load16Bit::LD_FROM_SP(address) => write!(f, "{} (0x{:04x}), SP", mnemonic, address),
+            Load16Bit::LD_HL_TO_SP() => write!(f, "{} SP, HL", mnemonic),
+            Load16Bit::LD_SP_OFFSET_TO_HL(offset) => {
+                write!(f, "{} HL, SP{:+}", mnemonic, *offset as i8)
+            }
+            Load16Bit::PUSH(r) | Load16Bit::POP(r) => write!(f, "{} {}", mnemonic, r),
+        }
+    }
+}
+
 #[cfg(test)]
 crate::instruction_tests! {
     load_16_bit_data_to_registers(registers, memory, cpu_flags) => {
@@ -118,6 +211,16 @@ crate::instruction_tests! {
         assert_eq!(0x7780, memory.get_u16(sp.into()));
     }
 
+    push_drops_stack_writes_to_rom(registers, memory, cpu_flags) => {
+        registers.SP = 0x0002;
+        registers.set_double(&DoubleRegister::BC, 0x1122);
+
+        Load16Bit::PUSH(DoubleRegister::BC).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+
+        assert_eq!(0x0000, registers.SP);
+        assert_eq!(0x0000, memory.get_u16(0x0000));
+    }
+
     pop_stack_memory_to_bc_register(registers, memory, cpu_flags) => {
         let sp = registers.decrement_sp();
         memory.set_u16(sp.into(), 0xABCD);
@@ -158,4 +261,32 @@ crate::instruction_tests! {
         // Lowest nibble (4 bits) of the AF register are unwriteable.
         assert_eq!(0xABC0, registers.get_double(&DoubleRegister::AF));
     }
+
+    ld_sp_offset_to_hl_adds_the_signed_offset(registers, memory, cpu_flags) => {
+        for (sp, offset, expected) in [(0xFFF8, 0x02, 0xFFFA), (0x0005, 0xFE, 0x0003), (0xFFFF, 0x01, 0x0000)] {
+            registers.set_double(&DoubleRegister::SP, sp);
+
+            let cycles = Load16Bit::LD_SP_OFFSET_TO_HL(offset).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+
+            assert_eq!(3, cycles);
+            assert_eq!(expected, registers.get_double(&DoubleRegister::HL), "SP {:04x} offset {:02x}", sp, offset);
+            assert_eq!(sp, registers.get_double(&DoubleRegister::SP));
+        }
+    }
+
+    ld_sp_offset_to_hl_sets_half_carry_and_carry_from_the_low_byte(registers, memory, cpu_flags) => {
+        for (sp, offset, expected_flags) in [(0x0000, 0x01, 0b0000_0000),
+                                             (0x000F, 0x01, 0b0010_0000),
+                                             (0x00F0, 0x10, 0b0001_0000),
+                                             (0x00FF, 0x01, 0b0011_0000),
+                                             (0x0001, 0xFF, 0b0011_0000),
+                                             (0x0000, 0xFF, 0b0000_0000)] {
+            registers.SP = sp;
+            registers.set_flags(0b1100_0000);
+
+            Load16Bit::LD_SP_OFFSET_TO_HL(offset).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+
+            assert_eq!(expected_flags, registers.get_flags(), "SP {:04x} offset {:02x}", sp, offset);
+        }
+    }
 }
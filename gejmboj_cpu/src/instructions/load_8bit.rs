@@ -1,3 +1,7 @@
+use std::fmt::Display;
+
+use super::{flag_effects::FlagEffects, utils, Operand};
+use crate::errors::CpuError;
 use crate::instruction_group;
 use crate::registers::{DoubleRegister, SingleRegister};
 
@@ -121,7 +125,7 @@ instruction_group! {
         LD_A_FROM_HL_DEC() [1] => {
             let address = registers.get_double(&DoubleRegister::HL);
             let value = memory.get(address.into());
-            registers.set_double(&DoubleRegister::HL, address - 1);
+            registers.set_double(&DoubleRegister::HL, address.wrapping_sub(1));
             registers.set_single(&SingleRegister::A, value);
             Ok(2)
         }
@@ -131,7 +135,7 @@ instruction_group! {
             let address = registers.get_double(&DoubleRegister::HL);
             let value = registers.get_single(&SingleRegister::A);
             memory.set(address.into(), value);
-            registers.set_double(&DoubleRegister::HL, address - 1);
+            registers.set_double(&DoubleRegister::HL, address.wrapping_sub(1));
             Ok(2)
         }
 
@@ -139,7 +143,7 @@ instruction_group! {
         LD_A_FROM_HL_INC() [1] => {
             let address = registers.get_double(&DoubleRegister::HL);
             let value = memory.get(address.into());
-            registers.set_double(&DoubleRegister::HL, address + 1);
+            registers.set_double(&DoubleRegister::HL, address.wrapping_add(1));
             registers.set_single(&SingleRegister::A, value);
             Ok(2)
         }
@@ -149,12 +153,105 @@ instruction_group! {
             let address = registers.get_double(&DoubleRegister::HL);
             let value = registers.get_single(&SingleRegister::A);
             memory.set(address.into(), value);
-            registers.set_double(&DoubleRegister::HL, address + 1);
+            registers.set_double(&DoubleRegister::HL, address.wrapping_add(1));
             Ok(2)
         }
     }
 }
 
+impl Load8Bit {
+    /// Returns the machine code of the instruction.
+    pub fn encode(&self) -> Result<Vec<u8>, CpuError> {
+        let r = |r| utils::single_register_code(r);
+        let with_address = |opcode: u8, address: &u16| {
+            let [lo, hi] = address.to_le_bytes();
+            vec![opcode, lo, hi]
+        };
+
+        Ok(match self {
+            Load8Bit::LD(r1, r2) => vec![0x40 | r(r1) << 3 | r(r2)],
+            Load8Bit::LD_FROM_HL(x) => vec![0x46 | r(x) << 3],
+            Load8Bit::LD_TO_HL(x) => vec![0x70 | r(x)],
+            Load8Bit::LD_N(x, operand) => vec![0x06 | r(x) << 3, *operand],
+            Load8Bit::LD_N_TO_HL(operand) => vec![0x36, *operand],
+            Load8Bit::LD_BC_TO_A() => vec![0x0A],
+            Load8Bit::LD_DE_TO_A() => vec![0x1A],
+            Load8Bit::LD_A_TO_BC() => vec![0x02],
+            Load8Bit::LD_A_TO_DE() => vec![0x12],
+            Load8Bit::LD_TO_A(address) => with_address(0xFA, address),
+            Load8Bit::LD_FROM_A(address) => with_address(0xEA, address),
+            Load8Bit::LDH_C_TO_A() => vec![0xF2],
+            Load8Bit::LDH_C_FROM_A() => vec![0xE2],
+            Load8Bit::LDH_TO_A(operand) => vec![0xF0, *operand],
+            Load8Bit::LDH_FROM_A(operand) => vec![0xE0, *operand],
+            Load8Bit::LD_A_FROM_HL_DEC() => vec![0x3A],
+            Load8Bit::LD_A_TO_HL_DEC() => vec![0x32],
+            Load8Bit::LD_A_FROM_HL_INC() => vec![0x2A],
+            Load8Bit::LD_A_TO_HL_INC() => vec![0x22],
+        })
+    }
+
+    /// Returns the operands of the instruction.
+    pub fn operands(&self) -> Vec<Operand> {
+        let a = Operand::Reg8(SingleRegister::A);
+
+        match self {
+            Load8Bit::LD(r1, r2) => vec![Operand::Reg8(*r1), Operand::Reg8(*r2)],
+            Load8Bit::LD_FROM_HL(r) => vec![Operand::Reg8(*r), Operand::HLIndirect],
+            Load8Bit::LD_TO_HL(r) => vec![Operand::HLIndirect, Operand::Reg8(*r)],
+            Load8Bit::LD_N(r, operand) => vec![Operand::Reg8(*r), Operand::Imm8(*operand)],
+            Load8Bit::LD_N_TO_HL(operand) => vec![Operand::HLIndirect, Operand::Imm8(*operand)],
+            Load8Bit::LD_BC_TO_A() => vec![a, Operand::Indirect(DoubleRegister::BC)],
+            Load8Bit::LD_DE_TO_A() => vec![a, Operand::Indirect(DoubleRegister::DE)],
+            Load8Bit::LD_A_TO_BC() => vec![Operand::Indirect(DoubleRegister::BC), a],
+            Load8Bit::LD_A_TO_DE() => vec![Operand::Indirect(DoubleRegister::DE), a],
+            Load8Bit::LD_TO_A(address) => vec![a, Operand::Address(*address)],
+            Load8Bit::LD_FROM_A(address) => vec![Operand::Address(*address), a],
+            Load8Bit::LDH_C_TO_A() => vec![a, Operand::HighC],
+            Load8Bit::LDH_C_FROM_A() => vec![Operand::HighC, a],
+            Load8Bit::LDH_TO_A(operand) => vec![a, Operand::HighAddress(*operand)],
+            Load8Bit::LDH_FROM_A(operand) => vec![Operand::HighAddress(*operand), a],
+            Load8Bit::LD_A_FROM_HL_DEC() => vec![a, Operand::HLDecrement],
+            Load8Bit::LD_A_TO_HL_DEC() => vec![Operand::HLDecrement, a],
+            Load8Bit::LD_A_FROM_HL_INC() => vec![a, Operand::HLIncrement],
+            Load8Bit::LD_A_TO_HL_INC() => vec![Operand::HLIncrement, a],
+        }
+    }
+
+    /// Returns the effects of the instruction on the flags, none.
+    pub fn flag_effects(&self) -> FlagEffects {
+        FlagEffects::NONE
+    }
+}
+
+impl Display for Load8Bit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ", self.mnemonic())?;
+
+        match self {
+            Load8Bit::LD(r1, r2) => write!(f, "{}, {}", r1, r2),
+            Load8Bit::LD_FROM_HL(r) => write!(f, "{}, (HL)", r),
+            Load8Bit::LD_TO_HL(r) => write!(f, "(HL), {}", r),
+            Load8Bit::LD_N(r, operand) => write!(f, "{}, 0x{:02x}", r, operand),
+            Load8Bit::LD_N_TO_HL(operand) => write!(f, "(HL), 0x{:02x}", operand),
+            Load8Bit::LD_BC_TO_A() => f.write_str("A, (BC)"),
+            Load8Bit::LD_DE_TO_A() => f.write_str("A, (DE)"),
+            Load8Bit::LD_A_TO_BC() => f.write_str("(BC), A"),
+            Load8Bit::LD_A_TO_DE() => f.write_str("(DE), A"),
+            Load8Bit::LD_TO_A(address) => write!(f, "A, (0x{:04x})", address),
+            Load8Bit::LD_FROM_A(address) => write!(f, "(0x{:04x}), A", address),
+            Load8Bit::LDH_C_TO_A() => f.write_str("A, (C)"),
+            Load8Bit::LDH_C_FROM_A() => f.write_str("(C), A"),
+            Load8Bit::LDH_TO_A(operand) => write!(f, "A, (0xff{:02x})", operand),
+            Load8Bit::LDH_FROM_A(operand) => write!(f, "(0xff{:02x}), A", operand),
+            Load8Bit::LD_A_FROM_HL_DEC() => f.write_str("A, (HL-)"),
+            Load8Bit::LD_A_TO_HL_DEC() => f.write_str("(HL-), A"),
+            Load8Bit::LD_A_FROM_HL_INC() => f.write_str("A, (HL+)"),
+            Load8Bit::LD_A_TO_HL_INC() => f.write_str("(HL+), A"),
+        }
+    }
+}
+
 #[cfg(test)]
 crate::instruction_tests! {
     load_data_from_register_r2_into_register_r1(registers, memory, cpu_flags) => {
@@ -1,9 +1,17 @@
+use std::fmt::Display;
+
 use crate::{
+    errors::CpuError,
     instruction_group,
-    instructions::utils,
-    registers::{SingleRegister, MASK_FLAG_CARRY, MASK_FLAG_ZERO},
+    instructions::{flag_effects::FlagEffects, utils, Operand},
+    registers::{Flag, SingleRegister},
 };
 
+/// Opcodes which are not assigned to any instruction, the CPU locks up when executing them.
+pub const ILLEGAL_OPCODES: [u8; 11] = [
+    0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD,
+];
+
 instruction_group! {
     /// Miscelleneous instructions
     ///
@@ -32,6 +40,13 @@ instruction_group! {
             Ok(1)
         }
 
+        /// Stops executing instructions until an enabled interrupt is requested, see
+        /// `CpuFlags::halted`
+        HALT() [1] => {
+            cpu_flags.halted = true;
+            Ok(1)
+        }
+
         /// Schedules interrupt handling to be enabled after the next machine cycle
         EI() [1] => {
             cpu_flags.IME_scheduled = true;
@@ -40,19 +55,18 @@ instruction_group! {
 
         /// Flips the carry flag (C) and clears the negative (N) and half-carry (H) flags
         CCF() [1] => {
-            let value = registers.get_flags();
-            let value = value & 0b1001_0000; // Clear N and H flags
-            let value = value ^ 0b0001_0000; // Flip C
-            registers.set_flags(value);
+            let carry = registers.get_flag(Flag::C);
+            registers.clear_flag(Flag::N);
+            registers.clear_flag(Flag::H);
+            registers.assign_flag(Flag::C, !carry);
             Ok(1)
         }
 
         /// Sets the carry flag (C) and clears the negative (N) and half-carry (H) flags
         SCF() [1] => {
-            let value = registers.get_flags();
-            let value = value & 0b1001_0000; // Clear N and H flags
-            let value = value | 0b0001_0000; // Set C
-            registers.set_flags(value);
+            registers.clear_flag(Flag::N);
+            registers.clear_flag(Flag::H);
+            registers.set_flag(Flag::C);
             Ok(1)
         }
 
@@ -111,14 +125,14 @@ instruction_group! {
         DAA() [1] => {
             let a = registers.get_single(&SingleRegister::A);
             let mut bcd_correction = 0;
-            let mut flags = 0;
+            let mut carry = false;
 
             if registers.is_half_carry() || (a & 0xF) > 9 {
                 bcd_correction = bcd_correction | 0x6;
             }
             if registers.is_carry() || a > 0x99 {
-                bcd_correction = bcd_correction | 0x60;
-                flags = flags | MASK_FLAG_CARRY;
+                bcd_correction |= 0x60;
+                carry = true;
             }
 
             if registers.is_negative() {
@@ -128,26 +142,71 @@ instruction_group! {
             let bcd = a.wrapping_add(bcd_correction);
             registers.set_single(&SingleRegister::A, bcd);
 
-            if bcd == 0 {
-                flags = flags | MASK_FLAG_ZERO;
-            }
-
-            registers.set_flags(flags);
+            let negative = registers.is_negative();
+            registers.set_flags_from(bcd == 0, negative, false, carry);
             Ok(1)
         }
 
         /// Flips all bits in the A register and sets the negative (N) and half-carry (H) flags
         CPL() [1] => {
-            let flags = registers.get_flags();
-            let flags = flags | 0b0110_0000; // Set N and H
-
             let value = registers.get_single(&SingleRegister::A);
             let value = value ^ 0b1111_1111; // Flip all bits
 
-            registers.set_flags(flags);
+            registers.set_flag(Flag::N);
+            registers.set_flag(Flag::H);
             registers.set_single(&SingleRegister::A, value);
             Ok(1)
         }
+
+        /// One of the `ILLEGAL_OPCODES`. Executing it fails, locking up the CPU is left to `CPU`.
+        ILLEGAL(opcode: u8) [1] => {
+            Err(CpuError::UnknownInstruction(*opcode))
+        }
+    }
+}
+
+impl Misc {
+    /// Returns the machine code of the instruction.
+    pub fn encode(&self) -> Result<Vec<u8>, CpuError> {
+        Ok(vec![match self {
+            Misc::NOP() => 0x00,
+            Misc::HALT() => 0x76,
+            Misc::DI() => 0xF3,
+            Misc::EI() => 0xFB,
+            Misc::CCF() => 0x3F,
+            Misc::SCF() => 0x37,
+            Misc::DAA() => 0x27,
+            Misc::CPL() => 0x2F,
+            Misc::ILLEGAL(opcode) => *opcode,
+        }])
+    }
+
+    /// Returns the operands of the instruction, the opcode of `ILLEGAL`.
+    pub fn operands(&self) -> Vec<Operand> {
+        match self {
+            Misc::ILLEGAL(opcode) => vec![Operand::Imm8(*opcode)],
+            _ => vec![],
+        }
+    }
+
+    /// Returns the effects of the instruction on the flags.
+    pub fn flag_effects(&self) -> FlagEffects {
+        FlagEffects::parse(match self {
+            Misc::NOP() | Misc::HALT() | Misc::DI() | Misc::EI() | Misc::ILLEGAL(_) => "----",
+            Misc::CCF() => "-00C",
+            Misc::SCF() => "-001",
+            Misc::DAA() => "Z-0C",
+            Misc::CPL() => "-11-",
+        })
+    }
+}
+
+impl Display for Misc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Misc::ILLEGAL(opcode) => write!(f, "{} 0x{:02x}", self.mnemonic(), opcode),
+            _ => f.write_str(self.mnemonic()),
+        }
     }
 }
 
@@ -0,0 +1,297 @@
+//! # Opcode table
+//!
+//! Metadata of all 512 opcodes, the 256 unprefixed ones followed by the 256 `CB` prefixed ones.
+//! The table is generated from the instruction definitions: every opcode is decoded, and
+//! executed once with all flags cleared and once with all flags set, which covers both outcomes
+//! of conditional instructions. Opcodes which are not decoded or illegal, and the `CB` prefix
+//! itself, have no entry.
+//!
+//! ```
+//! # use gejmboj_cpu::instructions::opcodes;
+//! // JP NZ, a16
+//! let info = opcodes::lookup(0xC2).unwrap();
+//! assert_eq!(("JP", 3), (info.mnemonic, info.length));
+//! assert!(info.min_cycles < info.max_cycles);
+//!
+//! // SWAP A
+//! assert_eq!("SWAP", opcodes::lookup_cb(0x37).unwrap().mnemonic);
+//! assert_eq!("Z000", opcodes::lookup_cb(0x37).unwrap().flags.to_string());
+//! assert_eq!(512, opcodes::table().len());
+//! ```
+//!
+//! The decoder of unprefixed opcodes is generated from a table of bit patterns, which is
+//! available as `instructions::OPCODE_PATTERNS`:
+//!
+//! ```
+//! # use gejmboj_cpu::instructions::opcodes;
+//! let pattern = opcodes::pattern(0xC2).unwrap();
+//! assert_eq!(("110cd010", "JP", "JPC"), (pattern.bits, pattern.mnemonic, pattern.variant));
+//! ```
+
+use std::sync::OnceLock;
+
+use crate::{
+    cpu::CpuFlags,
+    memory::Memory,
+    registers::{DoubleRegister, Registers},
+};
+
+use super::{decode, flag_effects::FlagEffects, OPCODE_PATTERNS};
+
+/// Number of opcodes: the 256 opcodes and the 256 `CB` prefixed ones.
+pub const OPCODES: usize = 0x200;
+
+/// Metadata of an opcode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OpcodeInfo {
+    /// The opcode, `CB` prefixed opcodes as `0xCBxx`
+    pub opcode: u16,
+    pub mnemonic: &'static str,
+    /// Length in bytes, including the `CB` prefix and immediate operands
+    pub length: u16,
+    /// Machine cycles when a condition is not met
+    pub min_cycles: u16,
+    /// Machine cycles when a condition is met
+    pub max_cycles: u16,
+    pub flags: FlagEffects,
+}
+
+/// A row of the table the decoder of unprefixed opcodes is generated from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OpcodePattern {
+    /// The bits of the opcode, most significant first. `0` and `1` have to match, any other
+    /// character is an operand bit.
+    pub bits: &'static str,
+    pub mnemonic: &'static str,
+    /// The instruction group, e.g. `ControlFlow`
+    pub group: &'static str,
+    /// The instruction within the group, e.g. `JPC`
+    pub variant: &'static str,
+}
+
+impl OpcodePattern {
+    /// Returns `true` if `opcode` matches the fixed bits of the pattern.
+    pub fn matches(&self, opcode: u8) -> bool {
+        self.bits.bytes().enumerate().all(|(index, bit)| match bit {
+            b'0' | b'1' => (opcode >> (7 - index)) & 1 == bit - b'0',
+            _ => true,
+        })
+    }
+}
+
+/// Returns the pattern the decoder decodes the unprefixed `opcode` by, `None` for illegal
+/// opcodes, the `CB` prefix and opcodes the decoder doesn't support.
+pub fn pattern(opcode: u8) -> Option<&'static OpcodePattern> {
+    if opcode == 0xCB || crate::instructions::misc::ILLEGAL_OPCODES.contains(&opcode) {
+        return None;
+    }
+    OPCODE_PATTERNS
+        .iter()
+        .find(|pattern| pattern.matches(opcode))
+}
+
+/// Returns the table of all opcodes, indexed by the opcode or `0x100 | opcode` for `CB`
+/// prefixed ones.
+pub fn table() -> &'static [Option<OpcodeInfo>] {
+    static TABLE: OnceLock<Vec<Option<OpcodeInfo>>> = OnceLock::new();
+
+    TABLE.get_or_init(|| {
+        let mut memory = Memory::new();
+        (0..OPCODES)
+            .map(|index| generate(index, &mut memory))
+            .collect()
+    })
+}
+
+/// Returns the metadata of the unprefixed `opcode`.
+pub fn lookup(opcode: u8) -> Option<&'static OpcodeInfo> {
+    table()[opcode as usize].as_ref()
+}
+
+/// Returns the metadata of the `CB` prefixed `opcode`.
+pub fn lookup_cb(opcode: u8) -> Option<&'static OpcodeInfo> {
+    table()[0x100 | opcode as usize].as_ref()
+}
+
+fn generate(index: usize, memory: &mut Memory) -> Option<OpcodeInfo> {
+    const ADDRESS: usize = 0xC000;
+    let (opcode, bytes) = match index {
+        0x100..=0x1FF => (0xCB00 | (index as u16 & 0xFF), [0xCB, index as u8, 0x00]),
+        _ => (index as u16, [index as u8, 0x00, 0x00]),
+    };
+    if index == 0xCB {
+        return None;
+    }
+    memory.load(ADDRESS, &bytes);
+    let (instruction, length) = decode(bytes[0], ADDRESS as u16, memory).ok()?;
+    if instruction.is_illegal() {
+        return None;
+    }
+
+    let cycles: Vec<u16> = [0x00, 0xF0]
+        .iter()
+        .filter_map(|flags| {
+            let mut registers = Registers::new();
+            registers.PC = ADDRESS as u16;
+            registers.SP = 0xD000;
+            // Pointers into work RAM which don't overflow when incremented or decremented
+            for r in [DoubleRegister::BC, DoubleRegister::DE, DoubleRegister::HL] {
+                registers.set_double(&r, 0xC100);
+            }
+            registers.set_flags(*flags);
+            instruction
+                .execute(&mut registers, memory, &mut CpuFlags::new())
+                .ok()
+                .map(|execution| execution.cycles)
+        })
+        .collect();
+
+    Some(OpcodeInfo {
+        opcode,
+        mnemonic: instruction.mnemonic(),
+        length: length as u16,
+        min_cycles: cycles.iter().copied().min().unwrap_or_default(),
+        max_cycles: cycles.iter().copied().max().unwrap_or_default(),
+        flags: instruction.flag_effects(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::flag_effects::FlagEffect;
+
+    /// Machine cycles of the unprefixed opcodes when conditions are met, `0` for unused opcodes
+    /// and the `CB` prefix.
+    #[rustfmt::skip]
+    const CYCLES: [u16; 256] = [
+        1, 3, 2, 2, 1, 1, 2, 1, 5, 2, 2, 2, 1, 1, 2, 1,
+        1, 3, 2, 2, 1, 1, 2, 1, 3, 2, 2, 2, 1, 1, 2, 1,
+        3, 3, 2, 2, 1, 1, 2, 1, 3, 2, 2, 2, 1, 1, 2, 1,
+        3, 3, 2, 2, 3, 3, 3, 1, 3, 2, 2, 2, 1, 1, 2, 1,
+        1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+        1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+        1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+        2, 2, 2, 2, 2, 2, 1, 2, 1, 1, 1, 1, 1, 1, 2, 1,
+        1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+        1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+        1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+        1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+        5, 3, 4, 4, 6, 4, 2, 4, 5, 4, 4, 0, 6, 6, 2, 4,
+        5, 3, 4, 0, 6, 4, 2, 4, 5, 4, 4, 0, 6, 0, 2, 4,
+        3, 3, 2, 0, 0, 4, 2, 4, 4, 1, 4, 0, 0, 0, 2, 4,
+        3, 3, 2, 1, 0, 4, 2, 4, 3, 2, 4, 1, 0, 0, 2, 4,
+    ];
+
+    #[test]
+    fn cycles_match_the_documented_timings() {
+        for info in table().iter().flatten() {
+            let expected = match info.opcode {
+                0xCB00..=0xCBFF => match info.opcode & 0xC7 {
+                    0x46 => 3,
+                    0x06 | 0x86 | 0xC6 => 4,
+                    _ => 2,
+                },
+                opcode => CYCLES[opcode as usize],
+            };
+
+            assert_eq!(expected, info.max_cycles, "{:04x}", info.opcode);
+        }
+    }
+
+    #[test]
+    fn conditional_opcodes_have_two_timings() {
+        let conditional: Vec<u16> = table()
+            .iter()
+            .flatten()
+            .filter(|x| x.min_cycles != x.max_cycles)
+            .map(|x| x.opcode)
+            .collect();
+
+        assert_eq!(
+            vec![
+                0x20, 0x28, 0x30, 0x38, 0xC0, 0xC2, 0xC4, 0xC8, 0xCA, 0xCC, 0xD0, 0xD2, 0xD4, 0xD8,
+                0xDA, 0xDC
+            ],
+            conditional
+        );
+        assert_eq!(None, lookup(0xCB));
+    }
+
+    #[test]
+    fn patterns_match_the_decoded_instructions() {
+        let memory = Memory::new();
+
+        for opcode in 0..=0xFF {
+            let decoded = decode(opcode, 0xC000, &memory);
+            match pattern(opcode) {
+                Some(pattern) => {
+                    let (instruction, _) = decoded.unwrap();
+                    let debug = format!("{:?}", instruction);
+                    let prefix = format!("{}({}", pattern.group, pattern.variant);
+
+                    assert_eq!(pattern.mnemonic, instruction.mnemonic(), "{:02x}", opcode);
+                    assert_eq!(pattern.group, instruction.group_name(), "{:02x}", opcode);
+                    assert!(
+                        debug
+                            .strip_prefix(&prefix)
+                            .is_some_and(|rest| rest.starts_with(['(', ')'])),
+                        "{:02x}: {}",
+                        opcode,
+                        debug
+                    );
+                }
+                None if opcode == 0xCB => {}
+                None => assert!(decoded.map_or(true, |(x, _)| x.is_illegal())),
+            }
+        }
+    }
+
+    #[test]
+    fn flag_effects_match_execution() {
+        let mut memory = Memory::new();
+
+        for info in table().iter().flatten() {
+            let [prefix, opcode] = info.opcode.to_be_bytes();
+            let effects = info.flags;
+
+            for flags in [0x00, 0xF0, 0x50, 0xA0] {
+                for value in [0x00, 0x01, 0x0F, 0x80, 0xFF] {
+                    let bytes = match prefix {
+                        0xCB => [0xCB, opcode, value],
+                        // 16-bit operands point into work RAM
+                        _ => [opcode, value, 0xC1],
+                    };
+                    memory.load(0xC000, &bytes);
+                    memory.load(0xC100, &[value, value]);
+                    let (instruction, _) = decode(bytes[0], 0xC000, &memory).unwrap();
+
+                    let mut registers = Registers::new();
+                    registers.PC = 0xC000;
+                    registers.SP = 0xC100;
+                    registers.set_double(&DoubleRegister::BC, 0xC100);
+                    registers.set_double(&DoubleRegister::DE, 0xC100 | value as u16);
+                    registers.set_double(&DoubleRegister::HL, 0xC100);
+                    registers.set_single(&crate::registers::SingleRegister::A, value);
+                    registers.set_flags(flags);
+                    instruction
+                        .execute(&mut registers, &mut memory, &mut CpuFlags::new())
+                        .unwrap();
+
+                    let result = registers.get_flags();
+                    let unchanged = effects.mask(FlagEffect::Unchanged);
+                    let set = effects.mask(FlagEffect::Set);
+                    let reset = effects.mask(FlagEffect::Reset);
+                    let context = format!(
+                        "{} ({}) F={:02x} n={:02x}",
+                        instruction, effects, flags, value
+                    );
+
+                    assert_eq!(flags & unchanged, result & unchanged, "{}", context);
+                    assert_eq!(set, result & set, "{}", context);
+                    assert_eq!(0, result & reset, "{}", context);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Operands of instructions, see `Instruction::operands`.
+//!
+//! ```
+//! # use gejmboj_cpu::instructions::{decode_bytes, Operand};
+//! # use gejmboj_cpu::registers::SingleRegister;
+//! // LD (0xff80), A
+//! let (instruction, _) = decode_bytes(&[0xE0, 0x80]).unwrap();
+//!
+//! assert_eq!(
+//!     vec![Operand::HighAddress(0x80), Operand::Reg8(SingleRegister::A)],
+//!     instruction.operands()
+//! );
+//! ```
+
+use std::fmt::Display;
+
+use crate::registers::{DoubleRegister, SingleRegister};
+
+use super::{Condition, Target};
+
+/// An operand of an instruction, printed as by the disassembler.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+    /// 8-bit register
+    Reg8(SingleRegister),
+    /// 16-bit register
+    Reg16(DoubleRegister),
+    /// 8-bit immediate
+    Imm8(u8),
+    /// 16-bit immediate
+    Imm16(u16),
+    /// Signed 8-bit immediate, e.g. the offset of `JR`
+    Offset(i8),
+    /// `SP+e`, the stack pointer plus a signed 8-bit immediate
+    StackOffset(i8),
+    /// `(BC)` or `(DE)`, the memory contents pointed to by the register
+    Indirect(DoubleRegister),
+    /// `(HL)`, the memory contents pointed to by HL
+    HLIndirect,
+    /// `(HL+)`, HL is incremented after the access
+    HLIncrement,
+    /// `(HL-)`, HL is decremented after the access
+    HLDecrement,
+    /// `(a16)`, the memory contents at the address
+    Address(u16),
+    /// `(0xff00 + a8)`, the memory contents at the address in the high page
+    HighAddress(u8),
+    /// `(0xff00 + C)`
+    HighC,
+    /// Condition of a conditional jump, call or return
+    Condition(Condition),
+    /// Bit index of bit operations
+    Bit(u8),
+    /// Address called by `RST`
+    ResetVector(u16),
+}
+
+impl From<Target> for Operand {
+    fn from(target: Target) -> Self {
+        match target {
+            Target::Register(r) => Operand::Reg8(r),
+            Target::HLIndirect => Operand::HLIndirect,
+        }
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operand::Reg8(r) => write!(f, "{}", r),
+            Operand::Reg16(r) => write!(f, "{}", r),
+            Operand::Imm8(n) => write!(f, "0x{:02x}", n),
+            Operand::Imm16(n) => write!(f, "0x{:04x}", n),
+            Operand::Offset(e) => write!(f, "{}", e),
+            Operand::StackOffset(e) => write!(f, "SP{:+}", e),
+            Operand::Indirect(r) => write!(f, "({})", r),
+            Operand::HLIndirect => f.write_str("(HL)"),
+            Operand::HLIncrement => f.write_str("(HL+)"),
+            Operand::HLDecrement => f.write_str("(HL-)"),
+            Operand::Address(address) => write!(f, "(0x{:04x})", address),
+            Operand::HighAddress(offset) => write!(f, "(0xff{:02x})", offset),
+            Operand::HighC => f.write_str("(C)"),
+            Operand::Condition(condition) => write!(f, "{}", condition),
+            Operand::Bit(bit) => write!(f, "{}", bit),
+            Operand::ResetVector(address) => write!(f, "0x{:02x}", address),
+        }
+    }
+}
@@ -1,4 +1,6 @@
-use super::utils::{self, get_register_value};
+use std::fmt::Display;
+
+use super::{flag_effects::FlagEffects, utils, Operand, Target};
 /// Rotate Shift instructions
 ///
 /// Some of the Rotate Shift instructions share their opcode and it's necessary to
@@ -31,7 +33,7 @@ use super::utils::{self, get_register_value};
 use crate::{
     errors::CpuError,
     instruction_group,
-    registers::{DoubleRegister, SingleRegister, MASK_FLAG_CARRY, MASK_FLAG_ZERO},
+    registers::{SingleRegister, MASK_FLAG_CARRY, MASK_FLAG_ZERO},
 };
 
 /// Decodes the `operand` into a `RotateShift` instruction.
@@ -48,14 +50,14 @@ use crate::{
 /// | `00_111_rrr` | `Srl`       |
 pub fn decode(operand: u8) -> Result<RotateShift, CpuError> {
     match utils::into_bits(operand) {
-        (0, 0, 0, 0, 0, _, _, _) => Ok(RotateShift::RLC(operand)),
-        (0, 0, 0, 0, 1, _, _, _) => Ok(RotateShift::RRC(operand)),
-        (0, 0, 0, 1, 0, _, _, _) => Ok(RotateShift::RL(operand)),
-        (0, 0, 0, 1, 1, _, _, _) => Ok(RotateShift::RR(operand)),
-        (0, 0, 1, 0, 0, _, _, _) => Ok(RotateShift::SLA(operand)),
-        (0, 0, 1, 0, 1, _, _, _) => Ok(RotateShift::SRA(operand)),
-        (0, 0, 1, 1, 0, _, _, _) => Ok(RotateShift::SWAP(operand)),
-        (0, 0, 1, 1, 1, _, _, _) => Ok(RotateShift::SRL(operand)),
+        (0, 0, 0, 0, 0, _, _, _) => Ok(RotateShift::RLC(operand.into())),
+        (0, 0, 0, 0, 1, _, _, _) => Ok(RotateShift::RRC(operand.into())),
+        (0, 0, 0, 1, 0, _, _, _) => Ok(RotateShift::RL(operand.into())),
+        (0, 0, 0, 1, 1, _, _, _) => Ok(RotateShift::RR(operand.into())),
+        (0, 0, 1, 0, 0, _, _, _) => Ok(RotateShift::SLA(operand.into())),
+        (0, 0, 1, 0, 1, _, _, _) => Ok(RotateShift::SRA(operand.into())),
+        (0, 0, 1, 1, 0, _, _, _) => Ok(RotateShift::SWAP(operand.into())),
+        (0, 0, 1, 1, 1, _, _, _) => Ok(RotateShift::SRL(operand.into())),
         _ => Err(CpuError::UnknownInstruction(operand)),
     }
 }
@@ -163,8 +165,8 @@ impl Op {
 instruction_group! {
     /// Bit rotate and shift instructions.
     ///
-    /// Some instructions operate on `m` which is indicated by the 8-bit operand passed
-    /// to the instruction as per the following table:
+    /// Some instructions operate on `m`, a `Target` decoded from the lowest bits of the `CB`
+    /// prefixed operand as per the following table:
     ///
     /// | Operand     | Target                                                    |
     /// |-------------|-----------------------------------------------------------|
@@ -265,22 +267,14 @@ instruction_group! {
         /// | N    | `0`           |
         /// | H    | `0`           |
         /// | C    | m<sup>7</sup> |
-        RLC(operand: u8) [2] => {
-            let (value, register) = get_register_value(registers, memory, *operand);
+        RLC(target: Target) [2] => {
+            let value = target.get(registers, memory);
             let (result, flags) = Op::RotateLeft(value).execute(0, &OpConfig::builder().set_z().build());
 
             registers.set_flags(flags);
 
-            match register {
-                Some(r) => {
-                    registers.set_single(&r, result);
-                    Ok(2)
-                }
-                None => {
-                    memory.set(registers.get_double(&DoubleRegister::HL).into(), result);
-                    Ok(4)
-                }
-            }
+            target.set(registers, memory, result);
+            Ok(target.read_modify_write_cycles())
         }
 
         /// Rotates contents of `m` to the left.
@@ -295,8 +289,8 @@ instruction_group! {
         /// | N    | `0`           |
         /// | H    | `0`           |
         /// | C    | m<sup>7</sup> |
-        RL(operand: u8) [2] => {
-            let (value, register) = get_register_value(registers, memory, *operand);
+        RL(target: Target) [2] => {
+            let value = target.get(registers, memory);
             let (result, flags) = Op::RotateLeft(value).execute(
                 registers.get_flags() & MASK_FLAG_CARRY,
                 &OpConfig::builder().add_carry().set_z().build()
@@ -304,16 +298,8 @@ instruction_group! {
 
             registers.set_flags(flags);
 
-            match register {
-                Some(r) => {
-                    registers.set_single(&r, result);
-                    Ok(2)
-                },
-                None => {
-                    memory.set(registers.get_double(&DoubleRegister::HL).into(), result);
-                    Ok(4)
-                }
-            }
+            target.set(registers, memory, result);
+            Ok(target.read_modify_write_cycles())
         }
 
         /// Rotates contents of `m` to the right.
@@ -328,22 +314,14 @@ instruction_group! {
         /// | N    | `0`           |
         /// | H    | `0`           |
         /// | C    | m<sup>0</sup> |
-        RRC(operand: u8) [2] => {
-            let (value, register) = get_register_value(registers, memory, *operand);
+        RRC(target: Target) [2] => {
+            let value = target.get(registers, memory);
             let (result, flags) = Op::RotateRight(value).execute(0, &OpConfig::builder().set_z().build());
 
             registers.set_flags(flags);
 
-            match register {
-                Some(r) => {
-                    registers.set_single(&r, result);
-                    Ok(2)
-                }
-                None => {
-                    memory.set(registers.get_double(&DoubleRegister::HL).into(), result);
-                    Ok(4)
-                }
-            }
+            target.set(registers, memory, result);
+            Ok(target.read_modify_write_cycles())
         }
 
         /// Rotates contents of `m` to the right.
@@ -358,8 +336,8 @@ instruction_group! {
         /// | N    | `0`           |
         /// | H    | `0`           |
         /// | C    | m<sup>0</sup> |
-        RR(operand: u8) [2] => {
-            let (value, register) = get_register_value(registers, memory, *operand);
+        RR(target: Target) [2] => {
+            let value = target.get(registers, memory);
             let (result, flags) = Op::RotateRight(value).execute(
                 registers.get_flags() & MASK_FLAG_CARRY,
                 &OpConfig::builder().add_carry().set_z().build()
@@ -367,16 +345,8 @@ instruction_group! {
 
             registers.set_flags(flags);
 
-            match register {
-                Some(r) => {
-                    registers.set_single(&r, result);
-                    Ok(2)
-                },
-                None => {
-                    memory.set(registers.get_double(&DoubleRegister::HL).into(), result);
-                    Ok(4)
-                }
-            }
+            target.set(registers, memory, result);
+            Ok(target.read_modify_write_cycles())
         }
 
         /// Shifts the contents of `m` to the left.
@@ -391,22 +361,14 @@ instruction_group! {
         /// | N    | `0`           |
         /// | H    | `0`           |
         /// | C    | m<sup>7</sup> |
-        SLA(operand: u8) [2] => {
-            let (value, register) = get_register_value(registers, memory, *operand);
+        SLA(target: Target) [2] => {
+            let value = target.get(registers, memory);
             let (result, flags) = Op::ShiftLeft(value).execute(0, &OpConfig::builder().set_z().build());
 
             registers.set_flags(flags);
 
-            match register {
-                Some(r) => {
-                    registers.set_single(&r, result);
-                    Ok(2)
-                },
-                None => {
-                    memory.set(registers.get_double(&DoubleRegister::HL).into(), result);
-                    Ok(4)
-                }
-            }
+            target.set(registers, memory, result);
+            Ok(target.read_modify_write_cycles())
         }
 
         /// Shifts the contents of `m` to the right.
@@ -421,22 +383,14 @@ instruction_group! {
         /// | N    | `0`           |
         /// | H    | `0`           |
         /// | C    | m<sup>0</sup> |
-        SRA(operand: u8) [2] => {
-            let (value, register) = get_register_value(registers, memory, *operand);
+        SRA(target: Target) [2] => {
+            let value = target.get(registers, memory);
             let (result, flags) = Op::ShiftRight(value).execute(0, &OpConfig::builder().set_z().repeat_tail().build());
 
             registers.set_flags(flags);
 
-            match register {
-                Some(r) => {
-                    registers.set_single(&r, result);
-                    Ok(2)
-                },
-                None => {
-                    memory.set(registers.get_double(&DoubleRegister::HL).into(), result);
-                    Ok(4)
-                }
-            }
+            target.set(registers, memory, result);
+            Ok(target.read_modify_write_cycles())
         }
 
         /// Shifts the contents of `m` to the right.
@@ -451,22 +405,14 @@ instruction_group! {
         /// | N    | `0`           |
         /// | H    | `0`           |
         /// | C    | m<sup>0</sup> |
-        SRL(operand: u8) [2] => {
-            let (value, register) = get_register_value(registers, memory, *operand);
+        SRL(target: Target) [2] => {
+            let value = target.get(registers, memory);
             let (result, flags) = Op::ShiftRight(value).execute(0, &OpConfig::builder().set_z().build());
 
             registers.set_flags(flags);
 
-            match register {
-                Some(r) => {
-                    registers.set_single(&r, result);
-                    Ok(2)
-                },
-                None => {
-                    memory.set(registers.get_double(&DoubleRegister::HL).into(), result);
-                    Ok(4)
-                }
-            }
+            target.set(registers, memory, result);
+            Ok(target.read_modify_write_cycles())
         }
 
         /// Swaps the high and low nibble of `m`.
@@ -479,8 +425,8 @@ instruction_group! {
         /// | N    | `0`           |
         /// | H    | `0`           |
         /// | C    | `0`           |
-        SWAP(operand: u8) [2] => {
-            let (value, register) = get_register_value(registers, memory, *operand);
+        SWAP(target: Target) [2] => {
+            let value = target.get(registers, memory);
 
             let flags = if value == 0 { MASK_FLAG_ZERO } else { 0 };
             registers.set_flags(flags);
@@ -489,15 +435,75 @@ instruction_group! {
             let hi_nibble = value & 0xF0;
             let result = (lo_nibble << 4) + (hi_nibble >> 4);
 
-            match register {
-                Some(r) => {
-                    registers.set_single(&r, result);
-                    Ok(2)
-                },
-                None => {
-                    memory.set(registers.get_double(&DoubleRegister::HL).into(), result);
-                    Ok(4)
-                }
+            target.set(registers, memory, result);
+            Ok(target.read_modify_write_cycles())
+        }
+    }
+}
+
+impl RotateShift {
+    /// Returns the machine code of the instruction.
+    pub fn encode(&self) -> Result<Vec<u8>, CpuError> {
+        Ok(match self {
+            RotateShift::RLCA() => vec![0x07],
+            RotateShift::RLA() => vec![0x17],
+            RotateShift::RRCA() => vec![0x0F],
+            RotateShift::RRA() => vec![0x1F],
+            RotateShift::RLC(target) => vec![0xCB, target.code()],
+            RotateShift::RRC(target) => vec![0xCB, 0x08 | target.code()],
+            RotateShift::RL(target) => vec![0xCB, 0x10 | target.code()],
+            RotateShift::RR(target) => vec![0xCB, 0x18 | target.code()],
+            RotateShift::SLA(target) => vec![0xCB, 0x20 | target.code()],
+            RotateShift::SRA(target) => vec![0xCB, 0x28 | target.code()],
+            RotateShift::SWAP(target) => vec![0xCB, 0x30 | target.code()],
+            RotateShift::SRL(target) => vec![0xCB, 0x38 | target.code()],
+        })
+    }
+
+    /// Returns the operands of the instruction.
+    pub fn operands(&self) -> Vec<Operand> {
+        match self {
+            RotateShift::RLCA() | RotateShift::RLA() | RotateShift::RRCA() | RotateShift::RRA() => {
+                vec![]
+            }
+            RotateShift::RLC(target)
+            | RotateShift::RL(target)
+            | RotateShift::RRC(target)
+            | RotateShift::RR(target)
+            | RotateShift::SLA(target)
+            | RotateShift::SRA(target)
+            | RotateShift::SRL(target)
+            | RotateShift::SWAP(target) => vec![(*target).into()],
+        }
+    }
+
+    /// Returns the effects of the instruction on the flags.
+    pub fn flag_effects(&self) -> FlagEffects {
+        FlagEffects::parse(match self {
+            RotateShift::RLCA() | RotateShift::RLA() | RotateShift::RRCA() | RotateShift::RRA() => {
+                "000C"
+            }
+            RotateShift::SWAP(_) => "Z000",
+            _ => "Z00C",
+        })
+    }
+}
+
+impl Display for RotateShift {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RotateShift::RLCA() | RotateShift::RLA() | RotateShift::RRCA() | RotateShift::RRA() => {
+                f.write_str(self.mnemonic())
+            }
+            RotateShift::RLC(target)
+            | RotateShift::RL(target)
+            | RotateShift::RRC(target)
+            | RotateShift::RR(target)
+            | RotateShift::SLA(target)
+            | RotateShift::SRA(target)
+            | RotateShift::SRL(target)
+            | RotateShift::SWAP(target) => {
+                write!(f, "{} {}", self.mnemonic(), target)
             }
         }
     }
@@ -516,20 +522,24 @@ crate::instruction_tests! {
         assert_eq!(0b0010_1000, registers.get_single(&SingleRegister::A));
     }
 
-    rlca_sets_flags_correctly(registers, memory, cpu_flags) => {
-        registers.set_flags(0b1110_0000);
-        RotateShift::RLCA().execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-        assert_eq!(0b0000_0000, registers.get_flags(), "Did not clear Z, N and H");
-        registers.clear();
-
-        registers.set_single(&SingleRegister::A, 0b0101_0101);
-        RotateShift::RLCA().execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-        assert_eq!(0b0000_0000, registers.get_flags(), "C should NOT be set");
-        registers.clear();
-
-        registers.set_single(&SingleRegister::A, 0b1010_1010);
-        RotateShift::RLCA().execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-        assert_eq!(0b0001_0000, registers.get_flags(), "C should be set");
+    rotate_a_sets_flags_correctly(registers, memory, cpu_flags) for (instruction, flags, a, expected) in [
+        rlca_clears_z_n_and_h: (RotateShift::RLCA(), 0b1110_0000, 0b0000_0000, 0b0000_0000),
+        rlca_resets_c: (RotateShift::RLCA(), 0b0000_0000, 0b0101_0101, 0b0000_0000),
+        rlca_sets_c: (RotateShift::RLCA(), 0b0000_0000, 0b1010_1010, 0b0001_0000),
+        rla_clears_z_n_and_h: (RotateShift::RLA(), 0b1110_0000, 0b0000_0000, 0b0000_0000),
+        rla_resets_c: (RotateShift::RLA(), 0b0000_0000, 0b0101_0101, 0b0000_0000),
+        rla_sets_c: (RotateShift::RLA(), 0b0000_0000, 0b1010_1010, 0b0001_0000),
+        rrca_clears_z_n_and_h: (RotateShift::RRCA(), 0b1110_0000, 0b0000_0000, 0b0000_0000),
+        rrca_sets_c: (RotateShift::RRCA(), 0b0000_0000, 0b0101_0101, 0b0001_0000),
+        rrca_resets_c: (RotateShift::RRCA(), 0b0000_0000, 0b1010_1010, 0b0000_0000),
+        rra_clears_z_n_and_h: (RotateShift::RRA(), 0b1110_0000, 0b0000_0000, 0b0000_0000),
+        rra_sets_c: (RotateShift::RRA(), 0b0000_0000, 0b0101_0101, 0b0001_0000),
+        rra_resets_c: (RotateShift::RRA(), 0b0000_0000, 0b1010_1010, 0b0000_0000),
+    ] => {
+        registers.set_flags(flags);
+        registers.set_single(&SingleRegister::A, a);
+        instruction.execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        assert_eq!(expected, registers.get_flags());
     }
 
     rla_takes_1_machine_cycle(registers, memory, cpu_flags) => {
@@ -550,22 +560,6 @@ crate::instruction_tests! {
         assert_eq!(0b0000_0001, registers.get_single(&SingleRegister::A));
     }
 
-    rla_sets_flags_correctly(registers, memory, cpu_flags) => {
-        registers.set_flags(0b1110_0000);
-        RotateShift::RLA().execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-        assert_eq!(0b0000_0000, registers.get_flags(), "Did not clear Z, N and H");
-        registers.clear();
-
-        registers.set_single(&SingleRegister::A, 0b0101_0101);
-        RotateShift::RLA().execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-        assert_eq!(0b0000_0000, registers.get_flags(), "C should NOT be set");
-        registers.clear();
-
-        registers.set_single(&SingleRegister::A, 0b1010_1010);
-        RotateShift::RLA().execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-        assert_eq!(0b0001_0000, registers.get_flags(), "C should be set");
-    }
-
     rrca_takes_1_machine_cycle(registers, memory, cpu_flags) => {
         let cycles = RotateShift::RRCA().execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
         assert_eq!(1, cycles);
@@ -577,23 +571,6 @@ crate::instruction_tests! {
         assert_eq!(0b0001_0100, registers.get_single(&SingleRegister::A));
     }
 
-    rrca_sets_flags_correctly(registers, memory, cpu_flags) => {
-        registers.set_flags(0b1110_0000);
-        RotateShift::RRCA().execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-        assert_eq!(0b0000_0000, registers.get_flags(), "Did not clear Z, N and H");
-        registers.clear();
-
-        registers.set_single(&SingleRegister::A, 0b0101_0101);
-        RotateShift::RRCA().execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-        assert_eq!(0b0001_0000, registers.get_flags(), "C should be set");
-        registers.clear();
-
-        registers.set_single(&SingleRegister::A, 0b1010_1010);
-        RotateShift::RRCA().execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-        assert_eq!(0b0000_0000, registers.get_flags(), "C should NOT be set");
-        registers.clear();
-    }
-
     rra_takes_1_machine_cycle(registers, memory, cpu_flags) => {
         let cycles = RotateShift::RRA().execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
         assert_eq!(1, cycles);
@@ -612,26 +589,9 @@ crate::instruction_tests! {
         assert_eq!(0b1000_0000, registers.get_single(&SingleRegister::A));
     }
 
-    rra_sets_flags_correctly(registers, memory, cpu_flags) => {
-        registers.set_flags(0b1110_0000);
-        RotateShift::RRA().execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-        assert_eq!(0b0000_0000, registers.get_flags(), "Did not clear Z, N and H");
-        registers.clear();
-
-        registers.set_single(&SingleRegister::A, 0b0101_0101);
-        RotateShift::RRA().execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-        assert_eq!(0b0001_0000, registers.get_flags(), "C should be set");
-        registers.clear();
-
-        registers.set_single(&SingleRegister::A, 0b1010_1010);
-        RotateShift::RRA().execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
-        assert_eq!(0b0000_0000, registers.get_flags(), "C should NOT be set");
-        registers.clear();
-    }
-
     rlc_returns_the_correct_machine_cycles(registers, memory, cpu_flags) => {
         for operand in 0..8 {
-            let cycles = RotateShift::RLC(operand).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+            let cycles = RotateShift::RLC(Target::from(operand)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
             if operand == 0b110 {
                 assert_eq!(4, cycles, "Incorrect number of machine cycles for HL");
@@ -654,7 +614,7 @@ crate::instruction_tests! {
                 registers.set_single(&operand.try_into().unwrap(), value);
             }
 
-            RotateShift::RLC(operand).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+            RotateShift::RLC(Target::from(operand)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
             if operand == 0b110 {
                 assert_eq!(expected, memory.get(registers.get_double(&DoubleRegister::HL).into()), "Incorrect result for (HL)");
@@ -669,19 +629,19 @@ crate::instruction_tests! {
 
     rlc_handles_flags_correctly(registers, memory, cpu_flags) => {
         registers.set_single(&SingleRegister::B, 0b0);
-        RotateShift::RLC(0).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        RotateShift::RLC(Target::from(0)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
         assert_eq!(0b1000_0000, registers.get_flags(), "Z flag not set");
         registers.clear();
 
         registers.set_single(&SingleRegister::B, 0b1000_0000);
-        RotateShift::RLC(0).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        RotateShift::RLC(Target::from(0)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
         assert_eq!(0b0001_0000, registers.get_flags(), "C flag not set");
         registers.clear();
     }
 
     rrc_returns_the_correct_machine_cycles(registers, memory, cpu_flags) => {
         for operand in 0..8 {
-            let cycles = RotateShift::RRC(operand).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+            let cycles = RotateShift::RRC(Target::from(operand)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
             if operand == 0b110 {
                 assert_eq!(4, cycles, "Incorrect number of machine cycles for HL");
@@ -704,7 +664,7 @@ crate::instruction_tests! {
                 registers.set_single(&operand.try_into().unwrap(), value);
             }
 
-            RotateShift::RRC(operand).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+            RotateShift::RRC(Target::from(operand)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
             if operand == 0b110 {
                 assert_eq!(expected, memory.get(registers.get_double(&DoubleRegister::HL).into()), "Incorrect result for (HL)");
@@ -719,19 +679,19 @@ crate::instruction_tests! {
 
     rrc_handles_flags_correctly(registers, memory, cpu_flags) => {
         registers.set_single(&SingleRegister::B, 0b0);
-        RotateShift::RRC(0).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        RotateShift::RRC(Target::from(0)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
         assert_eq!(0b1000_0000, registers.get_flags(), "Z flag not set");
         registers.clear();
 
         registers.set_single(&SingleRegister::B, 0b0000_0001);
-        RotateShift::RRC(0).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        RotateShift::RRC(Target::from(0)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
         assert_eq!(0b0001_0000, registers.get_flags(), "C flag not set");
         registers.clear();
     }
 
     rl_returns_the_correct_machine_cycles(registers, memory, cpu_flags) => {
         for operand in 0..8 {
-            let cycles = RotateShift::RL(operand).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+            let cycles = RotateShift::RL(Target::from(operand)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
             if operand == 0b110 {
                 assert_eq!(4, cycles, "Incorrect number of machine cycles for HL");
@@ -754,7 +714,7 @@ crate::instruction_tests! {
                 registers.set_single(&operand.try_into().unwrap(), value);
             }
 
-            RotateShift::RL(operand).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+            RotateShift::RL(Target::from(operand)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
             if operand == 0b110 {
                 assert_eq!(expected, memory.get(registers.get_double(&DoubleRegister::HL).into()), "Incorrect result for (HL)");
@@ -769,17 +729,17 @@ crate::instruction_tests! {
 
     rl_handles_flags_correctly(registers, memory, cpu_flags) => {
         registers.set_single(&SingleRegister::B, 0b0);
-        RotateShift::RL(0).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        RotateShift::RL(Target::from(0)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
         assert_eq!(0b1000_0000, registers.get_flags(), "Z flag not set");
         registers.clear();
 
         registers.set_single(&SingleRegister::B, 0b1000_0000);
-        RotateShift::RL(0).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        RotateShift::RL(Target::from(0)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
         assert_eq!(0b0001_0000, registers.get_flags(), "C flag not set");
         registers.clear();
 
         registers.set_flags(MASK_FLAG_CARRY);
-        RotateShift::RL(0).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        RotateShift::RL(Target::from(0)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
         assert_eq!(0b0000_0001, registers.get_single(&SingleRegister::B), "C flag not moved to m0");
         println!("Flags: {:08b}", registers.get_flags());
         assert_eq!(false, registers.is_carry(), "C flag was still set");
@@ -788,7 +748,7 @@ crate::instruction_tests! {
 
     rr_returns_the_correct_machine_cycles(registers, memory, cpu_flags) => {
         for operand in 0..8 {
-            let cycles = RotateShift::RR(operand).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+            let cycles = RotateShift::RR(Target::from(operand)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
             if operand == 0b110 {
                 assert_eq!(4, cycles, "Incorrect number of machine cycles for HL");
@@ -811,7 +771,7 @@ crate::instruction_tests! {
                 registers.set_single(&operand.try_into().unwrap(), value);
             }
 
-            RotateShift::RR(operand).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+            RotateShift::RR(Target::from(operand)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
             if operand == 0b110 {
                 assert_eq!(expected, memory.get(registers.get_double(&DoubleRegister::HL).into()), "Incorrect result for (HL)");
@@ -826,17 +786,17 @@ crate::instruction_tests! {
 
     rr_handles_flags_correctly(registers, memory, cpu_flags) => {
         registers.set_single(&SingleRegister::B, 0b0);
-        RotateShift::RR(0).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        RotateShift::RR(Target::from(0)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
         assert_eq!(0b1000_0000, registers.get_flags(), "Z flag not set");
         registers.clear();
 
         registers.set_single(&SingleRegister::B, 0b0000_0001);
-        RotateShift::RR(0).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        RotateShift::RR(Target::from(0)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
         assert_eq!(0b0001_0000, registers.get_flags(), "C flag not set");
         registers.clear();
 
         registers.set_flags(MASK_FLAG_CARRY);
-        RotateShift::RR(0).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        RotateShift::RR(Target::from(0)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
         assert_eq!(0b1000_0000, registers.get_single(&SingleRegister::B), "C flag not moved to m7");
         assert_eq!(false, registers.is_carry(), "C flag was still set");
         registers.clear();
@@ -844,7 +804,7 @@ crate::instruction_tests! {
 
     sla_returns_the_correct_machine_cycles(registers, memory, cpu_flags) => {
         for operand in 0..8 {
-            let cycles = RotateShift::SLA(operand).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+            let cycles = RotateShift::SLA(Target::from(operand)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
             if operand == 0b110 {
                 assert_eq!(4, cycles, "Incorrect number of machine cycles for HL");
@@ -867,7 +827,7 @@ crate::instruction_tests! {
                 registers.set_single(&operand.try_into().unwrap(), value);
             }
 
-            RotateShift::SLA(operand).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+            RotateShift::SLA(Target::from(operand)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
             if operand == 0b110 {
                 assert_eq!(expected, memory.get(registers.get_double(&DoubleRegister::HL).into()), "Incorrect result for (HL)");
@@ -882,17 +842,17 @@ crate::instruction_tests! {
 
     sla_handles_flags_correctly(registers, memory, cpu_flags) => {
         registers.set_single(&SingleRegister::B, 0b0);
-        RotateShift::SLA(0).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        RotateShift::SLA(Target::from(0)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
         assert_eq!(0b1000_0000, registers.get_flags(), "Z flag not set");
         registers.clear();
 
         registers.set_single(&SingleRegister::B, 0b1000_0001);
-        RotateShift::SLA(0).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        RotateShift::SLA(Target::from(0)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
         assert_eq!(0b0001_0000, registers.get_flags(), "C flag not set");
         registers.clear();
 
         registers.set_single(&SingleRegister::B, 0b1000_0000);
-        RotateShift::SLA(0).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        RotateShift::SLA(Target::from(0)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
         assert_eq!(0b1001_0000, registers.get_flags(), "C and Z flags not set");
         registers.clear();
     }
@@ -901,14 +861,14 @@ crate::instruction_tests! {
         registers.set_single(&SingleRegister::D, 0x80);
         memory.set(registers.get_double(&DoubleRegister::HL).into(), 0xFF);
 
-        RotateShift::SLA(0b010).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        RotateShift::SLA(Target::from(0b010)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
         assert_eq!(0, registers.get_single(&SingleRegister::D));
         assert_eq!(true, registers.is_carry());
         assert_eq!(true, registers.is_zero());
         assert_eq!(false, registers.is_half_carry());
         assert_eq!(false, registers.is_negative());
 
-        RotateShift::SLA(0b110).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        RotateShift::SLA(Target::from(0b110)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
         assert_eq!(0xfe, memory.get(registers.get_double(&DoubleRegister::HL).into()));
         assert_eq!(true, registers.is_carry());
         assert_eq!(false, registers.is_zero());
@@ -918,7 +878,7 @@ crate::instruction_tests! {
 
     sra_returns_the_correct_machine_cycles(registers, memory, cpu_flags) => {
         for operand in 0..8 {
-            let cycles = RotateShift::SRA(operand).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+            let cycles = RotateShift::SRA(Target::from(operand)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
             if operand == 0b110 {
                 assert_eq!(4, cycles, "Incorrect number of machine cycles for HL");
@@ -941,7 +901,7 @@ crate::instruction_tests! {
                 registers.set_single(&operand.try_into().unwrap(), value);
             }
 
-            RotateShift::SRA(operand).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+            RotateShift::SRA(Target::from(operand)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
             if operand == 0b110 {
                 assert_eq!(expected, memory.get(registers.get_double(&DoubleRegister::HL).into()), "Incorrect result for (HL)");
@@ -967,7 +927,7 @@ crate::instruction_tests! {
                 registers.set_single(&operand.try_into().unwrap(), value);
             }
 
-            RotateShift::SRA(operand).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+            RotateShift::SRA(Target::from(operand)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
             if operand == 0b110 {
                 assert_eq!(expected, memory.get(registers.get_double(&DoubleRegister::HL).into()), "Incorrect result for (HL)");
@@ -982,24 +942,24 @@ crate::instruction_tests! {
 
     sra_handles_flags_correctly(registers, memory, cpu_flags) => {
         registers.set_single(&SingleRegister::B, 0b0);
-        RotateShift::SRA(0).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        RotateShift::SRA(Target::from(0)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
         assert_eq!(0b1000_0000, registers.get_flags(), "Z flag not set");
         registers.clear();
 
         registers.set_single(&SingleRegister::B, 0b1000_0001);
-        RotateShift::SRA(0).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        RotateShift::SRA(Target::from(0)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
         assert_eq!(0b0001_0000, registers.get_flags(), "C flag not set");
         registers.clear();
 
         registers.set_single(&SingleRegister::B, 0b0000_0001);
-        RotateShift::SRA(0).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        RotateShift::SRA(Target::from(0)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
         assert_eq!(0b1001_0000, registers.get_flags(), "C and Z flags not set");
         registers.clear();
     }
 
     srl_returns_the_correct_machine_cycles(registers, memory, cpu_flags) => {
         for operand in 0..8 {
-            let cycles = RotateShift::SRL(operand).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+            let cycles = RotateShift::SRL(Target::from(operand)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
             if operand == 0b110 {
                 assert_eq!(4, cycles, "Incorrect number of machine cycles for HL");
@@ -1022,7 +982,7 @@ crate::instruction_tests! {
                 registers.set_single(&operand.try_into().unwrap(), value);
             }
 
-            RotateShift::SRL(operand).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+            RotateShift::SRL(Target::from(operand)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
             if operand == 0b110 {
                 assert_eq!(expected, memory.get(registers.get_double(&DoubleRegister::HL).into()), "Incorrect result for (HL)");
@@ -1048,7 +1008,7 @@ crate::instruction_tests! {
                 registers.set_single(&operand.try_into().unwrap(), value);
             }
 
-            RotateShift::SRL(operand).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+            RotateShift::SRL(Target::from(operand)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
             if operand == 0b110 {
                 assert_eq!(expected, memory.get(registers.get_double(&DoubleRegister::HL).into()), "Incorrect result for (HL)");
@@ -1063,24 +1023,24 @@ crate::instruction_tests! {
 
     srl_handles_flags_correctly(registers, memory, cpu_flags) => {
         registers.set_single(&SingleRegister::B, 0b0);
-        RotateShift::SRL(0).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        RotateShift::SRL(Target::from(0)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
         assert_eq!(0b1000_0000, registers.get_flags(), "Z flag not set");
         registers.clear();
 
         registers.set_single(&SingleRegister::B, 0b1000_0001);
-        RotateShift::SRL(0).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        RotateShift::SRL(Target::from(0)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
         assert_eq!(0b0001_0000, registers.get_flags(), "C flag not set");
         registers.clear();
 
         registers.set_single(&SingleRegister::B, 0b0000_0001);
-        RotateShift::SRL(0).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        RotateShift::SRL(Target::from(0)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
         assert_eq!(0b1001_0000, registers.get_flags(), "C and Z flags not set");
         registers.clear();
     }
 
     swap_returns_the_correct_machine_cycles(registers, memory, cpu_flags) => {
         for operand in 0..8 {
-            let cycles = RotateShift::SWAP(operand).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+            let cycles = RotateShift::SWAP(Target::from(operand)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
             if operand == 0b110 {
                 assert_eq!(4, cycles, "Incorrect number of machine cycles for HL");
@@ -1103,7 +1063,7 @@ crate::instruction_tests! {
                 registers.set_single(&operand.try_into().unwrap(), value);
             }
 
-            RotateShift::SWAP(operand).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+            RotateShift::SWAP(Target::from(operand)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
 
             if operand == 0b110 {
                 assert_eq!(expected, memory.get(registers.get_double(&DoubleRegister::HL).into()), "Incorrect result for (HL)");
@@ -1118,7 +1078,7 @@ crate::instruction_tests! {
 
     swap_handles_flags_correctly(registers, memory, cpu_flags) => {
         registers.set_single(&SingleRegister::B, 0b0);
-        RotateShift::SWAP(0).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
+        RotateShift::SWAP(Target::from(0)).execute(&mut registers, &mut memory, &mut cpu_flags).unwrap();
         assert_eq!(0b1000_0000, registers.get_flags(), "Z flag not set");
         registers.clear();
     }
@@ -1,6 +1,8 @@
+//! Instruction utility functions
+
 use crate::{
-    memory::Memory,
-    registers::{DoubleRegister, Registers, SingleRegister},
+    errors::CpuError,
+    registers::{DoubleRegister, SingleRegister},
 };
 
 /// Instruction utility functions
@@ -18,25 +20,39 @@ pub fn into_bits(x: u8) -> (u8, u8, u8, u8, u8, u8, u8, u8) {
     )
 }
 
-/// Return a tuple of the value from the register designated by the operand
-/// and optionally the affected `SingleRegister`.
-///
-/// Reads either from a `SingleRegister` or `(HL)`.
-pub fn get_register_value(
-    registers: &Registers,
-    memory: &Memory,
-    operand: u8,
-) -> (u8, Option<SingleRegister>) {
-    match into_bits(operand) {
-        (_, _, _, _, _, 1, 1, 0) => {
-            let value = memory.get(registers.get_double(&DoubleRegister::HL).into());
-            (value, None)
-        }
-        (_, _, _, _, _, a, b, c) => {
-            let r = (a, b, c).into();
-            let value = registers.get_single(&r);
-            (value, Some(r))
-        }
+/// Returns the 3 bit code of `r` used in opcodes, the inverse of `SingleRegister::from`.
+pub fn single_register_code(r: &SingleRegister) -> u8 {
+    match r {
+        SingleRegister::B => 0b000,
+        SingleRegister::C => 0b001,
+        SingleRegister::D => 0b010,
+        SingleRegister::E => 0b011,
+        SingleRegister::H => 0b100,
+        SingleRegister::L => 0b101,
+        SingleRegister::F => 0b110,
+        SingleRegister::A => 0b111,
+    }
+}
+
+/// Returns the 2 bit code of `r` used in the opcodes of `LD`, `INC`, `DEC` and `ADD HL`, where
+/// code `11` is `SP`. No opcode encodes `PC`.
+pub fn double_register_code(r: &DoubleRegister) -> Result<u8, CpuError> {
+    match r {
+        DoubleRegister::BC => Ok(0b00),
+        DoubleRegister::DE => Ok(0b01),
+        DoubleRegister::HL => Ok(0b10),
+        DoubleRegister::SP => Ok(0b11),
+        DoubleRegister::AF | DoubleRegister::PC => Err(CpuError::UnsupportedDoubleRegister(*r)),
+    }
+}
+
+/// Returns the 2 bit code of `r` used in the opcodes of `PUSH` and `POP`, where code `11` is
+/// `AF`.
+pub fn stack_register_code(r: &DoubleRegister) -> Result<u8, CpuError> {
+    match r {
+        DoubleRegister::AF => Ok(0b11),
+        DoubleRegister::SP => Err(CpuError::UnsupportedDoubleRegister(*r)),
+        r => double_register_code(r),
     }
 }
 
@@ -66,58 +82,6 @@ mod tests {
         assert_eq!(into_bits(0b1000_1000), (1, 0, 0, 0, 1, 0, 0, 0));
     }
 
-    #[test]
-    fn get_register_value_gets_the_correct_registers() {
-        let registers = Registers::new();
-        let memory = Memory::new();
-
-        for (operand, expected_register) in vec![
-            (0b000, Some(SingleRegister::B)),
-            (0b001, Some(SingleRegister::C)),
-            (0b010, Some(SingleRegister::D)),
-            (0b011, Some(SingleRegister::E)),
-            (0b100, Some(SingleRegister::H)),
-            (0b101, Some(SingleRegister::L)),
-            (0b110, None), // HL
-            (0b111, Some(SingleRegister::A)),
-        ] {
-            let (_, register) = get_register_value(&registers, &memory, operand);
-            assert_eq!(expected_register, register);
-        }
-    }
-
-    #[test]
-    fn get_register_value_gets_the_correct_single_register_values() {
-        let mut registers = Registers::new();
-        let memory = Memory::new();
-
-        for (operand, register, value) in vec![
-            (0b000, SingleRegister::B, 1),
-            (0b001, SingleRegister::C, 2),
-            (0b010, SingleRegister::D, 3),
-            (0b011, SingleRegister::E, 4),
-            (0b100, SingleRegister::H, 5),
-            (0b101, SingleRegister::L, 6),
-            (0b111, SingleRegister::A, 7),
-        ] {
-            registers.set_single(&register, value);
-            let (result, _) = get_register_value(&registers, &memory, operand);
-            assert_eq!(value, result);
-        }
-    }
-
-    #[test]
-    fn get_register_value_gets_the_correct_value_for_hl() {
-        let mut registers = Registers::new();
-        let mut memory = Memory::new();
-
-        registers.set_double(&DoubleRegister::HL, 0xAB);
-        memory.set(0xAB, 0xCD);
-
-        let (result, _) = get_register_value(&registers, &memory, 0b110);
-        assert_eq!(0xCD, result);
-    }
-
     #[test]
     fn twos_complement_works() {
         assert_eq!(0, twos_complement(0));
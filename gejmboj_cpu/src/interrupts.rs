@@ -1,5 +1,34 @@
+//! # Interrupts
+//!
+//! The five interrupt sources, each owning a bit in the interrupt enable register `IE` (`FFFF`)
+//! and the interrupt flag register `IF` (`FF0F`). Bit 0 has the highest priority:
+//!
+//! ```asciidoc
+//! ,-----.--------.----------.--------.
+//! | Bit | Source | Priority | Vector |
+//! |-----|--------|----------|--------|
+//! |  0  | VBlank |    0     |  0040  |
+//! |  1  | STAT   |    1     |  0048  |
+//! |  2  | Timer  |    2     |  0050  |
+//! |  3  | Serial |    3     |  0058  |
+//! |  4  | Joypad |    4     |  0060  |
+//! `-----´--------´----------´--------´
+//! ```
+//!
+//! ```
+//! # use gejmboj_cpu::interrupts::Interrupt;
+//! let (enabled, requested) = (0b0000_0110, 0b0001_0100);
+//!
+//! assert_eq!(Some(Interrupt::Timer), Interrupt::from_pending(enabled & requested));
+//! assert_eq!(0b0000_0100, Interrupt::Timer.mask());
+//! ```
+
+use std::convert::TryFrom;
+
+use crate::errors::CpuError;
+
 #[allow(non_camel_case_types)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Interrupt {
     VBlank,
     LCD_STAT,
@@ -9,6 +38,15 @@ pub enum Interrupt {
 }
 
 impl Interrupt {
+    /// All interrupts, highest priority first.
+    pub const ALL: [Interrupt; 5] = [
+        Interrupt::VBlank,
+        Interrupt::LCD_STAT,
+        Interrupt::Timer,
+        Interrupt::Serial,
+        Interrupt::Joypad,
+    ];
+
     pub fn priority(&self) -> u8 {
         match self {
             Interrupt::VBlank => 0,
@@ -28,4 +66,75 @@ impl Interrupt {
             Interrupt::Joypad => 0x0060,
         }
     }
+
+    /// Returns the bit of the interrupt in `IE` and `IF`.
+    pub const fn mask(&self) -> u8 {
+        match self {
+            Interrupt::VBlank => 0b0000_0001,
+            Interrupt::LCD_STAT => 0b0000_0010,
+            Interrupt::Timer => 0b0000_0100,
+            Interrupt::Serial => 0b0000_1000,
+            Interrupt::Joypad => 0b0001_0000,
+        }
+    }
+
+    /// Returns the highest priority interrupt of `pending`, usually `IE & IF`. The upper 3 bits
+    /// are ignored.
+    pub fn from_pending(pending: u8) -> Option<Interrupt> {
+        Interrupt::ALL
+            .iter()
+            .copied()
+            .find(|interrupt| pending & interrupt.mask() != 0)
+    }
+}
+
+/// Converts the bit of a single interrupt, see `Interrupt::mask`.
+///
+/// ```
+/// # use std::convert::TryFrom;
+/// # use gejmboj_cpu::interrupts::Interrupt;
+/// assert_eq!(Ok(Interrupt::Serial), Interrupt::try_from(0b0000_1000));
+/// assert!(Interrupt::try_from(0b0000_0011).is_err());
+/// ```
+impl TryFrom<u8> for Interrupt {
+    type Error = CpuError;
+
+    fn try_from(mask: u8) -> Result<Self, Self::Error> {
+        Interrupt::ALL
+            .iter()
+            .copied()
+            .find(|interrupt| interrupt.mask() == mask)
+            .ok_or_else(|| CpuError::Error(format!("Not a single interrupt: {:08b}", mask)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masks_follow_priorities_and_vectors() {
+        for interrupt in Interrupt::ALL {
+            assert_eq!(1 << interrupt.priority(), interrupt.mask());
+            assert_eq!(0x0040 + 8 * interrupt.priority() as u16, interrupt.vector());
+            assert_eq!(Ok(interrupt), Interrupt::try_from(interrupt.mask()));
+        }
+    }
+
+    #[test]
+    fn pending_interrupts_resolve_by_priority() {
+        assert_eq!(None, Interrupt::from_pending(0b1110_0000));
+        assert_eq!(
+            Some(Interrupt::Joypad),
+            Interrupt::from_pending(0b1111_0000)
+        );
+        assert_eq!(
+            Some(Interrupt::VBlank),
+            Interrupt::from_pending(0b0001_1111)
+        );
+        assert_eq!(
+            Some(Interrupt::LCD_STAT),
+            Interrupt::from_pending(0b0001_1010)
+        );
+    }
 }
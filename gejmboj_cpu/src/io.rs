@@ -0,0 +1,688 @@
+//! # Memory mapped I/O
+//!
+//! The I/O region `FF00-FF7F` is not plain memory. Every register belongs to a peripheral and
+//! has bits which are unused, read-only or write-only. `Io` dispatches accesses to the owning
+//! peripheral and applies the register's masks:
+//!
+//! - unused bits always read as `1`
+//! - writes only affect writable bits
+//! - unmapped registers read as `FF` and ignore writes
+//! - CGB-only registers are unmapped unless CGB registers are enabled, see `Io::set_cgb`
+//!
+//! On CGB the boot ROM selects DMG compatibility mode for DMG-only cartridges by writing `04`
+//! to KEY0 (`FF4C`) before disabling itself through `FF50`. KEY0 is locked from then on, and in
+//! compatibility mode the CGB-only registers are unmapped, locking VRAM bank and palettes.
+//!
+//! The CGB palette registers (`FF68-FF6B`) are dispatched to their `PaletteRam`, the button lines
+//! of P1 (`FF00`) are read from the `Joypad`. The timer registers (`FF04-FF07`) belong to the
+//! `Timer` and LCDC, STAT, LY and LYC to the `Ppu`, both are advanced by `Io::step` together
+//! with the `Serial` port transferring SB (`FF01`) as started through SC (`FF02`).
+//!
+//! ```
+//! # use gejmboj_cpu::io::Io;
+//! let mut io = Io::new();
+//!
+//! // Only the lower 3 bits of TAC exist
+//! io.write(0xFF07, 0x05);
+//! assert_eq!(0xFD, io.read(0xFF07));
+//!
+//! // Any write to DIV resets it
+//! io.write(0xFF04, 0xAB);
+//! assert_eq!(0x00, io.read(0xFF04));
+//! ```
+
+use std::convert::TryInto;
+
+use crate::{
+    interrupts::Interrupt,
+    joypad::{Button, Joypad},
+    model::Model,
+    palette::{PaletteRam, PALETTE_STATE_SIZE},
+    ppu::{Ppu, PPU_STATE_SIZE},
+    serial::{Serial, MASK_SC_INTERNAL_CLOCK, MASK_SC_START},
+    timer::{Timer, TIMER_STATE_SIZE},
+};
+
+/// First address of the I/O region.
+pub const IO_START: u16 = 0xFF00;
+
+/// Last address of the I/O region.
+pub const IO_END: u16 = 0xFF7F;
+
+pub const REGISTER_P1: u16 = 0xFF00;
+pub const REGISTER_SB: u16 = 0xFF01;
+pub const REGISTER_SC: u16 = 0xFF02;
+pub const REGISTER_DIV: u16 = 0xFF04;
+pub const REGISTER_TIMA: u16 = 0xFF05;
+pub const REGISTER_TMA: u16 = 0xFF06;
+pub const REGISTER_TAC: u16 = 0xFF07;
+pub const REGISTER_IF: u16 = 0xFF0F;
+pub const REGISTER_NR52: u16 = 0xFF26;
+pub const REGISTER_LCDC: u16 = 0xFF40;
+pub const REGISTER_STAT: u16 = 0xFF41;
+pub const REGISTER_LY: u16 = 0xFF44;
+pub const REGISTER_LYC: u16 = 0xFF45;
+pub const REGISTER_KEY0: u16 = 0xFF4C;
+pub const REGISTER_VBK: u16 = 0xFF4F;
+pub const REGISTER_BOOT: u16 = 0xFF50;
+pub const REGISTER_BCPS: u16 = 0xFF68;
+pub const REGISTER_BCPD: u16 = 0xFF69;
+pub const REGISTER_OCPS: u16 = 0xFF6A;
+pub const REGISTER_OCPD: u16 = 0xFF6B;
+
+const MASK_KEY0_DMG_COMPATIBILITY: u8 = 0b0000_0100;
+
+/// I/O register values left behind by the DMG boot ROM, as read by the CPU.
+const POST_BOOT_DMG: [(u16, u8); 41] = [
+    (0xFF00, 0xCF),
+    (0xFF01, 0x00),
+    (0xFF02, 0x7E),
+    (0xFF04, 0xAB),
+    (0xFF05, 0x00),
+    (0xFF06, 0x00),
+    (0xFF07, 0xF8),
+    (0xFF0F, 0xE1),
+    (0xFF10, 0x80),
+    (0xFF11, 0xBF),
+    (0xFF12, 0xF3),
+    (0xFF13, 0xFF),
+    (0xFF14, 0xBF),
+    (0xFF16, 0x3F),
+    (0xFF17, 0x00),
+    (0xFF18, 0xFF),
+    (0xFF19, 0xBF),
+    (0xFF1A, 0x7F),
+    (0xFF1B, 0xFF),
+    (0xFF1C, 0x9F),
+    (0xFF1D, 0xFF),
+    (0xFF1E, 0xBF),
+    (0xFF20, 0xFF),
+    (0xFF21, 0x00),
+    (0xFF22, 0x00),
+    (0xFF23, 0xBF),
+    (0xFF24, 0x77),
+    (0xFF25, 0xF3),
+    (0xFF26, 0xF1),
+    (0xFF40, 0x91),
+    (0xFF41, 0x85),
+    (0xFF42, 0x00),
+    (0xFF43, 0x00),
+    (0xFF44, 0x00),
+    (0xFF45, 0x00),
+    (0xFF46, 0xFF),
+    (0xFF47, 0xFC),
+    (0xFF48, 0xFF),
+    (0xFF49, 0xFF),
+    (0xFF4A, 0x00),
+    (0xFF4B, 0x00),
+];
+
+/// Which set of features the hardware exposes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Dmg,
+    Cgb,
+    /// CGB hardware running a DMG-only cartridge
+    DmgCompatibility,
+}
+
+/// The peripheral owning an I/O register.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Peripheral {
+    Joypad,
+    Serial,
+    Timer,
+    Interrupts,
+    Apu,
+    Ppu,
+    /// Boot ROM and hardware mode control
+    System,
+    Unmapped,
+}
+
+/// Describes how an I/O register is accessed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Register {
+    pub peripheral: Peripheral,
+    /// Bits which don't exist and always read as `1`
+    pub unused: u8,
+    /// Bits which are written by the CPU, all other bits are left untouched
+    pub writable: u8,
+    /// Register only exists on CGB
+    pub cgb_only: bool,
+}
+
+impl Register {
+    const fn new(peripheral: Peripheral, unused: u8, writable: u8) -> Self {
+        Self {
+            peripheral,
+            unused,
+            writable,
+            cgb_only: false,
+        }
+    }
+
+    const fn cgb_only(self) -> Self {
+        Self {
+            cgb_only: true,
+            ..self
+        }
+    }
+}
+
+/// Returns the register description for `address` in `FF00-FF7F`.
+pub fn register(address: u16) -> Register {
+    use Peripheral::*;
+
+    match address {
+        // P1 lower nibble are the button lines, read from the joypad
+        0xFF00 => Register::new(Joypad, 0xC0, 0x30),
+        0xFF01 => Register::new(Serial, 0x00, 0xFF),
+        0xFF02 => Register::new(Serial, 0x7E, 0x81),
+        0xFF04..=0xFF06 => Register::new(Timer, 0x00, 0xFF),
+        0xFF07 => Register::new(Timer, 0xF8, 0x07),
+        0xFF0F => Register::new(Interrupts, 0xE0, 0x1F),
+        // NRx1-NRx4 have write-only bits, which read as `1` too
+        0xFF10 => Register::new(Apu, 0x80, 0x7F),
+        0xFF11 | 0xFF16 => Register::new(Apu, 0x3F, 0xFF),
+        0xFF12 | 0xFF17 | 0xFF21 => Register::new(Apu, 0x00, 0xFF),
+        0xFF13 | 0xFF18 | 0xFF1B | 0xFF1D | 0xFF20 => Register::new(Apu, 0xFF, 0xFF),
+        0xFF14 | 0xFF19 | 0xFF1E | 0xFF23 => Register::new(Apu, 0xBF, 0xFF),
+        0xFF1A => Register::new(Apu, 0x7F, 0x80),
+        0xFF1C => Register::new(Apu, 0x9F, 0x60),
+        0xFF22 | 0xFF24 | 0xFF25 => Register::new(Apu, 0x00, 0xFF),
+        // NR52 channel status bits are read-only
+        0xFF26 => Register::new(Apu, 0x70, 0x80),
+        0xFF30..=0xFF3F => Register::new(Apu, 0x00, 0xFF),
+        0xFF40 => Register::new(Ppu, 0x00, 0xFF),
+        // STAT mode and coincidence bits are read-only
+        0xFF41 => Register::new(Ppu, 0x80, 0x78),
+        0xFF42 | 0xFF43 => Register::new(Ppu, 0x00, 0xFF),
+        0xFF44 => Register::new(Ppu, 0x00, 0x00),
+        0xFF45..=0xFF4B => Register::new(Ppu, 0x00, 0xFF),
+        0xFF4C => Register::new(System, 0xF3, 0x0C).cgb_only(),
+        0xFF4F => Register::new(Ppu, 0xFE, 0x01).cgb_only(),
+        0xFF50 => Register::new(System, 0xFE, 0x01),
+        0xFF68..=0xFF6B => Register::new(Ppu, 0x00, 0xFF).cgb_only(),
+        _ => Register::new(Unmapped, 0xFF, 0x00),
+    }
+}
+
+/// Size of the state saved by `Io::to_bytes`.
+pub(crate) const IO_STATE_SIZE: usize =
+    0x80 + 1 + PALETTE_STATE_SIZE * 2 + TIMER_STATE_SIZE + PPU_STATE_SIZE;
+
+/// The I/O registers, see module documentation.
+pub struct Io {
+    registers: [u8; 0x80],
+    cgb: bool,
+    dmg_compatibility: bool,
+    background_palettes: PaletteRam,
+    object_palettes: PaletteRam,
+    joypad: Joypad,
+    timer: Timer,
+    ppu: Ppu,
+    serial: Serial,
+}
+
+impl Default for Io {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Io {
+    pub fn new() -> Self {
+        Self {
+            registers: [0; 0x80],
+            cgb: false,
+            dmg_compatibility: false,
+            background_palettes: PaletteRam::new(),
+            object_palettes: PaletteRam::new(),
+            joypad: Joypad::new(),
+            timer: Timer::new(),
+            ppu: Ppu::new(),
+            serial: Serial::new(),
+        }
+    }
+
+    /// Returns the CGB background palettes.
+    pub fn background_palettes(&self) -> &PaletteRam {
+        &self.background_palettes
+    }
+
+    /// Returns the CGB object palettes.
+    pub fn object_palettes(&self) -> &PaletteRam {
+        &self.object_palettes
+    }
+
+    /// Returns the CGB background and object palettes mutably, bypassing the registers.
+    pub fn palettes_mut(&mut self) -> (&mut PaletteRam, &mut PaletteRam) {
+        (&mut self.background_palettes, &mut self.object_palettes)
+    }
+
+    /// Returns the joypad.
+    pub fn joypad(&self) -> &Joypad {
+        &self.joypad
+    }
+
+    /// Presses or releases `button`.
+    ///
+    /// Pressing a button of the selected group pulls its line low, which requests the joypad
+    /// interrupt.
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        let p1 = self.registers[index(REGISTER_P1)];
+        let before = self.joypad.lines(p1);
+        self.joypad.set(button, pressed);
+
+        if before & !self.joypad.lines(p1) > 0 {
+            self.registers[index(REGISTER_IF)] |= Interrupt::Joypad.mask();
+        }
+    }
+
+    /// Returns the timer.
+    pub fn timer(&self) -> &Timer {
+        &self.timer
+    }
+
+    /// Returns the PPU.
+    pub fn ppu(&self) -> &Ppu {
+        &self.ppu
+    }
+
+    /// Returns the PPU mutably, e.g. to take its events.
+    pub fn ppu_mut(&mut self) -> &mut Ppu {
+        &mut self.ppu
+    }
+
+    /// Returns the serial port.
+    pub fn serial(&self) -> &Serial {
+        &self.serial
+    }
+
+    /// Returns the serial port mutably, e.g. to attach a device.
+    pub fn serial_mut(&mut self) -> &mut Serial {
+        &mut self.serial
+    }
+
+    /// Advances the peripherals by `cycles` machine cycles, requesting their interrupts.
+    pub fn step(&mut self, cycles: u16) {
+        self.step_timer_and_serial(cycles);
+        for _ in 0..cycles {
+            self.step_ppu();
+        }
+    }
+
+    /// Advances the PPU by one machine cycle.
+    ///
+    /// The PPU doesn't interact with the timer and the serial port, so they are stepped apart.
+    pub(crate) fn step_ppu(&mut self) {
+        self.registers[index(REGISTER_IF)] |= self.ppu.step();
+    }
+
+    /// Advances the timer and the serial port by `cycles` machine cycles.
+    pub(crate) fn step_timer_and_serial(&mut self, cycles: u16) {
+        for _ in 0..cycles {
+            if self.timer.step() {
+                self.registers[index(REGISTER_IF)] |= Interrupt::Timer.mask();
+            }
+
+            let (sb, sc) = (
+                self.registers[index(REGISTER_SB)],
+                self.registers[index(REGISTER_SC)],
+            );
+            if let Some(received) = self.serial.step(sb, sc) {
+                self.registers[index(REGISTER_SB)] = received;
+                self.registers[index(REGISTER_SC)] &= !MASK_SC_START;
+                self.registers[index(REGISTER_IF)] |= Interrupt::Serial.mask();
+            }
+        }
+    }
+
+    /// Enables or disables the CGB-only registers.
+    pub fn set_cgb(&mut self, cgb: bool) {
+        self.cgb = cgb;
+    }
+
+    /// Returns the hardware mode, see module documentation.
+    pub fn mode(&self) -> Mode {
+        match (self.cgb, self.dmg_compatibility) {
+            (false, _) => Mode::Dmg,
+            (true, false) => Mode::Cgb,
+            (true, true) => Mode::DmgCompatibility,
+        }
+    }
+
+    /// Sets the registers to the values the boot ROM of `model` leaves behind.
+    ///
+    /// Use when running without a boot ROM, together with `Registers::new_post_boot`.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::{io::Io, model::Model};
+    /// let mut io = Io::new();
+    ///
+    /// io.initialize_post_boot(Model::Dmg);
+    ///
+    /// assert_eq!(0x91, io.read(0xFF40));
+    /// assert_eq!(0xE1, io.read(0xFF0F));
+    /// ```
+    pub fn initialize_post_boot(&mut self, model: Model) {
+        let adjustments: &[(u16, u8)] = match model {
+            Model::Dmg | Model::Mgb => &[],
+            Model::Sgb => &[(0xFF26, 0xF0)],
+            Model::Cgb => &[(0xFF02, 0x7F), (0xFF04, 0x00)],
+        };
+
+        for &(address, value) in POST_BOOT_DMG.iter().chain(adjustments) {
+            self.set_raw(address, value & !register(address).unused);
+        }
+    }
+
+    /// Returns the registers, mode flags and palette RAMs as bytes.
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.registers.to_vec();
+        bytes.push(self.cgb as u8 | (self.dmg_compatibility as u8) << 1);
+        bytes.extend(self.background_palettes.to_bytes());
+        bytes.extend(self.object_palettes.to_bytes());
+        bytes.extend(self.timer.to_bytes());
+        bytes.extend(self.ppu.to_bytes());
+        bytes
+    }
+
+    /// Restores a state saved with `to_bytes`, `None` if `bytes` has the wrong length.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let registers = bytes.get(..0x80)?;
+        let mode = *bytes.get(0x80)?;
+        let peripherals = bytes.len().checked_sub(TIMER_STATE_SIZE + PPU_STATE_SIZE)?;
+        let palettes = bytes.get(0x81..peripherals)?;
+        let (background, objects) = palettes.split_at(palettes.len() / 2);
+        let (timer, ppu) = bytes[peripherals..].split_at(TIMER_STATE_SIZE);
+
+        Some(Self {
+            registers: registers.try_into().ok()?,
+            cgb: mode & 0x01 > 0,
+            dmg_compatibility: mode & 0x02 > 0,
+            background_palettes: PaletteRam::from_bytes(background)?,
+            object_palettes: PaletteRam::from_bytes(objects)?,
+            joypad: Joypad::new(),
+            timer: Timer::from_bytes(timer)?,
+            ppu: Ppu::from_bytes(ppu)?,
+            serial: Serial::new(),
+        })
+    }
+
+    /// Returns `true` once the boot ROM has been disabled through `FF50`.
+    pub fn is_boot_finished(&self) -> bool {
+        self.registers[index(REGISTER_BOOT)] & 0x01 > 0
+    }
+
+    fn is_mapped(&self, register: &Register) -> bool {
+        register.peripheral != Peripheral::Unmapped
+            && (self.mode() == Mode::Cgb || !register.cgb_only)
+    }
+
+    /// Reads the register at `address` as seen by the CPU.
+    pub fn read(&self, address: u16) -> u8 {
+        let register = register(address);
+
+        if !self.is_mapped(&register) {
+            return 0xFF;
+        }
+
+        match register.peripheral {
+            Peripheral::Joypad => {
+                let p1 = self.registers[index(address)] & register.writable | register.unused;
+                p1 | self.joypad.lines(p1)
+            }
+            Peripheral::Ppu if address == REGISTER_BCPS => self.background_palettes.specification(),
+            Peripheral::Ppu if address == REGISTER_BCPD => self.background_palettes.read_data(),
+            Peripheral::Ppu if address == REGISTER_OCPS => self.object_palettes.specification(),
+            Peripheral::Ppu if address == REGISTER_OCPD => self.object_palettes.read_data(),
+            _ => self.get_raw(address) | register.unused,
+        }
+    }
+
+    /// Writes `value` to the register at `address` as the CPU would.
+    pub fn write(&mut self, address: u16, value: u8) {
+        let register = register(address);
+
+        if !self.is_mapped(&register) {
+            return;
+        }
+
+        match register.peripheral {
+            Peripheral::Timer => self.timer.write(address, value),
+            Peripheral::Ppu if is_lcd_register(address) => {
+                self.registers[index(REGISTER_IF)] |= self.ppu.write(address, value)
+            }
+            Peripheral::System if self.is_boot_finished() => {}
+            Peripheral::System if address == REGISTER_BOOT && value & 0x01 > 0 => {
+                self.registers[index(address)] = 0x01;
+                self.dmg_compatibility = self.cgb
+                    && self.registers[index(REGISTER_KEY0)] & MASK_KEY0_DMG_COMPATIBILITY > 0;
+            }
+            Peripheral::Ppu if address == REGISTER_BCPS => {
+                self.background_palettes.set_specification(value)
+            }
+            Peripheral::Ppu if address == REGISTER_BCPD => {
+                self.background_palettes.write_data(value)
+            }
+            Peripheral::Ppu if address == REGISTER_OCPS => {
+                self.object_palettes.set_specification(value)
+            }
+            Peripheral::Ppu if address == REGISTER_OCPD => self.object_palettes.write_data(value),
+            Peripheral::Serial if address == REGISTER_SC => {
+                self.registers[index(address)] = value & register.writable;
+
+                let start = MASK_SC_START | MASK_SC_INTERNAL_CLOCK;
+                if value & start == start && !self.serial.is_transferring() {
+                    self.serial.start(self.registers[index(REGISTER_SB)]);
+                }
+            }
+            _ => {
+                let current = self.registers[index(address)];
+                self.registers[index(address)] =
+                    (current & !register.writable) | (value & register.writable);
+            }
+        }
+    }
+
+    /// Returns the raw value of the register at `address`, ignoring masks.
+    ///
+    /// Intended for peripherals updating their own registers.
+    pub fn get_raw(&self, address: u16) -> u8 {
+        match register(address).peripheral {
+            Peripheral::Timer => self.timer.read(address),
+            Peripheral::Ppu if is_lcd_register(address) => self.ppu.read(address),
+            _ => self.registers[index(address)],
+        }
+    }
+
+    /// Sets the raw value of the register at `address`, ignoring masks.
+    ///
+    /// Intended for peripherals updating their own registers, e.g. the PPU setting `LY`.
+    pub fn set_raw(&mut self, address: u16, value: u8) {
+        match register(address).peripheral {
+            Peripheral::Timer => self.timer.set_raw(address, value),
+            Peripheral::Ppu if is_lcd_register(address) => self.ppu.set_raw(address, value),
+            _ => self.registers[index(address)] = value,
+        }
+    }
+}
+
+fn index(address: u16) -> usize {
+    (address - IO_START) as usize
+}
+
+/// Returns `true` for the PPU registers kept by the `Ppu`, the others are plain registers.
+fn is_lcd_register(address: u16) -> bool {
+    matches!(
+        address,
+        REGISTER_LCDC | REGISTER_STAT | REGISTER_LY | REGISTER_LYC
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unmapped_registers_read_ff_and_ignore_writes() {
+        let mut io = Io::new();
+
+        io.write(0xFF03, 0x00);
+        io.write(0xFF7F, 0x00);
+
+        assert_eq!(0xFF, io.read(0xFF03));
+        assert_eq!(0xFF, io.read(0xFF7F));
+        assert_eq!(0x00, io.get_raw(0xFF03));
+    }
+
+    #[test]
+    fn unused_interrupt_flag_bits_read_as_set() {
+        let mut io = Io::new();
+
+        io.write(REGISTER_IF, 0x01);
+
+        assert_eq!(0xE1, io.read(REGISTER_IF));
+        assert_eq!(0x01, io.get_raw(REGISTER_IF));
+    }
+
+    #[test]
+    fn read_only_bits_are_not_written() {
+        let mut io = Io::new();
+        io.set_raw(REGISTER_STAT, 0x03);
+        io.set_raw(REGISTER_LY, 0x90);
+
+        io.write(REGISTER_STAT, 0xFF);
+        io.write(REGISTER_LY, 0x00);
+
+        assert_eq!(0xFB, io.read(REGISTER_STAT));
+        assert_eq!(0x90, io.read(REGISTER_LY));
+    }
+
+    #[test]
+    fn cgb_registers_are_unmapped_on_dmg() {
+        let mut io = Io::new();
+
+        io.write(REGISTER_VBK, 0x01);
+        assert_eq!(0xFF, io.read(REGISTER_VBK));
+        assert_eq!(0x00, io.get_raw(REGISTER_VBK));
+
+        io.set_cgb(true);
+        io.write(REGISTER_VBK, 0x01);
+        assert_eq!(0xFF, io.read(REGISTER_VBK));
+        io.write(REGISTER_VBK, 0x00);
+        assert_eq!(0xFE, io.read(REGISTER_VBK));
+    }
+
+    #[test]
+    fn palette_registers_are_dispatched_to_palette_ram() {
+        let mut io = Io::new();
+        io.set_cgb(true);
+
+        io.write(REGISTER_OCPS, 0x80);
+        io.write(REGISTER_OCPD, 0xFF);
+        io.write(REGISTER_OCPD, 0x7F);
+
+        assert_eq!(0xC2, io.read(REGISTER_OCPS));
+        assert_eq!(0x7FFF, io.object_palettes().raw_color(0, 0));
+        assert_eq!(0x0000, io.background_palettes().raw_color(0, 0));
+    }
+
+    #[test]
+    fn key0_selects_dmg_compatibility_when_boot_finishes() {
+        let mut io = Io::new();
+        io.set_cgb(true);
+
+        io.write(REGISTER_KEY0, 0x04);
+        assert_eq!(Mode::Cgb, io.mode());
+
+        io.write(REGISTER_BOOT, 0x01);
+        assert_eq!(Mode::DmgCompatibility, io.mode());
+
+        io.write(REGISTER_VBK, 0x01);
+        io.write(REGISTER_BCPS, 0x80);
+        io.write(REGISTER_BCPD, 0xFF);
+        assert_eq!(0xFF, io.read(REGISTER_VBK));
+        assert_eq!(0x00, io.get_raw(REGISTER_VBK));
+        assert_eq!(0x0000, io.background_palettes().raw_color(0, 0));
+    }
+
+    #[test]
+    fn key0_and_boot_are_locked_once_boot_finishes() {
+        let mut io = Io::new();
+        io.set_cgb(true);
+
+        io.write(REGISTER_BOOT, 0x01);
+        io.write(REGISTER_KEY0, 0x04);
+        io.write(REGISTER_BOOT, 0x00);
+
+        assert!(io.is_boot_finished());
+        assert_eq!(0x00, io.get_raw(REGISTER_KEY0));
+        assert_eq!(Mode::Cgb, io.mode());
+    }
+
+    #[test]
+    fn dmg_has_no_compatibility_mode() {
+        let mut io = Io::new();
+
+        io.write(REGISTER_KEY0, 0x04);
+        io.write(REGISTER_BOOT, 0x01);
+
+        assert_eq!(Mode::Dmg, io.mode());
+    }
+
+    #[test]
+    fn post_boot_values_read_back_as_documented() {
+        let mut io = Io::new();
+
+        io.initialize_post_boot(Model::Sgb);
+
+        for &(address, value) in POST_BOOT_DMG.iter().filter(|(a, _)| *a != 0xFF26) {
+            assert_eq!(value, io.read(address), "{:04x}", address);
+        }
+        assert_eq!(0xF0, io.read(REGISTER_NR52));
+        assert_eq!(0x01, io.get_raw(REGISTER_IF));
+    }
+
+    #[test]
+    fn joypad_reads_released_buttons() {
+        let mut io = Io::new();
+
+        io.write(REGISTER_P1, 0x20);
+
+        assert_eq!(0xEF, io.read(REGISTER_P1));
+    }
+
+    #[test]
+    fn pressing_a_selected_button_requests_the_joypad_interrupt() {
+        let mut io = Io::new();
+        io.write(REGISTER_P1, 0x20);
+
+        io.set_button(Button::A, true);
+        assert_eq!(0x00, io.get_raw(REGISTER_IF));
+
+        io.set_button(Button::Down, true);
+        assert_eq!(0xE7, io.read(REGISTER_P1));
+        assert_eq!(0x10, io.get_raw(REGISTER_IF));
+    }
+
+    #[test]
+    fn registers_are_routed_to_their_peripheral() {
+        for (address, peripheral) in [
+            (0xFF00, Peripheral::Joypad),
+            (0xFF01, Peripheral::Serial),
+            (0xFF04, Peripheral::Timer),
+            (0xFF0F, Peripheral::Interrupts),
+            (0xFF26, Peripheral::Apu),
+            (0xFF3F, Peripheral::Apu),
+            (0xFF40, Peripheral::Ppu),
+            (0xFF4B, Peripheral::Ppu),
+            (0xFF4C, Peripheral::System),
+            (0xFF4D, Peripheral::Unmapped),
+        ] {
+            assert_eq!(peripheral, register(address).peripheral, "{:04x}", address);
+        }
+    }
+}
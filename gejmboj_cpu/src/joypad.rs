@@ -0,0 +1,113 @@
+//! # Joypad
+//!
+//! The eight buttons are wired as a 2x4 matrix read through P1 (`FF00`). Bits 4 and 5 select the
+//! direction and the action buttons, a selected line reads as `0` while its button is pressed:
+//!
+//! ```asciidoc
+//! Bit 5:   Select action buttons (0 = selected)
+//! Bit 4:   Select direction buttons (0 = selected)
+//! Bit 3:   Down  or Start
+//! Bit 2:   Up    or Select
+//! Bit 1:   Left  or B
+//! Bit 0:   Right or A
+//! ```
+//!
+//! ```
+//! # use gejmboj_cpu::io::{Io, REGISTER_P1};
+//! # use gejmboj_cpu::joypad::Button;
+//! let mut io = Io::new();
+//! io.set_button(Button::Start, true);
+//!
+//! io.write(REGISTER_P1, 0x10);
+//! assert_eq!(0xD7, io.read(REGISTER_P1));
+//!
+//! io.write(REGISTER_P1, 0x20);
+//! assert_eq!(0xEF, io.read(REGISTER_P1));
+//! ```
+
+/// A button of the joypad.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "wasm", wasm_bindgen::prelude::wasm_bindgen)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    /// Returns the bit of the button in `Joypad::pressed`, directions in the lower nibble and
+    /// actions in the upper nibble.
+    fn mask(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// Mask of P1 selecting the direction buttons when reset.
+const SELECT_DIRECTIONS: u8 = 0b0001_0000;
+
+/// Mask of P1 selecting the action buttons when reset.
+const SELECT_ACTIONS: u8 = 0b0010_0000;
+
+/// The pressed buttons.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Joypad {
+    pressed: u8,
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `true` if `button` is pressed.
+    pub fn is_pressed(&self, button: Button) -> bool {
+        self.pressed & button.mask() > 0
+    }
+
+    /// Presses or releases `button`.
+    pub fn set(&mut self, button: Button, pressed: bool) {
+        if pressed {
+            self.pressed |= button.mask();
+        } else {
+            self.pressed &= !button.mask();
+        }
+    }
+
+    /// Returns the button lines, the lower nibble of P1, for the selection bits in `p1`.
+    pub fn lines(&self, p1: u8) -> u8 {
+        let mut low = 0;
+        if p1 & SELECT_DIRECTIONS == 0 {
+            low |= self.pressed & 0x0F;
+        }
+        if p1 & SELECT_ACTIONS == 0 {
+            low |= self.pressed >> 4;
+        }
+        !low & 0x0F
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pressed_buttons_pull_their_selected_line_low() {
+        let mut joypad = Joypad::new();
+        joypad.set(Button::Left, true);
+        joypad.set(Button::A, true);
+
+        assert_eq!(0x0F, joypad.lines(0x30));
+        assert_eq!(0x0D, joypad.lines(0x20));
+        assert_eq!(0x0E, joypad.lines(0x10));
+        assert_eq!(0x0C, joypad.lines(0x00));
+
+        joypad.set(Button::A, false);
+        assert!(!joypad.is_pressed(Button::A));
+        assert_eq!(0x0F, joypad.lines(0x10));
+    }
+}
@@ -1,6 +1,42 @@
+pub mod asm;
+pub mod cartridge;
+pub mod colorization;
+pub mod counters;
+pub mod coverage;
 pub mod cpu;
+pub mod debugger;
+pub mod decode_cache;
+pub mod disassembler;
 pub mod errors;
+pub mod gameboy;
+#[cfg(feature = "gdb")]
+pub mod gdb;
+pub mod hooks;
+pub mod input;
 pub mod instructions;
+pub mod interrupts;
+pub mod io;
+pub mod joypad;
+pub mod link;
 pub mod macros;
 pub mod memory;
+pub mod model;
+pub mod palette;
+pub mod ppu;
+pub mod printer;
+#[cfg(feature = "profiling")]
+pub mod profiling;
+pub mod recorder;
 pub mod registers;
+pub mod renderer;
+pub mod rewind;
+pub mod savestate;
+pub mod serial;
+pub mod stack;
+pub mod symbols;
+pub mod throttle;
+pub mod timer;
+pub mod trace;
+pub mod vram;
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -0,0 +1,221 @@
+//! # Link cable over TCP
+//!
+//! `TcpLink` is a `SerialDevice` connecting two emulator instances, e.g. for multiplayer games.
+//! After a handshake checking the protocol version, the instances exchange two byte messages,
+//! a kind and a data byte:
+//!
+//! - `TRANSFER`: the sender drives the clock and shifts out the data byte
+//! - `REPLY`: the byte shifted in by the receiver of a `TRANSFER`
+//!
+//! The side which drives the clock sends a `TRANSFER` and waits for the `REPLY`. The other side
+//! answers with SB if it is listening on the external clock, `FF` otherwise. When both sides
+//! drive the clock at the same time, the `TRANSFER` of the other side serves as the reply, so
+//! both receive the byte of the other.
+//!
+//! Waiting for a reply blocks the emulator for at most the timeout, after which `FF` is received
+//! like with nothing connected, as is after the connection is lost.
+//!
+//! ```no_run
+//! # use std::net::TcpListener;
+//! # use gejmboj_cpu::{gameboy::GameBoy, link::TcpLink, model::Model};
+//! let mut gameboy = GameBoy::new(Model::Dmg);
+//!
+//! let listener = TcpListener::bind("localhost:5555").unwrap();
+//! let link = TcpLink::accept(&listener).unwrap();
+//! gameboy.memory_mut().io_mut().serial_mut().attach(Box::new(link));
+//! ```
+
+use std::{
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use crate::{errors::CpuError, serial::SerialDevice};
+
+/// Sent by both sides when connecting, followed by the protocol version.
+const MAGIC: &[u8; 4] = b"GBLK";
+
+const VERSION: u8 = 1;
+
+const TRANSFER: u8 = 0x01;
+const REPLY: u8 = 0x02;
+
+/// Default time to wait for a reply.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// A link cable to another emulator instance, see module documentation.
+#[derive(Debug)]
+pub struct TcpLink {
+    /// `None` once the connection is lost
+    stream: Option<TcpStream>,
+    timeout: Duration,
+}
+
+impl TcpLink {
+    /// Connects to an instance accepting connections at `address`.
+    pub fn connect(address: impl ToSocketAddrs) -> Result<Self, CpuError> {
+        let stream = TcpStream::connect(address).map_err(error)?;
+        Self::new(stream)
+    }
+
+    /// Accepts a connection from another instance on `listener`.
+    pub fn accept(listener: &TcpListener) -> Result<Self, CpuError> {
+        let (stream, _) = listener.accept().map_err(error)?;
+        Self::new(stream)
+    }
+
+    /// Performs the handshake on an established connection.
+    pub fn new(mut stream: TcpStream) -> Result<Self, CpuError> {
+        stream.set_nodelay(true).map_err(error)?;
+        stream.write_all(MAGIC).map_err(error)?;
+        stream.write_all(&[VERSION]).map_err(error)?;
+
+        let mut hello = [0; 5];
+        stream.read_exact(&mut hello).map_err(error)?;
+        if &hello[..4] != MAGIC {
+            return Err(CpuError::Error("Not a link cable connection".to_string()));
+        }
+        if hello[4] != VERSION {
+            return Err(CpuError::Error(format!(
+                "Unsupported link cable protocol version {}",
+                hello[4]
+            )));
+        }
+
+        Ok(Self {
+            stream: Some(stream),
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// Sets the time to wait for a reply, see module documentation.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Returns `false` once the connection is lost.
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// Runs `f` on the stream, dropping the connection if it fails.
+    fn with_stream<T>(&mut self, f: impl FnOnce(&mut TcpStream) -> io::Result<T>) -> Option<T> {
+        let result = f(self.stream.as_mut()?);
+        if result.is_err() {
+            self.stream = None;
+        }
+        result.ok()
+    }
+}
+
+impl SerialDevice for TcpLink {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        let timeout = self.timeout;
+        let reply = self.with_stream(|stream| {
+            stream.set_nonblocking(false)?;
+            stream.set_read_timeout(Some(timeout))?;
+            stream.write_all(&[TRANSFER, byte])?;
+
+            let mut message = [0; 2];
+            stream.read_exact(&mut message)?;
+            match message {
+                [TRANSFER, data] | [REPLY, data] => Ok(data),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Unknown message",
+                )),
+            }
+        });
+
+        reply.unwrap_or(0xFF)
+    }
+
+    fn poll(&mut self, byte: Option<u8>) -> Option<u8> {
+        self.with_stream(|stream| {
+            stream.set_nonblocking(true)?;
+
+            let mut message = [0; 2];
+            match stream.peek(&mut message) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(2) => {}
+                Ok(_) => return Ok(None),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(e) => return Err(e),
+            }
+            stream.read_exact(&mut message)?;
+
+            match message {
+                [TRANSFER, data] => {
+                    stream.set_nonblocking(false)?;
+                    stream.write_all(&[REPLY, byte.unwrap_or(0xFF)])?;
+                    Ok(Some(data))
+                }
+                // A late reply to a transfer which timed out
+                [REPLY, _] => Ok(None),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Unknown message",
+                )),
+            }
+        })
+        .flatten()
+    }
+}
+
+fn error(e: io::Error) -> CpuError {
+    CpuError::Error(format!("Link cable: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    fn connect() -> (TcpLink, TcpLink) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = thread::spawn(move || TcpLink::connect(address).unwrap());
+        let server = TcpLink::accept(&listener).unwrap();
+
+        (server, client.join().unwrap())
+    }
+
+    #[test]
+    fn the_listening_side_replies_to_transfers() {
+        let (mut master, mut slave) = connect();
+        master.set_timeout(Duration::from_secs(5));
+
+        let slave = thread::spawn(move || loop {
+            if let Some(received) = slave.poll(Some(0x34)) {
+                return received;
+            }
+        });
+
+        assert_eq!(0x34, master.transfer(0x12));
+        assert_eq!(0x12, slave.join().unwrap());
+    }
+
+    #[test]
+    fn both_sides_driving_the_clock_exchange_their_bytes() {
+        let (mut a, mut b) = connect();
+        a.set_timeout(Duration::from_secs(5));
+        b.set_timeout(Duration::from_secs(5));
+
+        let b = thread::spawn(move || b.transfer(0x34));
+
+        assert_eq!(0x34, a.transfer(0x12));
+        assert_eq!(0x12, b.join().unwrap());
+    }
+
+    #[test]
+    fn lost_connections_receive_ff() {
+        let (mut link, other) = connect();
+        drop(other);
+
+        assert_eq!(0xFF, link.transfer(0x12));
+        assert!(!link.is_connected());
+        assert_eq!(None, link.poll(Some(0x12)));
+    }
+}
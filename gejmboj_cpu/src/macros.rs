@@ -1,16 +1,29 @@
 //! Macros used within this crate
 
 /// Macro to define a group of instructions
+///
+/// The mnemonic of an instruction is its name up to the first `_`, e.g. `LD_N_TO_HL` is `LD`,
+/// unless given after the length, e.g. `JPC(..) [3] as "JP"`.
 #[macro_export]
 macro_rules! instruction_group {
+    (@mnemonic $item_name:ident $mnemonic:literal) => {
+        $mnemonic
+    };
+    (@mnemonic $item_name:ident) => {
+        match stringify!($item_name).split_once('_') {
+            Some((mnemonic, _)) => mnemonic,
+            None => stringify!($item_name),
+        }
+    };
     ( $(#[$groupdocs:meta])
       *$group_name:ident ($r:ident, $m:ident, $c:ident) {
           $($(#[$itemdocs:meta])*
-            $item_name:ident($($operand:ident: $t:tt),*) [ $length:literal ] => $execute:block)+
+            $item_name:ident($($operand:ident: $t:tt),*) [ $length:literal ]
+            $(as $mnemonic:literal)? => $execute:block)+
       }) => {
 
         $(#[$groupdocs])*
-        #[derive(Debug, PartialEq)]
+        #[derive(Debug, Clone, PartialEq)]
         #[allow(non_camel_case_types)]
         pub enum $group_name {
             $($(#[$itemdocs])*$item_name($($t),*),)+
@@ -19,9 +32,12 @@ macro_rules! instruction_group {
         impl $group_name {
             pub fn execute(&self,
                            $r: &mut $crate::registers::Registers,
-                           $m: &mut $crate::memory::Memory,
+                           $m: &mut impl $crate::memory::MemoryBus,
                            $c: &mut $crate::cpu::CpuFlags
             ) -> $crate::instructions::InstructionResult {
+                #[allow(unused_imports)]
+                use $crate::memory::MemoryBus as _;
+
                 match self {
                     $($group_name::$item_name($($operand),*) => $execute,)+
                 }
@@ -35,30 +51,81 @@ macro_rules! instruction_group {
                     },)+
                 }
             }
+
+            /// Returns the mnemonic of the instruction.
+            pub fn mnemonic(&self) -> &'static str {
+                match self {
+                    $($group_name::$item_name(..) => {
+                        $crate::instruction_group!(@mnemonic $item_name $($mnemonic)?)
+                    },)+
+                }
+            }
+
+            /// Returns the name of the instruction group.
+            pub fn group_name(&self) -> &'static str {
+                stringify!($group_name)
+            }
         }
     }
 }
 
+/// Macro to define instruction tests, each with fresh registers, memory and CPU flags
+///
+/// A test followed by `for pattern in [case: value, ..]` is expanded into a module of tests, one
+/// per case, with `value` bound to `pattern`:
+///
+/// ```ignore
+/// add_sets_flags(registers, memory, cpu_flags) for (a, flags) in [
+///     zero: (0x00, 0b1000_0000),
+///     half_carry: (0x0F, 0b0010_0000),
+/// ] => {
+///     ..
+/// }
+/// ```
 #[cfg(test)]
 #[doc(hidden)]
 #[macro_export]
 macro_rules! instruction_tests {
-    ($($testname:ident ($r:ident, $m:ident, $c:ident) => $testbody:block)*) => {
-        #[cfg(test)]
-        mod instruction_tests {
+    (@test $testname:ident ($r:ident, $m:ident, $c:ident) => $testbody:block) => {
+        #[test]
+        fn $testname() {
+            let mut $r = Registers::new();
+            let mut $m = $crate::memory::Memory::new();
+            let mut $c = $crate::cpu::CpuFlags::new();
+
+            $testbody
+        }
+    };
+    (@test $testname:ident ($r:ident, $m:ident, $c:ident)
+     for $pattern:tt in [$($case:ident: $value:expr),+] => $testbody:block) => {
+        mod $testname {
             use super::*;
-            #[allow(unused_imports)]
-            use $crate::registers::*;
 
             $(
                 #[test]
-                fn $testname() {
+                fn $case() {
                     let mut $r = Registers::new();
                     let mut $m = $crate::memory::Memory::new();
                     let mut $c = $crate::cpu::CpuFlags::new();
+                    let $pattern = $value;
 
                     $testbody
                 }
+            )+
+        }
+    };
+    ($($testname:ident ($r:ident, $m:ident, $c:ident)
+       $(for $pattern:tt in [$($case:ident: $value:expr),+ $(,)?])? => $testbody:block)*) => {
+        #[cfg(test)]
+        mod instruction_tests {
+            use super::*;
+            #[allow(unused_imports)]
+            use $crate::registers::*;
+
+            $(
+                $crate::instruction_tests!(
+                    @test $testname ($r, $m, $c) $(for $pattern in [$($case: $value),+])? => $testbody
+                );
             )*
         }
     }
@@ -69,21 +136,28 @@ macro_rules! instruction_tests {
 #[macro_export]
 macro_rules! combine_instructions {
     ($name:ident( $($group:ident),+ )) => {
-        #[derive(Debug, PartialEq)]
+        #[derive(Debug, Clone, PartialEq)]
         pub enum $name {
             $($group($group)),+
         }
 
         impl $name {
+            /// Executes the instruction, returning the consumed machine cycles and whether a
+            /// conditional branch was taken.
             pub fn execute(
                 &self,
-                mut registers: &mut $crate::registers::Registers,
-                mut memory: &mut $crate::memory::Memory,
-                mut cpu_flags: &mut $crate::cpu::CpuFlags,
-            ) -> InstructionResult {
-                match self {
-                    $($name::$group(instr) => instr.execute(&mut registers, &mut memory, &mut cpu_flags)),+
-                }
+                registers: &mut $crate::registers::Registers,
+                memory: &mut impl $crate::memory::MemoryBus,
+                cpu_flags: &mut $crate::cpu::CpuFlags,
+            ) -> Result<Execution, CpuError> {
+                let branch_taken = self
+                    .condition()
+                    .map(|condition| condition.is_fulfilled(registers));
+                let cycles = match self {
+                    $($name::$group(instr) => instr.execute(registers, memory, cpu_flags)),+
+                }?;
+
+                Ok(Execution { cycles, branch_taken })
             }
 
             pub fn length(&self) -> u16 {
@@ -91,6 +165,133 @@ macro_rules! combine_instructions {
                     $($name::$group(instr) => instr.length()),+
                 }
             }
+
+            /// Returns the mnemonic of the instruction, e.g. `LD`.
+            pub fn mnemonic(&self) -> &'static str {
+                match self {
+                    $($name::$group(instr) => instr.mnemonic()),+
+                }
+            }
+
+            /// Returns the name of the instruction group, e.g. `Load8Bit`.
+            pub fn group_name(&self) -> &'static str {
+                match self {
+                    $($name::$group(instr) => instr.group_name()),+
+                }
+            }
+
+            /// Returns the operands of the instruction, in the order the disassembler prints them.
+            pub fn operands(&self) -> Vec<Operand> {
+                match self {
+                    $($name::$group(instr) => instr.operands()),+
+                }
+            }
+
+            /// Returns the effects of the instruction on the flags.
+            pub fn flag_effects(&self) -> FlagEffects {
+                match self {
+                    $($name::$group(instr) => instr.flag_effects()),+
+                }
+            }
+
+            /// Returns the machine code of the instruction, including the `CB` prefix and any
+            /// immediate operands. Fails for register operands no opcode encodes, e.g.
+            /// `PUSH PC`.
+            pub fn encode(&self) -> Result<Vec<u8>, $crate::errors::CpuError> {
+                match self {
+                    $($name::$group(instr) => instr.encode()),+
+                }
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match self {
+                    $($name::$group(instr) => std::fmt::Display::fmt(instr, f)),+
+                }
+            }
+        }
+
+        #[cfg(test)]
+        mod round_trip_tests {
+            use super::*;
+
+            /// Decodes all 256 opcodes and 256 `CB` prefixed opcodes, followed by the immediate
+            /// bytes `34 12`, and checks that every decoded instruction encodes to the bytes it
+            /// was decoded from. Opcodes the decoder doesn't support are skipped, but every group
+            /// must decode at least once.
+            #[test]
+            fn all_opcodes_encode_to_their_bytes() {
+                let mut memory = $crate::memory::Memory::new();
+                let mut decoded = vec![];
+
+                for bytes in (0..=0xFF)
+                    .map(|opcode| [opcode, 0x34, 0x12])
+                    .chain((0..=0xFF).map(|opcode| [0xCB, opcode, 0x12]))
+                {
+                    memory.load(0, &bytes);
+                    let (instruction, size) =
+                        match $crate::instructions::decode(bytes[0], 0, &memory) {
+                            Ok(decoded) => decoded,
+                            Err(_) => continue,
+                        };
+                    let encoded = instruction.encode().unwrap();
+
+                    assert_eq!(&bytes[..encoded.len()], &encoded[..], "{}", instruction);
+                    assert_eq!(size, encoded.len(), "{}", instruction);
+                    assert_eq!(size, instruction.length() as usize, "{}", instruction);
+                    decoded.push(instruction);
+                }
+
+                $(
+                    assert!(
+                        decoded.iter().any(|instruction| matches!(instruction, $name::$group(_))),
+                        "No {} instruction decoded",
+                        stringify!($group)
+                    );
+                )+
+            }
         }
     };
 }
+
+/// Defines the decoder of unprefixed opcodes and the table of its patterns from rows of
+/// `(bits) "MNEMONIC" => Group::VARIANT(operands)`, matched in order. The bits, most significant
+/// first, are `0`, `1`, `_` or an identifier binding the bit for the operands.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! opcode_table {
+    ( $(#[$decode_docs:meta])*
+      fn $decode:ident($opcode:ident, $pc:ident, $memory:ident) -> $name:ident;
+
+      $(#[$table_docs:meta])*
+      const $table:ident;
+
+      {
+          $( ($($bit:tt),+) $mnemonic:literal
+             => $group:ident::$variant:ident($($operand:expr),* $(,)?) ),+ $(,)?
+      }) => {
+
+        $(#[$decode_docs])*
+        fn $decode(
+            $opcode: u8,
+            $pc: u16,
+            $memory: &impl $crate::memory::MemoryBus,
+        ) -> Option<$name> {
+            match $crate::instructions::utils::into_bits($opcode) {
+                $(($($bit),+) => Some($name::$group($group::$variant($($operand),*))),)+
+                _ => None,
+            }
+        }
+
+        $(#[$table_docs])*
+        pub const $table: &[$crate::instructions::opcodes::OpcodePattern] = &[
+            $($crate::instructions::opcodes::OpcodePattern {
+                bits: concat!($(stringify!($bit)),+),
+                mnemonic: $mnemonic,
+                group: stringify!($group),
+                variant: stringify!($variant),
+            },)+
+        ];
+    };
+}
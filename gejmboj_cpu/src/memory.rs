@@ -17,110 +17,1992 @@
 //! FF80-FFFE: High RAM (HRAM)
 //! FFFF:      IE register
 //! ```
+//!
+//! ## I/O registers
+//!
+//! Accesses to `FF00-FF7F` are dispatched to the owning peripheral by `Io`, which applies each
+//! register's read and write masks. Stepping the memory steps the peripherals too, e.g. the
+//! timer.
+//!
+//! ## Observers
+//!
+//! A `MemoryObserver` connected with `Memory::set_observer` is notified of every read and write
+//! made through `get` and `set`, together with the address of the instruction making the
+//! access. Writes blocked by OAM DMA never reach the bus and are not reported. Debugging tools
+//! should use `peek` which does not notify the observer.
+//!
+//! ## Echo RAM
+//!
+//! `E000-FDFF` mirrors `C000-DDFF`, reads and writes through either region access the same
+//! bytes. The last 512 bytes of WRAM (`DE00-DFFF`) have no mirror since the echo region ends
+//! where OAM begins, so an access to `FE00` is always an OAM access and never aliases `DE00`.
+//!
+//! ## Unchecked accesses
+//!
+//! Plain RAM is a fixed size array covering the whole address space. With the `unchecked`
+//! feature `get` and `set` skip its bounds checks, addresses beyond `FFFF` are truncated to 16
+//! bits instead of panicking.
+//!
+//! ## VRAM
+//!
+//! `8000-9FFF` is handled by `Vram`. On CGB revisions the VBK register (`FF4F`) selects which
+//! of its two banks the CPU sees.
+//!
+//! ## Boot ROM
+//!
+//! A boot ROM installed with `Memory::set_boot_rom` overlays the cartridge until it is disabled
+//! through `FF50`. The DMG boot ROM covers `0000-00FF`, the CGB boot ROM also `0200-08FF`
+//! leaving the cartridge header at `0100-01FF` visible.
+//!
+//! ## Unusable region
+//!
+//! `FEA0-FEFF` is not backed by ordinary RAM, what it does depends on the hardware revision,
+//! see `Revision`. Unmapped I/O registers read as `FF`, as do cartridge regions without RAM.
+//!
+//! ## Stack writes
+//!
+//! Stack writes (`CALL`, `PUSH` etc.) targeting ROM (`0000-7FFF`) or the unusable region
+//! (`FEA0-FEFF`) are dropped. Without this a misplaced stack pointer would silently patch the
+//! ROM image, or issue bank switching commands to the cartridge. If diagnostics are enabled
+//! every dropped write is reported as a `Diagnostic::StackWriteDropped`.
+//!
+//! ## OAM DMA
+//!
+//! Writing `XX` to `FF46` starts a transfer of `XX00-XX9F` to OAM (`FE00-FE9F`). The transfer
+//! is not instant, one byte is copied per machine cycle as the CPU advances it through
+//! `Memory::step_dma`, so a full transfer takes 160 machine cycles. Writing `FF46` while a
+//! transfer is running restarts it from the new source.
+//!
+//! While a transfer is running the DMA owns the bus, the CPU can only access `FF00-FFFF`, i.e.
+//! I/O registers and HRAM. `Memory::get` of any other address returns the byte just copied, and
+//! `Memory::set` is ignored. `Memory::peek` is not affected. The I/O registers are on the CPU's
+//! internal bus like HRAM, not on the bus the transfer drives, so they stay accessible too:
+//! the routine waiting for a transfer in HRAM keeps reading the timer or joypad, and writing
+//! `FF46` must reach the DMA to restart it.
+//!
+//! `CPU::tick` advances the bus one machine cycle before each memory access of an instruction,
+//! so the access happens in its machine cycle, e.g. a transfer started by `LDH (0x46), A` does
+//! not copy any bytes before the instruction ends.
+//!
+//! ## Strict mode
+//!
+//! Accesses the hardware ignores or answers with garbage usually point at a bug in the game. In
+//! strict mode, see `Memory::set_strict`, the first such access is kept until taken with
+//! `MemoryBus::take_access_violation`, which `CPU::tick` turns into a
+//! `CpuError::MemoryAccessViolation` after the instruction making it. Rejected writes are
+//! dropped. See `AccessKind` for the accesses reported.
+//!
+//! ## Serialization
+//!
+//! With the `serde` feature `Memory` implements `Serialize` and `Deserialize`. The flat memory,
+//! I/O registers, palette RAMs and VRAM banks are stored as byte strings, together with the
+//! model, revision and any running DMA transfer. The cartridge, observer, diagnostics and
+//! colorization table are not part of the state; a deserialized memory has none connected. The
+//! framebuffer starts out white and is rendered again from the next line on.
+
+#[cfg(feature = "serde")]
+mod serialization;
+mod timed;
+
+pub(crate) use timed::TimedBus;
+
+use std::{
+    cell::{Cell, RefCell},
+    convert::TryInto,
+    fmt::{Display, Write},
+    ops::RangeInclusive,
+};
+
+use crate::{
+    cartridge::Mapper,
+    colorization::{self, TitleColorization},
+    errors::CpuError,
+    io::{self, Io, IO_STATE_SIZE},
+    model::Model,
+    renderer::Framebuffer,
+    savestate::{self, Reader},
+    vram::{Vram, VRAM_BANK_SIZE},
+};
+
+#[cfg(feature = "profiling")]
+use crate::profiling::{Profiler, Subsystem};
+
+/// The memory as seen by the CPU.
+///
+/// Instructions, `decode` and `CPU::tick` are generic over this trait so that banked, mapped or
+/// instrumented memory implementations can be used in place of `Memory`. Only `get` and `set`
+/// are required.
+///
+/// ```
+/// # use gejmboj_cpu::{cpu::CPU, memory::MemoryBus, registers::Registers};
+/// struct FlatMemory(Vec<u8>);
+///
+/// impl MemoryBus for FlatMemory {
+///     fn get(&self, location: usize) -> u8 {
+///         self.0[location]
+///     }
+///
+///     fn set(&mut self, location: usize, value: u8) {
+///         self.0[location] = value;
+///     }
+/// }
+///
+/// let mut memory = FlatMemory(vec![0; 0x10000]);
+/// let mut registers = Registers::new();
+///
+/// CPU::new().tick(&mut registers, &mut memory).unwrap();
+///
+/// assert_eq!(1, registers.PC);
+/// ```
+pub trait MemoryBus {
+    /// Gets a `u8` value from memory.
+    fn get(&self, location: usize) -> u8;
+
+    /// Sets a `u8` value in memory.
+    fn set(&mut self, location: usize, value: u8);
+
+    /// Gets a `u8` value without side effects, for debugging tools. Defaults to `get`.
+    fn peek(&self, location: usize) -> u8 {
+        self.get(location)
+    }
+
+    /// Gets a little-endian `u16` value from memory.
+    fn get_u16(&self, location: usize) -> u16 {
+        let lo = self.get(location);
+        let hi = self.get((location + 1) & 0xFFFF);
+
+        u16::from_le_bytes([lo, hi])
+    }
+
+    /// Sets a little-endian `u16` value in memory.
+    fn set_u16(&mut self, location: usize, value: u16) {
+        let [lo, hi] = value.to_le_bytes();
+
+        self.set(location, lo);
+        self.set((location + 1) & 0xFFFF, hi);
+    }
+
+    /// Writes a byte pushed to the stack at `location`, defaults to `set`.
+    fn set_stack(&mut self, location: usize, value: u8) {
+        self.set(location, value);
+    }
+
+    /// Writes a `u16` value pushed to the stack at `location`, the upper byte first like the CPU
+    /// pushes it. Defaults to two `set_stack`.
+    fn set_stack_u16(&mut self, location: usize, value: u16) {
+        let [lo, hi] = value.to_le_bytes();
+
+        self.set_stack((location + 1) & 0xFFFF, hi);
+        self.set_stack(location, lo);
+    }
+
+    /// Returns the ROM offset `location` is mapped to, or `None` outside of ROM. Defaults to
+    /// `location` in `0000-7FFF`, i.e. no banking.
+    fn rom_offset(&self, location: usize) -> Option<usize> {
+        (location < 0x8000).then_some(location)
+    }
+
+    /// Advances hardware living on the bus, e.g. OAM DMA, by `cycles` machine cycles.
+    fn step(&mut self, _cycles: u16) {}
+
+    /// Informs the bus that the instruction at `pc` is about to be executed.
+    fn begin_instruction(&mut self, _pc: u16) {}
+
+    /// Checks that the memory state is one the hardware could be in, see `CPU::check_invariants`.
+    fn check_invariants(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Returns and forgets the address and kind of the first access rejected in strict mode, see
+    /// `Memory::set_strict`. Defaults to `None`.
+    fn take_access_violation(&mut self) -> Option<(u16, AccessKind)> {
+        None
+    }
+}
+
+/// Accesses rejected in strict mode, see `Memory::set_strict`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccessKind {
+    /// A write to ROM (`0000-7FFF`) without a bank controller to receive it
+    RomWrite,
+    /// A read from the unusable region (`FEA0-FEFF`)
+    UnusableRead,
+    /// A write to the unusable region (`FEA0-FEFF`)
+    UnusableWrite,
+    /// A read blocked by a running OAM DMA transfer
+    DmaBlockedRead,
+    /// A write blocked by a running OAM DMA transfer
+    DmaBlockedWrite,
+}
+
+impl Display for AccessKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            AccessKind::RomWrite => "write to ROM",
+            AccessKind::UnusableRead => "read from the unusable region",
+            AccessKind::UnusableWrite => "write to the unusable region",
+            AccessKind::DmaBlockedRead => "read blocked by OAM DMA",
+            AccessKind::DmaBlockedWrite => "write blocked by OAM DMA",
+        };
+        f.write_str(description)
+    }
+}
+
+/// A memory access reported to a `MemoryObserver`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryAccess {
+    pub address: u16,
+    pub value: u8,
+    /// Address of the instruction making the access
+    pub pc: u16,
+}
+
+/// Gets notified of memory accesses, see `Memory::set_observer`.
+///
+/// ```
+/// # use std::{cell::RefCell, rc::Rc};
+/// # use gejmboj_cpu::memory::{Memory, MemoryAccess, MemoryObserver};
+/// struct WriteLog(Rc<RefCell<Vec<MemoryAccess>>>);
+///
+/// impl MemoryObserver for WriteLog {
+///     fn on_write(&mut self, access: MemoryAccess) {
+///         self.0.borrow_mut().push(access);
+///     }
+/// }
+///
+/// let writes = Rc::new(RefCell::new(vec![]));
+/// let mut memory = Memory::new();
+/// memory.set_observer(Box::new(WriteLog(writes.clone())));
+///
+/// memory.set(0xC000, 0x42);
+///
+/// assert_eq!(
+///     vec![MemoryAccess { address: 0xC000, value: 0x42, pc: 0x0000 }],
+///     *writes.borrow()
+/// );
+/// ```
+pub trait MemoryObserver {
+    fn on_read(&mut self, _access: MemoryAccess) {}
+    fn on_write(&mut self, _access: MemoryAccess) {}
+}
+
+/// Hardware revision, selecting the behavior of the unusable region `FEA0-FEFF`.
+///
+/// OAM is never blocked by the PPU yet, so the `FF` reads and OAM corruption seen while it is
+/// are not modeled.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Revision {
+    /// DMG, MGB and SGB: reads return `00` and writes are ignored
+    #[default]
+    Dmg,
+    /// CGB revisions 0-D: the region behaves like RAM
+    CgbD,
+    /// CGB revision E and AGB: reads return the high nibble of the lower address byte twice,
+    /// e.g. `FEB4` reads `BB`, and writes are ignored
+    CgbE,
+}
+
+impl Revision {
+    /// Returns `true` for CGB revisions.
+    pub fn is_cgb(&self) -> bool {
+        !matches!(self, Revision::Dmg)
+    }
+}
+
+/// A copy of the address space as seen by the CPU, see `Memory::snapshot`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    image: Vec<u8>,
+}
+
+impl Snapshot {
+    /// Returns the value at `location` when the snapshot was taken.
+    pub fn get(&self, location: usize) -> u8 {
+        self.image[location]
+    }
+
+    /// Returns every byte which differs in `later`, in address order, see `Memory::diff`.
+    pub fn changes_to(&self, later: &Snapshot) -> Vec<Change> {
+        self.image
+            .iter()
+            .zip(later.image.iter())
+            .enumerate()
+            .filter(|(location, _)| !ECHO_RAM.contains(location))
+            .filter(|(_, (before, after))| before != after)
+            .map(|(location, (&before, &after))| Change {
+                address: location as u16,
+                before,
+                after,
+            })
+            .collect()
+    }
+
+    /// Returns the bytes of the whole address space.
+    pub(crate) fn image(&self) -> &[u8] {
+        &self.image
+    }
+
+    /// Returns the bytes of the whole address space mutably, echo RAM is not kept in sync.
+    pub(crate) fn image_mut(&mut self) -> &mut [u8] {
+        &mut self.image
+    }
+}
 
-use std::fmt::Display;
+/// A changed byte reported by `Memory::diff`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Change {
+    pub address: u16,
+    pub before: u8,
+    pub after: u8,
+}
+
+/// Diagnostic events reported by memory, see `Memory::enable_diagnostics`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Diagnostic {
+    /// A stack write to ROM or an unmapped region was dropped.
+    StackWriteDropped { address: u16, value: u8 },
+}
 
 pub struct Memory {
-    memory: Vec<u8>,
+    memory: Box<[u8; MEMORY_SIZE]>,
+    cartridge: Option<Box<dyn Mapper>>,
+    diagnostics: Option<Vec<Diagnostic>>,
+    strict: bool,
+    /// First access rejected in strict mode, not yet taken
+    violation: Cell<Option<(u16, AccessKind)>>,
+    dma: Option<OamDma>,
+    io: Io,
+    observer: Option<RefCell<Box<dyn MemoryObserver>>>,
+    pc: u16,
+    revision: Revision,
+    vram: Vram,
+    colorizations: Vec<TitleColorization>,
+    model: Model,
+    boot_rom: Option<Vec<u8>>,
+    framebuffer: Framebuffer,
+    #[cfg(feature = "profiling")]
+    profiler: Profiler,
+}
+
+/// Size of the address space.
+const MEMORY_SIZE: usize = 0xFFFF + 1;
+
+/// Size of the state saved by `Memory::write_state`.
+pub(crate) const MEMORY_STATE_SIZE: usize =
+    2 + MEMORY_SIZE + IO_STATE_SIZE + VRAM_BANK_SIZE * 2 + 5;
+
+/// Echo RAM, mirroring `C000-DDFF`.
+pub const ECHO_RAM: RangeInclusive<usize> = 0xE000..=0xFDFF;
+
+/// Address of the interrupt enable register.
+pub const REGISTER_IE: usize = 0xFFFF;
+
+/// Address of the OAM DMA source/start register.
+pub const REGISTER_DMA: usize = 0xFF46;
+
+const OAM_START: usize = 0xFE00;
+const OAM_SIZE: u16 = 0xA0;
+
+/// An OAM DMA transfer in progress.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct OamDma {
+    source: u16,
+    copied: u16,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Memory {
     pub fn new() -> Self {
         Self {
-            // 65536 bytes which is 0xFFFF + 1
-            memory: vec![0; 0xFFFF + 1],
+            memory: zeroed(),
+            cartridge: None,
+            diagnostics: None,
+            strict: false,
+            violation: Cell::new(None),
+            dma: None,
+            io: Io::new(),
+            observer: None,
+            pc: 0,
+            revision: Revision::default(),
+            vram: Vram::new(),
+            colorizations: vec![],
+            model: Model::default(),
+            boot_rom: None,
+            framebuffer: Framebuffer::new(),
+            #[cfg(feature = "profiling")]
+            profiler: Profiler::new(),
         }
     }
 
-    /// Sets a `u8` value in memory.
+    /// Creates a memory with `cartridge` connected to the external bus.
+    ///
+    /// Accesses to `0000-7FFF` and `A000-BFFF` are handled by the cartridge's memory bank
+    /// controller instead of the flat memory.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::{cartridge::mbc5::Mbc5, memory::Memory};
+    /// let mut rom = vec![0; 0x8000];
+    /// rom[0x0100] = 0xAB;
+    ///
+    /// let mut memory = Memory::with_cartridge(Box::new(Mbc5::new(rom, 0, false)));
+    /// memory.set(0x0100, 0x00);
+    ///
+    /// assert_eq!(0xAB, memory.get(0x0100));
+    /// ```
+    pub fn with_cartridge(cartridge: Box<dyn Mapper>) -> Self {
+        Self {
+            cartridge: Some(cartridge),
+            ..Self::new()
+        }
+    }
+
+    /// Creates a memory with `rom` installed at `0000`.
+    ///
+    /// ROMs larger than 32 KiB need a memory bank controller, see `Memory::with_cartridge`.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::memory::Memory;
+    /// let memory = Memory::from_rom(&[0x3C, 0x3C]);
+    ///
+    /// assert_eq!(0x3C3C, memory.get_u16(0x0000));
+    /// ```
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `rom` is larger than 32 KiB.
+    pub fn from_rom(rom: &[u8]) -> Self {
+        assert!(
+            rom.len() <= 0x8000,
+            "ROM of {} bytes does not fit without a memory bank controller",
+            rom.len()
+        );
+
+        let mut memory = Self::new();
+        memory.load(0x0000, rom);
+        memory
+    }
+
+    /// Copies `data` into memory starting at `offset`.
+    ///
+    /// Bytes are stored directly, without notifying the observer or triggering side effects
+    /// such as DMA. Regions handled by a connected cartridge are not affected.
     ///
     /// ```
     /// # use gejmboj_cpu::memory::Memory;
     /// let mut memory = Memory::new();
-    /// let value = 0xAB;
     ///
-    /// memory.set(0, value);
+    /// memory.load(0xC000, &[0xAB, 0xCD]);
     ///
-    /// assert_eq!(value, memory.get(0));
+    /// assert_eq!(0xCDAB, memory.get_u16(0xC000));
     /// ```
-    pub fn set(&mut self, location: usize, value: u8) {
-        self.memory[location] = value;
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `data` doesn't fit between `offset` and the end of memory.
+    pub fn load(&mut self, offset: usize, data: &[u8]) {
+        assert!(
+            offset + data.len() <= self.memory.len(),
+            "{} bytes at {:04x} exceed memory",
+            data.len(),
+            offset
+        );
+
+        for (location, &value) in (offset..).zip(data) {
+            if is_io(location) {
+                self.io.set_raw(location as u16, value);
+            } else if is_vram(location) {
+                self.vram.write(self.vram_bank(), location as u16, value);
+            } else {
+                self.memory[resolve_echo(location)] = value;
+            }
+        }
     }
 
-    /// Gets a `u8` value from memory.
+    /// Returns the hardware model.
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    /// Selects the hardware model, enabling its features and filling WRAM and HRAM with the
+    /// noise they hold at power on.
     ///
     /// ```
-    /// # use gejmboj_cpu::memory::Memory;
+    /// # use gejmboj_cpu::{io::Mode, memory::{Memory, Revision}, model::Model};
     /// let mut memory = Memory::new();
-    /// let value = 0xAB;
     ///
-    /// memory.set(0, value);
+    /// memory.set_model(Model::Cgb);
     ///
-    /// assert_eq!(value, memory.get(0));
+    /// assert_eq!(Mode::Cgb, memory.mode());
+    /// assert_eq!(Revision::CgbE, memory.revision());
     /// ```
-    pub fn get(&self, location: usize) -> u8 {
-        self.memory[location]
+    pub fn set_model(&mut self, model: Model) {
+        self.model = model;
+        self.set_revision(model.revision());
+
+        model.fill_power_on_ram(&mut self.memory[0xC000..=0xDFFF]);
+        model.fill_power_on_ram(&mut self.memory[0xFF80..=0xFFFE]);
     }
 
-    /// Gets a `u16` value from memory.
+    /// Returns the hardware revision, see `Revision`.
+    pub fn revision(&self) -> Revision {
+        self.revision
+    }
+
+    /// Selects the hardware revision, see `Revision`.
+    ///
+    /// Defaults to the revision of the model, use this to emulate another revision.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::memory::{Memory, Revision};
+    /// let mut memory = Memory::new();
+    /// assert_eq!(0x00, memory.get(0xFEB4));
+    ///
+    /// memory.set_revision(Revision::CgbE);
+    /// assert_eq!(0xBB, memory.get(0xFEB4));
+    /// ```
+    pub fn set_revision(&mut self, revision: Revision) {
+        self.revision = revision;
+        self.io.set_cgb(revision.is_cgb());
+    }
+
+    /// Returns VRAM, e.g. for the renderer to read both banks.
+    pub fn vram(&self) -> &Vram {
+        &self.vram
+    }
+
+    /// Returns the picture rendered so far, see `renderer`.
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    /// Returns the profiler which the host time spent rendering lines is accounted to.
+    #[cfg(feature = "profiling")]
+    pub(crate) fn profiler_mut(&mut self) -> &mut Profiler {
+        &mut self.profiler
+    }
+
+    /// Returns the VRAM bank currently visible to the CPU.
+    pub fn vram_bank(&self) -> u8 {
+        match self.io.mode() {
+            io::Mode::Cgb => self.io.get_raw(io::REGISTER_VBK) & 0x01,
+            _ => 0,
+        }
+    }
+
+    /// Returns the hardware mode, see `io::Mode`.
+    pub fn mode(&self) -> io::Mode {
+        self.io.mode()
+    }
+
+    /// Installs `boot_rom`, which runs from `0000` until it disables itself, see module
+    /// documentation.
     ///
     /// ```
     /// # use gejmboj_cpu::memory::Memory;
+    /// let mut memory = Memory::from_rom(&[0xAB; 0x200]);
+    /// memory.set_boot_rom(vec![0x31; 0x100]);
+    /// assert_eq!(0x31, memory.get(0x0000));
+    /// assert_eq!(0xAB, memory.get(0x0100));
+    ///
+    /// memory.set(0xFF50, 0x01);
+    /// assert_eq!(0xAB, memory.get(0x0000));
+    /// ```
+    pub fn set_boot_rom(&mut self, boot_rom: Vec<u8>) {
+        self.boot_rom = Some(boot_rom);
+    }
+
+    fn boot_rom_byte(&self, location: usize) -> Option<u8> {
+        if self.io.is_boot_finished() || (0x0100..0x0200).contains(&location) {
+            return None;
+        }
+        self.boot_rom.as_ref()?.get(location).copied()
+    }
+
+    /// Puts the I/O registers in the state the boot ROM leaves them in and hands over to the
+    /// cartridge, see `finish_boot`.
+    ///
+    /// Use with `Registers::new_post_boot` when running without a boot ROM.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::{memory::Memory, model::Model};
+    /// let mut memory = Memory::from_rom(&[0x00; 0x150]);
+    /// memory.set_model(Model::Dmg);
+    ///
+    /// memory.skip_boot();
+    ///
+    /// assert_eq!(0x91, memory.get(0xFF40));
+    /// assert!(memory.io().is_boot_finished());
+    /// ```
+    pub fn skip_boot(&mut self) {
+        self.io.initialize_post_boot(self.model);
+        self.finish_boot();
+    }
+
+    /// Sets the table used by `finish_boot` to colorize DMG games, see `colorization`.
+    pub fn set_colorization_table(&mut self, table: Vec<TitleColorization>) {
+        self.colorizations = table;
+    }
+
+    /// Does what the boot ROM does before handing over to the cartridge: selects DMG
+    /// compatibility mode on CGB for DMG-only cartridges, colorizing them, and disables the
+    /// boot ROM.
+    ///
+    /// Use when running without a boot ROM.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::{cartridge::mbc5::Mbc5, io::Mode, memory::{Memory, Revision}};
+    /// let rom = vec![0; 0x8000];
+    /// let mut memory = Memory::with_cartridge(Box::new(Mbc5::new(rom, 0, false)));
+    /// memory.set_revision(Revision::CgbE);
+    ///
+    /// memory.finish_boot();
+    ///
+    /// assert_eq!(Mode::DmgCompatibility, memory.mode());
+    /// ```
+    pub fn finish_boot(&mut self) {
+        let cgb_flag = self.peek(crate::cartridge::HEADER_CGB_FLAG);
+        if cgb_flag & 0x80 == 0 && self.mode() == io::Mode::Cgb {
+            let colorization =
+                colorization::select(|address| self.peek(address), &self.colorizations);
+            let (background, objects) = self.io.palettes_mut();
+            colorization.apply(background, objects);
+
+            self.set(io::REGISTER_KEY0 as usize, 0x04);
+        }
+        self.set(io::REGISTER_BOOT as usize, 0x01);
+    }
+
+    /// Returns the connected cartridge, if any.
+    pub fn cartridge(&self) -> Option<&dyn Mapper> {
+        self.cartridge.as_deref()
+    }
+
+    /// Returns the connected cartridge mutably, if any.
+    pub fn cartridge_mut(&mut self) -> Option<&mut (dyn Mapper + 'static)> {
+        self.cartridge.as_deref_mut()
+    }
+
+    /// Returns the I/O registers.
+    pub fn io(&self) -> &Io {
+        &self.io
+    }
+
+    /// Returns the I/O registers mutably.
+    pub fn io_mut(&mut self) -> &mut Io {
+        &mut self.io
+    }
+
+    /// Connects `observer`, replacing any previously connected observer.
+    pub fn set_observer(&mut self, observer: Box<dyn MemoryObserver>) {
+        self.observer = Some(RefCell::new(observer));
+    }
+
+    /// Disconnects and returns the observer, if any.
+    pub fn take_observer(&mut self) -> Option<Box<dyn MemoryObserver>> {
+        self.observer.take().map(RefCell::into_inner)
+    }
+
+    /// Starts collecting diagnostic events.
+    pub fn enable_diagnostics(&mut self) {
+        if self.diagnostics.is_none() {
+            self.diagnostics = Some(vec![]);
+        }
+    }
+
+    /// Returns the diagnostic events collected since the last call.
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        self.diagnostics
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Enables or disables strict mode, see the module documentation.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::memory::{AccessKind, Memory, MemoryBus};
     /// let mut memory = Memory::new();
-    /// let value = 0xABCD;
+    /// memory.set_strict(true);
     ///
-    /// memory.set_u16(42, value);
+    /// memory.set(0x2000, 0x01);
+    /// memory.get(0xFEA0);
     ///
-    /// assert_eq!(value, memory.get_u16(42));
+    /// assert_eq!(Some((0x2000, AccessKind::RomWrite)), memory.take_access_violation());
+    /// assert_eq!(None, memory.take_access_violation());
+    /// assert_eq!(0x00, memory.peek(0x2000));
     /// ```
-    pub fn get_u16(&self, location: usize) -> u16 {
-        let lo = self.get(location);
-        let hi = self.get(location + 1);
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+        self.violation.set(None);
+    }
 
-        u16::from_le_bytes([lo, hi])
+    /// Returns `true` in strict mode.
+    pub fn is_strict(&self) -> bool {
+        self.strict
     }
 
-    /// Sets a `u16` value in memory.
+    /// Keeps the access in strict mode unless an earlier one wasn't taken yet.
+    fn violate(&self, location: usize, kind: AccessKind) {
+        if self.strict && self.violation.get().is_none() {
+            self.violation.set(Some((location as u16, kind)));
+        }
+    }
+
+    /// Writes a `u16` value to the stack at `location`, the upper byte first.
+    ///
+    /// Unlike `set_u16` each byte targeting ROM or the unusable region is dropped, see
+    /// `set_stack`.
     ///
     /// ```
-    /// # use gejmboj_cpu::memory::Memory;
+    /// # use gejmboj_cpu::memory::{Diagnostic, Memory};
     /// let mut memory = Memory::new();
-    /// let value = 0xABCD;
+    /// memory.enable_diagnostics();
     ///
-    /// memory.set_u16(0, value);
+    /// memory.set_stack_u16(0x7FFF, 0xABCD);
     ///
-    /// assert_eq!(value, memory.get_u16(0));
+    /// assert_eq!(0x00, memory.get(0x7FFF));
+    /// assert_eq!(0xAB, memory.get(0x8000));
+    /// assert_eq!(
+    ///     vec![Diagnostic::StackWriteDropped { address: 0x7FFF, value: 0xCD }],
+    ///     memory.take_diagnostics()
+    /// );
     /// ```
-    pub fn set_u16(&mut self, location: usize, value: u16) {
+    pub fn set_stack_u16(&mut self, location: usize, value: u16) {
         let [lo, hi] = value.to_le_bytes();
 
-        self.set(location, lo);
-        self.set(location + 1, hi);
+        self.set_stack((location + 1) & 0xFFFF, hi);
+        self.set_stack(location, lo);
     }
-}
 
-impl Display for Memory {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
-        let columns = 16;
-        let bytes_string: String = self
-            .memory
-            .iter()
-            .map(|x| format!("{:02x?}", x))
-            .collect::<Vec<String>>()
-            .chunks(columns)
-            .enumerate()
-            .map(|(idx, bytes)| format!("{:03x?} | {} |", idx, bytes.join(" ").replace("00", "--")))
-            .collect::<Vec<String>>()
-            .join("\n");
-
-        write!(
-            f,
-            "
-       0  1  2  3  4  5  6  7  8  9  a  b  c  d  e  f
-    ,-------------------------------------------------,
-{}
-    `-------------------------------------------------´",
-            bytes_string
-        )
+    /// Writes a byte pushed to the stack at `location`, dropping it if it targets ROM or the
+    /// unusable region.
+    pub fn set_stack(&mut self, location: usize, value: u8) {
+        match location {
+            0x0000..=0x7FFF | 0xFEA0..=0xFEFF => {
+                self.violate(
+                    location,
+                    match location {
+                        0x0000..=0x7FFF => AccessKind::RomWrite,
+                        _ => AccessKind::UnusableWrite,
+                    },
+                );
+                if let Some(diagnostics) = self.diagnostics.as_mut() {
+                    diagnostics.push(Diagnostic::StackWriteDropped {
+                        address: location as u16,
+                        value,
+                    });
+                }
+            }
+            _ => self.set(location, value),
+        }
+    }
+
+    /// Returns `true` while an OAM DMA transfer is running.
+    pub fn is_dma_active(&self) -> bool {
+        self.dma.is_some()
+    }
+
+    /// Advances a running OAM DMA transfer by `cycles` machine cycles.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::memory::Memory;
+    /// let mut memory = Memory::new();
+    /// memory.set(0xC000, 0xAB);
+    /// memory.set(0xC09F, 0xCD);
+    ///
+    /// memory.set(0xFF46, 0xC0);
+    /// memory.step_dma(1);
+    /// assert_eq!(0xAB, memory.peek(0xFE00));
+    /// assert_eq!(0x00, memory.peek(0xFE9F));
+    ///
+    /// memory.step_dma(159);
+    /// assert_eq!(0xCD, memory.get(0xFE9F));
+    /// assert!(!memory.is_dma_active());
+    /// ```
+    pub fn step_dma(&mut self, cycles: u16) {
+        for _ in 0..cycles {
+            let dma = match self.dma.as_mut() {
+                Some(dma) => dma,
+                None => return,
+            };
+            let offset = dma.copied;
+            let source = dma.source as usize + offset as usize;
+
+            dma.copied += 1;
+            if dma.copied == OAM_SIZE {
+                self.dma = None;
+            }
+
+            let value = self.peek(source);
+            self.memory[OAM_START + offset as usize] = value;
+        }
+    }
+
+    /// Returns the byte the CPU sees at `location` while OAM DMA owns the bus, `None` if the
+    /// access is not in conflict with a running transfer.
+    fn dma_conflict(&self, location: usize) -> Option<u8> {
+        let dma = self.dma.filter(|dma| dma.copied > 0)?;
+        if location >= 0xFF00 {
+            return None;
+        }
+        Some(self.peek(dma.source as usize + dma.copied as usize - 1))
+    }
+
+    /// Appends the memory, I/O and DMA state to `out`, see `savestate`.
+    pub(crate) fn write_state(&self, out: &mut Vec<u8>) {
+        out.push(self.model as u8);
+        out.push(self.revision as u8);
+        out.extend(self.memory.iter());
+        out.extend(self.io.to_bytes());
+        out.extend(self.vram.as_bytes());
+        match self.dma {
+            Some(dma) => {
+                out.push(1);
+                out.extend(dma.source.to_le_bytes());
+                out.extend(dma.copied.to_le_bytes());
+            }
+            None => out.extend([0; 5]),
+        }
+    }
+
+    /// Restores state written by `write_state`. The cartridge, observer, diagnostics,
+    /// colorization table and serial device are kept, nothing is changed if the state is invalid.
+    pub(crate) fn read_state(&mut self, reader: &mut Reader) -> Result<(), CpuError> {
+        let model = savestate::model(reader.u8()?)?;
+        let revision = savestate::revision(reader.u8()?)?;
+        let memory: Box<[u8; MEMORY_SIZE]> = reader
+            .take(MEMORY_SIZE)?
+            .to_vec()
+            .into_boxed_slice()
+            .try_into()
+            .map_err(|_| reader.invalid("memory"))?;
+        let mut io =
+            Io::from_bytes(reader.take(IO_STATE_SIZE)?).ok_or_else(|| reader.invalid("I/O"))?;
+        let vram = Vram::from_bytes(reader.take(VRAM_BANK_SIZE * 2)?)
+            .ok_or_else(|| reader.invalid("VRAM"))?;
+        let dma = match reader.u8()? {
+            0 => {
+                reader.take(4)?;
+                None
+            }
+            1 => Some(OamDma {
+                source: reader.u16()?,
+                copied: reader.u16()?,
+            }),
+            _ => return Err(reader.invalid("OAM DMA")),
+        };
+
+        if matches!(dma, Some(dma) if dma.copied >= OAM_SIZE) {
+            return Err(reader.invalid("OAM DMA"));
+        }
+
+        self.model = model;
+        self.revision = revision;
+        self.memory = memory;
+        if let Some(device) = self.io.serial_mut().detach() {
+            io.serial_mut().attach(device);
+        }
+        self.io = io;
+        self.vram = vram;
+        self.dma = dma;
+
+        Ok(())
+    }
+
+    /// Takes a snapshot of the whole address space, to be compared later with `diff`.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            image: (0..self.memory.len())
+                .map(|location| self.peek(location))
+                .collect(),
+        }
+    }
+
+    /// Returns every byte which changed since `snapshot` was taken, in address order.
+    ///
+    /// Echo RAM is left out since its changes are already reported for WRAM.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::memory::{Change, Memory};
+    /// let mut memory = Memory::new();
+    /// memory.set(0xC000, 0x01);
+    /// let snapshot = memory.snapshot();
+    ///
+    /// memory.set(0xC000, 0x02);
+    /// memory.set(0xFF80, 0x03);
+    ///
+    /// assert_eq!(
+    ///     vec![
+    ///         Change { address: 0xC000, before: 0x01, after: 0x02 },
+    ///         Change { address: 0xFF80, before: 0x00, after: 0x03 },
+    ///     ],
+    ///     memory.diff(&snapshot)
+    /// );
+    /// ```
+    pub fn diff(&self, snapshot: &Snapshot) -> Vec<Change> {
+        snapshot.changes_to(&self.snapshot())
+    }
+
+    /// Returns a hexdump of `start-end` (inclusive) with an ASCII column.
+    ///
+    /// Zero bytes are shown as `--` and bytes outside the range are left blank.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::memory::Memory;
+    /// let mut memory = Memory::new();
+    /// memory.load(0xC002, b"Hi!");
+    ///
+    /// let dump = memory.dump_range(0xC002, 0xC004);
+    ///
+    /// assert_eq!(
+    ///     "c000 |       48 69 21                                  |   Hi!            |",
+    ///     dump.lines().nth(2).unwrap()
+    /// );
+    /// ```
+    pub fn dump_range(&self, start: u16, end: u16) -> String {
+        self.window(start, end).to_string()
+    }
+
+    /// Returns a displayable hexdump of `start-end` (inclusive), see `dump_range`.
+    ///
+    /// `Display` for `Memory` shows the whole address space, which is rarely what you want.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `start` is greater than `end`.
+    pub fn window(&self, start: u16, end: u16) -> MemoryDump<'_> {
+        assert!(start <= end, "{:04x} is after {:04x}", start, end);
+
+        MemoryDump {
+            memory: self,
+            start: start.into(),
+            end: end.into(),
+            non_zero_pages: false,
+        }
+    }
+
+    /// Sets a `u8` value in memory.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::memory::Memory;
+    /// let mut memory = Memory::new();
+    /// let value = 0xAB;
+    ///
+    /// memory.set(0, value);
+    ///
+    /// assert_eq!(value, memory.get(0));
+    /// ```
+    pub fn set(&mut self, location: usize, value: u8) {
+        if self.dma_conflict(location).is_some() {
+            return self.violate(location, AccessKind::DmaBlockedWrite);
+        }
+        if let Some(observer) = self.observer.as_mut() {
+            observer.get_mut().on_write(MemoryAccess {
+                address: location as u16,
+                value,
+                pc: self.pc,
+            });
+        }
+        // In strict mode the violation replaces the access
+        if is_rom(location) && !self.cartridge.as_ref().is_some_and(|c| c.has_controller()) {
+            self.violate(location, AccessKind::RomWrite);
+            if self.strict {
+                return;
+            }
+        }
+        if is_unusable(location) {
+            self.violate(location, AccessKind::UnusableWrite);
+            if self.strict {
+                return;
+            }
+        }
+        if let Some(cartridge) = self.cartridge.as_mut() {
+            match location {
+                0x0000..=0x7FFF => return cartridge.write_rom(location as u16, value),
+                0xA000..=0xBFFF => return cartridge.write_ram(location as u16, value),
+                _ => {}
+            }
+        }
+        if location == REGISTER_DMA {
+            self.start_dma(value);
+        }
+        if is_io(location) {
+            self.io.write(location as u16, value);
+            if location == io::REGISTER_LCDC as usize && self.io.ppu().is_blank() {
+                self.framebuffer.clear();
+            }
+            return;
+        }
+        if is_vram(location) {
+            return self.vram.write(self.vram_bank(), location as u16, value);
+        }
+        if is_unusable(location) && self.revision != Revision::CgbD {
+            return;
+        }
+        self.write(location, value);
+    }
+
+    fn start_dma(&mut self, value: u8) {
+        // Sources above DFXX hit echo RAM, same as the CPU would see
+        let source = u16::from_be_bytes([value, 0x00]);
+        self.dma = Some(OamDma { source, copied: 0 });
+    }
+
+    /// Gets a `u8` value from memory.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::memory::Memory;
+    /// let mut memory = Memory::new();
+    /// let value = 0xAB;
+    ///
+    /// memory.set(0, value);
+    ///
+    /// assert_eq!(value, memory.get(0));
+    /// ```
+    pub fn get(&self, location: usize) -> u8 {
+        let value = match self.dma_conflict(location) {
+            Some(value) => {
+                self.violate(location, AccessKind::DmaBlockedRead);
+                value
+            }
+            None => {
+                if is_unusable(location) {
+                    self.violate(location, AccessKind::UnusableRead);
+                }
+                self.peek(location)
+            }
+        };
+
+        if let Some(observer) = self.observer.as_ref() {
+            observer.borrow_mut().on_read(MemoryAccess {
+                address: location as u16,
+                value,
+                pc: self.pc,
+            });
+        }
+        value
+    }
+
+    /// Gets a `u8` value from memory without notifying the observer.
+    pub fn peek(&self, location: usize) -> u8 {
+        if let Some(value) = self.boot_rom_byte(location) {
+            return value;
+        }
+        if let Some(cartridge) = self.cartridge.as_ref() {
+            match location {
+                0x0000..=0x7FFF => return cartridge.read_rom(location as u16),
+                0xA000..=0xBFFF => return cartridge.read_ram(location as u16),
+                _ => {}
+            }
+        }
+        if is_io(location) {
+            return self.io.read(location as u16);
+        }
+        if is_vram(location) {
+            return self.vram.read(self.vram_bank(), location as u16);
+        }
+        if is_unusable(location) {
+            match self.revision {
+                Revision::Dmg => return 0x00,
+                Revision::CgbD => {}
+                Revision::CgbE => return (location as u8 >> 4) * 0x11,
+            }
+        }
+        self.read(location)
+    }
+
+    /// Reads plain RAM, bounds checked unless the `unchecked` feature is enabled.
+    #[cfg(not(feature = "unchecked"))]
+    #[inline]
+    fn read(&self, location: usize) -> u8 {
+        self.memory[resolve_echo(location)]
+    }
+
+    /// Reads plain RAM without a bounds check, `location` is truncated to 16 bits.
+    #[cfg(feature = "unchecked")]
+    #[inline]
+    fn read(&self, location: usize) -> u8 {
+        let index = resolve_echo(location & 0xFFFF);
+        // SAFETY: `index` is at most 0xFFFF and `memory` holds 0x10000 bytes
+        unsafe { *self.memory.get_unchecked(index) }
+    }
+
+    /// Writes plain RAM, bounds checked unless the `unchecked` feature is enabled.
+    #[cfg(not(feature = "unchecked"))]
+    #[inline]
+    fn write(&mut self, location: usize, value: u8) {
+        self.memory[resolve_echo(location)] = value;
+    }
+
+    /// Writes plain RAM without a bounds check, `location` is truncated to 16 bits.
+    #[cfg(feature = "unchecked")]
+    #[inline]
+    fn write(&mut self, location: usize, value: u8) {
+        let index = resolve_echo(location & 0xFFFF);
+        // SAFETY: `index` is at most 0xFFFF and `memory` holds 0x10000 bytes
+        unsafe { *self.memory.get_unchecked_mut(index) = value }
+    }
+
+    /// Gets a `u16` value from memory.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::memory::Memory;
+    /// let mut memory = Memory::new();
+    /// let value = 0xABCD;
+    ///
+    /// memory.set_u16(42, value);
+    ///
+    /// assert_eq!(value, memory.get_u16(42));
+    /// ```
+    pub fn get_u16(&self, location: usize) -> u16 {
+        let lo = self.get(location);
+        let hi = self.get((location + 1) & 0xFFFF);
+
+        u16::from_le_bytes([lo, hi])
+    }
+
+    /// Sets a `u16` value in memory.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::memory::Memory;
+    /// let mut memory = Memory::new();
+    /// let value = 0xABCD;
+    ///
+    /// memory.set_u16(0, value);
+    ///
+    /// assert_eq!(value, memory.get_u16(0));
+    /// ```
+    pub fn set_u16(&mut self, location: usize, value: u16) {
+        let [lo, hi] = value.to_le_bytes();
+
+        self.set(location, lo);
+        self.set((location + 1) & 0xFFFF, hi);
+    }
+}
+
+fn is_rom(location: usize) -> bool {
+    location <= 0x7FFF
+}
+
+fn is_io(location: usize) -> bool {
+    (io::IO_START as usize..=io::IO_END as usize).contains(&location)
+}
+
+impl MemoryBus for Memory {
+    fn get(&self, location: usize) -> u8 {
+        Memory::get(self, location)
+    }
+
+    fn set(&mut self, location: usize, value: u8) {
+        Memory::set(self, location, value)
+    }
+
+    fn peek(&self, location: usize) -> u8 {
+        Memory::peek(self, location)
+    }
+
+    fn set_stack(&mut self, location: usize, value: u8) {
+        Memory::set_stack(self, location, value)
+    }
+
+    fn set_stack_u16(&mut self, location: usize, value: u16) {
+        Memory::set_stack_u16(self, location, value)
+    }
+
+    fn take_access_violation(&mut self) -> Option<(u16, AccessKind)> {
+        self.violation.take()
+    }
+
+    fn rom_offset(&self, location: usize) -> Option<usize> {
+        if self.boot_rom_byte(location).is_some() {
+            return None;
+        }
+        match (self.cartridge.as_ref(), location) {
+            (Some(cartridge), 0x0000..=0x7FFF) => Some(cartridge.rom_offset(location as u16)),
+            (None, 0x0000..=0x7FFF) => Some(location),
+            _ => None,
+        }
+    }
+
+    fn begin_instruction(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    fn step(&mut self, cycles: u16) {
+        self.io.step_timer_and_serial(cycles);
+
+        for _ in 0..cycles {
+            if self.dma.is_some() {
+                self.step_dma(1);
+            }
+            self.io.step_ppu();
+
+            let ppu = self.io.ppu();
+            if let Some(line) = ppu.drawing_line().filter(|_| !ppu.is_blank()) {
+                // Time since the previous lap was spent executing instructions
+                #[cfg(feature = "profiling")]
+                self.profiler.lap(Subsystem::Cpu);
+
+                let oam = &self.memory[OAM_START..OAM_START + OAM_SIZE as usize];
+                self.framebuffer
+                    .render_line(line, &self.io, &self.vram, oam);
+
+                #[cfg(feature = "profiling")]
+                self.profiler.lap(Subsystem::Ppu);
+            }
+        }
+    }
+
+    /// Checks that the memory state is one the hardware could be in.
+    fn check_invariants(&self) -> Result<(), String> {
+        let interrupt_flags = self.io.get_raw(io::REGISTER_IF);
+        if interrupt_flags & 0xE0 != 0 {
+            return Err(format!("reserved IF bits are set: {:02x}", interrupt_flags));
+        }
+        self.io.ppu().check_invariants()?;
+        if let Some(dma) = self.dma {
+            if dma.copied >= OAM_SIZE {
+                return Err(format!("OAM DMA ran past OAM: {} bytes copied", dma.copied));
+            }
+            if dma.source & 0x00FF != 0 {
+                return Err(format!(
+                    "OAM DMA source is not page aligned: {:04x}",
+                    dma.source
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_vram(location: usize) -> bool {
+    (0x8000..=0x9FFF).contains(&location)
+}
+
+fn is_unusable(location: usize) -> bool {
+    (0xFEA0..=0xFEFF).contains(&location)
+}
+
+/// Allocates a zeroed address space on the heap, without building it on the stack first.
+fn zeroed() -> Box<[u8; MEMORY_SIZE]> {
+    vec![0; MEMORY_SIZE]
+        .into_boxed_slice()
+        .try_into()
+        .expect("Address space has a fixed size")
+}
+
+/// Maps addresses in echo RAM (`E000-FDFF`) to the WRAM address they mirror.
+pub(crate) fn resolve_echo(location: usize) -> usize {
+    match location {
+        _ if ECHO_RAM.contains(&location) => location - 0x2000,
+        _ => location,
+    }
+}
+
+/// A hexdump of a memory region, see `Memory::window`.
+///
+/// Rows are formatted straight into the formatter, so displaying a dump doesn't allocate.
+pub struct MemoryDump<'a> {
+    memory: &'a Memory,
+    start: usize,
+    end: usize,
+    non_zero_pages: bool,
+}
+
+/// Size of the pages left out by `MemoryDump::non_zero_pages`.
+const PAGE_SIZE: usize = 0x100;
+
+impl MemoryDump<'_> {
+    /// Leaves out the rows of 256 byte pages whose bytes in the region are all zero.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::memory::Memory;
+    /// let mut memory = Memory::new();
+    /// memory.load(0xC123, b"Hi!");
+    ///
+    /// let dump = memory.window(0xC000, 0xCFFF).non_zero_pages().to_string();
+    ///
+    /// assert_eq!(16 + 3, dump.lines().count());
+    /// assert!(dump.contains("\nc120 | "));
+    /// ```
+    pub fn non_zero_pages(mut self) -> Self {
+        self.non_zero_pages = true;
+        self
+    }
+
+    fn is_zero_page(&self, page: usize) -> bool {
+        let first = page.max(self.start);
+        let last = (page + PAGE_SIZE - 1).min(self.end);
+        (first..=last).all(|location| self.memory.peek(location) == 0)
+    }
+}
+
+impl Display for MemoryDump<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let columns = 16;
+
+        writeln!(f, "        0  1  2  3  4  5  6  7  8  9  a  b  c  d  e  f")?;
+        writeln!(f, "     ,{:-<49},{:-<18},", "", "")?;
+
+        let mut zero_page = false;
+        for row in (self.start - self.start % columns..=self.end).step_by(columns) {
+            if self.non_zero_pages && (row % PAGE_SIZE == 0 || row < self.start) {
+                zero_page = self.is_zero_page(row - row % PAGE_SIZE);
+            }
+            if zero_page {
+                continue;
+            }
+
+            write!(f, "{:04x} |", row)?;
+            for location in row..row + columns {
+                match self.memory.peek(location) {
+                    _ if location < self.start || location > self.end => f.write_str("   ")?,
+                    0x00 => f.write_str(" --")?,
+                    value => write!(f, " {:02x}", value)?,
+                }
+            }
+            f.write_str(" | ")?;
+            for location in row..row + columns {
+                f.write_char(match self.memory.peek(location) {
+                    _ if location < self.start || location > self.end => ' ',
+                    value @ 0x20..=0x7E => value as char,
+                    _ => '.',
+                })?;
+            }
+            writeln!(f, " |")?;
+        }
+
+        write!(f, "     `{:-<49}´{:-<18}´", "", "")
+    }
+}
+
+/// Shows the whole address space, the alternate flag (`{:#}`) leaves out pages which are all
+/// zero, see `MemoryDump::non_zero_pages`.
+impl Display for Memory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        let dump = self.window(0x0000, 0xFFFF);
+        match f.alternate() {
+            true => write!(f, "{}", dump.non_zero_pages()),
+            false => write!(f, "{}", dump),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strict_mode_reports_accesses_blocked_by_dma() {
+        let mut memory = Memory::new();
+        memory.set_strict(true);
+        memory.set(REGISTER_DMA, 0xC0);
+        memory.step_dma(1);
+
+        memory.get(0xFF80);
+        assert_eq!(None, memory.take_access_violation());
+
+        memory.get(0xC000);
+        memory.set(0xC000, 0x01);
+        assert_eq!(
+            Some((0xC000, AccessKind::DmaBlockedRead)),
+            memory.take_access_violation()
+        );
+        memory.set(0xC000, 0x01);
+        assert_eq!(
+            Some((0xC000, AccessKind::DmaBlockedWrite)),
+            memory.take_access_violation()
+        );
+    }
+
+    #[test]
+    fn stack_writes_to_unusable_region_are_dropped() {
+        let mut memory = Memory::new();
+
+        memory.set_stack_u16(0xFEA0, 0xABCD);
+
+        assert_eq!(0x0000, memory.get_u16(0xFEA0));
+    }
+
+    #[test]
+    fn diagnostics_are_only_collected_when_enabled() {
+        let mut memory = Memory::new();
+
+        memory.set_stack_u16(0x0000, 0xABCD);
+        assert!(memory.take_diagnostics().is_empty());
+
+        memory.enable_diagnostics();
+        memory.set_stack_u16(0x0000, 0xABCD);
+        assert_eq!(2, memory.take_diagnostics().len());
+        assert!(memory.take_diagnostics().is_empty());
+    }
+
+    #[test]
+    fn dma_copies_one_byte_per_machine_cycle() {
+        let mut memory = Memory::new();
+        for offset in 0..0xA0 {
+            memory.set(0x8000 + offset, offset as u8 + 1);
+        }
+
+        memory.set(REGISTER_DMA, 0x80);
+        assert!(memory.is_dma_active());
+
+        memory.step_dma(80);
+        assert_eq!(80, memory.peek(0xFE4F));
+        assert_eq!(0, memory.peek(0xFE50));
+
+        memory.step_dma(80);
+        assert!(!memory.is_dma_active());
+        for offset in 0..0xA0 {
+            assert_eq!(offset as u8 + 1, memory.get(0xFE00 + offset));
+        }
+    }
+
+    #[test]
+    fn dma_does_not_copy_past_oam() {
+        let mut memory = Memory::new();
+        memory.set(0xC0A0, 0xAB);
+
+        memory.set(REGISTER_DMA, 0xC0);
+        memory.step_dma(200);
+
+        assert_eq!(0x00, memory.get(0xFEA0));
+    }
+
+    #[test]
+    fn dma_reads_through_the_cartridge() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x4000] = 0x42;
+        let mut memory =
+            Memory::with_cartridge(Box::new(crate::cartridge::mbc5::Mbc5::new(rom, 0, false)));
+
+        memory.set(REGISTER_DMA, 0x40);
+        memory.step_dma(1);
+
+        assert_eq!(0x42, memory.peek(0xFE00));
+    }
+
+    #[test]
+    fn dma_restarts_when_the_register_is_written_again() {
+        let mut memory = Memory::new();
+        memory.set(0xC000, 0x11);
+        memory.set(0xC100, 0x22);
+        memory.set(0xC101, 0x33);
+
+        memory.set(REGISTER_DMA, 0xC0);
+        memory.step_dma(1);
+        memory.set(REGISTER_DMA, 0xC1);
+        memory.step_dma(2);
+
+        assert_eq!(0x22, memory.peek(0xFE00));
+        assert_eq!(0x33, memory.peek(0xFE01));
+    }
+
+    #[test]
+    fn u16_accesses_wrap_around_the_address_space() {
+        let mut memory = Memory::new();
+
+        memory.set_u16(0xFFFF, 0xABCD);
+        memory.set_stack_u16(0xFFFF, 0xABCD);
+
+        assert_eq!(0xCD, memory.get(0xFFFF));
+        assert_eq!(0xAB, memory.get(0x0000));
+        assert_eq!(0xABCD, memory.get_u16(0xFFFF));
+    }
+
+    #[test]
+    #[cfg(feature = "unchecked")]
+    fn unchecked_accesses_are_truncated_to_16_bits() {
+        let mut memory = Memory::new();
+
+        memory.set(0x1C000, 0x42);
+
+        assert_eq!(0x42, memory.get(0xC000));
+        assert_eq!(0x42, memory.get(0x2C000));
+    }
+
+    #[test]
+    fn framebuffer_is_white_while_the_lcd_is_blank() {
+        let mut memory = Memory::new();
+        memory.set(0x8000, 0xFF);
+        memory.set(0xFF47, 0xFF);
+        memory.io_mut().set_raw(io::REGISTER_LCDC, 0x91);
+        memory.step(20);
+        assert_eq!(0x00, memory.framebuffer().pixels()[0]);
+
+        memory.set(0xFF40, 0x11);
+        assert_eq!(0xFF, memory.framebuffer().pixels()[0]);
+
+        memory.set(0xFF40, 0x91);
+        memory.step(20);
+        assert_eq!(0xFF, memory.framebuffer().pixels()[0]);
+    }
+
+    #[test]
+    fn cpu_accesses_outside_hram_conflict_with_dma() {
+        let mut memory = Memory::new();
+        memory.set(0xC000, 0x11);
+        memory.set(0xC001, 0x22);
+        memory.set(0xD000, 0x33);
+        memory.set(0xFF80, 0x44);
+
+        memory.set(REGISTER_DMA, 0xC0);
+        assert_eq!(0x33, memory.get(0xD000));
+        memory.step_dma(2);
+
+        assert_eq!(0x22, memory.get(0xD000));
+        assert_eq!(0x22, memory.get(0x0000));
+        assert_eq!(0x44, memory.get(0xFF80));
+        assert_eq!(0xC0, memory.get(REGISTER_DMA));
+
+        memory.set(0xD000, 0x55);
+        memory.set(0xFF80, 0x66);
+        assert_eq!(0x33, memory.peek(0xD000));
+        assert_eq!(0x66, memory.peek(0xFF80));
+
+        memory.step_dma(158);
+        assert_eq!(0x33, memory.get(0xD000));
+    }
+
+    #[test]
+    fn io_registers_are_accessible_during_dma() {
+        let mut memory = Memory::new();
+        memory.set_strict(true);
+        memory.set(REGISTER_DMA, 0xC0);
+        memory.step_dma(2);
+
+        memory.set(0xFF42, 0x12);
+        memory.set(REGISTER_IE, 0x05);
+
+        assert_eq!(0x12, memory.get(0xFF42));
+        assert_eq!(0x05, memory.get(REGISTER_IE));
+        assert_eq!(None, memory.take_access_violation());
+    }
+
+    #[test]
+    fn writes_blocked_by_dma_do_not_notify_the_observer() {
+        let log = AccessLog::default();
+        let accesses = log.0.clone();
+        let mut memory = Memory::new();
+        memory.set(REGISTER_DMA, 0xC0);
+        memory.step_dma(2);
+        memory.set_observer(Box::new(log));
+
+        memory.set(0xD000, 0x55);
+        memory.set(0xFF80, 0x66);
+
+        assert_eq!(
+            vec![(
+                true,
+                MemoryAccess {
+                    address: 0xFF80,
+                    value: 0x66,
+                    pc: 0x0000
+                }
+            )],
+            *accesses.borrow()
+        );
+    }
+
+    #[test]
+    fn check_invariants_rejects_dma_past_oam() {
+        let mut memory = Memory::new();
+        memory.set(REGISTER_DMA, 0xC0);
+        assert_eq!(Ok(()), memory.check_invariants());
+
+        memory.dma = Some(OamDma {
+            source: 0xC000,
+            copied: OAM_SIZE,
+        });
+        assert!(memory.check_invariants().is_err());
+    }
+
+    #[test]
+    fn io_accesses_are_dispatched_with_masks() {
+        let mut memory = Memory::new();
+
+        memory.set(0xFF07, 0xFF);
+        memory.set(0xFF03, 0x00);
+
+        assert_eq!(0xFF, memory.get(0xFF07));
+        assert_eq!(0x07, memory.io().get_raw(0xFF07));
+        assert_eq!(0xFF, memory.get(0xFF03));
+    }
+
+    #[test]
+    fn check_invariants_rejects_reserved_if_bits() {
+        let mut memory = Memory::new();
+        memory.set(0xFF0F, 0xFF);
+        assert_eq!(Ok(()), memory.check_invariants());
+
+        memory.io_mut().set_raw(io::REGISTER_IF, 0xFF);
+        assert!(memory.check_invariants().is_err());
+    }
+
+    #[derive(Default)]
+    struct AccessLog(std::rc::Rc<RefCell<Vec<(bool, MemoryAccess)>>>);
+
+    impl MemoryObserver for AccessLog {
+        fn on_read(&mut self, access: MemoryAccess) {
+            self.0.borrow_mut().push((false, access));
+        }
+
+        fn on_write(&mut self, access: MemoryAccess) {
+            self.0.borrow_mut().push((true, access));
+        }
+    }
+
+    #[test]
+    fn observer_is_notified_of_accesses_with_pc() {
+        let log = AccessLog::default();
+        let accesses = log.0.clone();
+        let mut memory = Memory::new();
+        memory.set_observer(Box::new(log));
+
+        MemoryBus::begin_instruction(&mut memory, 0x0150);
+        memory.set_u16(0xC000, 0xABCD);
+        memory.get(0xC001);
+        memory.peek(0xC000);
+
+        let access = |address, value| MemoryAccess {
+            address,
+            value,
+            pc: 0x0150,
+        };
+        assert_eq!(
+            vec![
+                (true, access(0xC000, 0xCD)),
+                (true, access(0xC001, 0xAB)),
+                (false, access(0xC001, 0xAB)),
+            ],
+            *accesses.borrow()
+        );
+    }
+
+    #[test]
+    fn dma_does_not_notify_the_observer() {
+        let log = AccessLog::default();
+        let accesses = log.0.clone();
+        let mut memory = Memory::new();
+        memory.set(REGISTER_DMA, 0xC0);
+        memory.set_observer(Box::new(log));
+
+        memory.step_dma(160);
+
+        assert!(accesses.borrow().is_empty());
+        assert!(memory.take_observer().is_some());
+    }
+
+    #[test]
+    fn load_does_not_trigger_side_effects() {
+        let log = AccessLog::default();
+        let accesses = log.0.clone();
+        let mut memory = Memory::new();
+        memory.set_observer(Box::new(log));
+
+        memory.load(0xFF40, &[0x91; 0x10]);
+
+        assert!(!memory.is_dma_active());
+        assert!(accesses.borrow().is_empty());
+        assert_eq!(0x91, memory.io().get_raw(0xFF46));
+    }
+
+    #[test]
+    fn load_into_echo_ram_writes_wram() {
+        let mut memory = Memory::new();
+
+        memory.load(0xE000, &[0x42]);
+
+        assert_eq!(0x42, memory.get(0xC000));
+    }
+
+    #[test]
+    #[should_panic]
+    fn load_past_end_of_memory_panics() {
+        Memory::new().load(0xFFFF, &[0x00, 0x00]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn from_rom_rejects_banked_roms() {
+        Memory::from_rom(&[0x00; 0x8001]);
+    }
+
+    #[test]
+    fn dump_range_covers_partial_rows() {
+        let mut memory = Memory::new();
+        memory.load(0xC00F, &[0x41, 0x00, 0xFF]);
+
+        let dump = memory.dump_range(0xC00F, 0xC011);
+        let rows: Vec<&str> = dump.lines().skip(2).take(2).collect();
+
+        assert_eq!(
+            vec![
+                "c000 |                                              41 |                A |",
+                "c010 | -- ff                                           | ..               |",
+            ],
+            rows
+        );
+        assert_eq!(5, dump.lines().count());
+    }
+
+    #[test]
+    #[should_panic]
+    fn window_rejects_reversed_range() {
+        Memory::new().window(0xC001, 0xC000);
+    }
+
+    #[test]
+    fn display_shows_all_memory() {
+        let dump = Memory::new().to_string();
+
+        assert_eq!(0x1000 + 3, dump.lines().count());
+        assert!(dump.contains("\nfff0 | "));
+    }
+
+    #[test]
+    fn alternate_display_leaves_out_zero_pages() {
+        let mut memory = Memory::new();
+        memory.load(0xC0FF, &[0x01]);
+        memory.load(0xD000, &[0x00]);
+
+        let dump = format!("{:#}", memory);
+
+        assert!(dump.contains("\nc0f0 | "));
+        assert!(!dump.contains("\nd000 | "));
+    }
+
+    #[test]
+    fn diff_sees_cartridge_and_io_changes() {
+        let rom = vec![0; 0x8000];
+        let mut memory = Memory::with_cartridge(Box::new(crate::cartridge::mbc5::Mbc5::new(
+            rom, 0x2000, false,
+        )));
+        memory.set(0x0000, 0x0A);
+        let snapshot = memory.snapshot();
+
+        memory.set(0xA000, 0x42);
+        memory.set(0xFF07, 0x01);
+
+        assert_eq!(
+            vec![
+                Change {
+                    address: 0xA000,
+                    before: 0x00,
+                    after: 0x42
+                },
+                Change {
+                    address: 0xFF07,
+                    before: 0xF8,
+                    after: 0xF9
+                },
+            ],
+            memory.diff(&snapshot)
+        );
+    }
+
+    #[test]
+    fn diff_of_unchanged_memory_is_empty() {
+        let mut memory = Memory::new();
+        memory.set(0xE000, 0x01);
+        let snapshot = memory.snapshot();
+
+        assert_eq!(0x01, snapshot.get(0xC000));
+        assert!(memory.diff(&snapshot).is_empty());
+    }
+
+    #[test]
+    fn unusable_region_ignores_writes_on_dmg() {
+        let mut memory = Memory::new();
+
+        memory.set(0xFEA0, 0x42);
+        memory.set(0xFEFF, 0x42);
+
+        assert_eq!(0x00, memory.get(0xFEA0));
+        assert_eq!(0x00, memory.get(0xFEFF));
+    }
+
+    #[test]
+    fn unusable_region_is_ram_on_early_cgb_revisions() {
+        let mut memory = Memory::new();
+        memory.set_revision(Revision::CgbD);
+
+        memory.set(0xFEA0, 0x42);
+
+        assert_eq!(0x42, memory.get(0xFEA0));
+    }
+
+    #[test]
+    fn unusable_region_repeats_address_nibble_on_cgb_e() {
+        let mut memory = Memory::new();
+        memory.set_revision(Revision::CgbE);
+
+        memory.set(0xFEA0, 0x42);
+
+        assert_eq!(0xAA, memory.get(0xFEA0));
+        assert_eq!(0xAA, memory.get(0xFEAF));
+        assert_eq!(0xFF, memory.get(0xFEF3));
+    }
+
+    #[test]
+    fn vbk_selects_the_vram_bank_on_cgb() {
+        let mut memory = Memory::new();
+        memory.set_revision(Revision::CgbE);
+
+        memory.set(0x8000, 0x11);
+        memory.set(io::REGISTER_VBK as usize, 0x01);
+        memory.set(0x8000, 0x22);
+
+        assert_eq!(0x22, memory.get(0x8000));
+        assert_eq!(0xFF, memory.get(io::REGISTER_VBK as usize));
+        assert_eq!(0x11, memory.vram().read(0, 0x8000));
+        assert_eq!(0x22, memory.vram().read(1, 0x8000));
+    }
+
+    #[test]
+    fn vbk_is_ignored_on_dmg() {
+        let mut memory = Memory::new();
+
+        memory.set(io::REGISTER_VBK as usize, 0x01);
+        memory.set(0x9800, 0x22);
+
+        assert_eq!(0, memory.vram_bank());
+        assert_eq!(0x22, memory.vram().read(0, 0x9800));
+    }
+
+    #[test]
+    fn finish_boot_keeps_cgb_mode_for_cgb_cartridges() {
+        let mut memory = Memory::from_rom(&[0x00; 0x150]);
+        memory.load(crate::cartridge::HEADER_CGB_FLAG, &[0x80]);
+        memory.set_revision(Revision::CgbD);
+
+        memory.finish_boot();
+
+        assert_eq!(io::Mode::Cgb, memory.mode());
+        assert!(memory.io().is_boot_finished());
+    }
+
+    #[test]
+    fn vram_bank_is_locked_in_dmg_compatibility_mode() {
+        let mut memory = Memory::from_rom(&[0x00; 0x150]);
+        memory.set_revision(Revision::CgbE);
+        memory.finish_boot();
+
+        memory.set(io::REGISTER_VBK as usize, 0x01);
+
+        assert_eq!(0, memory.vram_bank());
+    }
+
+    #[test]
+    fn finish_boot_colorizes_dmg_games() {
+        let mut memory = Memory::from_rom(&[0x00; 0x150]);
+        memory.set_revision(Revision::CgbE);
+
+        memory.finish_boot();
+
+        let objects = memory.io().object_palettes();
+        assert_eq!(colorization::DEFAULT.object1[1], objects.raw_color(1, 1));
+        assert_eq!(
+            colorization::DEFAULT.background[2],
+            memory.io().background_palettes().raw_color(0, 2)
+        );
+    }
+
+    #[test]
+    fn finish_boot_does_not_colorize_on_dmg() {
+        let mut memory = Memory::from_rom(&[0x00; 0x150]);
+
+        memory.finish_boot();
+
+        assert_eq!(0x0000, memory.io().background_palettes().raw_color(0, 0));
+    }
+
+    #[test]
+    fn set_model_fills_ram_with_noise() {
+        let mut memory = Memory::new();
+
+        memory.set_model(Model::Mgb);
+
+        assert_eq!(Model::Mgb, memory.model());
+        assert_eq!(io::Mode::Dmg, memory.mode());
+        assert!((0xC000..=0xDFFF).any(|location| memory.get(location) != 0));
+        assert_eq!(0x00, memory.get(0xFFFF));
+        assert_eq!(0x00, memory.get(0x8000));
+    }
+
+    #[test]
+    fn writes_to_wram_are_visible_in_echo_ram() {
+        let mut memory = Memory::new();
+
+        memory.set(0xC000, 0x42);
+        memory.set(0xDDFF, 0x43);
+
+        assert_eq!(0x42, memory.get(0xE000));
+        assert_eq!(0x43, memory.get(0xFDFF));
+    }
+
+    #[test]
+    fn writes_to_echo_ram_are_visible_in_wram() {
+        let mut memory = Memory::new();
+
+        memory.set(0xE000, 0x42);
+        memory.set(0xFDFF, 0x43);
+
+        assert_eq!(0x42, memory.get(0xC000));
+        assert_eq!(0x43, memory.get(0xDDFF));
+    }
+
+    #[test]
+    fn u16_accesses_are_mirrored_from_both_sides() {
+        let mut memory = Memory::new();
+
+        memory.set_u16(0xC100, 0xABCD);
+        assert_eq!(0xABCD, memory.get_u16(0xE100));
+
+        memory.set_u16(0xE200, 0x1234);
+        assert_eq!(0x1234, memory.get_u16(0xC200));
+    }
+
+    #[test]
+    fn u16_access_straddling_the_end_of_echo_ram_spills_into_oam() {
+        let mut memory = Memory::new();
+
+        memory.set_u16(0xFDFF, 0xABCD);
+
+        assert_eq!(0xCD, memory.get(0xDDFF));
+        assert_eq!(0xAB, memory.get(0xFE00));
+        assert_eq!(0x00, memory.get(0xDE00));
+    }
+
+    #[test]
+    fn u16_access_straddling_the_end_of_mirrored_wram_does_not_touch_oam() {
+        let mut memory = Memory::new();
+
+        memory.set_u16(0xDDFF, 0xABCD);
+
+        assert_eq!(0xABCD, memory.get_u16(0xDDFF));
+        assert_eq!(0xCD, memory.get(0xFDFF));
+        assert_eq!(0xAB, memory.get(0xDE00));
+        assert_eq!(0x00, memory.get(0xFE00));
+    }
+
+    #[test]
+    fn wram_tail_and_oam_are_not_aliased() {
+        let mut memory = Memory::new();
+
+        memory.set(0xDE00, 0x11);
+        memory.set(0xFE00, 0x22);
+        memory.set(0xDFFF, 0x33);
+
+        assert_eq!(0x11, memory.get(0xDE00));
+        assert_eq!(0x22, memory.get(0xFE00));
+        assert_eq!(0x33, memory.get(0xDFFF));
     }
 }
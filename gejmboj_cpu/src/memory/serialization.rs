@@ -0,0 +1,153 @@
+//! `serde` support for `Memory`, see the module documentation of `memory`.
+
+use std::{convert::TryInto, fmt};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{Memory, OamDma, Revision, MEMORY_SIZE};
+use crate::{io::Io, model::Model, vram::Vram};
+
+#[derive(Serialize)]
+#[serde(rename = "Memory")]
+struct StateRef<'a> {
+    model: Model,
+    revision: Revision,
+    memory: Bytes<'a>,
+    io: Bytes<'a>,
+    vram: Bytes<'a>,
+    dma: Option<OamDma>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "Memory")]
+struct State {
+    model: Model,
+    revision: Revision,
+    memory: ByteBuf,
+    io: ByteBuf,
+    vram: ByteBuf,
+    dma: Option<OamDma>,
+}
+
+/// Serializes as a byte string rather than a sequence of numbers.
+struct Bytes<'a>(&'a [u8]);
+
+impl Serialize for Bytes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+/// Deserializes a byte string, or a sequence of bytes for formats without byte strings.
+struct ByteBuf(Vec<u8>);
+
+impl<'de> Deserialize<'de> for ByteBuf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = ByteBuf;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a byte string")
+            }
+
+            fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<ByteBuf, E> {
+                Ok(ByteBuf(bytes.to_vec()))
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> Result<ByteBuf, E> {
+                Ok(ByteBuf(bytes))
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<ByteBuf, A::Error> {
+                let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(byte) = seq.next_element()? {
+                    bytes.push(byte);
+                }
+                Ok(ByteBuf(bytes))
+            }
+        }
+
+        deserializer.deserialize_byte_buf(Visitor)
+    }
+}
+
+impl Serialize for Memory {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        StateRef {
+            model: self.model,
+            revision: self.revision,
+            memory: Bytes(&self.memory[..]),
+            io: Bytes(&self.io.to_bytes()),
+            vram: Bytes(self.vram.as_bytes()),
+            dma: self.dma,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Memory {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let state = State::deserialize(deserializer)?;
+
+        let io = Io::from_bytes(&state.io.0)
+            .ok_or_else(|| de::Error::invalid_length(state.io.0.len(), &"an I/O state"))?;
+        let vram = Vram::from_bytes(&state.vram.0)
+            .ok_or_else(|| de::Error::invalid_length(state.vram.0.len(), &"two VRAM banks"))?;
+        let length = state.memory.0.len();
+        let memory: Box<[u8; MEMORY_SIZE]> = state
+            .memory
+            .0
+            .into_boxed_slice()
+            .try_into()
+            .map_err(|_| de::Error::invalid_length(length, &"65536 bytes of memory"))?;
+
+        Ok(Self {
+            memory,
+            dma: state.dma,
+            io,
+            revision: state.revision,
+            vram,
+            model: state.model,
+            ..Self::new()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_round_trips() {
+        let mut memory = Memory::new();
+        memory.set_model(Model::Cgb);
+        memory.set(0xC123, 0xAB);
+        memory.set(0xFF4F, 0x01);
+        memory.set(0x8000, 0xCD);
+        memory.set(0xFF68, 0x80);
+        memory.set(0xFF69, 0x1F);
+        memory.set(0xFF46, 0xC1);
+        memory.step_dma(3);
+
+        let json = serde_json::to_string(&memory).unwrap();
+        let restored: Memory = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(Model::Cgb, restored.model());
+        assert_eq!(Revision::CgbE, restored.revision());
+        assert_eq!(0xAB, restored.peek(0xC123));
+        assert_eq!(0xCD, restored.vram().read(1, 0x8000));
+        assert_eq!(0x001F, restored.io().background_palettes().raw_color(0, 0));
+        assert!(restored.is_dma_active());
+        assert!(restored.diff(&memory.snapshot()).is_empty());
+    }
+
+    #[test]
+    fn truncated_memory_is_rejected() {
+        let mut json = serde_json::to_value(Memory::new()).unwrap();
+        json["memory"].as_array_mut().unwrap().pop();
+
+        assert!(serde_json::from_value::<Memory>(json).is_err());
+    }
+}
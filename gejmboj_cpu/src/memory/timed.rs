@@ -0,0 +1,138 @@
+//! Memory accesses on their machine cycle, see `TimedBus`.
+
+use std::cell::{Cell, RefCell};
+
+use super::MemoryBus;
+
+/// A bus advancing the hardware on it, e.g. OAM DMA, by one machine cycle before every memory
+/// access, so the access sees the hardware as it is in the machine cycle it happens.
+///
+/// The opcode and operand bytes are decoded up front and account for the first machine cycles of
+/// an instruction, one each. The cycles the instruction spends without accessing memory are
+/// spent at its end by `finish`, except for the internal cycle decrementing SP before a write to
+/// the stack. The upper byte of a stack write lands in the cycle after it, the lower byte in the
+/// cycle after that.
+pub(crate) struct TimedBus<'a, M: MemoryBus> {
+    memory: RefCell<&'a mut M>,
+    cycles: Cell<u16>,
+}
+
+impl<'a, M: MemoryBus> TimedBus<'a, M> {
+    /// Starts timing an instruction whose `fetched` opcode and operand bytes took a machine
+    /// cycle each.
+    pub(crate) fn new(memory: &'a mut M, fetched: u16) -> Self {
+        memory.step(fetched);
+
+        Self {
+            memory: RefCell::new(memory),
+            cycles: Cell::new(fetched),
+        }
+    }
+
+    /// Spends the machine cycles of the instruction which were not spent on memory accesses.
+    pub(crate) fn finish(self, cycles: u16) {
+        let remaining = cycles.saturating_sub(self.cycles.get());
+        self.memory.into_inner().step(remaining);
+    }
+
+    fn advance(&self) {
+        self.cycles.set(self.cycles.get() + 1);
+        self.memory.borrow_mut().step(1);
+    }
+}
+
+impl<M: MemoryBus> MemoryBus for TimedBus<'_, M> {
+    fn get(&self, location: usize) -> u8 {
+        self.advance();
+        self.memory.borrow().get(location)
+    }
+
+    fn set(&mut self, location: usize, value: u8) {
+        self.advance();
+        self.memory.get_mut().set(location, value);
+    }
+
+    fn peek(&self, location: usize) -> u8 {
+        self.memory.borrow().peek(location)
+    }
+
+    fn set_stack(&mut self, location: usize, value: u8) {
+        self.advance();
+        self.memory.get_mut().set_stack(location, value);
+    }
+
+    fn set_stack_u16(&mut self, location: usize, value: u16) {
+        let [lo, hi] = value.to_le_bytes();
+
+        self.advance();
+        self.set_stack((location + 1) & 0xFFFF, hi);
+        self.set_stack(location, lo);
+    }
+
+    fn rom_offset(&self, location: usize) -> Option<usize> {
+        self.memory.borrow().rom_offset(location)
+    }
+
+    fn step(&mut self, cycles: u16) {
+        self.cycles.set(self.cycles.get() + cycles);
+        self.memory.get_mut().step(cycles);
+    }
+
+    fn begin_instruction(&mut self, pc: u16) {
+        self.memory.get_mut().begin_instruction(pc);
+    }
+
+    fn check_invariants(&self) -> Result<(), String> {
+        self.memory.borrow().check_invariants()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records the machine cycle of every access.
+    #[derive(Default)]
+    struct Log {
+        cycle: u16,
+        accesses: RefCell<Vec<(u16, char)>>,
+    }
+
+    impl MemoryBus for Log {
+        fn get(&self, _location: usize) -> u8 {
+            self.accesses.borrow_mut().push((self.cycle, 'r'));
+            0
+        }
+
+        fn set(&mut self, _location: usize, _value: u8) {
+            self.accesses.borrow_mut().push((self.cycle, 'w'));
+        }
+
+        fn step(&mut self, cycles: u16) {
+            self.cycle += cycles;
+        }
+    }
+
+    #[test]
+    fn accesses_happen_in_their_machine_cycle() {
+        let mut log = Log::default();
+
+        // CALL nn: 3 fetch cycles, the internal cycle and the stack writes
+        let mut bus = TimedBus::new(&mut log, 3);
+        bus.set_stack_u16(0xFFFC, 0x1234);
+        bus.finish(6);
+        assert_eq!(vec![(5, 'w'), (6, 'w')], log.accesses.replace(vec![]));
+        assert_eq!(6, log.cycle);
+
+        // LD A, (HL): the read in the second cycle
+        let bus = TimedBus::new(&mut log, 1);
+        bus.get(0xC000);
+        bus.finish(2);
+        assert_eq!(vec![(8, 'r')], log.accesses.replace(vec![]));
+        assert_eq!(8, log.cycle);
+
+        // ADD SP, e: the internal cycles at the end
+        TimedBus::new(&mut log, 2).finish(4);
+        assert_eq!(12, log.cycle);
+    }
+}
@@ -0,0 +1,92 @@
+//! # Hardware models
+//!
+//! The emulated machine is selected with `Model`. It decides which features are available,
+//! e.g. CGB registers, and the state the hardware starts in.
+
+use crate::memory::Revision;
+
+/// A Game Boy hardware model.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "wasm", wasm_bindgen::prelude::wasm_bindgen)]
+pub enum Model {
+    /// Original Game Boy
+    #[default]
+    Dmg,
+    /// Game Boy Pocket
+    Mgb,
+    /// Super Game Boy
+    Sgb,
+    /// Game Boy Color
+    Cgb,
+}
+
+impl Model {
+    /// Returns `true` if the model has the CGB features.
+    pub fn is_cgb(&self) -> bool {
+        matches!(self, Model::Cgb)
+    }
+
+    /// Returns the hardware revision emulated for the model.
+    pub fn revision(&self) -> Revision {
+        match self {
+            Model::Dmg | Model::Mgb | Model::Sgb => Revision::Dmg,
+            Model::Cgb => Revision::CgbE,
+        }
+    }
+
+    /// Returns the size of the boot ROM in bytes, including the gap of the CGB boot ROM where the
+    /// cartridge header is visible.
+    pub fn boot_rom_size(&self) -> usize {
+        match self {
+            Model::Dmg | Model::Mgb | Model::Sgb => 0x100,
+            Model::Cgb => 0x900,
+        }
+    }
+
+    /// Fills `ram` with the noise work RAM holds at power on.
+    ///
+    /// The pattern differs between units, a fixed seed per model keeps runs reproducible.
+    pub fn fill_power_on_ram(&self, ram: &mut [u8]) {
+        let mut state: u32 = match self {
+            Model::Dmg => 0x1D4B_3A77,
+            Model::Mgb => 0x2E81_5C9D,
+            Model::Sgb => 0x5A0F_C3E1,
+            Model::Cgb => 0x7B3C_1E95,
+        };
+
+        for byte in ram {
+            // xorshift32
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            *byte = state as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_cgb_has_cgb_features() {
+        assert!(!Model::Dmg.is_cgb());
+        assert!(!Model::Mgb.is_cgb());
+        assert!(!Model::Sgb.is_cgb());
+        assert!(Model::Cgb.is_cgb());
+        assert_eq!(Revision::CgbE, Model::Cgb.revision());
+    }
+
+    #[test]
+    fn power_on_ram_is_reproducible_noise() {
+        let mut first = [0; 64];
+        let mut second = [0; 64];
+
+        Model::Dmg.fill_power_on_ram(&mut first);
+        Model::Dmg.fill_power_on_ram(&mut second);
+
+        assert_eq!(first, second);
+        assert!(first.iter().any(|&x| x != first[0]));
+    }
+}
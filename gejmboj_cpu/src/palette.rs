@@ -0,0 +1,212 @@
+//! # CGB color palettes
+//!
+//! CGB has 8 background and 8 object palettes of 4 colors each, stored in two separate 64 byte
+//! palette RAMs. They are accessed through a specification register (`BCPS`/`OCPS`) selecting a
+//! byte and a data register (`BCPD`/`OCPD`) reading or writing it:
+//!
+//! ```asciidoc
+//! Bit 7:   Auto increment the address after writing to the data register
+//! Bit 6:   Unused
+//! Bit 0-5: Address
+//! ```
+//!
+//! Colors are stored as little-endian 15-bit values, 5 bits per channel:
+//!
+//! ```asciidoc
+//! Bit 10-14: Blue
+//! Bit 5-9:   Green
+//! Bit 0-4:   Red
+//! ```
+
+use std::convert::TryInto;
+
+const MASK_AUTO_INCREMENT: u8 = 0b1000_0000;
+const MASK_ADDRESS: u8 = 0b0011_1111;
+
+/// Size of the state saved by `PaletteRam::to_bytes`.
+pub(crate) const PALETTE_STATE_SIZE: usize = 64 + 1;
+
+/// A 24-bit RGB color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl From<u16> for Rgb {
+    /// Converts a 15-bit CGB color, scaling each channel to 8 bits.
+    fn from(x: u16) -> Self {
+        let scale = |channel: u16| {
+            let c = (channel & 0x1F) as u8;
+            (c << 3) | (c >> 2)
+        };
+
+        Self {
+            r: scale(x),
+            g: scale(x >> 5),
+            b: scale(x >> 10),
+        }
+    }
+}
+
+/// A palette RAM with its specification register, see module documentation.
+pub struct PaletteRam {
+    data: [u8; 64],
+    specification: u8,
+}
+
+impl Default for PaletteRam {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PaletteRam {
+    pub fn new() -> Self {
+        Self {
+            data: [0; 64],
+            specification: 0,
+        }
+    }
+
+    /// Reads the specification register.
+    pub fn specification(&self) -> u8 {
+        self.specification | 0b0100_0000
+    }
+
+    /// Writes the specification register.
+    pub fn set_specification(&mut self, value: u8) {
+        self.specification = value & (MASK_AUTO_INCREMENT | MASK_ADDRESS);
+    }
+
+    /// Reads the byte selected by the specification register.
+    pub fn read_data(&self) -> u8 {
+        self.data[self.address()]
+    }
+
+    /// Writes the byte selected by the specification register, auto incrementing the address
+    /// if enabled.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::palette::{PaletteRam, Rgb};
+    /// let mut palettes = PaletteRam::new();
+    /// palettes.set_specification(0b1000_0010);
+    ///
+    /// palettes.write_data(0x1F);
+    /// palettes.write_data(0x00);
+    ///
+    /// assert_eq!(0b1100_0100, palettes.specification());
+    /// assert_eq!(Rgb { r: 0xFF, g: 0x00, b: 0x00 }, palettes.color(0, 1));
+    /// ```
+    pub fn write_data(&mut self, value: u8) {
+        self.data[self.address()] = value;
+
+        if self.specification & MASK_AUTO_INCREMENT > 0 {
+            let address = (self.specification + 1) & MASK_ADDRESS;
+            self.specification = MASK_AUTO_INCREMENT | address;
+        }
+    }
+
+    /// Returns the 15-bit value of `color` (0-3) in `palette` (0-7).
+    pub fn raw_color(&self, palette: u8, color: u8) -> u16 {
+        let address = (palette as usize & 0x07) * 8 + (color as usize & 0x03) * 2;
+
+        u16::from_le_bytes([self.data[address], self.data[address + 1]])
+    }
+
+    /// Sets the 15-bit value of `color` (0-3) in `palette` (0-7).
+    pub fn set_raw_color(&mut self, palette: u8, color: u8, value: u16) {
+        let address = (palette as usize & 0x07) * 8 + (color as usize & 0x03) * 2;
+
+        self.data[address..address + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// Returns `color` (0-3) in `palette` (0-7) as RGB.
+    pub fn color(&self, palette: u8, color: u8) -> Rgb {
+        self.raw_color(palette, color).into()
+    }
+
+    /// Returns the palette RAM followed by the specification register.
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.data.to_vec();
+        bytes.push(self.specification);
+        bytes
+    }
+
+    /// Restores a palette RAM saved with `to_bytes`, `None` if `bytes` has the wrong length.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (specification, data) = bytes.split_last()?;
+
+        Some(Self {
+            data: data.try_into().ok()?,
+            specification: *specification,
+        })
+    }
+
+    fn address(&self) -> usize {
+        (self.specification & MASK_ADDRESS) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn address_wraps_on_auto_increment() {
+        let mut palettes = PaletteRam::new();
+        palettes.set_specification(0b1011_1111);
+
+        palettes.write_data(0xAB);
+        palettes.write_data(0xCD);
+
+        assert_eq!(0b1100_0001, palettes.specification());
+        assert_eq!(0x00CD, palettes.raw_color(0, 0));
+        assert_eq!(0xAB00, palettes.raw_color(7, 3));
+    }
+
+    #[test]
+    fn address_is_kept_without_auto_increment() {
+        let mut palettes = PaletteRam::new();
+        palettes.set_specification(0x08);
+
+        palettes.write_data(0xAB);
+        palettes.write_data(0xCD);
+
+        assert_eq!(0x48, palettes.specification());
+        assert_eq!(0xCD, palettes.read_data());
+    }
+
+    #[test]
+    fn raw_colors_are_stored_little_endian() {
+        let mut palettes = PaletteRam::new();
+        palettes.set_raw_color(1, 2, 0x1234);
+
+        palettes.set_specification(0x0C);
+        assert_eq!(0x34, palettes.read_data());
+        palettes.set_specification(0x0D);
+        assert_eq!(0x12, palettes.read_data());
+    }
+
+    #[test]
+    fn colors_are_scaled_to_8_bits() {
+        assert_eq!(Rgb { r: 0, g: 0, b: 0 }, Rgb::from(0x0000));
+        assert_eq!(
+            Rgb {
+                r: 0xFF,
+                g: 0xFF,
+                b: 0xFF
+            },
+            Rgb::from(0x7FFF)
+        );
+        assert_eq!(
+            Rgb {
+                r: 0x08,
+                g: 0x84,
+                b: 0xFF
+            },
+            Rgb::from(0b11111_10000_00001)
+        );
+    }
+}
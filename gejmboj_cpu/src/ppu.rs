@@ -0,0 +1,482 @@
+//! # PPU timing
+//!
+//! The PPU draws 154 lines of 114 machine cycles each, lines `144-153` are the vertical blank.
+//! LY (`FF44`) holds the current line and the lower bits of STAT (`FF41`) the mode:
+//!
+//! ```asciidoc
+//! Mode 2: OAM scan, the first 20 machine cycles of a line
+//! Mode 3: Drawing, the next 43 machine cycles
+//! Mode 0: Horizontal blank, the rest of the line
+//! Mode 1: Vertical blank, lines 144-153
+//! ```
+//!
+//! Mode 3 always takes its minimum length. Lines are rendered by `renderer` when mode 3 begins,
+//! see `Ppu::drawing_line`.
+//!
+//! ## LY=LYC coincidence
+//!
+//! Bit 2 of STAT is set while LY equals LYC (`FF45`). In the machine cycle LY changes the
+//! comparison is not ready and the flag reads `0`. Line 153 reports LY `153` only for its first
+//! machine cycle, then `0`, so a LYC of `0` matches late in line 153 already.
+//!
+//! ## Turning the LCD off and on
+//!
+//! While LCDC bit 7 is reset the PPU is idle: LY reads `0` and STAT reports mode 0. When it's
+//! turned on again the first line skips the OAM scan, reporting mode 0 instead of mode 2, and the
+//! first frame is not shown, see `Ppu::is_blank`. Both are reported as `LcdEvent`s.
+//!
+//! ## Interrupts
+//!
+//! The vertical blank interrupt is requested when line 144 begins. The STAT interrupt is requested
+//! when any of the sources enabled in STAT becomes active while none was before:
+//!
+//! ```asciidoc
+//! Bit 6: LY=LYC
+//! Bit 5: Mode 2
+//! Bit 4: Mode 1
+//! Bit 3: Mode 0
+//! ```
+//!
+//! ```
+//! # use gejmboj_cpu::ppu::{Ppu, LINE_CYCLES};
+//! let mut ppu = Ppu::new();
+//! // LCD on, LY=LYC interrupt enabled for line 2
+//! ppu.write(0xFF40, 0x80);
+//! ppu.write(0xFF41, 0x40);
+//! ppu.write(0xFF45, 2);
+//!
+//! let cycles = (1..).find(|_| ppu.step() & 0x02 > 0).unwrap();
+//!
+//! assert_eq!(2 * LINE_CYCLES + 1, cycles);
+//! assert_eq!(0xC6, ppu.read(0xFF41) | 0x80);
+//! ```
+
+use std::convert::TryInto;
+
+use crate::{
+    interrupts::Interrupt,
+    io::{REGISTER_LCDC, REGISTER_LY, REGISTER_LYC, REGISTER_STAT},
+};
+
+/// Machine cycles of a line.
+pub const LINE_CYCLES: u16 = 114;
+
+/// Lines of a frame, including the vertical blank.
+pub const LINES: u8 = 154;
+
+/// First line of the vertical blank.
+pub const VBLANK_LINE: u8 = 144;
+
+/// Machine cycles of mode 2 and the minimum of mode 3.
+const OAM_SCAN_CYCLES: u16 = 20;
+const DRAWING_CYCLES: u16 = 43;
+
+const MASK_LCDC_ENABLE: u8 = 0b1000_0000;
+const MASK_STAT_COINCIDENCE: u8 = 0b0000_0100;
+const MASK_STAT_MODE: u8 = 0b0000_0011;
+
+/// Interrupt requests returned by `Ppu::step`, as bits of IF.
+pub const INTERRUPT_VBLANK: u8 = Interrupt::VBlank.mask();
+pub const INTERRUPT_STAT: u8 = Interrupt::LCD_STAT.mask();
+
+/// Size of the state saved by `Ppu::to_bytes`.
+pub(crate) const PPU_STATE_SIZE: usize = 7;
+
+/// The LCD was turned off or on through LCDC, see `Ppu::take_events`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LcdEvent {
+    TurnedOff,
+    /// The LCD stays blank until the next frame begins
+    TurnedOn,
+}
+
+/// The PPU, see module documentation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ppu {
+    lcdc: u8,
+    stat: u8,
+    lyc: u8,
+    line: u8,
+    /// Machine cycle within the line
+    cycle: u16,
+    /// Any enabled STAT interrupt source is active
+    stat_line: bool,
+    /// The LCD was turned on in this line
+    first_line: bool,
+    /// The LCD was turned on in this frame
+    first_frame: bool,
+    events: Vec<LcdEvent>,
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ppu {
+    pub fn new() -> Self {
+        Self {
+            lcdc: 0,
+            stat: 0,
+            lyc: 0,
+            line: 0,
+            cycle: 0,
+            stat_line: false,
+            first_line: false,
+            first_frame: false,
+            events: vec![],
+        }
+    }
+
+    /// Returns `true` if the LCD shows no picture, either because it's off or because it was
+    /// turned on during this frame.
+    pub fn is_blank(&self) -> bool {
+        !self.is_on() || self.first_frame
+    }
+
+    /// Takes the LCD events which happened since the last call.
+    pub fn take_events(&mut self) -> Vec<LcdEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Returns the line if the PPU began drawing it in the last machine cycle.
+    pub fn drawing_line(&self) -> Option<u8> {
+        (self.is_on() && self.line < VBLANK_LINE && self.cycle == OAM_SCAN_CYCLES)
+            .then_some(self.line)
+    }
+
+    fn is_on(&self) -> bool {
+        self.lcdc & MASK_LCDC_ENABLE > 0
+    }
+
+    /// Advances the PPU by one machine cycle, returning the requested interrupts.
+    pub fn step(&mut self) -> u8 {
+        if !self.is_on() {
+            return 0;
+        }
+
+        self.cycle += 1;
+        if self.cycle == LINE_CYCLES {
+            self.cycle = 0;
+            self.line = (self.line + 1) % LINES;
+            self.first_line = false;
+            self.first_frame &= self.line != 0;
+        }
+
+        let vblank = match self.line == VBLANK_LINE && self.cycle == 0 {
+            true => INTERRUPT_VBLANK,
+            false => 0,
+        };
+        vblank | self.update_stat()
+    }
+
+    /// Reads the register at `address`, one of LCDC, STAT, LY and LYC.
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            REGISTER_LCDC => self.lcdc,
+            REGISTER_STAT => self.stat,
+            REGISTER_LY => self.ly(),
+            REGISTER_LYC => self.lyc,
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes `value` to the register at `address` as the CPU would, returning the requested
+    /// interrupts.
+    ///
+    /// The mode and coincidence bits of STAT and LY are read-only.
+    pub fn write(&mut self, address: u16, value: u8) -> u8 {
+        match address {
+            REGISTER_LCDC => {
+                let was_on = self.is_on();
+                self.lcdc = value;
+                match (was_on, self.is_on()) {
+                    (true, false) => self.turn_off(),
+                    (false, true) => self.turn_on(),
+                    _ => {}
+                }
+            }
+            REGISTER_STAT => {
+                self.stat = (value & !(MASK_STAT_COINCIDENCE | MASK_STAT_MODE))
+                    | (self.stat & (MASK_STAT_COINCIDENCE | MASK_STAT_MODE))
+            }
+            REGISTER_LYC => self.lyc = value,
+            _ => return 0,
+        }
+        match self.is_on() {
+            true => self.update_stat(),
+            false => 0,
+        }
+    }
+
+    fn turn_off(&mut self) {
+        self.line = 0;
+        self.cycle = 0;
+        self.stat &= !MASK_STAT_MODE;
+        self.stat_line = false;
+        self.first_frame = false;
+        self.events.push(LcdEvent::TurnedOff);
+    }
+
+    fn turn_on(&mut self) {
+        self.line = 0;
+        self.cycle = 0;
+        self.first_line = true;
+        self.first_frame = true;
+        self.events.push(LcdEvent::TurnedOn);
+    }
+
+    /// Sets a register without any side effects.
+    pub fn set_raw(&mut self, address: u16, value: u8) {
+        match address {
+            REGISTER_LCDC => self.lcdc = value,
+            REGISTER_STAT => self.stat = value,
+            REGISTER_LY => self.line = value % LINES,
+            REGISTER_LYC => self.lyc = value,
+            _ => {}
+        }
+    }
+
+    /// Returns the line reported in LY.
+    fn ly(&self) -> u8 {
+        match (self.line, self.cycle) {
+            (153, 1..) => 0,
+            (line, _) => line,
+        }
+    }
+
+    /// Returns `true` while LY equals LYC, `false` in the machine cycle LY changes.
+    fn coincidence(&self) -> bool {
+        let changing = match self.line {
+            0 => false,
+            153 => self.cycle <= 1,
+            _ => self.cycle == 0,
+        };
+        !changing && self.ly() == self.lyc
+    }
+
+    fn mode(&self) -> u8 {
+        match self.cycle {
+            _ if self.line >= VBLANK_LINE => 1,
+            cycle if cycle < OAM_SCAN_CYCLES && self.first_line => 0,
+            cycle if cycle < OAM_SCAN_CYCLES => 2,
+            cycle if cycle < OAM_SCAN_CYCLES + DRAWING_CYCLES => 3,
+            _ => 0,
+        }
+    }
+
+    /// Updates the mode and coincidence bits of STAT, returning the STAT interrupt on a rising
+    /// edge of the enabled sources.
+    fn update_stat(&mut self) -> u8 {
+        let coincidence = self.coincidence();
+        let mode = self.mode();
+        self.stat = (self.stat & !(MASK_STAT_COINCIDENCE | MASK_STAT_MODE))
+            | (coincidence as u8) << 2
+            | mode;
+
+        let stat_line = (coincidence && self.stat & 0x40 > 0)
+            || (mode == 2 && self.stat & 0x20 > 0)
+            || (mode == 1 && self.stat & 0x10 > 0)
+            || (mode == 0 && self.stat & 0x08 > 0);
+        let rising = stat_line && !self.stat_line;
+        self.stat_line = stat_line;
+
+        match rising {
+            true => INTERRUPT_STAT,
+            false => 0,
+        }
+    }
+
+    /// Checks that the PPU state is one the hardware could be in: the mode in STAT is the one of
+    /// the position in the frame, LY and the mode are `0` while the LCD is off.
+    pub(crate) fn check_invariants(&self) -> Result<(), String> {
+        if self.line >= LINES || self.cycle >= LINE_CYCLES {
+            return Err(format!(
+                "PPU is past the frame: line {}, cycle {}",
+                self.line, self.cycle
+            ));
+        }
+        let mode = match self.is_on() {
+            true => self.mode(),
+            false if self.line == 0 => 0,
+            false => return Err(format!("LY is {} while the LCD is off", self.line)),
+        };
+        if self.stat & MASK_STAT_MODE != mode {
+            return Err(format!(
+                "STAT reports mode {} in mode {}, LY {}",
+                self.stat & MASK_STAT_MODE,
+                mode,
+                self.ly()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the registers and the position in the frame as bytes.
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.lcdc, self.stat, self.lyc, self.line];
+        bytes.push(self.cycle as u8);
+        bytes.push(self.stat_line as u8);
+        bytes.push(self.first_line as u8 | (self.first_frame as u8) << 1);
+        bytes
+    }
+
+    /// Restores a state saved with `to_bytes`, `None` if `bytes` is invalid.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: [u8; PPU_STATE_SIZE] = bytes.try_into().ok()?;
+        if bytes[3] >= LINES || bytes[4] as u16 >= LINE_CYCLES || bytes[5] > 1 || bytes[6] > 3 {
+            return None;
+        }
+
+        Some(Self {
+            lcdc: bytes[0],
+            stat: bytes[1],
+            lyc: bytes[2],
+            line: bytes[3],
+            cycle: bytes[4] as u16,
+            stat_line: bytes[5] > 0,
+            first_line: bytes[6] & 0x01 > 0,
+            first_frame: bytes[6] & 0x02 > 0,
+            events: vec![],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A PPU which is on since before the current frame
+    fn enabled_ppu(stat: u8, lyc: u8) -> Ppu {
+        let mut ppu = Ppu::new();
+        ppu.set_raw(REGISTER_LCDC, 0x80);
+        ppu.write(REGISTER_STAT, stat);
+        ppu.write(REGISTER_LYC, lyc);
+        ppu
+    }
+
+    /// Returns the machine cycles of the frame in which `interrupt` is requested.
+    fn requests(ppu: &mut Ppu, interrupt: u8) -> Vec<u32> {
+        (1..=LINES as u32 * LINE_CYCLES as u32)
+            .filter(|_| ppu.step() & interrupt > 0)
+            .collect()
+    }
+
+    #[test]
+    fn lines_and_modes_follow_the_frame_timing() {
+        let mut ppu = enabled_ppu(0x00, 0xFF);
+
+        let modes = (0..LINE_CYCLES)
+            .map(|_| {
+                let mode = ppu.read(REGISTER_STAT) & 0x03;
+                ppu.step();
+                mode
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![2; 20], modes[..20]);
+        assert_eq!(vec![3; 43], modes[20..63]);
+        assert_eq!(vec![0; 51], modes[63..]);
+        assert_eq!(1, ppu.read(REGISTER_LY));
+
+        let vblank = requests(&mut ppu, INTERRUPT_VBLANK);
+        assert_eq!(vec![143 * LINE_CYCLES as u32], vblank);
+        assert_eq!(1, ppu.read(REGISTER_LY));
+    }
+
+    #[test]
+    fn coincidence_is_not_ready_when_ly_changes() {
+        let mut ppu = enabled_ppu(0x00, 1);
+
+        for _ in 0..LINE_CYCLES {
+            ppu.step();
+        }
+        assert_eq!(1, ppu.read(REGISTER_LY));
+        assert_eq!(0x00, ppu.read(REGISTER_STAT) & MASK_STAT_COINCIDENCE);
+
+        ppu.step();
+        assert_eq!(0x04, ppu.read(REGISTER_STAT) & MASK_STAT_COINCIDENCE);
+    }
+
+    #[test]
+    fn lyc_zero_matches_during_line_153() {
+        let mut ppu = enabled_ppu(0x40, 0);
+        ppu.set_raw(REGISTER_LY, 153);
+        ppu.step();
+
+        let stat = requests(&mut ppu, INTERRUPT_STAT);
+
+        assert_eq!(1, stat.len());
+        assert_eq!(1, stat[0]);
+    }
+
+    #[test]
+    fn turning_the_lcd_off_and_on_restarts_the_frame() {
+        let mut ppu = enabled_ppu(0x00, 0xFF);
+        for _ in 0..LINE_CYCLES * 3 + 30 {
+            ppu.step();
+        }
+
+        ppu.write(REGISTER_LCDC, 0x00);
+        ppu.step();
+        assert_eq!(0, ppu.read(REGISTER_LY));
+        assert_eq!(0, ppu.read(REGISTER_STAT) & MASK_STAT_MODE);
+        assert!(ppu.is_blank());
+
+        ppu.write(REGISTER_LCDC, 0x80);
+        assert_eq!(
+            vec![LcdEvent::TurnedOff, LcdEvent::TurnedOn],
+            ppu.take_events()
+        );
+        let modes = (0..LINE_CYCLES * 2)
+            .map(|_| {
+                ppu.step();
+                ppu.read(REGISTER_STAT) & MASK_STAT_MODE
+            })
+            .collect::<Vec<_>>();
+        // The first line skips the OAM scan, the second doesn't
+        assert_eq!(0, modes[0]);
+        assert_eq!(3, modes[OAM_SCAN_CYCLES as usize]);
+        assert_eq!(2, modes[LINE_CYCLES as usize]);
+        assert!(ppu.is_blank());
+
+        for _ in 0..(LINES as u16 - 2) * LINE_CYCLES {
+            ppu.step();
+        }
+        assert!(!ppu.is_blank());
+    }
+
+    #[test]
+    fn enabled_sources_only_interrupt_on_their_rising_edge() {
+        // Mode 0 is directly followed by mode 2 of the next line, only the vertical blank
+        // separates mode 0 of line 143 and mode 2 of line 0
+        let mut ppu = enabled_ppu(0x28, 0xFF);
+
+        let stat = requests(&mut ppu, INTERRUPT_STAT);
+
+        assert_eq!(145, stat.len());
+        assert_eq!(63, stat[0]);
+        assert_eq!(LINES as u32 * LINE_CYCLES as u32, stat[144]);
+
+        // Writing STAT enables a source which is already active
+        let mut ppu = enabled_ppu(0x00, 0xFF);
+        assert_eq!(INTERRUPT_STAT, ppu.write(REGISTER_STAT, 0x20));
+    }
+
+    #[test]
+    fn check_invariants_rejects_modes_not_matching_ly() {
+        let mut ppu = enabled_ppu(0x00, 0xFF);
+        for _ in 0..LINES as u16 * LINE_CYCLES {
+            ppu.step();
+            assert_eq!(Ok(()), ppu.check_invariants());
+        }
+
+        ppu.set_raw(REGISTER_LY, VBLANK_LINE);
+        assert!(ppu.check_invariants().is_err());
+
+        ppu.write(REGISTER_LCDC, 0x00);
+        assert_eq!(Ok(()), ppu.check_invariants());
+        ppu.set_raw(REGISTER_LY, 1);
+        assert!(ppu.check_invariants().is_err());
+    }
+}
@@ -0,0 +1,398 @@
+//! # Game Boy Printer
+//!
+//! The printer is a `SerialDevice` the Game Boy sends packets to, driving the clock:
+//!
+//! ```asciidoc
+//! ,--------.---------.-------------.--------.------.----------.-----------.--------.
+//! | Magic  | Command | Compression | Length | Data | Checksum | Keepalive | Status |
+//! |--------|---------|-------------|--------|------|----------|-----------|--------|
+//! | 88 33  |   1     |      1      |   2    |  n   |    2     |    00     |   00   |
+//! `--------´---------´-------------´--------´------´----------´-----------´--------´
+//! ```
+//!
+//! Length and checksum are little endian, the checksum is the sum of all bytes from the command
+//! to the end of the data. The printer answers `00` to every byte but the last two, the
+//! keepalive is answered with `81` and the status byte with the status after the packet:
+//!
+//! ```asciidoc
+//! Bit 4: Packet error
+//! Bit 3: Unprocessed data
+//! Bit 2: Image data full
+//! Bit 1: Printing
+//! Bit 0: Checksum error
+//! ```
+//!
+//! The commands are:
+//!
+//! - `01`: Initialize, dropping the received image data
+//! - `02`: Print the image data, with 4 bytes of data: sheets, margins, palette and exposure
+//! - `04`: Image data, 2 bits per pixel tiles, 20 per row. Run-length encoded if compression is
+//!   `01`, a run of `n + 2` times the next byte if bit 7 of `n` is set, otherwise `n + 1`
+//!   bytes as is. An empty packet marks the end of the data.
+//! - `0F`: Status inquiry
+//!
+//! Printing renders the image data to a `Printout` immediately, which is passed to the callback
+//! registered with `Printer::on_print` or kept until `Printer::take_printouts`. The printing bit
+//! stays set for a few status inquiries afterwards, since games wait for it.
+//!
+//! ```
+//! # use std::{cell::RefCell, rc::Rc};
+//! # use gejmboj_cpu::{io::Io, printer::Printer};
+//! let printouts = Rc::new(RefCell::new(vec![]));
+//! let printouts_handle = printouts.clone();
+//!
+//! let mut printer = Printer::new();
+//! printer.on_print(Box::new(move |printout| printouts_handle.borrow_mut().push(printout)));
+//!
+//! let mut io = Io::new();
+//! io.serial_mut().attach(Box::new(printer));
+//! ```
+
+use crate::{
+    renderer::{SHADES, WIDTH},
+    serial::SerialDevice,
+};
+
+const MAGIC: [u8; 2] = [0x88, 0x33];
+
+/// Bytes from the magic up to the data.
+const HEADER_SIZE: usize = 6;
+
+const COMMAND_INITIALIZE: u8 = 0x01;
+const COMMAND_PRINT: u8 = 0x02;
+const COMMAND_DATA: u8 = 0x04;
+const COMMAND_STATUS: u8 = 0x0F;
+
+/// Answer to the keepalive byte.
+const DEVICE_ID: u8 = 0x81;
+
+pub const STATUS_CHECKSUM_ERROR: u8 = 0b0000_0001;
+pub const STATUS_PRINTING: u8 = 0b0000_0010;
+pub const STATUS_IMAGE_DATA_FULL: u8 = 0b0000_0100;
+pub const STATUS_UNPROCESSED_DATA: u8 = 0b0000_1000;
+pub const STATUS_PACKET_ERROR: u8 = 0b0001_0000;
+
+/// Most data bytes in a packet, two rows of tiles.
+const MAX_DATA_SIZE: usize = 0x280;
+
+/// Size of the image data buffer, 9 packets.
+const IMAGE_CAPACITY: usize = MAX_DATA_SIZE * 9;
+
+const TILE_SIZE: usize = 16;
+const TILES_PER_ROW: usize = WIDTH / 8;
+
+/// Status inquiries answered with the printing bit set after printing.
+const PRINTING_INQUIRIES: u8 = 4;
+
+/// A printed image, `WIDTH` pixels wide.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Printout {
+    /// Height in pixels
+    pub height: usize,
+    /// The pixels row by row, 4 bytes per pixel as RGBA
+    pub pixels: Vec<u8>,
+    /// Lines of paper fed before (upper nibble) and after (lower nibble) the image
+    pub margins: u8,
+    /// Darkness, `00-7F`
+    pub exposure: u8,
+}
+
+impl Printout {
+    /// Renders tiles of `image` through `palette`, mapped like BGP. Palette `00`, which some
+    /// games send, prints like `E4`.
+    fn render(image: &[u8], palette: u8, margins: u8, exposure: u8) -> Self {
+        let palette = if palette == 0 { 0xE4 } else { palette };
+        let rows = image.len() / (TILE_SIZE * TILES_PER_ROW);
+        let height = rows * 8;
+        let mut pixels = vec![0; WIDTH * height * 4];
+
+        for (index, tile) in image
+            .chunks_exact(TILE_SIZE)
+            .take(rows * TILES_PER_ROW)
+            .enumerate()
+        {
+            let (row, column) = (index / TILES_PER_ROW, index % TILES_PER_ROW);
+
+            for (line, bytes) in tile.chunks_exact(2).enumerate() {
+                for bit in 0..8 {
+                    let color = (bytes[1] >> (7 - bit) & 1) << 1 | bytes[0] >> (7 - bit) & 1;
+                    let shade = palette >> (color * 2) & 0b11;
+                    let offset = ((row * 8 + line) * WIDTH + column * 8 + bit) * 4;
+                    pixels[offset..offset + 4].copy_from_slice(&SHADES[shade as usize]);
+                }
+            }
+        }
+
+        Self {
+            height,
+            pixels,
+            margins,
+            exposure,
+        }
+    }
+}
+
+/// The Game Boy Printer, see module documentation.
+#[derive(Default)]
+pub struct Printer {
+    /// Bytes of the packet received so far, from the magic on
+    packet: Vec<u8>,
+    status: u8,
+    /// Status sent at the end of the current packet
+    reply: u8,
+    image: Vec<u8>,
+    printing: u8,
+    printouts: Vec<Printout>,
+    print_callback: Option<Box<dyn FnMut(Printout)>>,
+}
+
+impl std::fmt::Debug for Printer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Printer")
+            .field("status", &self.status)
+            .field("image", &self.image.len())
+            .field("printouts", &self.printouts.len())
+            .finish()
+    }
+}
+
+impl Printer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a callback which is called with every printout, instead of keeping them.
+    pub fn on_print(&mut self, callback: Box<dyn FnMut(Printout)>) {
+        self.print_callback = Some(callback);
+    }
+
+    /// Returns the status, see module documentation.
+    pub fn status(&self) -> u8 {
+        self.status
+    }
+
+    /// Removes and returns the printouts kept since the last call.
+    pub fn take_printouts(&mut self) -> Vec<Printout> {
+        std::mem::take(&mut self.printouts)
+    }
+
+    /// Executes the complete packet, `length` bytes of data followed by the checksum.
+    fn execute(&mut self, length: usize) {
+        let (body, checksum) = self.packet[2..].split_at(HEADER_SIZE - 2 + length);
+        let sum = body
+            .iter()
+            .fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+
+        if sum != u16::from_le_bytes([checksum[0], checksum[1]]) {
+            self.status |= STATUS_CHECKSUM_ERROR;
+            self.reply = self.status;
+            return;
+        }
+        self.status &= !(STATUS_CHECKSUM_ERROR | STATUS_PACKET_ERROR);
+
+        let (command, compressed) = (body[0], body[1] & 0x01 > 0);
+        let data = &body[4..];
+        match command {
+            COMMAND_INITIALIZE => {
+                self.image.clear();
+                self.printing = 0;
+                self.status = 0;
+            }
+            COMMAND_PRINT if length == 4 => {
+                let printout = Printout::render(&self.image, data[2], data[1], data[3]);
+                match &mut self.print_callback {
+                    Some(callback) => callback(printout),
+                    None => self.printouts.push(printout),
+                }
+
+                self.image.clear();
+                self.printing = PRINTING_INQUIRIES;
+                self.status &= !(STATUS_UNPROCESSED_DATA | STATUS_IMAGE_DATA_FULL);
+                self.status |= STATUS_PRINTING;
+            }
+            COMMAND_DATA => {
+                let data = if compressed {
+                    decompress(data)
+                } else {
+                    data.to_vec()
+                };
+                let free = IMAGE_CAPACITY - self.image.len();
+                self.image.extend(data.iter().take(free));
+
+                if !self.image.is_empty() {
+                    self.status |= STATUS_UNPROCESSED_DATA;
+                }
+                if self.image.len() == IMAGE_CAPACITY {
+                    self.status |= STATUS_IMAGE_DATA_FULL;
+                }
+            }
+            COMMAND_STATUS => {}
+            _ => self.status |= STATUS_PACKET_ERROR,
+        }
+        self.reply = self.status;
+
+        if command == COMMAND_STATUS && self.printing > 0 {
+            self.printing -= 1;
+            if self.printing == 0 {
+                self.status &= !STATUS_PRINTING;
+            }
+        }
+    }
+}
+
+impl SerialDevice for Printer {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        match self.packet.len() {
+            0 | 1 if byte != MAGIC[self.packet.len()] => {
+                self.packet.clear();
+                return 0x00;
+            }
+            _ => self.packet.push(byte),
+        }
+
+        if self.packet.len() < HEADER_SIZE {
+            return 0x00;
+        }
+        let length = u16::from_le_bytes([self.packet[4], self.packet[5]]) as usize;
+        if length > MAX_DATA_SIZE {
+            self.status |= STATUS_PACKET_ERROR;
+            self.packet.clear();
+            return 0x00;
+        }
+
+        // The index of the last byte received, relative to the end of the data
+        match (self.packet.len() - 1).checked_sub(HEADER_SIZE + length) {
+            Some(1) => self.execute(length),
+            Some(2) => return DEVICE_ID,
+            Some(3) => {
+                self.packet.clear();
+                return self.reply;
+            }
+            _ => {}
+        }
+        0x00
+    }
+}
+
+/// Expands run-length encoded image data, see module documentation.
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut expanded = vec![];
+    let mut bytes = data.iter();
+
+    while let Some(&control) = bytes.next() {
+        if control & 0x80 > 0 {
+            let count = (control & 0x7F) as usize + 2;
+            if let Some(&byte) = bytes.next() {
+                expanded.extend(std::iter::repeat_n(byte, count));
+            }
+        } else {
+            let count = control as usize + 1;
+            expanded.extend(bytes.by_ref().take(count));
+        }
+    }
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sends a packet, returning the answers to the keepalive and status bytes.
+    fn send(printer: &mut Printer, command: u8, compression: u8, data: &[u8]) -> (u8, u8) {
+        let mut packet = vec![command, compression];
+        packet.extend((data.len() as u16).to_le_bytes());
+        packet.extend(data);
+        let checksum = packet
+            .iter()
+            .fold(0u16, |sum, &b| sum.wrapping_add(b as u16));
+        packet.extend(checksum.to_le_bytes());
+
+        for &byte in MAGIC.iter().chain(&packet) {
+            assert_eq!(0x00, printer.transfer(byte));
+        }
+        (printer.transfer(0x00), printer.transfer(0x00))
+    }
+
+    /// A row of tiles, the first black and the rest white
+    fn tile_row() -> Vec<u8> {
+        let mut row = vec![0xFF; TILE_SIZE];
+        row.resize(TILE_SIZE * TILES_PER_ROW, 0x00);
+        row
+    }
+
+    #[test]
+    fn printing_renders_the_image_data() {
+        let mut printer = Printer::new();
+
+        assert_eq!(
+            (DEVICE_ID, 0x00),
+            send(&mut printer, COMMAND_INITIALIZE, 0, &[])
+        );
+        assert_eq!(
+            (DEVICE_ID, STATUS_UNPROCESSED_DATA),
+            send(&mut printer, COMMAND_DATA, 0, &tile_row())
+        );
+        send(&mut printer, COMMAND_DATA, 0, &[]);
+        assert_eq!(
+            (DEVICE_ID, STATUS_PRINTING),
+            send(&mut printer, COMMAND_PRINT, 0, &[1, 0x13, 0xE4, 0x40])
+        );
+
+        let printouts = printer.take_printouts();
+        assert_eq!(1, printouts.len());
+        assert_eq!(8, printouts[0].height);
+        assert_eq!(0x13, printouts[0].margins);
+        assert_eq!(SHADES[3], printouts[0].pixels[..4]);
+        assert_eq!(SHADES[0], printouts[0].pixels[8 * 4..9 * 4]);
+
+        for _ in 0..PRINTING_INQUIRIES {
+            assert_eq!(
+                (DEVICE_ID, STATUS_PRINTING),
+                send(&mut printer, COMMAND_STATUS, 0, &[])
+            );
+        }
+        assert_eq!(
+            (DEVICE_ID, 0x00),
+            send(&mut printer, COMMAND_STATUS, 0, &[])
+        );
+    }
+
+    #[test]
+    fn compressed_image_data_is_expanded() {
+        let mut printer = Printer::new();
+
+        // 16 bytes of FF, then 304 bytes of 00 in runs of at most 129
+        let mut data = vec![0x80 | 14, 0xFF];
+        for count in [129, 129, 46] {
+            data.extend([0x80 | (count - 2), 0x00]);
+        }
+        send(&mut printer, COMMAND_DATA, 1, &data);
+
+        assert_eq!(tile_row(), printer.image);
+        assert_eq!(
+            vec![1, 2, 3, 4, 4, 4],
+            decompress(&[0x02, 1, 2, 3, 0x81, 4])
+        );
+    }
+
+    #[test]
+    fn invalid_packets_are_reported() {
+        let mut printer = Printer::new();
+
+        for &byte in &[0x88, 0x33, COMMAND_DATA, 0x00, 0x01, 0x00, 0xAB, 0x00, 0x00] {
+            printer.transfer(byte);
+        }
+        assert_eq!(DEVICE_ID, printer.transfer(0x00));
+        assert_eq!(STATUS_CHECKSUM_ERROR, printer.transfer(0x00));
+        assert!(printer.image.is_empty());
+
+        assert_eq!(
+            (DEVICE_ID, STATUS_PACKET_ERROR),
+            send(&mut printer, 0x05, 0, &[])
+        );
+        assert_eq!(
+            (DEVICE_ID, 0x00),
+            send(&mut printer, COMMAND_STATUS, 0, &[])
+        );
+    }
+}
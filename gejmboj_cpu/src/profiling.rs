@@ -0,0 +1,141 @@
+//! # Profiling
+//!
+//! Accumulates host time spent in each emulated subsystem, enabled with the `profiling` feature.
+//! The statistics are collected per frame: `GameBoy::frame_stats` returns everything accumulated
+//! since the previous call and starts over.
+//!
+//! Time is accounted in laps: each lap charges the host time since the previous one to a single
+//! subsystem, so the subsystems never overlap and add up to the time spent in `run_frame`.
+//! Reading the clock is not free, so laps are rare: `GameBoy::run_frame` laps once per frame
+//! and `Memory::step` around each rendered line, the peripherals stepped every machine cycle
+//! are charged to the CPU.
+
+use std::time::{Duration, Instant};
+
+/// The subsystems which host time is accounted to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Subsystem {
+    /// Instruction decoding and execution, including interrupt dispatch and stepping the timer,
+    /// serial port, OAM DMA and PPU modes along the memory accesses
+    Cpu,
+    /// Rendering lines into the framebuffer
+    Ppu,
+    /// Latching queued input events at the start of the frame
+    Scheduler,
+}
+
+const SUBSYSTEMS: [Subsystem; 3] = [Subsystem::Cpu, Subsystem::Ppu, Subsystem::Scheduler];
+
+/// Host time spent per subsystem.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FrameStats {
+    spent: [Duration; 3],
+    steps: u64,
+}
+
+impl FrameStats {
+    /// Returns the host time spent in `subsystem`.
+    pub fn get(&self, subsystem: Subsystem) -> Duration {
+        self.spent[subsystem as usize]
+    }
+
+    /// Returns the total host time spent in all subsystems.
+    pub fn total(&self) -> Duration {
+        self.spent.iter().sum()
+    }
+
+    /// Returns the number of steps (instructions) the statistics were collected over.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Returns each subsystem together with the host time spent in it.
+    pub fn iter(&self) -> impl Iterator<Item = (Subsystem, Duration)> + '_ {
+        SUBSYSTEMS.iter().map(move |s| (*s, self.get(*s)))
+    }
+}
+
+/// Accumulates `FrameStats`, see module documentation.
+pub(crate) struct Profiler {
+    stats: FrameStats,
+    /// End of the previous lap
+    lap: Instant,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            stats: FrameStats::default(),
+            lap: Instant::now(),
+        }
+    }
+
+    /// Starts a new lap without accounting the time since the previous one.
+    pub fn restart(&mut self) {
+        self.lap = Instant::now();
+    }
+
+    /// Accounts the host time since the previous lap to `subsystem`.
+    pub fn lap(&mut self, subsystem: Subsystem) {
+        let now = Instant::now();
+        self.stats.spent[subsystem as usize] += now - self.lap;
+        self.lap = now;
+    }
+
+    /// Counts completed steps.
+    pub fn count_steps(&mut self, steps: u64) {
+        self.stats.steps += steps;
+    }
+
+    /// Returns the statistics collected since the last call and resets them.
+    pub fn frame_stats(&mut self) -> FrameStats {
+        std::mem::take(&mut self.stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lap_accounts_time_since_the_previous_lap() {
+        let mut profiler = Profiler::new();
+
+        std::thread::sleep(Duration::from_millis(2));
+        let before = Instant::now();
+        profiler.lap(Subsystem::Ppu);
+        profiler.lap(Subsystem::Cpu);
+        let after = Instant::now();
+        profiler.count_steps(1);
+
+        let stats = profiler.frame_stats();
+        assert!(stats.get(Subsystem::Ppu) >= Duration::from_millis(2));
+        assert!(stats.get(Subsystem::Cpu) <= after - before);
+        assert_eq!(Duration::ZERO, stats.get(Subsystem::Scheduler));
+        assert_eq!(1, stats.steps());
+    }
+
+    #[test]
+    fn restart_leaves_out_the_time_since_the_previous_lap() {
+        let mut profiler = Profiler::new();
+
+        std::thread::sleep(Duration::from_millis(2));
+        let before = Instant::now();
+        profiler.restart();
+        profiler.lap(Subsystem::Cpu);
+        let after = Instant::now();
+
+        assert!(profiler.frame_stats().get(Subsystem::Cpu) <= after - before);
+    }
+
+    #[test]
+    fn frame_stats_resets_the_statistics() {
+        let mut profiler = Profiler::new();
+        profiler.lap(Subsystem::Cpu);
+        profiler.count_steps(1);
+
+        profiler.frame_stats();
+
+        assert_eq!(FrameStats::default(), profiler.frame_stats());
+    }
+}
@@ -0,0 +1,226 @@
+//! # Memory recorder
+//!
+//! Opt-in recording of the memory contents frame by frame, making it possible to go back and
+//! look at the memory image at any recorded frame, or to find out which addresses changed
+//! between two frames.
+//!
+//! Only the initial memory image is stored in full. Every recorded frame after that is stored
+//! as a delta against the previous frame, where consecutive changed bytes are grouped into runs.
+//!
+//! ```
+//! # use gejmboj_cpu::{memory::Memory, recorder::MemoryRecorder};
+//! let mut memory = Memory::new();
+//! let mut recorder = MemoryRecorder::new(&memory);
+//!
+//! memory.set(0xC000, 0x42);
+//! let frame = recorder.record_frame(&memory);
+//!
+//! assert_eq!(vec![0xC000], recorder.changed_between(0, frame).unwrap());
+//! assert_eq!(0x00, recorder.image_at(0).unwrap()[0xC000]);
+//! assert_eq!(0x42, recorder.image_at(frame).unwrap()[0xC000]);
+//! ```
+
+use crate::memory::{self, Change, Memory, Snapshot, ECHO_RAM};
+
+/// A run of consecutive changed bytes, starting at `address`.
+#[derive(Debug, PartialEq)]
+struct Run {
+    address: u16,
+    bytes: Vec<u8>,
+}
+
+/// The changes made to memory during a single frame.
+#[derive(Debug, PartialEq, Default)]
+struct FrameDelta {
+    runs: Vec<Run>,
+}
+
+impl FrameDelta {
+    /// Groups `changes`, in address order as returned by `Memory::diff`, into runs.
+    fn from_changes(changes: Vec<Change>) -> Self {
+        let mut runs: Vec<Run> = vec![];
+
+        for change in changes {
+            match runs.last_mut() {
+                Some(run) if run.address as usize + run.bytes.len() == change.address as usize => {
+                    run.bytes.push(change.after)
+                }
+                _ => runs.push(Run {
+                    address: change.address,
+                    bytes: vec![change.after],
+                }),
+            }
+        }
+
+        Self { runs }
+    }
+
+    fn apply(&self, image: &mut [u8]) {
+        for run in &self.runs {
+            let start = run.address as usize;
+            image[start..start + run.bytes.len()].copy_from_slice(&run.bytes);
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.runs.iter().map(|run| run.bytes.len()).sum()
+    }
+}
+
+/// Records per-frame memory deltas.
+pub struct MemoryRecorder {
+    base: Snapshot,
+    deltas: Vec<FrameDelta>,
+    current: Snapshot,
+}
+
+impl MemoryRecorder {
+    /// Starts a new recording with the current contents of `memory` as frame `0`.
+    pub fn new(memory: &Memory) -> Self {
+        let base = memory.snapshot();
+
+        Self {
+            current: base.clone(),
+            base,
+            deltas: vec![],
+        }
+    }
+
+    /// Records the current contents of `memory` as a new frame and returns its frame number.
+    pub fn record_frame(&mut self, memory: &Memory) -> usize {
+        let snapshot = memory.snapshot();
+
+        let changes = self.current.changes_to(&snapshot);
+        self.deltas.push(FrameDelta::from_changes(changes));
+        self.current = snapshot;
+        self.deltas.len()
+    }
+
+    /// Returns the number of recorded frames, including the initial frame.
+    pub fn frame_count(&self) -> usize {
+        self.deltas.len() + 1
+    }
+
+    /// Returns the number of bytes that changed during `frame`, or `None` if it wasn't recorded.
+    ///
+    /// Echo RAM is left out since its changes are already counted for WRAM.
+    pub fn changes_in_frame(&self, frame: usize) -> Option<usize> {
+        match frame {
+            0 => Some(0),
+            _ => self.deltas.get(frame - 1).map(FrameDelta::size),
+        }
+    }
+
+    /// Reconstructs the full memory image at `frame`, or `None` if it wasn't recorded.
+    pub fn image_at(&self, frame: usize) -> Option<Vec<u8>> {
+        self.snapshot_at(frame)
+            .map(|snapshot| snapshot.image().to_vec())
+    }
+
+    /// Lists the addresses whose value differs between frame `from` and frame `to`.
+    ///
+    /// Addresses which changed in between but were restored to their original value are not
+    /// included, neither are echo RAM addresses since they only mirror WRAM. Returns `None` if
+    /// either frame wasn't recorded.
+    pub fn changed_between(&self, from: usize, to: usize) -> Option<Vec<u16>> {
+        let from = self.snapshot_at(from)?;
+        let to = self.snapshot_at(to)?;
+
+        Some(
+            from.changes_to(&to)
+                .iter()
+                .map(|change| change.address)
+                .collect(),
+        )
+    }
+
+    fn snapshot_at(&self, frame: usize) -> Option<Snapshot> {
+        if frame >= self.frame_count() {
+            return None;
+        }
+        let mut snapshot = self.base.clone();
+        let image = snapshot.image_mut();
+        for delta in &self.deltas[..frame] {
+            delta.apply(image);
+        }
+        // The deltas leave out echo RAM
+        for location in ECHO_RAM {
+            image[location] = image[memory::resolve_echo(location)];
+        }
+
+        Some(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_delta_groups_consecutive_changes_into_runs() {
+        let changes = [(1, 1), (2, 2), (4, 3)]
+            .iter()
+            .map(|&(address, after)| Change {
+                address,
+                before: 0,
+                after,
+            })
+            .collect();
+
+        let delta = FrameDelta::from_changes(changes);
+
+        assert_eq!(
+            vec![
+                Run {
+                    address: 1,
+                    bytes: vec![1, 2]
+                },
+                Run {
+                    address: 4,
+                    bytes: vec![3]
+                },
+            ],
+            delta.runs
+        );
+        assert_eq!(3, delta.size());
+    }
+
+    #[test]
+    fn images_can_be_reconstructed_for_every_frame() {
+        let mut memory = Memory::new();
+        let mut recorder = MemoryRecorder::new(&memory);
+
+        for frame in 1..=5u8 {
+            memory.set(0xC000 + frame as usize, frame);
+            memory.set(0xFF80, frame);
+            recorder.record_frame(&memory);
+        }
+
+        let image = recorder.image_at(3).unwrap();
+        assert_eq!(3, image[0xFF80]);
+        assert_eq!(2, image[0xC002]);
+        assert_eq!(0, image[0xC004]);
+        assert_eq!(2, image[0xE002]);
+
+        assert_eq!(6, recorder.frame_count());
+        assert_eq!(Some(2), recorder.changes_in_frame(3));
+        assert_eq!(None, recorder.image_at(6));
+    }
+
+    #[test]
+    fn changed_between_ignores_restored_values() {
+        let mut memory = Memory::new();
+        let mut recorder = MemoryRecorder::new(&memory);
+
+        memory.set(0xC000, 1);
+        memory.set(0xC001, 1);
+        recorder.record_frame(&memory);
+
+        memory.set(0xC000, 0);
+        recorder.record_frame(&memory);
+
+        assert_eq!(Some(vec![0xC001]), recorder.changed_between(0, 2));
+        assert_eq!(Some(vec![0xC000]), recorder.changed_between(1, 2));
+        assert_eq!(None, recorder.changed_between(0, 3));
+    }
+}
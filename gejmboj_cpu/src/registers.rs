@@ -45,17 +45,59 @@
 //! ## Stack pointer register (SP)
 //!
 //! The stack pointer register is initialized to `0xFFFE` and grows top-down, which means it is decremented.
+//!
+//! ## Display
+//!
+//! `Registers` display as a table, the flags shown by letter or `-` when clear. The alternate
+//! form (`{:#}`) fits on a single line, e.g. for traces:
+//!
+//! ```
+//! # use gejmboj_cpu::{model::Model, registers::Registers};
+//! let registers = Registers::new_post_boot(Model::Dmg);
+//!
+//! assert_eq!(
+//!     "PC:0100 SP:fffe AF:01b0 BC:0013 DE:00d8 HL:014d Z-HC",
+//!     format!("{:#}", registers)
+//! );
+//! ```
 
-use std::{convert::TryFrom, fmt::Display};
+use std::{convert::TryFrom, fmt::Display, str::FromStr};
 
-use crate::errors::CpuError;
+use crate::{errors::CpuError, model::Model};
 
 pub const MASK_FLAG_CARRY: u8 = 0b0001_0000;
 pub const MASK_FLAG_HALF_CARRY: u8 = 0b0010_0000;
 pub const MASK_FLAG_NEGATIVE: u8 = 0b0100_0000;
 pub const MASK_FLAG_ZERO: u8 = 0b1000_0000;
 
+/// A flag in the flag register `F`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Flag {
+    /// Zero
+    Z,
+    /// Negative
+    N,
+    /// Half-carry
+    H,
+    /// Carry
+    C,
+}
+
+impl Flag {
+    /// Returns the bit mask of the flag in register `F`.
+    pub fn mask(&self) -> u8 {
+        match self {
+            Flag::Z => MASK_FLAG_ZERO,
+            Flag::N => MASK_FLAG_NEGATIVE,
+            Flag::H => MASK_FLAG_HALF_CARRY,
+            Flag::C => MASK_FLAG_CARRY,
+        }
+    }
+}
+
 #[allow(non_snake_case)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Registers {
     A: u8,
     B: u8,
@@ -68,6 +110,30 @@ pub struct Registers {
 
     pub PC: u16,
     pub SP: u16,
+
+    #[cfg_attr(feature = "serde", serde(skip))]
+    dirty: Option<DirtyTracking>,
+}
+
+/// Registers written since the last `Registers::clear_dirty`.
+#[derive(Debug, Clone, Copy)]
+struct DirtyTracking {
+    /// Bits by `dirty_bit`
+    mask: u16,
+    /// `PC` and `SP` are public fields, so they are compared against their values when cleared
+    pc: u16,
+    sp: u16,
+}
+
+/// Compares register values, ignoring dirty tracking.
+impl PartialEq for Registers {
+    fn eq(&self, other: &Self) -> bool {
+        DIFF_SINGLE_REGISTERS
+            .iter()
+            .all(|r| self.get_single(r) == other.get_single(r))
+            && self.SP == other.SP
+            && self.PC == other.PC
+    }
 }
 
 impl Registers {
@@ -84,9 +150,42 @@ impl Registers {
 
             PC: 0,
             SP: 0xFFFE,
+
+            dirty: None,
         }
     }
 
+    /// Creates registers holding the values the boot ROM of `model` leaves behind.
+    ///
+    /// Use when running without a boot ROM. The DMG and MGB flags depend on the header
+    /// checksum, the values for a valid checksum are used.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::{model::Model, registers::*};
+    /// let registers = Registers::new_post_boot(Model::Dmg);
+    ///
+    /// assert_eq!(0x01B0, registers.get_double(&DoubleRegister::AF));
+    /// assert_eq!(0x0100, registers.PC);
+    /// assert_eq!(0xFFFE, registers.SP);
+    /// ```
+    pub fn new_post_boot(model: Model) -> Self {
+        let (af, bc, de, hl) = match model {
+            Model::Dmg => (0x01B0, 0x0013, 0x00D8, 0x014D),
+            Model::Mgb => (0xFFB0, 0x0013, 0x00D8, 0x014D),
+            Model::Sgb => (0x0100, 0x0014, 0x0000, 0xC060),
+            Model::Cgb => (0x1180, 0x0000, 0xFF56, 0x000D),
+        };
+
+        let mut registers = Self::new();
+        registers.set_double(&DoubleRegister::AF, af);
+        registers.set_double(&DoubleRegister::BC, bc);
+        registers.set_double(&DoubleRegister::DE, de);
+        registers.set_double(&DoubleRegister::HL, hl);
+        registers.PC = 0x0100;
+        registers.SP = 0xFFFE;
+        registers
+    }
+
     /// Sets the value of a `SingleRegister`.
     ///
     /// ## Examples
@@ -111,6 +210,7 @@ impl Registers {
     /// assert_eq!(0xF0, registers.get_single(&SingleRegister::F));
     /// ```
     pub fn set_single(&mut self, r: &SingleRegister, value: u8) {
+        self.mark_dirty(dirty_bit(r));
         match r {
             SingleRegister::A => {
                 self.A = value;
@@ -181,6 +281,7 @@ impl Registers {
             DoubleRegister::DE => u16::from_be_bytes([self.D, self.E]),
             DoubleRegister::HL => u16::from_be_bytes([self.H, self.L]),
             DoubleRegister::SP => self.SP,
+            DoubleRegister::PC => self.PC,
         }
     }
 
@@ -208,6 +309,7 @@ impl Registers {
     /// assert_eq!(0xABC0, registers.get_double(&DoubleRegister::AF));
     /// ```
     pub fn set_double(&mut self, r: &DoubleRegister, value: u16) {
+        self.mark_dirty(dirty_bits(r));
         let [hi, lo] = value.to_be_bytes();
         match r {
             DoubleRegister::AF => {
@@ -226,9 +328,8 @@ impl Registers {
                 self.H = hi;
                 self.L = lo;
             }
-            DoubleRegister::SP => {
-                self.SP = u16::from_be_bytes([hi, lo]);
-            }
+            DoubleRegister::SP => self.SP = value,
+            DoubleRegister::PC => self.PC = value,
         }
     }
 
@@ -247,7 +348,8 @@ impl Registers {
     /// assert_eq!(0xFFFE, registers.get_double(&DoubleRegister::SP));
     /// ```
     pub fn increment_sp(&mut self) -> u16 {
-        self.SP = self.SP + 2;
+        self.mark_dirty(dirty_bits(&DoubleRegister::SP));
+        self.SP = self.SP.wrapping_add(2);
         self.SP
     }
 
@@ -265,10 +367,85 @@ impl Registers {
     /// assert_eq!(0xFFFC, registers.get_double(&DoubleRegister::SP));
     /// ```
     pub fn decrement_sp(&mut self) -> u16 {
-        self.SP = self.SP - 2;
+        self.mark_dirty(dirty_bits(&DoubleRegister::SP));
+        self.SP = self.SP.wrapping_sub(2);
         self.SP
     }
 
+    /// Decrements the SP by 1 and returns new SP value, for pushing a single byte.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// # use gejmboj_cpu::registers::*;
+    /// let mut registers = Registers::new();
+    ///
+    /// registers.decrement_sp_byte();
+    /// assert_eq!(0xFFFD, registers.get_double(&DoubleRegister::SP));
+    /// ```
+    pub fn decrement_sp_byte(&mut self) -> u16 {
+        self.mark_dirty(dirty_bits(&DoubleRegister::SP));
+        self.SP = self.SP.wrapping_sub(1);
+        self.SP
+    }
+
+    /// Returns `true` if `flag` is set.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// # use gejmboj_cpu::registers::*;
+    /// let mut registers = Registers::new();
+    ///
+    /// registers.set_flag(Flag::H);
+    ///
+    /// assert_eq!(true, registers.get_flag(Flag::H));
+    /// assert_eq!(false, registers.get_flag(Flag::C));
+    /// ```
+    pub fn get_flag(&self, flag: Flag) -> bool {
+        self.F & flag.mask() > 0
+    }
+
+    /// Sets `flag`.
+    pub fn set_flag(&mut self, flag: Flag) {
+        self.mark_dirty(dirty_bit(&SingleRegister::F));
+        self.F |= flag.mask();
+    }
+
+    /// Clears `flag`.
+    pub fn clear_flag(&mut self, flag: Flag) {
+        self.mark_dirty(dirty_bit(&SingleRegister::F));
+        self.F &= !flag.mask();
+    }
+
+    /// Sets `flag` if `set` is `true`, clears it otherwise.
+    pub fn assign_flag(&mut self, flag: Flag, set: bool) {
+        if set {
+            self.set_flag(flag)
+        } else {
+            self.clear_flag(flag)
+        }
+    }
+
+    /// Sets all flags at once.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// # use gejmboj_cpu::registers::*;
+    /// let mut registers = Registers::new();
+    ///
+    /// registers.set_flags_from(true, false, true, false);
+    ///
+    /// assert_eq!(0b1010_0000, registers.get_flags());
+    /// ```
+    pub fn set_flags_from(&mut self, zero: bool, negative: bool, half_carry: bool, carry: bool) {
+        self.assign_flag(Flag::Z, zero);
+        self.assign_flag(Flag::N, negative);
+        self.assign_flag(Flag::H, half_carry);
+        self.assign_flag(Flag::C, carry);
+    }
+
     /// Returns `true` if the carry flag is set.
     ///
     /// ## Examples
@@ -284,7 +461,7 @@ impl Registers {
     /// assert_eq!(true, registers.is_carry());
     /// ```
     pub fn is_carry(&self) -> bool {
-        self.F & MASK_FLAG_CARRY > 0
+        self.get_flag(Flag::C)
     }
 
     /// Returns `true` if the half carry flag is set.
@@ -302,7 +479,7 @@ impl Registers {
     /// assert_eq!(true, registers.is_half_carry());
     /// ```
     pub fn is_half_carry(&self) -> bool {
-        self.F & MASK_FLAG_HALF_CARRY > 0
+        self.get_flag(Flag::H)
     }
 
     /// Returns `true` if the negative flag is set.
@@ -320,7 +497,7 @@ impl Registers {
     /// assert_eq!(true, registers.is_negative());
     /// ```
     pub fn is_negative(&self) -> bool {
-        self.F & MASK_FLAG_NEGATIVE > 0
+        self.get_flag(Flag::N)
     }
 
     /// Returns `true` if the zero flag is set.
@@ -338,7 +515,7 @@ impl Registers {
     /// assert_eq!(true, registers.is_zero());
     /// ```
     pub fn is_zero(&self) -> bool {
-        self.F & MASK_FLAG_ZERO > 0
+        self.get_flag(Flag::Z)
     }
 
     /// Returns the value of the flag register `F`.
@@ -366,6 +543,7 @@ impl Registers {
     /// assert_eq!(0b1111_0000, registers.get_flags());
     /// ```
     pub fn set_flags(&mut self, flags: u8) {
+        self.mark_dirty(dirty_bit(&SingleRegister::F));
         self.F = flags & 0xF0;
     }
 
@@ -384,11 +562,7 @@ impl Registers {
     /// assert_eq!(false, registers.is_carry());
     /// ```
     pub fn set_carry(&mut self, set: bool) {
-        if set {
-            self.F = self.F | MASK_FLAG_CARRY
-        } else {
-            self.F = self.F & !MASK_FLAG_CARRY
-        }
+        self.assign_flag(Flag::C, set)
     }
 
     /// Convenience function to set or reset the half-carry flag.
@@ -406,11 +580,7 @@ impl Registers {
     /// assert_eq!(false, registers.is_half_carry());
     /// ```
     pub fn set_half_carry(&mut self, set: bool) {
-        if set {
-            self.F = self.F | MASK_FLAG_HALF_CARRY
-        } else {
-            self.F = self.F & !MASK_FLAG_HALF_CARRY
-        }
+        self.assign_flag(Flag::H, set)
     }
 
     /// Convenience function to set or reset the negative flag.
@@ -428,11 +598,7 @@ impl Registers {
     /// assert_eq!(false, registers.is_negative());
     /// ```
     pub fn set_negative(&mut self, set: bool) {
-        if set {
-            self.F = self.F | MASK_FLAG_NEGATIVE
-        } else {
-            self.F = self.F & !MASK_FLAG_NEGATIVE
-        }
+        self.assign_flag(Flag::N, set)
     }
 
     /// Convenience function to set or reset the zero flag.
@@ -450,13 +616,97 @@ impl Registers {
     /// assert_eq!(false, registers.is_zero());
     /// ```
     pub fn set_zero(&mut self, set: bool) {
-        if set {
-            self.F = self.F | MASK_FLAG_ZERO
-        } else {
-            self.F = self.F & !MASK_FLAG_ZERO
+        self.assign_flag(Flag::Z, set)
+    }
+
+    /// Starts recording which registers are written, e.g. to highlight the registers an
+    /// instruction changed. Writes to the public `PC` and `SP` fields can't be recorded, those
+    /// registers are dirty if they differ from their values at the last `clear_dirty`.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::registers::*;
+    /// let mut registers = Registers::new();
+    /// registers.enable_dirty_tracking();
+    ///
+    /// registers.set_single(&SingleRegister::H, 0x12);
+    /// registers.PC = 0x0001;
+    ///
+    /// assert!(registers.is_single_dirty(&SingleRegister::H));
+    /// assert!(!registers.is_single_dirty(&SingleRegister::L));
+    /// assert!(registers.is_double_dirty(&DoubleRegister::HL));
+    /// assert!(registers.is_double_dirty(&DoubleRegister::PC));
+    ///
+    /// registers.clear_dirty();
+    /// assert!(!registers.is_double_dirty(&DoubleRegister::HL));
+    /// ```
+    pub fn enable_dirty_tracking(&mut self) {
+        self.dirty = Some(DirtyTracking {
+            mask: 0,
+            pc: self.PC,
+            sp: self.SP,
+        });
+    }
+
+    /// Stops recording which registers are written.
+    pub fn disable_dirty_tracking(&mut self) {
+        self.dirty = None;
+    }
+
+    /// Returns `true` if dirty tracking is enabled.
+    pub fn is_dirty_tracking(&self) -> bool {
+        self.dirty.is_some()
+    }
+
+    /// Marks every register clean.
+    pub fn clear_dirty(&mut self) {
+        if self.dirty.is_some() {
+            self.enable_dirty_tracking();
+        }
+    }
+
+    /// Returns `true` if `r` was written since the last `clear_dirty`, always `false` unless
+    /// dirty tracking is enabled.
+    pub fn is_single_dirty(&self, r: &SingleRegister) -> bool {
+        self.dirty_mask() & dirty_bit(r) != 0
+    }
+
+    /// Returns `true` if either half of `r` was written since the last `clear_dirty`, or for `SP`
+    /// and `PC` if they were written or changed. Always `false` unless dirty tracking is enabled.
+    pub fn is_double_dirty(&self, r: &DoubleRegister) -> bool {
+        self.dirty_mask() & dirty_bits(r) != 0
+    }
+
+    fn dirty_mask(&self) -> u16 {
+        match self.dirty {
+            Some(dirty) => {
+                let mut mask = dirty.mask;
+                if self.SP != dirty.sp {
+                    mask |= dirty_bits(&DoubleRegister::SP);
+                }
+                if self.PC != dirty.pc {
+                    mask |= dirty_bits(&DoubleRegister::PC);
+                }
+                mask
+            }
+            None => 0,
+        }
+    }
+
+    fn mark_dirty(&mut self, bits: u16) {
+        if let Some(dirty) = self.dirty.as_mut() {
+            dirty.mask |= bits;
         }
     }
 
+    /// Checks that the register state is one the hardware could be in.
+    #[cfg(any(test, feature = "paranoid"))]
+    pub(crate) fn check_invariants(&self) -> Result<(), String> {
+        if self.F & 0x0F != 0 {
+            return Err(format!("low nibble of F is set: {:02x}", self.F));
+        }
+        Ok(())
+    }
+
     #[cfg(test)]
     pub fn clear(&mut self) {
         self.A = 0;
@@ -471,21 +721,166 @@ impl Registers {
         self.PC = 0;
         self.SP = 0xFFFE
     }
+
+    /// Returns every register which changed since `before`, 8-bit registers first and `SP` and
+    /// `PC` last.
+    ///
+    /// ```
+    /// # use gejmboj_cpu::registers::*;
+    /// let before = Registers::new();
+    /// let mut after = before.clone();
+    ///
+    /// after.set_single(&SingleRegister::B, 0x12);
+    /// after.PC = 0x0001;
+    ///
+    /// assert_eq!(
+    ///     vec![
+    ///         RegisterChange::Single {
+    ///             register: SingleRegister::B,
+    ///             before: 0x00,
+    ///             after: 0x12,
+    ///         },
+    ///         RegisterChange::Double {
+    ///             register: DoubleRegister::PC,
+    ///             before: 0x0000,
+    ///             after: 0x0001,
+    ///         },
+    ///     ],
+    ///     after.diff(&before)
+    /// );
+    /// ```
+    pub fn diff(&self, before: &Registers) -> Vec<RegisterChange> {
+        let singles = DIFF_SINGLE_REGISTERS.iter().filter_map(|register| {
+            let (before, after) = (before.get_single(register), self.get_single(register));
+            (before != after).then_some(RegisterChange::Single {
+                register: *register,
+                before,
+                after,
+            })
+        });
+        let doubles = DIFF_DOUBLE_REGISTERS.iter().filter_map(|register| {
+            let (before, after) = (before.get_double(register), self.get_double(register));
+            (before != after).then_some(RegisterChange::Double {
+                register: *register,
+                before,
+                after,
+            })
+        });
+        singles.chain(doubles).collect()
+    }
+}
+
+/// Returns the bit of `r` in `DirtyTracking::mask`.
+fn dirty_bit(r: &SingleRegister) -> u16 {
+    match r {
+        SingleRegister::A => 1 << 0,
+        SingleRegister::F => 1 << 1,
+        SingleRegister::B => 1 << 2,
+        SingleRegister::C => 1 << 3,
+        SingleRegister::D => 1 << 4,
+        SingleRegister::E => 1 << 5,
+        SingleRegister::H => 1 << 6,
+        SingleRegister::L => 1 << 7,
+    }
 }
 
+/// Returns the bits of both halves of `r` in `DirtyTracking::mask`.
+fn dirty_bits(r: &DoubleRegister) -> u16 {
+    match r {
+        DoubleRegister::AF => dirty_bit(&SingleRegister::A) | dirty_bit(&SingleRegister::F),
+        DoubleRegister::BC => dirty_bit(&SingleRegister::B) | dirty_bit(&SingleRegister::C),
+        DoubleRegister::DE => dirty_bit(&SingleRegister::D) | dirty_bit(&SingleRegister::E),
+        DoubleRegister::HL => dirty_bit(&SingleRegister::H) | dirty_bit(&SingleRegister::L),
+        DoubleRegister::SP => 1 << 8,
+        DoubleRegister::PC => 1 << 9,
+    }
+}
+
+/// A register which differs between two `Registers`, see `Registers::diff`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RegisterChange {
+    Single {
+        register: SingleRegister,
+        before: u8,
+        after: u8,
+    },
+    Double {
+        register: DoubleRegister,
+        before: u16,
+        after: u16,
+    },
+}
+
+/// The registers compared by `Registers::diff`, in the order changes are reported.
+const DIFF_SINGLE_REGISTERS: [SingleRegister; 8] = [
+    SingleRegister::A,
+    SingleRegister::F,
+    SingleRegister::B,
+    SingleRegister::C,
+    SingleRegister::D,
+    SingleRegister::E,
+    SingleRegister::H,
+    SingleRegister::L,
+];
+const DIFF_DOUBLE_REGISTERS: [DoubleRegister; 2] = [DoubleRegister::SP, DoubleRegister::PC];
+
+/// The flags in the order displayed.
+const FLAGS: [(Flag, char); 4] = [
+    (Flag::Z, 'Z'),
+    (Flag::N, 'N'),
+    (Flag::H, 'H'),
+    (Flag::C, 'C'),
+];
+
 impl Display for Registers {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [z, n, h, c] = FLAGS.map(|(flag, name)| match self.get_flag(flag) {
+            true => name,
+            false => '-',
+        });
+        let af = self.get_double(&DoubleRegister::AF);
+        let bc = self.get_double(&DoubleRegister::BC);
+        let de = self.get_double(&DoubleRegister::DE);
+        let hl = self.get_double(&DoubleRegister::HL);
+
+        if f.alternate() {
+            return write!(
+                f,
+                "PC:{:04x} SP:{:04x} AF:{:04x} BC:{:04x} DE:{:04x} HL:{:04x} {}{}{}{}",
+                self.PC, self.SP, af, bc, de, hl, z, n, h, c
+            );
+        }
+
         write!(
             f,
             "
-PC:{:04x?} SP:{:04x?}
+PC:{:04x} SP:{:04x}
+
+ A:{:02x} {:02x}:F  AF:{:04x}
+ B:{:02x} {:02x}:C  BC:{:04x}
+ D:{:02x} {:02x}:E  DE:{:04x}
+ H:{:02x} {:02x}:L  HL:{:04x}
 
- A:{:02x?} {:02x?}:F
- B:{:02x?} {:02x?}:C
- D:{:02x?} {:02x?}:E
- H:{:02x?} {:02x?}:L
+ Flags: {} {} {} {}
 ",
-            self.PC, self.SP, self.A, self.F, self.B, self.C, self.D, self.E, self.H, self.L
+            self.PC,
+            self.SP,
+            self.A,
+            self.F,
+            af,
+            self.B,
+            self.C,
+            bc,
+            self.D,
+            self.E,
+            de,
+            self.H,
+            self.L,
+            hl,
+            z,
+            n,
+            h,
+            c
         )
     }
 }
@@ -503,6 +898,12 @@ pub enum SingleRegister {
     L,
 }
 
+impl Display for SingleRegister {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
 impl From<(u8, u8, u8)> for SingleRegister {
     fn from(x: (u8, u8, u8)) -> Self {
         match (x.0 > 0, x.1 > 0, x.2 > 0) {
@@ -536,14 +937,72 @@ impl TryFrom<u8> for SingleRegister {
     }
 }
 
-/// Represents a 16-bit general purpose register.
-#[derive(Debug, PartialEq)]
+/// Parses a register name like `A` or `l`, ignoring case.
+///
+/// ```
+/// # use gejmboj_cpu::registers::SingleRegister;
+/// assert_eq!(Ok(SingleRegister::A), "a".parse());
+/// assert!("AF".parse::<SingleRegister>().is_err());
+/// ```
+impl FromStr for SingleRegister {
+    type Err = CpuError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "A" => Ok(SingleRegister::A),
+            "B" => Ok(SingleRegister::B),
+            "C" => Ok(SingleRegister::C),
+            "D" => Ok(SingleRegister::D),
+            "E" => Ok(SingleRegister::E),
+            "F" => Ok(SingleRegister::F),
+            "H" => Ok(SingleRegister::H),
+            "L" => Ok(SingleRegister::L),
+            _ => Err(CpuError::Error(format!("Unknown 8-bit register '{}'", s))),
+        }
+    }
+}
+
+/// Represents a 16-bit register.
+///
+/// `PC` is never encoded in an opcode, it's here so `get_double` and `set_double` cover every
+/// 16-bit register.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum DoubleRegister {
     AF,
     BC,
     DE,
     HL,
     SP,
+    PC,
+}
+
+impl Display for DoubleRegister {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// Parses a register name like `HL` or `sp`, ignoring case.
+///
+/// ```
+/// # use gejmboj_cpu::registers::DoubleRegister;
+/// assert_eq!(Ok(DoubleRegister::SP), "sp".parse());
+/// assert_eq!(Ok(DoubleRegister::PC), "PC".parse());
+/// ```
+impl FromStr for DoubleRegister {
+    type Err = CpuError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "AF" => Ok(DoubleRegister::AF),
+            "BC" => Ok(DoubleRegister::BC),
+            "DE" => Ok(DoubleRegister::DE),
+            "HL" => Ok(DoubleRegister::HL),
+            "SP" => Ok(DoubleRegister::SP),
+            "PC" => Ok(DoubleRegister::PC),
+            _ => Err(CpuError::Error(format!("Unknown 16-bit register '{}'", s))),
+        }
+    }
 }
 
 impl From<(u8, u8, u8)> for DoubleRegister {
@@ -557,3 +1016,106 @@ impl From<(u8, u8, u8)> for DoubleRegister {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dirty_tracking_records_writes() {
+        let mut registers = Registers::new();
+        registers.set_single(&SingleRegister::B, 0x01);
+        assert!(!registers.is_single_dirty(&SingleRegister::B));
+
+        registers.enable_dirty_tracking();
+        registers.set_carry(false);
+        registers.decrement_sp();
+        registers.set_double(&DoubleRegister::DE, 0);
+
+        assert!(registers.is_single_dirty(&SingleRegister::F));
+        assert!(!registers.is_single_dirty(&SingleRegister::A));
+        assert!(registers.is_double_dirty(&DoubleRegister::AF));
+        assert!(registers.is_double_dirty(&DoubleRegister::SP));
+        assert!(registers.is_single_dirty(&SingleRegister::E));
+        assert!(!registers.is_double_dirty(&DoubleRegister::PC));
+    }
+
+    #[test]
+    fn dirty_tracking_is_ignored_when_comparing() {
+        let mut tracked = Registers::new();
+        tracked.enable_dirty_tracking();
+        tracked.set_single(&SingleRegister::A, 0x01);
+        let mut untracked = Registers::new();
+        untracked.set_single(&SingleRegister::A, 0x01);
+
+        assert_eq!(untracked, tracked);
+    }
+
+    #[test]
+    fn registers_parse_from_names_ignoring_case() {
+        for register in [SingleRegister::A, SingleRegister::F, SingleRegister::L] {
+            assert_eq!(Ok(register), register.to_string().to_lowercase().parse());
+        }
+        for register in [DoubleRegister::AF, DoubleRegister::HL, DoubleRegister::PC] {
+            assert_eq!(Ok(register), register.to_string().to_lowercase().parse());
+        }
+
+        assert!("X".parse::<SingleRegister>().is_err());
+        assert!("A".parse::<DoubleRegister>().is_err());
+        assert!("".parse::<DoubleRegister>().is_err());
+    }
+
+    #[test]
+    fn display_shows_pairs_and_flags() {
+        let registers = Registers::new_post_boot(Model::Dmg);
+
+        assert_eq!(
+            "
+PC:0100 SP:fffe
+
+ A:01 b0:F  AF:01b0
+ B:00 13:C  BC:0013
+ D:00 d8:E  DE:00d8
+ H:01 4d:L  HL:014d
+
+ Flags: Z - H C
+",
+            registers.to_string()
+        );
+    }
+
+    #[test]
+    fn pc_is_a_double_register() {
+        let mut registers = Registers::new();
+
+        registers.set_double(&DoubleRegister::PC, 0x1234);
+
+        assert_eq!(0x1234, registers.PC);
+        assert_eq!(0x1234, registers.get_double(&DoubleRegister::PC));
+    }
+
+    #[test]
+    fn check_invariants_rejects_low_nibble_of_f() {
+        let mut registers = Registers::new();
+        assert_eq!(Ok(()), registers.check_invariants());
+
+        registers.F = 0x01;
+        assert!(registers.check_invariants().is_err());
+    }
+}
+
+#[cfg(feature = "serde")]
+#[cfg(test)]
+mod serde_tests {
+    use super::*;
+
+    #[test]
+    fn registers_round_trip() {
+        let registers = Registers::new_post_boot(Model::Cgb);
+
+        let json = serde_json::to_string(&registers).unwrap();
+        let restored: Registers = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(registers, restored);
+    }
+}
@@ -0,0 +1,325 @@
+//! # Renderer
+//!
+//! Renders the picture into a `Framebuffer`, one line at a time when the PPU begins drawing it,
+//! i.e. with the registers as they are at the start of mode 3. LCDC (`FF40`) selects the layers:
+//!
+//! ```asciidoc
+//! Bit 6: Window tile map, 0 = 9800-9BFF, 1 = 9C00-9FFF
+//! Bit 5: Window enable
+//! Bit 4: Tile data, 0 = 8800-97FF (signed indexes), 1 = 8000-8FFF
+//! Bit 3: Background tile map, 0 = 9800-9BFF, 1 = 9C00-9FFF
+//! Bit 2: Object size, 0 = 8x8, 1 = 8x16
+//! Bit 1: Object enable
+//! Bit 0: Background and window enable
+//! ```
+//!
+//! The background is scrolled by SCY/SCX (`FF42`/`FF43`), the window is placed at WY/WX
+//! (`FF4A`/`FF4B`, offset by 7) and keeps its own line counter. Up to 10 objects are drawn per
+//! line, the one with the smaller X coordinate, then the one earlier in OAM, wins.
+//!
+//! While the LCD shows no picture, see `Ppu::is_blank`, the framebuffer is white.
+//!
+//! Colors are mapped through BGP, OBP0 and OBP1 (`FF47-FF49`) to four shades of gray, also on
+//! CGB where the color palettes and tile attributes are not applied yet.
+//!
+//! ```
+//! # use gejmboj_cpu::{memory::{Memory, MemoryBus}, renderer::{SHADES, WIDTH}};
+//! let mut memory = Memory::new();
+//! // Tile 1 in the top left corner has a black top row
+//! memory.set(0x9800, 1);
+//! memory.set(0x8010, 0xFF);
+//! memory.set(0x8011, 0xFF);
+//! memory.set(0xFF47, 0xE4);
+//! // Turned on without blanking the first frame
+//! memory.io_mut().set_raw(0xFF40, 0x91);
+//!
+//! memory.step(20);
+//!
+//! let pixels = memory.framebuffer().pixels();
+//! assert_eq!(SHADES[3], pixels[0..4]);
+//! assert_eq!(SHADES[0], pixels[8 * 4..9 * 4]);
+//! assert_eq!(0xFF, pixels[WIDTH * 4]);
+//! ```
+
+use crate::{
+    io::{Io, REGISTER_LCDC},
+    vram::Vram,
+};
+
+/// Width of the picture in pixels.
+pub const WIDTH: usize = 160;
+
+/// Height of the picture in pixels.
+pub const HEIGHT: usize = 144;
+
+/// The four shades, from white to black, as RGBA.
+pub const SHADES: [[u8; 4]; 4] = [
+    [0xFF, 0xFF, 0xFF, 0xFF],
+    [0xAA, 0xAA, 0xAA, 0xFF],
+    [0x55, 0x55, 0x55, 0xFF],
+    [0x00, 0x00, 0x00, 0xFF],
+];
+
+const REGISTER_SCY: u16 = 0xFF42;
+const REGISTER_SCX: u16 = 0xFF43;
+const REGISTER_BGP: u16 = 0xFF47;
+const REGISTER_OBP0: u16 = 0xFF48;
+const REGISTER_OBP1: u16 = 0xFF49;
+const REGISTER_WY: u16 = 0xFF4A;
+const REGISTER_WX: u16 = 0xFF4B;
+
+const OBJECTS: usize = 40;
+const OBJECTS_PER_LINE: usize = 10;
+
+/// The rendered picture, `WIDTH` x `HEIGHT` pixels as RGBA.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Framebuffer {
+    pixels: Vec<u8>,
+    /// Line of the window drawn next
+    window_line: u8,
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Framebuffer {
+    /// Creates a white framebuffer.
+    pub fn new() -> Self {
+        Self {
+            pixels: SHADES[0].repeat(WIDTH * HEIGHT),
+            window_line: 0,
+        }
+    }
+
+    /// Returns the pixels row by row, 4 bytes per pixel.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Fills the framebuffer with white.
+    pub(crate) fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Renders `line` from the registers in `io`, `vram` and `oam` (`FE00-FE9F`).
+    pub(crate) fn render_line(&mut self, line: u8, io: &Io, vram: &Vram, oam: &[u8]) {
+        let lcdc = io.read(REGISTER_LCDC);
+        if line == 0 {
+            self.window_line = 0;
+        }
+
+        let mut colors = [0; WIDTH];
+        if lcdc & 0x01 > 0 {
+            self.render_background(&mut colors, line, lcdc, io, vram);
+        }
+        let palette = io.read(REGISTER_BGP);
+        for (x, color) in colors.iter().enumerate() {
+            self.set_pixel(x, line, shade(palette, *color));
+        }
+
+        if lcdc & 0x02 > 0 {
+            self.render_objects(&colors, line, lcdc, io, vram, oam);
+        }
+    }
+
+    /// Writes the color indexes of the background and window of `line` to `colors`.
+    fn render_background(
+        &mut self,
+        colors: &mut [u8; WIDTH],
+        line: u8,
+        lcdc: u8,
+        io: &Io,
+        vram: &Vram,
+    ) {
+        let wx = io.read(REGISTER_WX) as usize;
+        let window = lcdc & 0x20 > 0 && line >= io.read(REGISTER_WY) && wx < WIDTH + 7;
+        let y = line.wrapping_add(io.read(REGISTER_SCY));
+
+        for (x, color) in colors.iter_mut().enumerate() {
+            *color = match window && x + 7 >= wx {
+                true => tile_color(
+                    vram,
+                    lcdc,
+                    lcdc & 0x40 > 0,
+                    (x + 7 - wx) as u8,
+                    self.window_line,
+                ),
+                false => {
+                    let x = (x as u8).wrapping_add(io.read(REGISTER_SCX));
+                    tile_color(vram, lcdc, lcdc & 0x08 > 0, x, y)
+                }
+            };
+        }
+
+        if window {
+            self.window_line += 1;
+        }
+    }
+
+    /// Draws the objects on `line` over the `background` color indexes.
+    fn render_objects(
+        &mut self,
+        background: &[u8; WIDTH],
+        line: u8,
+        lcdc: u8,
+        io: &Io,
+        vram: &Vram,
+        oam: &[u8],
+    ) {
+        let height = if lcdc & 0x04 > 0 { 16 } else { 8 };
+        let mut objects: Vec<&[u8]> = oam
+            .chunks(4)
+            .take(OBJECTS)
+            .filter(|object| {
+                let top = object[0] as i16 - 16;
+                (top..top + height).contains(&(line as i16))
+            })
+            .take(OBJECTS_PER_LINE)
+            .collect();
+        // Drawn back to front, stable so earlier objects stay in front
+        objects.sort_by_key(|object| object[1]);
+
+        for object in objects.iter().rev() {
+            let [y, x, tile, flags] = [object[0], object[1], object[2], object[3]];
+            let mut row = (line as i16 - (y as i16 - 16)) as u16;
+            if flags & 0x40 > 0 {
+                row = height as u16 - 1 - row;
+            }
+            let tile = match height {
+                16 => tile & 0xFE,
+                _ => tile,
+            };
+            let address = 0x8000 + tile as u16 * 16 + row * 2;
+            let palette = match flags & 0x10 > 0 {
+                true => io.read(REGISTER_OBP1),
+                false => io.read(REGISTER_OBP0),
+            };
+
+            for column in 0..8 {
+                let screen_x = x as i16 - 8 + column;
+                if !(0..WIDTH as i16).contains(&screen_x) {
+                    continue;
+                }
+                let bit = match flags & 0x20 > 0 {
+                    true => column as u8,
+                    false => 7 - column as u8,
+                };
+                let color = pixel(vram, address, bit);
+                let hidden = flags & 0x80 > 0 && background[screen_x as usize] > 0;
+                if color > 0 && !hidden {
+                    self.set_pixel(screen_x as usize, line, shade(palette, color));
+                }
+            }
+        }
+    }
+
+    fn set_pixel(&mut self, x: usize, line: u8, shade: u8) {
+        let offset = (line as usize * WIDTH + x) * 4;
+        self.pixels[offset..offset + 4].copy_from_slice(&SHADES[shade as usize]);
+    }
+}
+
+/// Returns the color index at `x`, `y` of the tile map selected by `high_map`.
+fn tile_color(vram: &Vram, lcdc: u8, high_map: bool, x: u8, y: u8) -> u8 {
+    let map = if high_map { 0x9C00 } else { 0x9800 };
+    let tile = vram.read(0, map + (y as u16 / 8) * 32 + x as u16 / 8);
+    let tile_address = match lcdc & 0x10 > 0 {
+        true => 0x8000 + tile as u16 * 16,
+        false => (0x9000 + tile as i8 as i32 * 16) as u16,
+    };
+    pixel(vram, tile_address + (y as u16 % 8) * 2, 7 - x % 8)
+}
+
+/// Returns the color index of `bit` in the tile row at `address`.
+fn pixel(vram: &Vram, address: u16, bit: u8) -> u8 {
+    let low = vram.read(0, address) >> bit & 0x01;
+    let high = vram.read(0, address + 1) >> bit & 0x01;
+    high << 1 | low
+}
+
+/// Maps a color index through `palette` to a shade.
+fn shade(palette: u8, color: u8) -> u8 {
+    palette >> (color * 2) & 0x03
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use super::*;
+
+    fn io(lcdc: u8) -> Io {
+        let mut io = Io::new();
+        io.set_raw(REGISTER_LCDC, lcdc);
+        io.write(REGISTER_BGP, 0xE4);
+        io.write(REGISTER_OBP0, 0xE4);
+        io
+    }
+
+    fn shade_at(framebuffer: &Framebuffer, x: usize, line: usize) -> [u8; 4] {
+        let offset = (line * WIDTH + x) * 4;
+        framebuffer.pixels()[offset..offset + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn background_is_scrolled() {
+        let mut io = io(0x91);
+        let mut vram = Vram::new();
+        // Tile 1 at map position (1, 1) has a dark gray top row
+        vram.write(0, 0x9821, 1);
+        vram.write(0, 0x8011, 0xFF);
+        io.write(REGISTER_SCX, 4);
+        io.write(REGISTER_SCY, 8);
+        let mut framebuffer = Framebuffer::new();
+
+        framebuffer.render_line(0, &io, &vram, &[0; 0xA0]);
+
+        assert_eq!(SHADES[0], shade_at(&framebuffer, 3, 0));
+        assert_eq!(SHADES[2], shade_at(&framebuffer, 4, 0));
+        assert_eq!(SHADES[2], shade_at(&framebuffer, 11, 0));
+        assert_eq!(SHADES[0], shade_at(&framebuffer, 12, 0));
+    }
+
+    #[test]
+    fn window_covers_the_background_from_wx() {
+        let mut io = io(0xF1);
+        let mut vram = Vram::new();
+        // Window map at 9C00 uses tile 1, filled black
+        vram.write(0, 0x9C00, 1);
+        vram.write(0, 0x8010, 0xFF);
+        vram.write(0, 0x8011, 0xFF);
+        io.write(REGISTER_WX, 7 + 80);
+        io.write(REGISTER_WY, 0);
+        let mut framebuffer = Framebuffer::new();
+
+        framebuffer.render_line(0, &io, &vram, &[0; 0xA0]);
+
+        assert_eq!(SHADES[0], shade_at(&framebuffer, 79, 0));
+        assert_eq!(SHADES[3], shade_at(&framebuffer, 80, 0));
+        assert_eq!(1, framebuffer.window_line);
+    }
+
+    #[test]
+    fn objects_with_smaller_x_are_in_front() {
+        let io = io(0x93);
+        let mut vram = Vram::new();
+        // Tile 1 has a light gray top row, tile 2 a black one
+        vram.write(0, 0x8010, 0xFF);
+        vram.write(0, 0x8020, 0xFF);
+        vram.write(0, 0x8021, 0xFF);
+        let mut oam = [0; 0xA0];
+        oam[0..4].copy_from_slice(&[16, 12, 1, 0]);
+        oam[4..8].copy_from_slice(&[16, 8, 2, 0]);
+        let mut framebuffer = Framebuffer::new();
+
+        framebuffer.render_line(0, &io, &vram, &oam);
+
+        assert_eq!(SHADES[3], shade_at(&framebuffer, 0, 0));
+        assert_eq!(SHADES[3], shade_at(&framebuffer, 7, 0));
+        assert_eq!(SHADES[1], shade_at(&framebuffer, 8, 0));
+        assert_eq!(SHADES[0], shade_at(&framebuffer, 12, 0));
+    }
+}
@@ -0,0 +1,256 @@
+//! # Rewind
+//!
+//! `Rewind` captures save states of the machine at a fixed interval of machine cycles and can
+//! step the machine back through them, newest first.
+//!
+//! Only the newest snapshot is kept as a complete save state. Every older snapshot is stored as
+//! the difference to the snapshot following it: the bytes of both are XORed and the runs of
+//! zeros, i.e. unchanged bytes, are dropped. Consecutive snapshots mostly differ in a few
+//! hundred bytes of RAM, so a snapshot usually costs far less than a full save state.
+//!
+//! When the snapshots exceed the memory budget the oldest are discarded.
+//!
+//! ```
+//! # use gejmboj_cpu::{cpu::CPU, memory::Memory, registers::Registers, rewind::Rewind};
+//! let mut cpu = CPU::new();
+//! let mut registers = Registers::new();
+//! let mut memory = Memory::new();
+//! let mut rewind = Rewind::new(1, 1024 * 1024);
+//!
+//! rewind.record(&cpu, &registers, &memory);
+//! cpu.tick(&mut registers, &mut memory).unwrap();
+//! assert_eq!(1, registers.PC);
+//!
+//! rewind.step_back(&mut cpu, &mut registers, &mut memory).unwrap();
+//! assert_eq!(0, registers.PC);
+//! ```
+
+use std::{collections::VecDeque, convert::TryInto};
+
+use crate::{cpu::CPU, errors::CpuError, memory::Memory, registers::Registers, savestate};
+
+/// A rewind buffer, see module documentation.
+pub struct Rewind {
+    interval: u64,
+    budget: usize,
+    last_capture: Option<u64>,
+    newest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl Rewind {
+    /// Creates a rewind buffer capturing a snapshot every `interval` machine cycles, using at most
+    /// `budget` bytes.
+    ///
+    /// The newest snapshot is always kept, even if it alone exceeds the budget.
+    pub fn new(interval: u64, budget: usize) -> Self {
+        Self {
+            interval,
+            budget,
+            last_capture: None,
+            newest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    /// Captures a snapshot if `interval` machine cycles have passed since the last one.
+    ///
+    /// Call after every instruction, returns `true` if a snapshot was captured.
+    pub fn record(&mut self, cpu: &CPU, registers: &Registers, memory: &Memory) -> bool {
+        let due = match self.last_capture {
+            Some(last) => cpu.cycles() >= last + self.interval,
+            None => true,
+        };
+
+        if due {
+            self.capture(cpu, registers, memory);
+        }
+
+        due
+    }
+
+    /// Captures a snapshot regardless of the interval.
+    pub fn capture(&mut self, cpu: &CPU, registers: &Registers, memory: &Memory) {
+        let state = savestate::save(cpu, registers, memory);
+
+        if let Some(previous) = self.newest.replace(state) {
+            let delta = encode(&previous, self.newest.as_ref().unwrap());
+            self.deltas.push_back(delta);
+        }
+
+        self.last_capture = Some(cpu.cycles());
+
+        while self.memory_usage() > self.budget && self.deltas.pop_front().is_some() {}
+    }
+
+    /// Restores the newest snapshot and removes it, returns `false` if there is none.
+    pub fn step_back(
+        &mut self,
+        cpu: &mut CPU,
+        registers: &mut Registers,
+        memory: &mut Memory,
+    ) -> Result<bool, CpuError> {
+        let state = match self.newest.take() {
+            Some(state) => state,
+            None => return Ok(false),
+        };
+
+        savestate::load(&state, cpu, registers, memory)?;
+
+        self.newest = self.deltas.pop_back().map(|delta| decode(&state, &delta));
+        self.last_capture = Some(cpu.cycles());
+
+        Ok(true)
+    }
+
+    /// Returns the number of snapshots.
+    pub fn len(&self) -> usize {
+        self.newest.iter().count() + self.deltas.len()
+    }
+
+    /// Returns `true` if there are no snapshots.
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    /// Returns the number of bytes used by the snapshots.
+    pub fn memory_usage(&self) -> usize {
+        self.newest.as_ref().map_or(0, Vec::len) + self.deltas.iter().map(Vec::len).sum::<usize>()
+    }
+
+    /// Removes all snapshots.
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.last_capture = None;
+    }
+}
+
+/// Encodes `previous` relative to `next` as runs of unchanged bytes (`u32` length) followed by
+/// runs of XORed changed bytes (`u32` length and bytes).
+fn encode(previous: &[u8], next: &[u8]) -> Vec<u8> {
+    let xored: Vec<u8> = previous.iter().zip(next).map(|(a, b)| a ^ b).collect();
+    let mut delta = Vec::new();
+    let mut position = 0;
+
+    while position < xored.len() {
+        let unchanged = xored[position..].iter().take_while(|&&x| x == 0).count();
+        position += unchanged;
+        let changed = xored[position..].iter().take_while(|&&x| x != 0).count();
+
+        delta.extend((unchanged as u32).to_le_bytes());
+        delta.extend((changed as u32).to_le_bytes());
+        delta.extend(&xored[position..position + changed]);
+        position += changed;
+    }
+
+    delta
+}
+
+/// Restores the state encoded by `encode` relative to `next`.
+fn decode(next: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut previous = next.to_vec();
+    let mut position = 0;
+    let mut rest = delta;
+
+    while !rest.is_empty() {
+        let unchanged = u32::from_le_bytes(rest[0..4].try_into().unwrap()) as usize;
+        let changed = u32::from_le_bytes(rest[4..8].try_into().unwrap()) as usize;
+        position += unchanged;
+
+        for (byte, x) in previous[position..position + changed]
+            .iter_mut()
+            .zip(&rest[8..8 + changed])
+        {
+            *byte ^= x;
+        }
+
+        position += changed;
+        rest = &rest[8 + changed..];
+    }
+
+    previous
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine() -> (CPU, Registers, Memory) {
+        let mut memory = Memory::new();
+        // INC A, repeated
+        memory.load(0x0000, &[0x3C; 0x100]);
+
+        (CPU::new(), Registers::new(), memory)
+    }
+
+    #[test]
+    fn deltas_round_trip() {
+        let previous = [0, 1, 2, 3, 0, 0, 7, 8];
+        let next = [0, 1, 5, 3, 0, 9, 7, 0];
+
+        assert_eq!(previous.to_vec(), decode(&next, &encode(&previous, &next)));
+        assert_eq!(next.to_vec(), decode(&previous, &encode(&next, &previous)));
+    }
+
+    #[test]
+    fn snapshots_are_captured_every_interval() {
+        let (mut cpu, mut registers, mut memory) = machine();
+        let mut rewind = Rewind::new(3, usize::MAX);
+
+        for _ in 0..7 {
+            rewind.record(&cpu, &registers, &memory);
+            cpu.tick(&mut registers, &mut memory).unwrap();
+        }
+
+        // Cycles 0, 3, 6
+        assert_eq!(3, rewind.len());
+    }
+
+    #[test]
+    fn steps_back_through_snapshots_newest_first() {
+        let (mut cpu, mut registers, mut memory) = machine();
+        let mut rewind = Rewind::new(1, usize::MAX);
+
+        for _ in 0..5 {
+            rewind.record(&cpu, &registers, &memory);
+            cpu.tick(&mut registers, &mut memory).unwrap();
+            memory.set(0xC000 + registers.PC as usize, 0xAB);
+        }
+
+        for pc in (0..5).rev() {
+            assert!(rewind
+                .step_back(&mut cpu, &mut registers, &mut memory)
+                .unwrap());
+            assert_eq!(pc, registers.PC);
+            assert_eq!(pc as u64, cpu.cycles());
+            assert_eq!(0x00, memory.get(0xC001 + pc as usize));
+        }
+
+        assert!(rewind.is_empty());
+        assert!(!rewind
+            .step_back(&mut cpu, &mut registers, &mut memory)
+            .unwrap());
+    }
+
+    #[test]
+    fn oldest_snapshots_are_dropped_over_budget() {
+        let (mut cpu, mut registers, mut memory) = machine();
+        let full = savestate::save(&cpu, &registers, &memory).len();
+        let mut rewind = Rewind::new(1, full + 200);
+
+        for _ in 0..20 {
+            rewind.record(&cpu, &registers, &memory);
+            cpu.tick(&mut registers, &mut memory).unwrap();
+        }
+
+        assert!(rewind.memory_usage() <= full + 200);
+        assert!(rewind.len() > 1 && rewind.len() < 20);
+
+        while rewind
+            .step_back(&mut cpu, &mut registers, &mut memory)
+            .unwrap()
+        {}
+        assert!(registers.PC > 0);
+    }
+}
@@ -0,0 +1,289 @@
+//! # Save states
+//!
+//! `save` serializes the complete machine, i.e. CPU, registers, memory and peripherals, into a
+//! compact binary blob which `load` restores. All values are little-endian:
+//!
+//! ```asciidoc
+//! 0-3:   Magic, "GJMB"
+//! 4-5:   Format version, see `VERSION`
+//! 6-:    CPU: model, IME and HALT flags, machine cycles (u64)
+//!        Registers: AF, BC, DE, HL, SP, PC (u16)
+//!        Memory: model, revision, 64 KiB memory, I/O registers, palettes, timer and PPU, VRAM banks,
+//!        OAM DMA transfer
+//! ```
+//!
+//! Blobs are validated before anything is restored, a blob with a foreign magic, another
+//! version, the wrong length or invalid values is rejected with `CpuError::InvalidSaveState`.
+//!
+//! The cartridge is not part of the state, the memory keeps the cartridge it is connected to
+//! when loading. Memory bank controller registers and cartridge RAM are not saved.
+//!
+//! ```
+//! # use gejmboj_cpu::{cpu::CPU, memory::Memory, registers::Registers, savestate};
+//! let cpu = CPU::new();
+//! let mut registers = Registers::new();
+//! let mut memory = Memory::new();
+//! registers.PC = 0x0150;
+//! memory.set(0xC000, 0xAB);
+//!
+//! let state = savestate::save(&cpu, &registers, &memory);
+//!
+//! let mut restored_cpu = CPU::new();
+//! let mut restored_registers = Registers::new();
+//! let mut restored_memory = Memory::new();
+//! savestate::load(&state, &mut restored_cpu, &mut restored_registers, &mut restored_memory)
+//!     .unwrap();
+//!
+//! assert_eq!(0x0150, restored_registers.PC);
+//! assert_eq!(0xAB, restored_memory.get(0xC000));
+//! ```
+
+use std::convert::TryInto;
+
+use crate::{
+    cpu::{CpuFlags, CPU},
+    errors::CpuError,
+    memory::{Memory, Revision, MEMORY_STATE_SIZE},
+    model::Model,
+    registers::{DoubleRegister, Registers},
+};
+
+/// Identifies a save state blob.
+pub const MAGIC: [u8; 4] = *b"GJMB";
+
+/// Version of the save state format, increased on every incompatible change.
+pub const VERSION: u16 = 4;
+
+/// Size of a save state blob.
+const STATE_SIZE: usize =
+    MAGIC.len() + 2 + CPU_STATE_SIZE + REGISTERS_STATE_SIZE + MEMORY_STATE_SIZE;
+
+const CPU_STATE_SIZE: usize = 1 + 1 + 8;
+
+const REGISTERS_STATE_SIZE: usize = 6 * 2;
+
+const DOUBLE_REGISTERS: [DoubleRegister; 6] = [
+    DoubleRegister::AF,
+    DoubleRegister::BC,
+    DoubleRegister::DE,
+    DoubleRegister::HL,
+    DoubleRegister::SP,
+    DoubleRegister::PC,
+];
+
+/// Serializes the machine into a save state blob.
+pub fn save(cpu: &CPU, registers: &Registers, memory: &Memory) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    out.extend(VERSION.to_le_bytes());
+
+    out.push(cpu.model() as u8);
+    let flags = cpu.flags();
+    out.push(flags.IME as u8 | (flags.IME_scheduled as u8) << 1 | (flags.halted as u8) << 2);
+    out.extend(cpu.cycles().to_le_bytes());
+
+    for register in DOUBLE_REGISTERS.iter() {
+        out.extend(registers.get_double(register).to_le_bytes());
+    }
+
+    memory.write_state(&mut out);
+    out
+}
+
+/// Restores the machine from a save state blob created by `save`.
+///
+/// Nothing is changed if the blob is invalid.
+pub fn load(
+    state: &[u8],
+    cpu: &mut CPU,
+    registers: &mut Registers,
+    memory: &mut Memory,
+) -> Result<(), CpuError> {
+    let mut reader = Reader::new(state);
+
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(reader.invalid("magic"));
+    }
+
+    let version = reader.u16()?;
+    if version != VERSION {
+        return Err(CpuError::InvalidSaveState(format!(
+            "Unsupported version {}, expected {}",
+            version, VERSION
+        )));
+    }
+
+    if state.len() != STATE_SIZE {
+        return Err(CpuError::InvalidSaveState(format!(
+            "Expected {} bytes, got {}",
+            STATE_SIZE,
+            state.len()
+        )));
+    }
+
+    let model = model(reader.u8()?)?;
+    let ime = reader.u8()?;
+    if ime & 0b1111_1000 > 0 {
+        return Err(reader.invalid("IME flags"));
+    }
+    let flags = CpuFlags {
+        IME: ime & 0x01 > 0,
+        IME_scheduled: ime & 0x02 > 0,
+        halted: ime & 0x04 > 0,
+    };
+    let cycles = u64::from_le_bytes(reader.take(8)?.try_into().unwrap());
+
+    let mut restored = Registers::new();
+    for register in DOUBLE_REGISTERS.iter() {
+        let value = reader.u16()?;
+        if *register == DoubleRegister::AF && value & 0x000F > 0 {
+            return Err(reader.invalid("F register"));
+        }
+        restored.set_double(register, value);
+    }
+
+    memory.read_state(&mut reader)?;
+    cpu.restore(model, flags, cycles);
+    *registers = restored;
+
+    Ok(())
+}
+
+/// Reads values from a save state, failing on truncated input.
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    pub(crate) fn take(&mut self, length: usize) -> Result<&'a [u8], CpuError> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + length)
+            .ok_or_else(|| CpuError::InvalidSaveState("Truncated".to_string()))?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, CpuError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, CpuError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    /// Returns an error for an invalid `what` ending at the current position.
+    pub(crate) fn invalid(&self, what: &str) -> CpuError {
+        CpuError::InvalidSaveState(format!("Invalid {} before byte {}", what, self.position))
+    }
+}
+
+pub(crate) fn model(x: u8) -> Result<Model, CpuError> {
+    match x {
+        0 => Ok(Model::Dmg),
+        1 => Ok(Model::Mgb),
+        2 => Ok(Model::Sgb),
+        3 => Ok(Model::Cgb),
+        _ => Err(CpuError::InvalidSaveState(format!("Unknown model {}", x))),
+    }
+}
+
+pub(crate) fn revision(x: u8) -> Result<Revision, CpuError> {
+    match x {
+        0 => Ok(Revision::Dmg),
+        1 => Ok(Revision::CgbD),
+        2 => Ok(Revision::CgbE),
+        _ => Err(CpuError::InvalidSaveState(format!(
+            "Unknown revision {}",
+            x
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine() -> (CPU, Registers, Memory) {
+        let mut cpu = CPU::with_model(Model::Cgb);
+        let mut registers = Registers::new();
+        let mut memory = Memory::new();
+        memory.set_model(Model::Cgb);
+
+        // EI, HALT
+        memory.load(0x0000, &[0xFB, 0x76]);
+        cpu.tick(&mut registers, &mut memory).unwrap();
+        cpu.tick(&mut registers, &mut memory).unwrap();
+        registers.set_double(&DoubleRegister::BC, 0x1234);
+        memory.set(0xFF4F, 0x01);
+        memory.set(0x9800, 0xCD);
+        memory.set(0xFF46, 0xC0);
+        memory.step_dma(10);
+
+        (cpu, registers, memory)
+    }
+
+    #[test]
+    fn machine_round_trips() {
+        let (cpu, registers, memory) = machine();
+        let state = save(&cpu, &registers, &memory);
+
+        let mut restored_cpu = CPU::new();
+        let mut restored_registers = Registers::new();
+        let mut restored_memory = Memory::new();
+        load(
+            &state,
+            &mut restored_cpu,
+            &mut restored_registers,
+            &mut restored_memory,
+        )
+        .unwrap();
+
+        assert_eq!(Model::Cgb, restored_cpu.model());
+        assert_eq!(cpu.flags(), restored_cpu.flags());
+        assert!(restored_cpu.flags().halted);
+        assert_eq!(2, restored_cpu.cycles());
+        assert_eq!(registers, restored_registers);
+        assert_eq!(0xCD, restored_memory.vram().read(1, 0x9800));
+        assert!(restored_memory.is_dma_active());
+        assert!(restored_memory.diff(&memory.snapshot()).is_empty());
+        assert_eq!(
+            state,
+            save(&restored_cpu, &restored_registers, &restored_memory)
+        );
+    }
+
+    #[test]
+    fn invalid_states_are_rejected_without_changes() {
+        let (cpu, registers, memory) = machine();
+        let state = save(&cpu, &registers, &memory);
+
+        let mut foreign = state.clone();
+        foreign[0] = b'X';
+        let mut newer = state.clone();
+        newer[4] = VERSION as u8 + 1;
+        let mut truncated = state.clone();
+        truncated.pop();
+        let mut unknown_model = state.clone();
+        unknown_model[6] = 9;
+        let mut corrupt_flags = state.clone();
+        corrupt_flags[16] = 0x01;
+
+        for invalid in [foreign, newer, truncated, unknown_model, corrupt_flags].iter() {
+            let mut cpu = CPU::new();
+            let mut registers = Registers::new();
+            let mut memory = Memory::new();
+
+            let result = load(invalid, &mut cpu, &mut registers, &mut memory);
+
+            assert!(matches!(result, Err(CpuError::InvalidSaveState(_))));
+            assert_eq!(0, cpu.cycles());
+            assert_eq!(0, registers.PC);
+            assert_eq!(Model::Dmg, memory.model());
+        }
+    }
+}
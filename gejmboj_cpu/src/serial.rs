@@ -0,0 +1,215 @@
+//! # Serial port
+//!
+//! A byte is shifted out of SB (`FF01`) while the byte of the other Game Boy is shifted in, one
+//! bit per clock pulse. SC (`FF02`) starts the transfer and selects who drives the clock:
+//!
+//! ```asciidoc
+//! Bit 7: Transfer start, cleared when the transfer completes
+//! Bit 0: Clock, 0 = external (driven by the other Game Boy), 1 = internal (8192 Hz)
+//! ```
+//!
+//! On the internal clock a transfer takes 8 bits of 128 machine cycles each. On the external
+//! clock it completes whenever the other side transfers. Either way SB holds the received byte
+//! afterwards and the serial interrupt is requested.
+//!
+//! What is on the other end of the cable is a `SerialDevice`, attached with `Serial::attach`.
+//! Without a device the Game Boy receives `FF` and transfers on the external clock never
+//! complete. Transfers in progress are not part of save states.
+//!
+//! ```
+//! # use gejmboj_cpu::io::{Io, REGISTER_IF, REGISTER_SB, REGISTER_SC};
+//! # use gejmboj_cpu::serial::SerialDevice;
+//! /// Sends back the complement of every byte
+//! struct Inverter;
+//!
+//! impl SerialDevice for Inverter {
+//!     fn transfer(&mut self, byte: u8) -> u8 {
+//!         !byte
+//!     }
+//! }
+//!
+//! let mut io = Io::new();
+//! io.serial_mut().attach(Box::new(Inverter));
+//!
+//! io.write(REGISTER_SB, 0x0F);
+//! io.write(REGISTER_SC, 0x81);
+//! io.step(1024);
+//!
+//! assert_eq!(0xF0, io.read(REGISTER_SB));
+//! assert_eq!(0x7F, io.read(REGISTER_SC));
+//! assert_eq!(0xE8, io.read(REGISTER_IF));
+//! ```
+
+/// Transfer start bit of SC.
+pub const MASK_SC_START: u8 = 0b1000_0000;
+
+/// Internal clock bit of SC.
+pub const MASK_SC_INTERNAL_CLOCK: u8 = 0b0000_0001;
+
+/// Machine cycles per bit on the internal clock.
+pub const CYCLES_PER_BIT: u16 = 128;
+
+/// Machine cycles per byte on the internal clock.
+pub const CYCLES_PER_BYTE: u16 = CYCLES_PER_BIT * 8;
+
+/// The other end of the link cable.
+pub trait SerialDevice {
+    /// Exchanges `byte` with the device as the Game Boy drives the clock, returning the byte
+    /// shifted in.
+    fn transfer(&mut self, byte: u8) -> u8;
+
+    /// Polled once per bit period while the Game Boy doesn't drive the clock, to let the device
+    /// drive it instead. `byte` is SB if the Game Boy waits for a transfer on the external
+    /// clock, `None` if it isn't listening.
+    ///
+    /// Returns the byte shifted in if the device transferred, which is dropped unless the Game
+    /// Boy was listening. By default the device never drives the clock.
+    fn poll(&mut self, byte: Option<u8>) -> Option<u8> {
+        let _ = byte;
+        None
+    }
+}
+
+/// The serial port, see module documentation.
+#[derive(Default)]
+pub struct Serial {
+    device: Option<Box<dyn SerialDevice>>,
+    /// Received byte and remaining machine cycles of a transfer on the internal clock
+    transfer: Option<(u8, u16)>,
+    /// Machine cycles since the device was last polled
+    cycles: u16,
+}
+
+impl std::fmt::Debug for Serial {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Serial")
+            .field("attached", &self.device.is_some())
+            .field("transfer", &self.transfer)
+            .finish()
+    }
+}
+
+impl Serial {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Connects `device` to the port, replacing any device attached before.
+    pub fn attach(&mut self, device: Box<dyn SerialDevice>) {
+        self.device = Some(device);
+    }
+
+    /// Disconnects and returns the attached device.
+    pub fn detach(&mut self) -> Option<Box<dyn SerialDevice>> {
+        self.device.take()
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.device.is_some()
+    }
+
+    /// Returns `true` while a transfer on the internal clock is in progress.
+    pub fn is_transferring(&self) -> bool {
+        self.transfer.is_some()
+    }
+
+    /// Starts a transfer of `sb` on the internal clock.
+    pub fn start(&mut self, sb: u8) {
+        let received = match &mut self.device {
+            Some(device) => device.transfer(sb),
+            None => 0xFF,
+        };
+        self.transfer = Some((received, CYCLES_PER_BYTE));
+    }
+
+    /// Advances the port by one machine cycle, returning the received byte when a transfer
+    /// completes.
+    pub fn step(&mut self, sb: u8, sc: u8) -> Option<u8> {
+        if sc & MASK_SC_START == 0 {
+            self.transfer = None;
+        }
+
+        if let Some((received, cycles)) = &mut self.transfer {
+            *cycles -= 1;
+            let received = *received;
+
+            if *cycles == 0 {
+                self.transfer = None;
+                return Some(received);
+            }
+            return None;
+        }
+
+        self.cycles += 1;
+        if self.cycles < CYCLES_PER_BIT {
+            return None;
+        }
+        self.cycles = 0;
+
+        let listening = sc & (MASK_SC_START | MASK_SC_INTERNAL_CLOCK) == MASK_SC_START;
+        let received = self.device.as_mut()?.poll(Some(sb).filter(|_| listening));
+        received.filter(|_| listening)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Drives the clock with a queue of bytes, receiving into `received`
+    struct Master {
+        bytes: Vec<u8>,
+        received: std::rc::Rc<std::cell::RefCell<Vec<u8>>>,
+    }
+
+    impl SerialDevice for Master {
+        fn transfer(&mut self, _: u8) -> u8 {
+            0xFF
+        }
+
+        fn poll(&mut self, byte: Option<u8>) -> Option<u8> {
+            let next = self.bytes.pop()?;
+            self.received.borrow_mut().extend(byte);
+            Some(next)
+        }
+    }
+
+    #[test]
+    fn transfers_on_the_internal_clock_take_a_byte_period() {
+        let mut serial = Serial::new();
+        serial.start(0x12);
+
+        for _ in 1..CYCLES_PER_BYTE {
+            assert_eq!(None, serial.step(0x12, 0x81));
+        }
+        assert_eq!(Some(0xFF), serial.step(0x12, 0x81));
+        assert!(!serial.is_transferring());
+    }
+
+    #[test]
+    fn clearing_the_start_bit_cancels_the_transfer() {
+        let mut serial = Serial::new();
+        serial.start(0x12);
+
+        assert_eq!(None, serial.step(0x12, 0x01));
+        assert!(!serial.is_transferring());
+    }
+
+    #[test]
+    fn transfers_on_the_external_clock_complete_when_the_device_transfers() {
+        let received = std::rc::Rc::default();
+        let mut serial = Serial::new();
+        serial.attach(Box::new(Master {
+            bytes: vec![0x56, 0x34],
+            received: std::rc::Rc::clone(&received),
+        }));
+
+        // Not listening, the byte is dropped
+        let steps = (0..CYCLES_PER_BIT).map(|_| serial.step(0x12, 0x00));
+        assert_eq!(None, steps.last().unwrap());
+
+        let steps = (0..CYCLES_PER_BIT).map(|_| serial.step(0x12, 0x80));
+        assert_eq!(Some(0x56), steps.last().unwrap());
+        assert_eq!(vec![0x12], *received.borrow());
+    }
+}
@@ -0,0 +1,39 @@
+//! # Stack
+//!
+//! Pushing and popping through `SP`, shared by `CALL`, `RST`, `RET`, `PUSH`, `POP` and the
+//! interrupt dispatch. The stack grows downwards: a push decrements `SP` before writing, a pop
+//! increments it after reading.
+//!
+//! ```
+//! # use gejmboj_cpu::{memory::Memory, registers::Registers, stack};
+//! let mut registers = Registers::new();
+//! let mut memory = Memory::new();
+//!
+//! stack::push_u16(&mut registers, &mut memory, 0x1234);
+//! assert_eq!(0xFFFC, registers.SP);
+//!
+//! assert_eq!(0x1234, stack::pop_u16(&mut registers, &memory));
+//! assert_eq!(0xFFFE, registers.SP);
+//! ```
+
+use crate::{memory::MemoryBus, registers::Registers};
+
+/// Pushes `value` through `MemoryBus::set_stack_u16`, i.e. bytes targeting ROM are dropped.
+pub fn push_u16(registers: &mut Registers, memory: &mut impl MemoryBus, value: u16) {
+    let sp = registers.decrement_sp();
+    memory.set_stack_u16(sp.into(), value);
+}
+
+/// Pops a `u16` value.
+pub fn pop_u16(registers: &mut Registers, memory: &impl MemoryBus) -> u16 {
+    let value = memory.get_u16(registers.SP.into());
+    registers.increment_sp();
+    value
+}
+
+/// Pushes a single byte through `MemoryBus::set_stack`, for pushes which act in between their
+/// two bytes like the interrupt dispatch.
+pub fn push_u8(registers: &mut Registers, memory: &mut impl MemoryBus, value: u8) {
+    let sp = registers.decrement_sp_byte();
+    memory.set_stack(sp.into(), value);
+}
@@ -0,0 +1,155 @@
+//! # Symbols
+//!
+//! Labels loaded from RGBDS style `.sym` files, one `bank:address label` per line and comments
+//! starting with `;`:
+//!
+//! ```asciidoc
+//! ; File generated by rgblink
+//! 00:0150 Main
+//! 00:0153 Main.loop
+//! 01:4000 LoadTiles
+//! ```
+//!
+//! Addresses are resolved to the closest label at or before them in the same bank, printed as
+//! `bank:label` or `bank:label+offset`. The bank of an address in ROM is the bank currently
+//! mapped there, see `bank`, other addresses are in bank `00`.
+//!
+//! ```
+//! # use gejmboj_cpu::symbols::Symbols;
+//! let symbols: Symbols = "00:0150 Main\n01:4000 LoadTiles".parse().unwrap();
+//!
+//! assert_eq!(Some("00:Main+3".to_string()), symbols.resolve(0x00, 0x0153));
+//! assert_eq!(Some("01:LoadTiles".to_string()), symbols.resolve(0x01, 0x4000));
+//! assert_eq!(None, symbols.resolve(0x02, 0x4000));
+//! assert_eq!(Some((0x01, 0x4000)), symbols.address("LoadTiles"));
+//! ```
+
+use std::{collections::BTreeMap, str::FromStr};
+
+use crate::{cartridge::ROM_BANK_SIZE, errors::CpuError, memory::MemoryBus};
+
+/// Labels by bank and address, see module documentation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Symbols {
+    labels: BTreeMap<(u16, u16), String>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a label, replacing any label at the same bank and address.
+    pub fn insert(&mut self, bank: u16, address: u16, label: &str) {
+        self.labels.insert((bank, address), label.to_string());
+    }
+
+    /// Returns the label at exactly `address` in `bank`.
+    pub fn label(&self, bank: u16, address: u16) -> Option<&str> {
+        self.labels.get(&(bank, address)).map(String::as_str)
+    }
+
+    /// Returns the closest label at or before `address` in `bank`, and the offset from it.
+    pub fn closest(&self, bank: u16, address: u16) -> Option<(&str, u16)> {
+        self.labels
+            .range((bank, 0)..=(bank, address))
+            .next_back()
+            .map(|((_, at), label)| (label.as_str(), address - at))
+    }
+
+    /// Returns `address` as `bank:label` or `bank:label+offset`.
+    pub fn resolve(&self, bank: u16, address: u16) -> Option<String> {
+        self.closest(bank, address)
+            .map(|(label, offset)| match offset {
+                0 => format!("{:02x}:{}", bank, label),
+                _ => format!("{:02x}:{}+{}", bank, label, offset),
+            })
+    }
+
+    /// Returns the bank and address of `label`.
+    pub fn address(&self, label: &str) -> Option<(u16, u16)> {
+        self.labels
+            .iter()
+            .find(|(_, x)| x.as_str() == label)
+            .map(|(at, _)| *at)
+    }
+
+    /// Returns the number of labels.
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+}
+
+impl FromStr for Symbols {
+    type Err = CpuError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut symbols = Symbols::new();
+
+        for (number, line) in s.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let error =
+                || CpuError::Error(format!("Invalid symbol on line {}: {}", number + 1, line));
+            let mut parts = line.split_whitespace();
+            let (location, label) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(location), Some(label), None) => (location, label),
+                _ => return Err(error()),
+            };
+            let (bank, address) = location.split_once(':').ok_or_else(error)?;
+            let bank = u16::from_str_radix(bank, 16).map_err(|_| error())?;
+            let address = u16::from_str_radix(address, 16).map_err(|_| error())?;
+
+            symbols.insert(bank, address, label);
+        }
+
+        Ok(symbols)
+    }
+}
+
+/// Returns the bank `address` is in, i.e. the ROM bank mapped there or `0` outside of ROM.
+pub fn bank(memory: &impl MemoryBus, address: u16) -> u16 {
+    memory
+        .rom_offset(address as usize)
+        .map_or(0, |offset| (offset / ROM_BANK_SIZE) as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        let symbols: Symbols = "; File generated by rgblink\n\n00:0150 Main ; entry\n"
+            .parse()
+            .unwrap();
+
+        assert_eq!(1, symbols.len());
+        assert_eq!(Some("Main"), symbols.label(0x00, 0x0150));
+        assert_eq!(None, symbols.resolve(0x00, 0x014F));
+    }
+
+    #[test]
+    fn invalid_lines_are_rejected() {
+        for invalid in ["0150 Main", "00:0150", "00:0150 Main Extra", "00:XYZ Main"].iter() {
+            assert!(invalid.parse::<Symbols>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn rom_addresses_are_in_the_mapped_bank() {
+        let memory = Memory::new();
+
+        assert_eq!(0, bank(&memory, 0x0150));
+        assert_eq!(1, bank(&memory, 0x4000));
+        assert_eq!(0, bank(&memory, 0xC000));
+    }
+}
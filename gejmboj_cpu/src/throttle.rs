@@ -0,0 +1,146 @@
+//! # Frame pacing
+//!
+//! `Throttle` paces emulated frames to the hardware frame rate of 4194304 / 70224 Hz, about
+//! 59.7275 Hz. Frontends call `Throttle::wait` once per frame, it sleeps until shortly before
+//! the frame is due and spins for the rest, since sleeps tend to overshoot.
+//!
+//! Frames are due on a fixed schedule counted from the first frame, rather than a frame duration
+//! after the previous one, so oversleeping doesn't accumulate into drift. A frontend falling more
+//! than `MAX_LAG` behind, e.g. after being suspended, starts a new schedule instead of running
+//! frames as fast as it can to catch up.
+//!
+//! ```
+//! # use gejmboj_cpu::{gameboy::GameBoy, model::Model, throttle::Throttle};
+//! let mut gameboy = GameBoy::new(Model::Dmg);
+//! gameboy.load_rom(vec![0; 0x8000]).unwrap();
+//! let mut throttle = Throttle::new();
+//!
+//! for _ in 0..3 {
+//!     gameboy.run_frame().unwrap();
+//!     throttle.wait();
+//! }
+//! ```
+
+use std::time::{Duration, Instant};
+
+use crate::cpu::{FRAME_CYCLES, T_CYCLES_PER_M_CYCLE};
+
+/// T-cycles per second.
+pub const CLOCK_RATE: u64 = 4_194_304;
+
+/// Frames per second.
+pub const FRAME_RATE: f64 = CLOCK_RATE as f64 / (FRAME_CYCLES * T_CYCLES_PER_M_CYCLE) as f64;
+
+/// How far behind schedule frames may fall before a new schedule is started.
+pub const MAX_LAG: Duration = Duration::from_millis(100);
+
+/// Time before a frame is due which is spent spinning rather than sleeping by default.
+pub const DEFAULT_SPIN: Duration = Duration::from_millis(1);
+
+/// Paces frames to the hardware frame rate, see module documentation.
+#[derive(Debug, Clone)]
+pub struct Throttle {
+    /// When the current schedule started
+    start: Option<Instant>,
+    /// Frames waited for in the current schedule
+    frames: u64,
+    spin: Duration,
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Throttle {
+    pub fn new() -> Self {
+        Self {
+            start: None,
+            frames: 0,
+            spin: DEFAULT_SPIN,
+        }
+    }
+
+    /// Sets the time before a frame is due spent spinning, `Duration::ZERO` only sleeps.
+    pub fn set_spin(&mut self, spin: Duration) {
+        self.spin = spin;
+    }
+
+    /// Waits until the next frame is due, returning how long it waited. The first call only
+    /// starts the schedule.
+    pub fn wait(&mut self) -> Duration {
+        let now = Instant::now();
+        let start = match self.start {
+            Some(start) => start,
+            None => return self.restart(now),
+        };
+
+        self.frames += 1;
+        let due = start + elapsed(self.frames);
+        if now > due + MAX_LAG {
+            return self.restart(now);
+        }
+
+        if let Some(sleep) = due.checked_duration_since(now + self.spin) {
+            std::thread::sleep(sleep);
+        }
+        while Instant::now() < due {
+            std::hint::spin_loop();
+        }
+        due.saturating_duration_since(now)
+    }
+
+    /// Starts a new schedule with the next frame due one frame from now.
+    pub fn reset(&mut self) {
+        self.start = None;
+        self.frames = 0;
+    }
+
+    fn restart(&mut self, now: Instant) -> Duration {
+        self.start = Some(now);
+        self.frames = 0;
+        Duration::ZERO
+    }
+}
+
+/// Returns the time `frames` frames take on hardware, exact to the nanosecond.
+fn elapsed(frames: u64) -> Duration {
+    let t_cycles = frames as u128 * (FRAME_CYCLES * T_CYCLES_PER_M_CYCLE) as u128;
+    Duration::from_nanos((t_cycles * 1_000_000_000 / CLOCK_RATE as u128) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_are_due_on_a_fixed_schedule() {
+        assert_eq!(Duration::from_nanos(16_742_706), elapsed(1));
+        assert_eq!(Duration::from_nanos(1_004_562_377), elapsed(60));
+        assert!((FRAME_RATE - 59.7275).abs() < 0.0001);
+    }
+
+    #[test]
+    fn wait_paces_frames() {
+        let mut throttle = Throttle::new();
+        let started = Instant::now();
+
+        for _ in 0..4 {
+            throttle.wait();
+        }
+
+        assert!(started.elapsed() >= elapsed(3));
+    }
+
+    #[test]
+    fn lagging_behind_starts_a_new_schedule() {
+        let mut throttle = Throttle::new();
+        throttle.wait();
+
+        std::thread::sleep(MAX_LAG + elapsed(1));
+
+        assert_eq!(Duration::ZERO, throttle.wait());
+        assert!(throttle.wait() > Duration::ZERO);
+    }
+}
@@ -0,0 +1,276 @@
+//! # Timer
+//!
+//! The timer is driven by a 16-bit counter incremented every T-cycle, DIV (`FF04`) reads its
+//! upper byte. TIMA (`FF05`) is incremented on the falling edge of one bit of the counter while
+//! the timer is enabled, selected by TAC (`FF07`):
+//!
+//! ```asciidoc
+//! Bit 2:   Enable
+//! Bit 0-1: Counter bit, 00 = 9 (4096 Hz), 01 = 3 (262144 Hz), 10 = 5 (65536 Hz), 11 = 7 (16384 Hz)
+//! ```
+//!
+//! Since TIMA watches the falling edge of `enabled && bit`, writing DIV, which resets the
+//! counter, or writing TAC can increment TIMA too.
+//!
+//! When TIMA overflows it reads `00` for a machine cycle before it is reloaded from TMA
+//! (`FF06`) and the timer interrupt is requested. Writing TIMA in that cycle cancels the reload.
+//! In the machine cycle of the reload writes to TIMA are ignored, while writes to TMA are
+//! copied to TIMA as well.
+//!
+//! ```
+//! # use gejmboj_cpu::timer::Timer;
+//! let mut timer = Timer::new();
+//! // Enabled, counting every 16 T-cycles
+//! timer.write(0xFF07, 0x05);
+//! timer.write(0xFF05, 0xFF);
+//! timer.write(0xFF06, 0x80);
+//!
+//! for _ in 0..4 {
+//!     assert!(!timer.step());
+//! }
+//! assert_eq!(0x00, timer.read(0xFF05));
+//! assert!(timer.step());
+//! assert_eq!(0x80, timer.read(0xFF05));
+//! ```
+
+use std::convert::TryInto;
+
+use crate::io::{REGISTER_DIV, REGISTER_TAC, REGISTER_TIMA, REGISTER_TMA};
+
+/// Size of the state saved by `Timer::to_bytes`.
+pub(crate) const TIMER_STATE_SIZE: usize = 6;
+
+/// Where the timer is in reloading TIMA after an overflow.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Reload {
+    Idle,
+    /// TIMA overflowed and reads `00`, it's reloaded in the next machine cycle
+    Pending,
+    /// TIMA was reloaded from TMA in this machine cycle
+    Reloaded,
+}
+
+/// The timer, see module documentation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timer {
+    counter: u16,
+    tima: u8,
+    tma: u8,
+    tac: u8,
+    reload: Reload,
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Self {
+            counter: 0,
+            tima: 0,
+            tma: 0,
+            tac: 0,
+            reload: Reload::Idle,
+        }
+    }
+
+    /// Advances the timer by one machine cycle, returning `true` if the timer interrupt is
+    /// requested.
+    pub fn step(&mut self) -> bool {
+        let interrupt = match self.reload {
+            Reload::Pending => {
+                self.tima = self.tma;
+                self.reload = Reload::Reloaded;
+                true
+            }
+            Reload::Reloaded => {
+                self.reload = Reload::Idle;
+                false
+            }
+            Reload::Idle => false,
+        };
+
+        self.set_counter(self.counter.wrapping_add(4));
+        interrupt
+    }
+
+    /// Reads the register at `address` (`FF04-FF07`), unused TAC bits read as `0`.
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            REGISTER_DIV => (self.counter >> 8) as u8,
+            REGISTER_TIMA => self.tima,
+            REGISTER_TMA => self.tma,
+            REGISTER_TAC => self.tac,
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes `value` to the register at `address` (`FF04-FF07`) as the CPU would.
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            REGISTER_DIV => self.set_counter(0),
+            REGISTER_TIMA => match self.reload {
+                Reload::Pending => {
+                    self.tima = value;
+                    self.reload = Reload::Idle;
+                }
+                Reload::Reloaded => {}
+                Reload::Idle => self.tima = value,
+            },
+            REGISTER_TMA => {
+                self.tma = value;
+                if self.reload == Reload::Reloaded {
+                    self.tima = value;
+                }
+            }
+            REGISTER_TAC => {
+                let signal = self.signal();
+                self.tac = value & 0x07;
+                self.detect_falling_edge(signal);
+            }
+            _ => {}
+        }
+    }
+
+    /// Sets a register without any side effects, DIV sets the upper byte of the counter.
+    pub fn set_raw(&mut self, address: u16, value: u8) {
+        match address {
+            REGISTER_DIV => self.counter = u16::from_be_bytes([value, 0x00]),
+            REGISTER_TIMA => self.tima = value,
+            REGISTER_TMA => self.tma = value,
+            REGISTER_TAC => self.tac = value & 0x07,
+            _ => {}
+        }
+    }
+
+    fn set_counter(&mut self, counter: u16) {
+        let signal = self.signal();
+        self.counter = counter;
+        self.detect_falling_edge(signal);
+    }
+
+    /// Returns the input of the falling edge detector incrementing TIMA.
+    fn signal(&self) -> bool {
+        let bit = [9, 3, 5, 7][(self.tac & 0x03) as usize];
+        self.tac & 0x04 > 0 && self.counter & (1 << bit) > 0
+    }
+
+    fn detect_falling_edge(&mut self, before: bool) {
+        if !before || self.signal() {
+            return;
+        }
+
+        let (tima, overflow) = self.tima.overflowing_add(1);
+        self.tima = tima;
+        if overflow {
+            self.reload = Reload::Pending;
+        }
+    }
+
+    /// Returns the counter, registers and reload state as bytes.
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.counter.to_le_bytes().to_vec();
+        bytes.extend([self.tima, self.tma, self.tac, self.reload as u8]);
+        bytes
+    }
+
+    /// Restores a state saved with `to_bytes`, `None` if `bytes` is invalid.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: [u8; TIMER_STATE_SIZE] = bytes.try_into().ok()?;
+        let reload = match bytes[5] {
+            0 => Reload::Idle,
+            1 => Reload::Pending,
+            2 => Reload::Reloaded,
+            _ => return None,
+        };
+
+        Some(Self {
+            counter: u16::from_le_bytes([bytes[0], bytes[1]]),
+            tima: bytes[2],
+            tma: bytes[3],
+            tac: bytes[4] & 0x07,
+            reload,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A timer enabled at 262144 Hz, TIMA incremented every 4 machine cycles
+    fn fast_timer(tima: u8) -> Timer {
+        let mut timer = Timer::new();
+        timer.write(REGISTER_TAC, 0x05);
+        timer.write(REGISTER_TIMA, tima);
+        timer
+    }
+
+    #[test]
+    fn tima_counts_at_the_selected_frequency() {
+        for (tac, cycles) in [(0x04, 256), (0x05, 4), (0x06, 16), (0x07, 64)] {
+            let mut timer = Timer::new();
+            timer.write(REGISTER_TAC, tac);
+
+            for _ in 0..cycles * 3 {
+                timer.step();
+            }
+
+            assert_eq!(3, timer.read(REGISTER_TIMA), "TAC {:02x}", tac);
+        }
+    }
+
+    #[test]
+    fn writing_tima_before_the_reload_cancels_it() {
+        let mut timer = fast_timer(0xFF);
+        timer.write(REGISTER_TMA, 0x80);
+        for _ in 0..4 {
+            timer.step();
+        }
+
+        timer.write(REGISTER_TIMA, 0x10);
+
+        assert!(!timer.step());
+        assert_eq!(0x10, timer.read(REGISTER_TIMA));
+    }
+
+    #[test]
+    fn writes_in_the_reload_cycle_follow_tma() {
+        let mut timer = fast_timer(0xFF);
+        timer.write(REGISTER_TMA, 0x80);
+        for _ in 0..5 {
+            timer.step();
+        }
+
+        timer.write(REGISTER_TIMA, 0x10);
+        assert_eq!(0x80, timer.read(REGISTER_TIMA));
+        timer.write(REGISTER_TMA, 0x20);
+        assert_eq!(0x20, timer.read(REGISTER_TIMA));
+
+        timer.step();
+        timer.write(REGISTER_TIMA, 0x10);
+        assert_eq!(0x10, timer.read(REGISTER_TIMA));
+    }
+
+    #[test]
+    fn writing_div_or_tac_on_a_set_bit_increments_tima() {
+        let mut timer = fast_timer(0x00);
+        for _ in 0..2 {
+            timer.step();
+        }
+
+        // Counter bit 3 is set after 2 machine cycles
+        timer.write(REGISTER_DIV, 0xAB);
+        assert_eq!(0x01, timer.read(REGISTER_TIMA));
+        assert_eq!(0x00, timer.read(REGISTER_DIV));
+
+        for _ in 0..2 {
+            timer.step();
+        }
+        timer.write(REGISTER_TAC, 0x01);
+        assert_eq!(0x02, timer.read(REGISTER_TIMA));
+    }
+}
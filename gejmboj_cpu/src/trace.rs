@@ -0,0 +1,285 @@
+//! # Execution trace
+//!
+//! Opt-in ring buffer of the last executed instructions, see `CPU::enable_trace`. Each entry
+//! holds the address and bytes of the instruction, and the registers after executing it. When
+//! the buffer is full the oldest entry is dropped.
+//!
+//! Dump the trace after a `CpuError`, or whenever it is of interest, by printing it:
+//!
+//! ```
+//! # use gejmboj_cpu::{cpu::CPU, memory::Memory, registers::Registers};
+//! let mut cpu = CPU::new();
+//! let mut registers = Registers::new();
+//! let mut memory = Memory::new();
+//! // INC A, ADD A, 0x3B
+//! memory.load(0x0000, &[0x3C, 0xC6, 0x3B]);
+//! cpu.enable_trace(16);
+//!
+//! cpu.tick(&mut registers, &mut memory).unwrap();
+//! cpu.tick(&mut registers, &mut memory).unwrap();
+//!
+//! assert_eq!(
+//!     "0000: 3c        AF:0100 BC:0000 DE:0000 HL:0000 SP:fffe PC:0001\n\
+//!      0001: c6 3b     AF:3c00 BC:0000 DE:0000 HL:0000 SP:fffe PC:0003\n",
+//!     cpu.trace().unwrap().to_string()
+//! );
+//! ```
+//!
+//! With dirty tracking enabled on the registers, see `Registers::enable_dirty_tracking`, each
+//! entry records the register pairs the instruction wrote and the alternate form (`{:#}`) marks
+//! them with a `*`:
+//!
+//! ```
+//! # use gejmboj_cpu::{cpu::CPU, memory::Memory, registers::Registers};
+//! # let mut cpu = CPU::new();
+//! # let mut registers = Registers::new();
+//! # let mut memory = Memory::new();
+//! // INC A
+//! memory.load(0x0000, &[0x3C]);
+//! cpu.enable_trace(16);
+//! registers.enable_dirty_tracking();
+//!
+//! cpu.tick(&mut registers, &mut memory).unwrap();
+//!
+//! assert_eq!(
+//!     "0000: 3c        AF:0100* BC:0000 DE:0000 HL:0000 SP:fffe PC:0001*\n",
+//!     format!("{:#}", cpu.trace().unwrap())
+//! );
+//! ```
+//!
+//! Print `Trace::with_symbols` to label the instruction addresses, see `symbols`.
+//!
+//! For comparing against reference logs, see `doctor`.
+
+pub mod doctor;
+
+use std::{collections::VecDeque, fmt::Display};
+
+use crate::{
+    registers::{DoubleRegister, Registers},
+    symbols::Symbols,
+};
+
+/// An executed instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEntry {
+    /// Address of the instruction
+    pub address: u16,
+    /// Bank of the instruction, see `symbols::bank`
+    pub bank: u16,
+    bytes: [u8; 3],
+    length: usize,
+    /// Registers after executing the instruction
+    pub af: u16,
+    pub bc: u16,
+    pub de: u16,
+    pub hl: u16,
+    pub sp: u16,
+    pub pc: u16,
+    /// Register pairs written by the instruction, in the order of `PAIRS`
+    dirty: [bool; 6],
+}
+
+/// The register pairs of an entry in the order displayed.
+const PAIRS: [DoubleRegister; 6] = [
+    DoubleRegister::AF,
+    DoubleRegister::BC,
+    DoubleRegister::DE,
+    DoubleRegister::HL,
+    DoubleRegister::SP,
+    DoubleRegister::PC,
+];
+
+impl TraceEntry {
+    pub(crate) fn new(address: u16, bank: u16, bytes: &[u8], registers: &Registers) -> Self {
+        let mut padded = [0; 3];
+        padded[..bytes.len()].copy_from_slice(bytes);
+
+        Self {
+            address,
+            bank,
+            bytes: padded,
+            length: bytes.len(),
+            af: registers.get_double(&DoubleRegister::AF),
+            bc: registers.get_double(&DoubleRegister::BC),
+            de: registers.get_double(&DoubleRegister::DE),
+            hl: registers.get_double(&DoubleRegister::HL),
+            sp: registers.get_double(&DoubleRegister::SP),
+            pc: registers.get_double(&DoubleRegister::PC),
+            dirty: PAIRS.map(|pair| registers.is_double_dirty(&pair)),
+        }
+    }
+
+    /// Returns `true` if the instruction wrote `pair`, always `false` unless dirty tracking was
+    /// enabled on the registers.
+    pub fn is_dirty(&self, pair: &DoubleRegister) -> bool {
+        PAIRS
+            .iter()
+            .zip(self.dirty)
+            .any(|(p, dirty)| p == pair && dirty)
+    }
+
+    /// Returns the opcode and operand bytes of the instruction.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.length]
+    }
+}
+
+impl Display for TraceEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bytes: Vec<String> = self.bytes().iter().map(|x| format!("{:02x}", x)).collect();
+
+        let values = [self.af, self.bc, self.de, self.hl, self.sp, self.pc];
+
+        write!(f, "{:04x}: {:<9}", self.address, bytes.join(" "))?;
+        for ((pair, value), dirty) in PAIRS.iter().zip(values).zip(self.dirty) {
+            write!(f, " {}:{:04x}", pair, value)?;
+            if dirty && f.alternate() {
+                write!(f, "*")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A ring buffer of the last executed instructions, see module documentation.
+#[derive(Debug)]
+pub struct Trace {
+    entries: VecDeque<TraceEntry>,
+    capacity: usize,
+}
+
+impl Trace {
+    /// Creates a trace keeping the last `capacity` instructions.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Adds an entry, dropping the oldest if the trace is full.
+    pub(crate) fn push(&mut self, entry: TraceEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Returns the entries, oldest first.
+    pub fn entries(&self) -> impl Iterator<Item = &TraceEntry> {
+        self.entries.iter()
+    }
+
+    /// Returns the most recently executed instruction.
+    pub fn last(&self) -> Option<&TraceEntry> {
+        self.entries.back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Removes all entries.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Returns the trace printed with the label of each instruction address.
+    pub fn with_symbols<'a>(&'a self, symbols: &'a Symbols) -> SymbolizedTrace<'a> {
+        SymbolizedTrace {
+            trace: self,
+            symbols,
+        }
+    }
+}
+
+impl Display for Trace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for entry in &self.entries {
+            match f.alternate() {
+                true => writeln!(f, "{:#}", entry)?,
+                false => writeln!(f, "{}", entry)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A trace printed with labels, see `Trace::with_symbols`.
+pub struct SymbolizedTrace<'a> {
+    trace: &'a Trace,
+    symbols: &'a Symbols,
+}
+
+impl Display for SymbolizedTrace<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for entry in self.trace.entries() {
+            match self.symbols.resolve(entry.bank, entry.address) {
+                Some(label) => writeln!(f, "{} ; {}", entry, label)?,
+                None => writeln!(f, "{}", entry)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oldest_entries_are_dropped() {
+        let registers = Registers::new();
+        let mut trace = Trace::new(2);
+
+        for address in 0..5 {
+            trace.push(TraceEntry::new(address, 0, &[0x00], &registers));
+        }
+
+        assert_eq!(
+            vec![3, 4],
+            trace.entries().map(|x| x.address).collect::<Vec<_>>()
+        );
+        assert_eq!(4, trace.last().unwrap().address);
+    }
+
+    #[test]
+    fn entries_are_labeled_by_bank_and_address() {
+        let registers = Registers::new();
+        let symbols: Symbols = "01:4000 LoadTiles".parse().unwrap();
+        let mut trace = Trace::new(4);
+        trace.push(TraceEntry::new(0x4002, 1, &[0x00], &registers));
+        trace.push(TraceEntry::new(0x4002, 2, &[0x00], &registers));
+
+        let lines: Vec<String> = trace
+            .with_symbols(&symbols)
+            .to_string()
+            .lines()
+            .map(String::from)
+            .collect();
+
+        assert!(lines[0].ends_with("PC:0000 ; 01:LoadTiles+2"));
+        assert!(lines[1].ends_with("PC:0000"));
+    }
+
+    #[test]
+    fn empty_trace_keeps_nothing() {
+        let mut trace = Trace::new(0);
+
+        trace.push(TraceEntry::new(0, 0, &[0x00], &Registers::new()));
+
+        assert!(trace.is_empty());
+    }
+}
@@ -0,0 +1,99 @@
+//! # Gameboy Doctor logs
+//!
+//! Gameboy Doctor compares a CPU log against reference logs of the Blargg test ROMs. Each line
+//! holds the registers before an instruction is executed, and the 4 bytes at PC:
+//!
+//! ```asciidoc
+//! A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02
+//! ```
+//!
+//! The reference logs start at `0100` with the post boot registers, see
+//! `Registers::new_post_boot`, and assume LY (`FF44`) always reads `90`.
+//!
+//! ```
+//! # use gejmboj_cpu::{cpu::CPU, memory::Memory, model::Model, registers::Registers};
+//! # use gejmboj_cpu::trace::doctor::DoctorLog;
+//! let mut cpu = CPU::new();
+//! let mut registers = Registers::new_post_boot(Model::Dmg);
+//! let mut memory = Memory::new();
+//! let mut log = DoctorLog::new(vec![]);
+//!
+//! log.write(&registers, &memory).unwrap();
+//! cpu.tick(&mut registers, &mut memory).unwrap();
+//! log.write(&registers, &memory).unwrap();
+//!
+//! assert_eq!(
+//!     "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,00,00,00\n\
+//!      A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0101 PCMEM:00,00,00,00\n",
+//!     String::from_utf8(log.into_inner()).unwrap()
+//! );
+//! ```
+
+use std::io::{self, Write};
+
+use crate::{
+    memory::MemoryBus,
+    registers::{Registers, SingleRegister},
+};
+
+/// Returns the log line for the instruction at PC, memory is read with `MemoryBus::peek`.
+pub fn line(registers: &Registers, memory: &impl MemoryBus) -> String {
+    let register = |r| registers.get_single(&r);
+    let pc = registers.PC as usize;
+
+    format!(
+        "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+        register(SingleRegister::A),
+        registers.get_flags(),
+        register(SingleRegister::B),
+        register(SingleRegister::C),
+        register(SingleRegister::D),
+        register(SingleRegister::E),
+        register(SingleRegister::H),
+        register(SingleRegister::L),
+        registers.SP,
+        registers.PC,
+        memory.peek(pc),
+        memory.peek((pc + 1) & 0xFFFF),
+        memory.peek((pc + 2) & 0xFFFF),
+        memory.peek((pc + 3) & 0xFFFF),
+    )
+}
+
+/// Writes a Gameboy Doctor log, one line per instruction.
+pub struct DoctorLog<W> {
+    output: W,
+}
+
+impl<W: Write> DoctorLog<W> {
+    pub fn new(output: W) -> Self {
+        Self { output }
+    }
+
+    /// Logs the instruction at PC, call before every `CPU::tick`.
+    pub fn write(&mut self, registers: &Registers, memory: &impl MemoryBus) -> io::Result<()> {
+        writeln!(self.output, "{}", line(registers, memory))
+    }
+
+    /// Returns the output.
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+
+    #[test]
+    fn pcmem_wraps_at_the_end_of_memory() {
+        let mut registers = Registers::new();
+        let mut memory = Memory::new();
+        registers.PC = 0xFFFE;
+        memory.set(0xFFFE, 0xAB);
+        memory.set(0xFFFF, 0xCD);
+
+        assert!(line(&registers, &memory).ends_with("PC:FFFE PCMEM:AB,CD,00,00"));
+    }
+}
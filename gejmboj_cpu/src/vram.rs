@@ -0,0 +1,133 @@
+//! # Video RAM
+//!
+//! VRAM (`8000-9FFF`) holds tile data and tile maps. CGB has a second bank, selected for CPU
+//! accesses with bit 0 of the VBK register (`FF4F`). In bank 1 the tile map area (`9800-9FFF`)
+//! holds the attributes of the tile at the same address in bank 0, see `TileAttributes`.
+//!
+//! The renderer is not bound by VBK and reads either bank through `Vram::read`.
+
+/// First address of VRAM.
+pub const VRAM_START: u16 = 0x8000;
+
+/// Size of a VRAM bank in bytes.
+pub const VRAM_BANK_SIZE: usize = 0x2000;
+
+/// CGB background map attributes, stored in VRAM bank 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileAttributes {
+    /// Background palette number (0-7)
+    pub palette: u8,
+    /// VRAM bank holding the tile data (0-1)
+    pub bank: u8,
+    pub x_flip: bool,
+    pub y_flip: bool,
+    /// Background has priority over objects
+    pub priority: bool,
+}
+
+impl From<u8> for TileAttributes {
+    fn from(x: u8) -> Self {
+        Self {
+            palette: x & 0b0000_0111,
+            bank: (x & 0b0000_1000) >> 3,
+            x_flip: x & 0b0010_0000 > 0,
+            y_flip: x & 0b0100_0000 > 0,
+            priority: x & 0b1000_0000 > 0,
+        }
+    }
+}
+
+/// Both VRAM banks, see module documentation.
+pub struct Vram {
+    banks: Vec<u8>,
+}
+
+impl Default for Vram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vram {
+    pub fn new() -> Self {
+        Self {
+            banks: vec![0; VRAM_BANK_SIZE * 2],
+        }
+    }
+
+    /// Reads `address` (`8000-9FFF`) in `bank`.
+    pub fn read(&self, bank: u8, address: u16) -> u8 {
+        self.banks[index(bank, address)]
+    }
+
+    /// Writes `value` to `address` (`8000-9FFF`) in `bank`.
+    pub fn write(&mut self, bank: u8, address: u16, value: u8) {
+        self.banks[index(bank, address)] = value;
+    }
+
+    /// Returns the attributes of the tile map entry at `address` (`9800-9FFF`).
+    ///
+    /// ```
+    /// # use gejmboj_cpu::vram::Vram;
+    /// let mut vram = Vram::new();
+    /// vram.write(1, 0x9800, 0b0010_1011);
+    ///
+    /// let attributes = vram.tile_attributes(0x9800);
+    ///
+    /// assert_eq!(3, attributes.palette);
+    /// assert_eq!(1, attributes.bank);
+    /// assert!(attributes.x_flip);
+    /// ```
+    pub fn tile_attributes(&self, address: u16) -> TileAttributes {
+        self.read(1, address).into()
+    }
+
+    /// Returns both banks, bank 0 first.
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.banks
+    }
+
+    /// Restores banks saved with `as_bytes`, `None` if `bytes` has the wrong length.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        (bytes.len() == VRAM_BANK_SIZE * 2).then(|| Self {
+            banks: bytes.to_vec(),
+        })
+    }
+}
+
+fn index(bank: u8, address: u16) -> usize {
+    (bank as usize & 0x01) * VRAM_BANK_SIZE + (address - VRAM_START) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn banks_are_separate() {
+        let mut vram = Vram::new();
+
+        vram.write(0, 0x8000, 0x11);
+        vram.write(1, 0x8000, 0x22);
+        vram.write(1, 0x9FFF, 0x33);
+
+        assert_eq!(0x11, vram.read(0, 0x8000));
+        assert_eq!(0x22, vram.read(1, 0x8000));
+        assert_eq!(0x00, vram.read(0, 0x9FFF));
+        assert_eq!(0x33, vram.read(1, 0x9FFF));
+    }
+
+    #[test]
+    fn tile_attributes_are_decoded() {
+        assert_eq!(
+            TileAttributes {
+                palette: 7,
+                bank: 0,
+                x_flip: false,
+                y_flip: true,
+                priority: true,
+            },
+            TileAttributes::from(0b1101_0111)
+        );
+    }
+}
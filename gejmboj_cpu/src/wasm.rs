@@ -0,0 +1,92 @@
+//! # WebAssembly bindings
+//!
+//! Exposes a `GameBoy` to JavaScript through wasm-bindgen, so a page can run a game in the
+//! browser. Only available with the `wasm` feature, build with e.g.
+//! `wasm-pack build gejmboj_cpu --features wasm`.
+//!
+//! The framebuffer is `160x144` pixels as RGBA, ready to be put into an `ImageData`:
+//!
+//! ```js
+//! import init, { Emulator, Model, Button } from "./pkg/gejmboj_cpu.js";
+//!
+//! await init();
+//! const emulator = new Emulator(Model.Dmg, rom);
+//! const image = context.createImageData(160, 144);
+//!
+//! function frame() {
+//!   emulator.run_frame();
+//!   image.data.set(emulator.framebuffer());
+//!   context.putImageData(image, 0, 0);
+//!   requestAnimationFrame(frame);
+//! }
+//!
+//! document.onkeydown = (event) => emulator.set_button(Button.Start, event.key === "Enter");
+//! requestAnimationFrame(frame);
+//! ```
+//!
+//! Errors are thrown as JavaScript `Error`s carrying the message of the `CpuError`.
+
+use wasm_bindgen::prelude::*;
+
+use crate::{gameboy::GameBoy, joypad::Button, model::Model};
+
+/// A machine running in the browser, see module documentation.
+#[wasm_bindgen]
+pub struct Emulator {
+    gameboy: GameBoy,
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    /// Creates a machine of `model` running the cartridge `rom`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(model: Model, rom: Vec<u8>) -> Result<Emulator, JsError> {
+        let mut gameboy = GameBoy::new(model);
+        gameboy.load_rom(rom)?;
+        Ok(Self { gameboy })
+    }
+
+    /// Runs the machine for one video frame.
+    pub fn run_frame(&mut self) -> Result<(), JsError> {
+        self.gameboy.run_frame()?;
+        Ok(())
+    }
+
+    /// Returns a copy of the framebuffer.
+    pub fn framebuffer(&self) -> Vec<u8> {
+        self.gameboy.framebuffer().pixels().to_vec()
+    }
+
+    /// Presses or releases `button`.
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        match pressed {
+            true => self.gameboy.press_button(button),
+            false => self.gameboy.release_button(button),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::{HEIGHT, WIDTH};
+
+    #[test]
+    fn emulator_runs_frames() {
+        // JR -2 at the entry point
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0102].copy_from_slice(&[0x18, 0xFE]);
+        let mut emulator = Emulator::new(Model::Dmg, rom).unwrap();
+
+        emulator.set_button(Button::A, true);
+        emulator.run_frame().unwrap();
+
+        assert_eq!(WIDTH * HEIGHT * 4, emulator.framebuffer().len());
+        assert!(emulator
+            .gameboy
+            .memory()
+            .io()
+            .joypad()
+            .is_pressed(Button::A));
+    }
+}
@@ -0,0 +1,199 @@
+//! Runs the SingleStepTests SM83 test vectors, one JSON file of cases per opcode.
+//!
+//! The vectors are not part of the repository, so the test is ignored by default. Point
+//! `SM83_TESTS` at their directory to run it, it fails if the variable is not set:
+//!
+//! ```sh
+//! git clone https://github.com/SingleStepTests/sm83
+//! SM83_TESTS=sm83/v1 cargo test --test single_step -- --ignored --nocapture
+//! ```
+//!
+//! `SM83_OPCODES` limits the run to a comma separated list of files, e.g. `00,cb 11`. Every case
+//! sets up the registers and a flat 64 KiB RAM, executes one instruction with `CPU::tick` and
+//! compares the registers, IME, RAM and the number of machine cycles. Failures are summarized
+//! per opcode.
+
+use std::{env, fs, path::Path};
+
+use gejmboj_cpu::{
+    cpu::CPU,
+    memory::MemoryBus,
+    registers::{DoubleRegister, Registers, SingleRegister},
+};
+use serde_json::Value;
+
+/// Memory without any mapped hardware, as the test vectors expect.
+struct FlatMemory(Vec<u8>);
+
+impl MemoryBus for FlatMemory {
+    fn get(&self, location: usize) -> u8 {
+        self.0[location]
+    }
+
+    fn set(&mut self, location: usize, value: u8) {
+        self.0[location] = value;
+    }
+}
+
+const REGISTERS: [(&str, SingleRegister); 8] = [
+    ("a", SingleRegister::A),
+    ("b", SingleRegister::B),
+    ("c", SingleRegister::C),
+    ("d", SingleRegister::D),
+    ("e", SingleRegister::E),
+    ("f", SingleRegister::F),
+    ("h", SingleRegister::H),
+    ("l", SingleRegister::L),
+];
+
+const DOUBLE_REGISTERS: [(&str, DoubleRegister); 2] =
+    [("sp", DoubleRegister::SP), ("pc", DoubleRegister::PC)];
+
+fn number(state: &Value, key: &str) -> u16 {
+    state[key]
+        .as_u64()
+        .unwrap_or_else(|| panic!("Missing {}", key)) as u16
+}
+
+fn ram(state: &Value) -> impl Iterator<Item = (usize, u8)> + '_ {
+    state["ram"].as_array().into_iter().flatten().map(|entry| {
+        (
+            entry[0].as_u64().unwrap() as usize,
+            entry[1].as_u64().unwrap() as u8,
+        )
+    })
+}
+
+/// Runs a single case, returning a description of every difference to the final state.
+fn run_case(case: &Value) -> Vec<String> {
+    let initial = &case["initial"];
+    let expected = &case["final"];
+
+    let mut cpu = CPU::new();
+    let mut registers = Registers::new();
+    let mut memory = FlatMemory(vec![0; 0x10000]);
+    for (key, register) in REGISTERS.iter() {
+        registers.set_single(register, number(initial, key) as u8);
+    }
+    for (key, register) in DOUBLE_REGISTERS.iter() {
+        registers.set_double(register, number(initial, key));
+    }
+    cpu.flags_mut().IME = number(initial, "ime") > 0;
+    for (address, value) in ram(initial) {
+        memory.set(address, value);
+    }
+
+    let mut differences = vec![];
+    let result = match cpu.tick(&mut registers, &mut memory) {
+        Ok(result) => result,
+        Err(e) => return vec![e.to_string()],
+    };
+
+    for (key, register) in REGISTERS.iter() {
+        let actual = registers.get_single(register) as u16;
+        if actual != number(expected, key) {
+            differences.push(format!(
+                "{} {:02x}, expected {:02x}",
+                key,
+                actual,
+                number(expected, key)
+            ));
+        }
+    }
+    let doubles = DOUBLE_REGISTERS
+        .iter()
+        .map(|(key, register)| (*key, registers.get_double(register)));
+    for (key, actual) in doubles.chain([("ime", cpu.flags().IME as u16)]) {
+        if actual != number(expected, key) {
+            differences.push(format!(
+                "{} {:04x}, expected {:04x}",
+                key,
+                actual,
+                number(expected, key)
+            ));
+        }
+    }
+    for (address, value) in ram(expected) {
+        if memory.get(address) != value {
+            differences.push(format!(
+                "({:04x}) {:02x}, expected {:02x}",
+                address,
+                memory.get(address),
+                value
+            ));
+        }
+    }
+    let cycles = case["cycles"].as_array().map_or(0, Vec::len) as u16;
+    if result.cycles != cycles {
+        differences.push(format!("{} cycles, expected {}", result.cycles, cycles));
+    }
+
+    differences
+}
+
+/// Runs all cases of a file, returning the number of cases, failed cases and the first failure.
+fn run_file(path: &Path) -> (usize, usize, Option<String>) {
+    let json = fs::read_to_string(path).unwrap();
+    let cases: Vec<Value> = serde_json::from_str(&json).unwrap();
+
+    let mut failed = 0;
+    let mut first_failure = None;
+    for case in cases.iter() {
+        let differences = run_case(case);
+        if !differences.is_empty() {
+            failed += 1;
+            first_failure.get_or_insert_with(|| {
+                format!(
+                    "{}: {}",
+                    case["name"].as_str().unwrap_or("?"),
+                    differences.join(", ")
+                )
+            });
+        }
+    }
+    (cases.len(), failed, first_failure)
+}
+
+#[test]
+#[ignore = "needs the SingleStepTests vectors, see the module documentation"]
+fn single_step_tests() {
+    let directory = env::var("SM83_TESTS")
+        .expect("SM83_TESTS must point at the directory of the SingleStepTests vectors");
+    let opcodes = env::var("SM83_OPCODES").ok();
+
+    let mut paths: Vec<_> = fs::read_dir(&directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .filter(|path| match (&opcodes, path.file_stem()) {
+            (Some(opcodes), Some(stem)) => opcodes.split(',').any(|opcode| stem == opcode.trim()),
+            _ => true,
+        })
+        .collect();
+    paths.sort();
+
+    let mut failures = vec![];
+    for path in paths.iter() {
+        let (cases, failed, first_failure) = run_file(path);
+        if let Some(first_failure) = first_failure {
+            failures.push(format!(
+                "{}: {}/{} failed, first {}",
+                path.file_stem().unwrap().to_string_lossy(),
+                failed,
+                cases,
+                first_failure
+            ));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} opcodes failed:\n{}",
+        failures.len(),
+        paths.len(),
+        failures.join("\n")
+    );
+}